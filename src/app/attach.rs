@@ -0,0 +1,338 @@
+//! Attaching to a player running in another process (e.g. `tools-rs
+//! --daemon`).
+//!
+//! An attached application keeps browsing and playlists to itself, but
+//! playback belongs to the other player: the commands that would go to the
+//! player thread become [`Request`]s ([`Effect::Daemon`]), and the other
+//! player's replies and events ([`Msg::Daemon`](super::Msg::Daemon)) update
+//! the playing track, the queue and the settings shown. Statistics, ratings,
+//! bookmarks and the session are left to the other player as well: they are
+//! read at start, and changed here in memory only, so that the other
+//! player's files are never overwritten with a stale copy.
+
+use std::collections::HashMap;
+
+use tracing::error;
+
+use super::{App, Effect};
+use crate::command::Amount;
+use crate::common::{AppCommand, PlaybackStatus, Track};
+use crate::queue::RepeatMode;
+use crate::remote::{Event, Reply, Request, TrackInfo};
+
+impl App {
+    /// Makes the application a client of a player running elsewhere, and
+    /// asks that player for its state.
+    ///
+    /// Both are expected to have scanned the same library: tracks are
+    /// matched by path.
+    pub fn attached(mut self) -> Self {
+        self.attached = true;
+        self.library.set_read_only();
+        self.bookmarks.set_read_only();
+        // The restored session belongs to this process, not to the other player
+        self.effects.retain(|effect| !matches!(effect, Effect::Player(_)));
+        self.request(Request::Queue);
+        self.request(Request::Status);
+        self
+    }
+
+    /// Asks the other player for something.
+    fn request(&mut self, request: Request) {
+        self.effects.push(Effect::Daemon(request));
+    }
+
+    /// Turns a command for the player thread into a request for the other
+    /// player.
+    pub(super) fn forward(&mut self, command: AppCommand) {
+        let request = match command {
+            AppCommand::Play { index, path } => {
+                // The queue mirrors the other player's entry for entry, so
+                // positions carry over; a track it lacks is named instead
+                let current = self.queue.position().filter(|&p| self.queue.tracks().get(p) == Some(&index));
+                match current.or_else(|| self.queue.tracks().iter().position(|&i| i == index)) {
+                    Some(position) => Request::Play(Some(position)),
+                    None => Request::PlayFile(path),
+                }
+            }
+            AppCommand::TogglePlayPause => Request::Toggle,
+            AppCommand::SetVolume(volume) => Request::Volume(Amount::To((volume * 100.0).round() as u32)),
+            AppCommand::Seek(position) => Request::Seek(Amount::To(position)),
+            // The other player keeps running
+            AppCommand::Shutdown => return,
+        };
+        self.request(request);
+    }
+
+    /// Replaces the other player's queue with this one, after it was loaded
+    /// here.
+    pub(super) fn forward_queue(&mut self) {
+        if !self.attached {
+            return;
+        }
+        let paths = self.queue.tracks().iter().map(|&i| self.tracks[i].path.clone()).collect();
+        self.request(Request::ClearQueue);
+        self.request(Request::Enqueue(paths));
+    }
+
+    /// Returns the shuffle and repeat settings.
+    pub(super) fn options(&self) -> (bool, RepeatMode) {
+        (self.queue.shuffle(), self.queue.repeat())
+    }
+
+    /// Passes on the settings changed since `before` to the other player.
+    pub(super) fn forward_options(&mut self, before: (bool, RepeatMode)) {
+        let (shuffle, repeat) = self.options();
+        if shuffle != before.0 {
+            self.request(Request::Shuffle(Some(shuffle)));
+        }
+        if repeat != before.1 {
+            self.request(Request::Repeat(Some(repeat)));
+        }
+    }
+
+    /// Applies a reply or event from the other player.
+    pub(super) fn handle_daemon(&mut self, reply: Reply) {
+        match reply {
+            Reply::Done => {}
+            Reply::Error(message) => error!("{}", message),
+            Reply::Status(status) => {
+                self.status = status.state;
+                self.position = status.position;
+                self.total = status.duration;
+                self.volume = status.volume as f32 / 100.0;
+                self.playing = status.track.and_then(|t| self.tracks.iter().position(|track| track.path == t.path));
+                let tracks = self.queue.tracks().to_vec();
+                let source = self.queue.source().map(str::to_string);
+                self.queue.restore(tracks, status.queue_position, source, status.shuffle, status.repeat);
+            }
+            Reply::Tracks(tracks) => {
+                let queue = self.match_queue(tracks);
+                let (shuffle, repeat) = self.options();
+                let source = self.queue.source().map(str::to_string);
+                self.queue.restore(queue, self.queue.position(), source, shuffle, repeat);
+            }
+            Reply::Event(event) => match event {
+                Event::TrackStarted(track) => {
+                    self.playing = self.tracks.iter().position(|t| t.path == track.path);
+                    self.status = PlaybackStatus::Playing;
+                    self.position = Default::default();
+                    self.total = track.duration;
+                    self.bookmark_selected = 0;
+                    // For the position in the queue
+                    self.request(Request::Status);
+                }
+                Event::Progress(position) => self.position = position,
+                Event::TrackEnded => {}
+                Event::Error(message) => error!("{}", message),
+                Event::State(state) => self.status = state,
+                Event::Volume(volume) => self.volume = volume as f32 / 100.0,
+                Event::Queue => {
+                    self.request(Request::Queue);
+                    self.request(Request::Status);
+                }
+                Event::Options => self.request(Request::Status),
+            },
+        }
+    }
+
+    /// Maps the other player's queue to track indices, entry for entry.
+    ///
+    /// Tracks missing from this library are kept as stubs behind the library
+    /// tracks, so that queue positions mean the same to both players; the
+    /// stubs of the previous queue are replaced.
+    fn match_queue(&mut self, tracks: Vec<TrackInfo>) -> Vec<usize> {
+        let playing = self.playing_path();
+        self.tracks.truncate(self.tracks.len() - self.stubs);
+        self.stubs = 0;
+        let mut by_path: HashMap<_, _> = self.tracks.iter().enumerate().map(|(i, t)| (t.path.clone(), i)).collect();
+        let queue = tracks
+            .into_iter()
+            .map(|info| match by_path.get(&info.path) {
+                Some(&index) => index,
+                None => {
+                    let index = self.tracks.len();
+                    by_path.insert(info.path.clone(), index);
+                    self.tracks.push(stub(info, index));
+                    self.stubs += 1;
+                    index
+                }
+            })
+            .collect();
+        self.playing = playing.and_then(|path| by_path.get(&path).copied());
+        queue
+    }
+}
+
+/// Returns a track for a file known only from the other player's queue.
+fn stub(info: TrackInfo, index: usize) -> Track {
+    Track {
+        id: index as u64,
+        path: info.path,
+        duration: info.duration,
+        title: info.title,
+        artist: info.artist,
+        album: info.album,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::app::{update, Msg};
+    use crate::harness::TempDir;
+    use crate::remote::Status;
+
+    fn app() -> App {
+        let tracks = (0..4)
+            .map(|i| Track { id: i, path: PathBuf::from(format!("/music/track{}.mp3", i)), ..Default::default() })
+            .collect();
+        App::scratch(tracks).attached()
+    }
+
+    /// Applies a message and returns the requests for the other player.
+    fn requests(app: &mut App, msg: Msg) -> Vec<Request> {
+        update(app, msg)
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Daemon(request) => Some(request),
+                Effect::Player(command) => panic!("attached, yet {:?} went to the player thread", command),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn status(queue_position: Option<usize>, shuffle: bool) -> Status {
+        Status {
+            state: PlaybackStatus::Playing,
+            track: queue_position.map(|p| TrackInfo {
+                path: PathBuf::from(format!("/music/track{}.mp3", p + 1)),
+                title: None,
+                artist: None,
+                album: None,
+                duration: None,
+            }),
+            position: Duration::from_secs(30),
+            duration: Some(Duration::from_secs(200)),
+            volume: 60,
+            shuffle,
+            repeat: RepeatMode::All,
+            queue_position,
+            queue_length: 3,
+        }
+    }
+
+    #[test]
+    fn test_state_follows_the_other_player() {
+        let mut app = app();
+        assert_eq!(requests(&mut app, Msg::Resize(80, 24)), [Request::Queue, Request::Status]);
+
+        let queue = (1..4).map(|i| TrackInfo::from(&app.tracks[i])).collect();
+        requests(&mut app, Msg::Daemon(Reply::Tracks(queue)));
+        requests(&mut app, Msg::Daemon(Reply::Status(status(Some(1), false))));
+        assert_eq!(app.queue.tracks(), &[1, 2, 3]);
+        assert_eq!((app.queue.current(), app.playing, app.status()), (Some(2), Some(2), PlaybackStatus::Playing));
+        assert_eq!((app.position(), app.volume, app.queue.repeat()), (Duration::from_secs(30), 0.6, RepeatMode::All));
+
+        let started = Event::TrackStarted(TrackInfo::from(&app.tracks[3]));
+        assert_eq!(requests(&mut app, Msg::Daemon(Reply::Event(started))), [Request::Status]);
+        assert_eq!((app.playing, app.position()), (Some(3), Duration::ZERO));
+        requests(&mut app, Msg::Daemon(Reply::Event(Event::Progress(Duration::from_secs(4)))));
+        requests(&mut app, Msg::Daemon(Reply::Event(Event::State(PlaybackStatus::Paused))));
+        requests(&mut app, Msg::Daemon(Reply::Event(Event::Volume(80))));
+        assert_eq!((app.position(), app.status(), app.volume), (Duration::from_secs(4), PlaybackStatus::Paused, 0.8));
+
+        assert_eq!(requests(&mut app, Msg::Daemon(Reply::Event(Event::Queue))), [Request::Queue, Request::Status]);
+        assert_eq!(requests(&mut app, Msg::Daemon(Reply::Event(Event::Options))), [Request::Status]);
+        // Settings coming from the other player are not sent back to it
+        assert!(requests(&mut app, Msg::Daemon(Reply::Status(status(Some(1), true)))).is_empty());
+        assert!(app.queue.shuffle());
+    }
+
+    #[test]
+    fn test_playback_goes_to_the_other_player() {
+        let mut app = app();
+        requests(&mut app, Msg::Resize(80, 24));
+        let queue = (1..4).map(|i| TrackInfo::from(&app.tracks[i])).collect();
+        requests(&mut app, Msg::Daemon(Reply::Tracks(queue)));
+        requests(&mut app, Msg::Daemon(Reply::Status(status(Some(0), false))));
+
+        app.skip(true);
+        app.toggle_pause();
+        app.seek_to(Duration::from_secs(10));
+        app.send(AppCommand::SetVolume(0.5));
+        app.queue.set_repeat(RepeatMode::One);
+        let forwarded = requests(&mut app, Msg::Tick);
+        assert_eq!(forwarded, [
+            Request::Play(Some(1)),
+            Request::Toggle,
+            Request::Seek(Amount::To(Duration::from_secs(10))),
+            Request::Volume(Amount::To(50)),
+        ]);
+
+        // Playing from the track list replaces the other player's queue
+        app.play_row(0);
+        let paths = (0..4).map(|i| PathBuf::from(format!("/music/track{}.mp3", i))).collect();
+        assert_eq!(requests(&mut app, Msg::Tick), [
+            Request::ClearQueue,
+            Request::Enqueue(paths),
+            Request::Play(Some(0)),
+        ]);
+
+        // Changed settings are passed on
+        let key = crossterm::event::KeyEvent::from(crossterm::event::KeyCode::Char('r'));
+        assert_eq!(requests(&mut app, Msg::Key(key)), [Request::Repeat(Some(RepeatMode::Off))]);
+    }
+
+    #[test]
+    fn test_queue_keeps_tracks_missing_here() {
+        let mut app = app();
+        requests(&mut app, Msg::Resize(80, 24));
+        let elsewhere = TrackInfo {
+            path: PathBuf::from("/elsewhere/song.mp3"),
+            title: Some("Song".to_string()),
+            artist: None,
+            album: None,
+            duration: None,
+        };
+        let queue = vec![TrackInfo::from(&app.tracks[1]), elsewhere, TrackInfo::from(&app.tracks[3])];
+        requests(&mut app, Msg::Daemon(Reply::Tracks(queue.clone())));
+        requests(&mut app, Msg::Daemon(Reply::Status(status(Some(2), false))));
+        assert_eq!(app.queue.tracks(), &[1, 4, 3]);
+        assert_eq!((app.queue.current(), app.playing), (Some(3), Some(3)));
+        assert_eq!(app.tracks[4].title.as_deref(), Some("Song"));
+        assert_eq!(app.rows.len(), 4, "the stub is not part of the library");
+
+        // Positions mean the same to both players
+        app.skip(false);
+        assert_eq!(requests(&mut app, Msg::Tick), [Request::Play(Some(1))]);
+        // A track the other player has not queued is named by its path
+        app.play_track(0);
+        assert_eq!(requests(&mut app, Msg::Tick), [Request::PlayFile(PathBuf::from("/music/track0.mp3"))]);
+        assert_eq!(app.queue.tracks().len(), 3);
+
+        // The stubs are rebuilt, not piled up
+        requests(&mut app, Msg::Daemon(Reply::Tracks(queue[..2].to_vec())));
+        assert_eq!((app.queue.tracks(), app.tracks.len()), (&[1, 4][..], 5));
+        requests(&mut app, Msg::Daemon(Reply::Tracks(queue[2..].to_vec())));
+        assert_eq!((app.queue.tracks(), app.tracks.len()), (&[3][..], 4));
+    }
+
+    #[test]
+    fn test_other_players_files_are_left_alone() {
+        let dir = TempDir::new("attach");
+        let tracks = vec![Track { path: PathBuf::from("/music/a.mp3"), ..Default::default() }];
+        let mut app = App::new(tracks).with_data_dir(dir.path()).attached();
+        app.rate_track(0, 4);
+        app.bookmarks.add(Path::new("/music/a.mp3"), "intro", Duration::from_secs(3)).unwrap();
+        app.save();
+
+        // Kept in memory, but not written over the other player's files
+        assert_eq!(app.library.stats(Path::new("/music/a.mp3")).rating, 4);
+        assert!(std::fs::read_dir(dir.path()).unwrap().next().is_none());
+    }
+}
@@ -0,0 +1,97 @@
+//! Threads feeding the main loop's message channel.
+//!
+//! Everything the application reacts to arrives on one channel of [`Msg`]s,
+//! so the main loop sleeps until something happens (or the next deadline
+//! from [`super::App::next_deadline`]) instead of polling each source.
+
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use crossterm::event::{self, Event};
+use tracing::error;
+
+use super::Msg;
+
+impl Msg {
+    /// Returns the message for a terminal event, or `None` for events the
+    /// application ignores (focus changes, pastes).
+    pub fn from_event(event: Event) -> Option<Self> {
+        match event {
+            Event::Key(key) => Some(Self::Key(key)),
+            Event::Mouse(mouse) => Some(Self::Mouse(mouse)),
+            Event::Resize(width, height) => Some(Self::Resize(width, height)),
+            _ => None,
+        }
+    }
+}
+
+/// Starts a thread reading terminal input into a channel.
+///
+/// The thread ends when the channel is closed or the terminal cannot be read.
+///
+/// # Arguments
+///
+/// * `tx` - Channel the input is sent on
+pub fn spawn_input(tx: Sender<Msg>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        match event::read() {
+            Ok(event) => {
+                let Some(msg) = Msg::from_event(event) else { continue };
+                if tx.send(msg).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("无法读取终端输入: {}", e);
+                break;
+            }
+        }
+    })
+}
+
+/// Starts a thread passing the values of one channel on to another as
+/// messages (e.g. player events as [`Msg::Player`]).
+///
+/// The thread ends when either channel is closed.
+///
+/// # Arguments
+///
+/// * `rx` - Channel to read from
+/// * `tx` - Channel to send the messages on
+/// * `wrap` - Turns a value into a message
+pub fn forward<T: Send + 'static>(rx: Receiver<T>, tx: Sender<Msg>, wrap: fn(T) -> Msg) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for value in rx {
+            if tx.send(wrap(value)).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent};
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_from_event_ignores_focus_and_paste() {
+        assert!(matches!(Msg::from_event(Event::Resize(80, 24)), Some(Msg::Resize(80, 24))));
+        assert!(matches!(Msg::from_event(Event::Key(KeyEvent::from(KeyCode::Enter))), Some(Msg::Key(_))));
+        assert!(Msg::from_event(Event::FocusGained).is_none());
+        assert!(Msg::from_event(Event::Paste("text".to_string())).is_none());
+    }
+
+    #[test]
+    fn test_forward_ends_when_the_source_closes() {
+        let (value_tx, value_rx) = mpsc::channel();
+        let (msg_tx, msg_rx) = mpsc::channel();
+        let handle = forward(value_rx, msg_tx, |(w, h)| Msg::Resize(w, h));
+        value_tx.send((10, 5)).unwrap();
+        drop(value_tx);
+        handle.join().unwrap();
+        let received: Vec<Msg> = msg_rx.iter().collect();
+        assert!(matches!(received[..], [Msg::Resize(10, 5)]));
+    }
+}
@@ -0,0 +1,3174 @@
+//! Application state and event handling.
+//!
+//! The whole UI is a state machine: [`App`] holds the state, [`update`]
+//! applies a [`Msg`] (terminal input, a player event, a notification, a
+//! timer tick) and returns the [`Effect`]s the caller has to carry out, and
+//! [`render`] draws the state onto a frame. The binary owns the terminal,
+//! the player thread and the channels; this module never blocks and never
+//! touches them, so tests drive it with plain values.
+//!
+//! Nothing happens on a fixed cadence: the caller waits for the next message
+//! or until [`App::next_deadline`], and only draws when [`update`] returns
+//! [`Effect::Redraw`].
+//!
+//! ```no_run
+//! use ratatui::{backend::TestBackend, Terminal};
+//! use tools_rs::app::{self, App, Effect, Msg};
+//! use tools_rs::common::AppEvent;
+//!
+//! let mut app = App::new(Vec::new());
+//! app::update(&mut app, Msg::Resize(80, 24));
+//! for effect in app::update(&mut app, Msg::Player(AppEvent::TrackEnded)) {
+//!     if let Effect::Player(command) = effect {
+//!         // send to the player thread
+//!     }
+//! }
+//! let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+//! terminal.draw(|frame| app::render(frame, &app)).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
+use tracing::{error, info};
+use walkdir::WalkDir;
+
+use crate::bookmarks::BookmarkStore;
+use crate::clock::{Clock, SystemClock};
+use crate::command::{self, Command, CommandHistory};
+use crate::common::{AppCommand, AppEvent, PlaybackStatus, Track};
+use crate::config::Config;
+use crate::keymap::{self, Action, KeySpec, Keymap, Lookup};
+use crate::library::{HistoryEvent, LibraryDb, TrackStats};
+use crate::notify::{Notification, Notifications};
+use crate::paths;
+use crate::playlist::smart::{self, SmartPlaylistStore};
+use crate::playlist::store::PlaylistStore;
+use crate::playlist::{self, Playlist, PlaylistError, PlaylistFormat};
+use crate::query::Query;
+use crate::queue::Queue;
+use crate::remote::{Event, Reply, Request};
+use crate::session::Session;
+use crate::tags;
+use crate::ui::layout::{AppLayout, LayoutManager, Region};
+use crate::ui::theme::{self, Theme, ThemeError};
+use crate::ui::widgets::{HelpWidget, MessageLogWidget, PlaybackControlWidget, TrackListWidget, VisualizationMode};
+
+mod attach;
+mod input;
+mod remote;
+mod render;
+
+pub use input::{forward, spawn_input};
+pub use render::render;
+
+/// Maximum number of tracks in the "Recently played" and "Most played" views
+const HISTORY_VIEW_LIMIT: usize = 100;
+
+/// How long a key that starts a longer sequence waits for the next key
+const KEY_SEQUENCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest count that can be typed before a command
+const MAX_COUNT: usize = 9999;
+
+/// Track rows per page before the first frame has been laid out
+const DEFAULT_PAGE_ROWS: usize = 20;
+
+/// Longest time between two clicks on the same cell that counts as a double-click
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Rows the track list scrolls per mouse wheel step
+const WHEEL_ROWS: isize = 3;
+
+/// Volume change per column the mouse is dragged over the volume readout
+const VOLUME_PER_COLUMN: f32 = 0.02;
+
+/// Which collection the track list is showing (also the items of the sidebar).
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum View {
+    /// All tracks found in the library
+    Library,
+    /// Tracks from the listening history, newest first
+    Recent,
+    /// Tracks with the most completed plays
+    MostPlayed,
+    /// The named playlist with this name
+    Playlist(String),
+    /// The smart playlist with this name
+    Smart(String),
+}
+
+impl View {
+    /// Returns the key identifying the view in the saved session.
+    fn session_key(&self) -> String {
+        match self {
+            Self::Library => "library".to_string(),
+            Self::Recent => "recent".to_string(),
+            Self::MostPlayed => "most-played".to_string(),
+            Self::Playlist(name) => format!("playlist:{}", name),
+            Self::Smart(name) => format!("smart:{}", name),
+        }
+    }
+
+    /// Parses a session key, falling back to the library for unknown keys.
+    fn from_session_key(key: &str) -> Self {
+        match key.split_once(':') {
+            Some(("playlist", name)) => Self::Playlist(name.to_string()),
+            Some(("smart", name)) => Self::Smart(name.to_string()),
+            _ => match key {
+                "recent" => Self::Recent,
+                "most-played" => Self::MostPlayed,
+                _ => Self::Library,
+            },
+        }
+    }
+}
+
+/// Views listed at the top of the sidebar, before the playlists.
+const BUILTIN_VIEWS: [View; 3] = [View::Library, View::Recent, View::MostPlayed];
+
+/// Column the track list is sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    /// The order of the view itself
+    Default,
+    Title,
+    Artist,
+    Rating,
+    Plays,
+    Skips,
+    LastPlayed,
+}
+
+impl SortKey {
+    /// Returns the next key in the `o` cycle.
+    fn next(self) -> Self {
+        match self {
+            Self::Default => Self::Title,
+            Self::Title => Self::Artist,
+            Self::Artist => Self::Rating,
+            Self::Rating => Self::Plays,
+            Self::Plays => Self::Skips,
+            Self::Skips => Self::LastPlayed,
+            Self::LastPlayed => Self::Default,
+        }
+    }
+
+    /// Parses a label as saved in the session (`Default` for unknown labels).
+    fn from_label(label: &str) -> Self {
+        let mut key = Self::Default.next();
+        while key != Self::Default {
+            if key.label() == label {
+                return key;
+            }
+            key = key.next();
+        }
+        Self::Default
+    }
+
+    /// Returns the label shown in the track list title.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Default => "",
+            Self::Title => "title",
+            Self::Artist => "artist",
+            Self::Rating => "rating",
+            Self::Plays => "plays",
+            Self::Skips => "skips",
+            Self::LastPlayed => "last played",
+        }
+    }
+}
+
+/// Which pane receives navigation keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    /// The track list
+    Tracks,
+    /// The playlist sidebar
+    Sidebar,
+    /// The bookmarks panel
+    Bookmarks,
+}
+
+/// Input mode of the key dispatch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// Keys are looked up in the keymap
+    Normal,
+    /// The next key names the mark to set (after `m`)
+    SetMark,
+    /// The next key names the mark to jump to (after `'`)
+    JumpToMark,
+}
+
+impl Mode {
+    /// Returns the indicator shown in the status bar.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::SetMark => "MARK",
+            Self::JumpToMark => "JUMP",
+        }
+    }
+}
+
+/// What a held mouse button is dragging.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Drag {
+    /// The playback position, along the progress bar
+    Seek,
+    /// The volume, from the column and volume where the drag started
+    Volume(u16, f32),
+}
+
+impl Focus {
+    /// Returns the keymap context of the pane.
+    fn context(self) -> keymap::Context {
+        match self {
+            Self::Tracks => keymap::Context::Tracks,
+            Self::Sidebar => keymap::Context::Sidebar,
+            Self::Bookmarks => keymap::Context::Bookmarks,
+        }
+    }
+}
+
+/// Text input requested from the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PromptKind {
+    /// Name for a new playlist
+    NewPlaylist,
+    /// New name for the playlist with this name
+    RenamePlaylist(String),
+    /// Query filtering the track list, applied while typing
+    Search,
+    /// Name for a smart playlist saving the current filter
+    SaveSmart,
+    /// New name for the smart playlist with this name
+    RenameSmart(String),
+    /// Name for a bookmark at this position of the playing track
+    Bookmark(Duration),
+    /// Ex-style command line (after `:`)
+    Command,
+}
+
+/// Active text prompt with its current input.
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+/// Main application state.
+///
+/// Changed only through [`update`] and drawn by [`render`].
+pub struct App {
+    /// List of all available tracks
+    tracks: Vec<Track>,
+    /// Index of currently selected track
+    selected: usize,
+    /// Index of currently playing track (if any)
+    playing: Option<usize>,
+    /// Current playback status
+    status: PlaybackStatus,
+    /// Current playback position
+    position: Duration,
+    /// Total duration of current track
+    total: Option<Duration>,
+    /// Current volume level (0.0 to 2.0)
+    volume: f32,
+    /// Waveform data for visualization
+    wave: Vec<u64>,
+    /// UI color theme
+    theme: Theme,
+    /// Name of the active theme
+    theme_name: String,
+    /// Whether UI is in compact mode
+    compact_mode: bool,
+    /// Active configuration
+    config: Config,
+    /// Key bindings, with the config file's overrides applied
+    keymap: Keymap,
+    /// Keys of a sequence typed so far (e.g. the first `g` of `gg`)
+    pending_keys: Vec<KeySpec>,
+    /// When the last key of the pending sequence or count was pressed
+    pending_since: Instant,
+    /// Input mode
+    mode: Mode,
+    /// Count typed before a command (e.g. the 5 of `5j`)
+    count: Option<usize>,
+    /// Tracks remembered under mark names
+    marks: HashMap<char, usize>,
+    /// First track-list row shown
+    scroll: usize,
+    /// Cached layout to avoid recalculation (width, height, sidebar shown)
+    cached_layout: Option<(u16, u16, bool, AppLayout)>,
+    /// Saved named playlists
+    playlists: PlaylistStore,
+    /// Saved smart playlists
+    smart: SmartPlaylistStore,
+    /// Query filtering the track list (if any)
+    filter: Option<Query>,
+    /// Directory the statistics, bookmarks and session are kept in
+    data_dir: PathBuf,
+    /// Listening statistics and history
+    library: LibraryDb,
+    /// Track started by the player that has not ended or been skipped yet
+    listening: Option<usize>,
+    /// Resume positions and named bookmarks
+    bookmarks: BookmarkStore,
+    /// Whether the bookmarks panel is shown
+    bookmarks_visible: bool,
+    /// Selected bookmark in the panel
+    bookmark_selected: usize,
+    /// Whether ratings are also written into the files' tags
+    write_rating_tags: bool,
+    /// Column the track list is sorted by
+    sort: SortKey,
+    /// Whether the sort order is reversed
+    sort_reversed: bool,
+    /// Tracks that playback advances through
+    queue: Queue,
+    /// Collection shown in the track list
+    view: View,
+    /// Track indices shown in the track list, in display order
+    rows: Vec<usize>,
+    /// Playlist entry shown in each row, in playlist views
+    entries: Vec<usize>,
+    /// Whether the playlist sidebar is shown
+    sidebar_visible: bool,
+    /// Pane receiving navigation keys
+    focus: Focus,
+    /// Selected sidebar item (built-in views, then playlists, then smart playlists)
+    sidebar_selected: usize,
+    /// Active text prompt (if any)
+    prompt: Option<Prompt>,
+    /// Earlier command lines
+    history: CommandHistory,
+    /// Tab completions of the command line and the one shown
+    completions: Option<(Vec<String>, usize)>,
+    /// Lines scrolled in the help popup, while it is shown
+    help_scroll: Option<usize>,
+    /// Toasts and the message log
+    notifications: Notifications,
+    /// Lines scrolled in the message log, while it is shown
+    log_scroll: Option<usize>,
+    /// How the visualization is drawn
+    visualization: VisualizationMode,
+    /// Time and cell of the last left click, to detect double-clicks
+    last_click: Option<(Instant, u16, u16)>,
+    /// What the held mouse button is dragging (if anything)
+    drag: Option<Drag>,
+    /// When the visualization last advanced
+    last_frame: Instant,
+    /// Terminal size (width, height) the layout is computed for
+    size: Option<(u16, u16)>,
+    /// Effects requested while handling the current message
+    effects: Vec<Effect>,
+    /// Source of the current time
+    clock: Arc<dyn Clock>,
+    /// Channels of remote clients that asked to hear about changes
+    subscribers: Vec<Sender<Reply>>,
+    /// Whether playback belongs to another player (see [`App::attached`])
+    attached: bool,
+    /// Number of tracks at the end of `tracks` that are queued by the other
+    /// player but missing from this library
+    stubs: usize,
+    /// Temporary data directory of [`App::scratch`], removed with the application
+    #[cfg(test)]
+    scratch_dir: Option<crate::harness::TempDir>,
+}
+
+impl App {
+    /// Creates a new application instance with the default configuration
+    /// and empty stores.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - List of available audio tracks
+    pub fn new(tracks: Vec<Track>) -> Self {
+        let rows = (0..tracks.len()).collect();
+        Self {
+            tracks,
+            selected: 0,
+            playing: None,
+            status: PlaybackStatus::Stopped,
+            position: Duration::from_secs(0),
+            total: None,
+            volume: 1.0,
+            wave: vec![0; 80],
+            theme: Theme::default(),
+            theme_name: "dark".to_string(),
+            compact_mode: false,
+            config: Config::default(),
+            keymap: Keymap::default(),
+            pending_keys: Vec::new(),
+            pending_since: Instant::now(),
+            mode: Mode::Normal,
+            count: None,
+            marks: HashMap::new(),
+            scroll: 0,
+            cached_layout: None,
+            playlists: PlaylistStore::new(paths::playlists_dir()),
+            smart: SmartPlaylistStore::new(paths::playlists_dir()),
+            filter: None,
+            data_dir: paths::data_dir(),
+            library: LibraryDb::new(paths::data_dir()),
+            listening: None,
+            bookmarks: BookmarkStore::new(paths::data_dir()),
+            bookmarks_visible: false,
+            bookmark_selected: 0,
+            write_rating_tags: false,
+            sort: SortKey::Default,
+            sort_reversed: false,
+            queue: Queue::new(),
+            view: View::Library,
+            rows,
+            entries: Vec::new(),
+            sidebar_visible: false,
+            focus: Focus::Tracks,
+            sidebar_selected: 0,
+            prompt: None,
+            history: CommandHistory::new(),
+            completions: None,
+            help_scroll: None,
+            notifications: Notifications::new(Config::default().ui.toast_timeout),
+            log_scroll: None,
+            visualization: VisualizationMode::default(),
+            last_click: None,
+            drag: None,
+            last_frame: Instant::now(),
+            size: None,
+            effects: Vec::new(),
+            clock: Arc::new(SystemClock),
+            subscribers: Vec::new(),
+            attached: false,
+            stubs: 0,
+            #[cfg(test)]
+            scratch_dir: None,
+        }
+    }
+
+    /// Makes the application read the time from a clock other than the
+    /// system's (e.g. a [`crate::clock::ManualClock`] in tests).
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Source of the current time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.pending_since = clock.now();
+        self.last_frame = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the clock the application reads the time from.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Makes the application keep its playlists, statistics, bookmarks and
+    /// session in a directory other than [`paths::data_dir`], starting out
+    /// empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Data directory; playlists go into its `playlists` subdirectory
+    pub fn with_data_dir(mut self, dir: &Path) -> Self {
+        self.playlists = PlaylistStore::new(dir.join("playlists"));
+        self.smart = SmartPlaylistStore::new(dir.join("playlists"));
+        self.library = LibraryDb::new(dir);
+        self.bookmarks = BookmarkStore::new(dir);
+        self.data_dir = dir.to_path_buf();
+        self
+    }
+
+    /// Returns the playback status.
+    pub fn status(&self) -> PlaybackStatus {
+        self.status
+    }
+
+    /// Returns the playback position within the current track.
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// Returns the playing or paused track (if any).
+    pub fn current_track(&self) -> Option<&Track> {
+        self.playing.and_then(|i| self.tracks.get(i))
+    }
+
+    /// Returns the track under the cursor in the track list (if any).
+    pub fn selected_track(&self) -> Option<&Track> {
+        self.rows.get(self.selected).and_then(|&i| self.tracks.get(i))
+    }
+
+    /// Creates the application as the player starts it: with the
+    /// configuration applied and the saved playlists, statistics, bookmarks
+    /// and session loaded from the data directory.
+    ///
+    /// Files that cannot be read are reported and left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - List of available audio tracks
+    /// * `config` - Configuration to start with
+    pub fn start(tracks: Vec<Track>, config: Config) -> Self {
+        let mut app = Self::new(tracks);
+        app.sidebar_visible = config.ui.sidebar;
+        app.apply_config(config);
+        match PlaylistStore::open(paths::playlists_dir()) {
+            Ok((store, skipped)) => {
+                for e in skipped {
+                    error!("已跳过: {}", e);
+                }
+                app.playlists = store;
+            }
+            Err(e) => error!("{}", e),
+        }
+        match SmartPlaylistStore::open(paths::playlists_dir()) {
+            Ok((store, skipped)) => {
+                for e in skipped {
+                    error!("已跳过: {}", e);
+                }
+                app.smart = store;
+            }
+            Err(e) => error!("{}", e),
+        }
+        match LibraryDb::open(paths::data_dir()) {
+            Ok((db, skipped)) => {
+                for e in skipped {
+                    error!("已跳过: {}", e);
+                }
+                app.library = db;
+            }
+            Err(e) => {
+                // Never replace statistics that could not be read
+                error!("{}; 本次播放统计不会保存", e);
+                app.library.set_read_only();
+            }
+        }
+        for track in &app.tracks {
+            if let Some(rating) = track.rating {
+                app.library.seed_rating(&track.path, rating);
+            }
+        }
+        match BookmarkStore::open(paths::data_dir()) {
+            Ok(store) => app.bookmarks = store,
+            Err(e) => error!("{}", e),
+        }
+        match Session::load(&paths::data_dir()) {
+            Ok(Some((session, skipped))) => {
+                for e in skipped {
+                    error!("已跳过: {}", e);
+                }
+                app.restore(session);
+            }
+            Ok(None) => {}
+            Err(e) => error!("无法恢复会话: {}", e),
+        }
+        app
+    }
+
+    /// Saves what should survive a restart: the resume position of a track
+    /// that is still playing, the statistics and the session.
+    ///
+    /// Failures are reported rather than returned, so that quitting goes on.
+    /// An attached application saves nothing: all of it belongs to the
+    /// player it is attached to.
+    pub fn save(&mut self) {
+        if self.attached {
+            return;
+        }
+        if self.remember_position(false) {
+            self.save_bookmarks();
+        }
+        self.save_library();
+        if let Err(e) = self.library.save() {
+            error!("无法保存播放统计: {}", e);
+        }
+        self.save_playlists();
+        if let Err(e) = self.session().save(&self.data_dir) {
+            error!("无法保存会话: {}", e);
+        }
+    }
+
+    /// Writes the listening statistics and history changed since the last
+    /// write ([`Effect::SaveLibrary`]).
+    pub fn save_library(&mut self) {
+        if let Err(e) = self.library.flush() {
+            error!("无法保存播放统计: {}", e);
+        }
+    }
+
+    /// Writes the resume positions and bookmarks ([`Effect::SaveBookmarks`]).
+    pub fn save_bookmarks(&self) {
+        if let Err(e) = self.bookmarks.save() {
+            error!("无法保存书签: {}", e);
+        }
+    }
+
+    /// Writes the playlists and smart playlists changed since the last write
+    /// ([`Effect::SavePlaylists`]).
+    pub fn save_playlists(&mut self) {
+        if let Err(e) = self.playlists.flush() {
+            error!("无法保存播放列表: {}", e);
+        }
+        if let Err(e) = self.smart.flush() {
+            error!("无法保存智能播放列表: {}", e);
+        }
+    }
+
+    /// Returns the active configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Requests a command for the player thread, or for the player the
+    /// application is attached to.
+    fn send(&mut self, command: AppCommand) {
+        if self.attached {
+            self.forward(command);
+        } else {
+            self.effects.push(Effect::Player(command));
+        }
+    }
+
+    /// Asks for the process to be suspended to the shell, pausing playback
+    /// meanwhile if so configured.
+    fn suspend(&mut self) {
+        let pause = self.config.player.pause_on_suspend && self.status == PlaybackStatus::Playing;
+        if pause {
+            self.send(AppCommand::TogglePlayPause);
+        }
+        self.effects.push(Effect::Suspend);
+        if pause {
+            self.send(AppCommand::TogglePlayPause);
+        }
+    }
+
+    /// Advances the visualization if a frame is due and ends a key sequence
+    /// or count that has waited long enough.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn tick(&mut self) -> bool {
+        let now = self.clock.now();
+        if self.animating() && now >= self.last_frame + self.frame_interval() {
+            self.last_frame = now;
+            // Generate simulated waveform based on playback position
+            let t = self.position.as_secs_f64();
+            let v = ((t * 6.0).sin() * 0.5 + 0.5) * 100.0 * self.volume.min(1.0) as f64;
+            // Maintain rolling window of waveform samples
+            if !self.wave.is_empty() {
+                self.wave.remove(0);
+            }
+            self.wave.push(v.clamp(0.0, 100.0) as u64);
+        }
+
+        // A sequence prefix that is also bound on its own runs once no further key follows
+        !self.pending_input_expired(now) || self.flush_pending_input()
+    }
+
+    /// Returns whether the visualization is shown and moving.
+    fn animating(&self) -> bool {
+        let shown = matches!(&self.cached_layout, Some((_, _, _, layout)) if layout.visualization.is_some());
+        shown && self.status == PlaybackStatus::Playing && !self.bookmarks_visible && !self.popup_open()
+    }
+
+    /// Returns the time between two frames of the visualization.
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.config.ui.visualization_fps.max(1)
+    }
+
+    /// Returns whether a key sequence or count has waited long enough to end.
+    fn pending_input_expired(&self, now: Instant) -> bool {
+        let pending = self.count.is_some() || !self.pending_keys.is_empty();
+        pending && now.saturating_duration_since(self.pending_since) >= KEY_SEQUENCE_TIMEOUT
+    }
+
+    /// Returns when the application next needs a [`Msg::Tick`]: for a frame
+    /// of the visualization, to end a key sequence or to hide a toast.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` while nothing is waiting on time, so the caller can
+    /// sleep until the next event.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let frame = self.animating().then(|| self.last_frame + self.frame_interval());
+        let pending = (self.count.is_some() || !self.pending_keys.is_empty())
+            .then(|| self.pending_since + KEY_SEQUENCE_TIMEOUT);
+        let toast = self.notifications.next_expiry(self.clock.now());
+        [frame, pending, toast].into_iter().flatten().min()
+    }
+
+    /// Recomputes the layout for the terminal size and keeps the selection
+    /// in view.
+    fn relayout(&mut self) {
+        let Some((width, height)) = self.size else { return };
+        self.compact_mode = width < self.config.ui.compact_width;
+        self.get_layout(width, height);
+        self.scroll_to_selection();
+    }
+
+    /// Returns the layout for a terminal area: the cached one if it was
+    /// computed for the same size and panes.
+    fn layout(&self, size: Rect) -> AppLayout {
+        match &self.cached_layout {
+            Some((width, height, sidebar, layout))
+                if (*width, *height, *sidebar) == (size.width, size.height, self.sidebar_visible) => layout.clone(),
+            _ => LayoutManager::new(size)
+                .with_sidebar(self.sidebar_visible)
+                .with_compact_width(self.config.ui.compact_width)
+                .calculate_layout(),
+        }
+    }
+
+    /// Gets the layout for the current terminal size, using cache if available.
+    ///
+    /// This method implements layout caching to avoid recalculating the layout
+    /// on every frame when the terminal size hasn't changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Current terminal width
+    /// * `height` - Current terminal height
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the calculated layout.
+    fn get_layout(&mut self, width: u16, height: u16) -> &AppLayout {
+        // Check if we have a cached layout for this size
+        let sidebar = self.sidebar_visible;
+        let needs_recalc = match &self.cached_layout {
+            Some((cached_w, cached_h, cached_sidebar, _)) => {
+                *cached_w != width || *cached_h != height || *cached_sidebar != sidebar
+            }
+            None => true,
+        };
+
+        if needs_recalc {
+            let size = ratatui::layout::Rect::new(0, 0, width, height);
+            let layout_manager = LayoutManager::new(size)
+                .with_sidebar(sidebar)
+                .with_compact_width(self.config.ui.compact_width);
+            let layout = layout_manager.calculate_layout();
+            self.cached_layout = Some((width, height, sidebar, layout));
+        }
+
+        // Safe to unwrap because we just set it
+        &self.cached_layout.as_ref().unwrap().3
+    }
+
+    /// Returns the sidebar views: the built-in views, named playlists, then
+    /// smart playlists.
+    fn sidebar_views(&self) -> Vec<View> {
+        let mut views = BUILTIN_VIEWS.to_vec();
+        views.extend(self.playlists.names().into_iter().map(|n| View::Playlist(n.to_string())));
+        views.extend(self.smart.names().into_iter().map(|n| View::Smart(n.to_string())));
+        views
+    }
+
+    /// Returns the sidebar labels; smart playlists are marked with `⚙`.
+    fn sidebar_items(&self) -> Vec<String> {
+        self.sidebar_views()
+            .into_iter()
+            .map(|view| match view {
+                View::Library => "Library".to_string(),
+                View::Recent => "Recently played".to_string(),
+                View::MostPlayed => "Most played".to_string(),
+                View::Playlist(name) => name,
+                View::Smart(name) => format!("⚙ {}", name),
+            })
+            .collect()
+    }
+
+    /// Returns the number of sidebar items.
+    fn sidebar_len(&self) -> usize {
+        BUILTIN_VIEWS.len() + self.playlists.len() + self.smart.len()
+    }
+
+    /// Returns the view at a sidebar position.
+    fn sidebar_entry(&self, index: usize) -> Option<View> {
+        self.sidebar_views().into_iter().nth(index)
+    }
+
+    /// Returns the sidebar position showing the current view.
+    fn sidebar_active(&self) -> Option<usize> {
+        self.sidebar_views().iter().position(|v| *v == self.view)
+    }
+
+    /// Returns the playlist name at a sidebar position (`None` unless it is a
+    /// named playlist).
+    fn sidebar_playlist(&self, index: usize) -> Option<String> {
+        match self.sidebar_entry(index)? {
+            View::Playlist(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the tracks of the library, without those only known from the
+    /// other player's queue.
+    fn library_tracks(&self) -> &[Track] {
+        &self.tracks[..self.tracks.len() - self.stubs]
+    }
+
+    /// Maps library paths to track indices, keeping only tracks in the library.
+    fn track_indices<'a>(&self, paths: impl IntoIterator<Item = &'a std::path::Path>) -> Vec<usize> {
+        let by_path: std::collections::HashMap<_, _> =
+            self.library_tracks().iter().enumerate().map(|(i, t)| (t.path.as_path(), i)).collect();
+        paths.into_iter().filter_map(|p| by_path.get(p).copied()).collect()
+    }
+
+    /// Orders rows by the sort column; ties keep the view order.
+    fn sort_rows(&self, rows: &mut [usize]) {
+        let text = |i: usize, field: fn(&Track) -> Option<&String>| {
+            field(&self.tracks[i]).map(|s| s.to_lowercase()).unwrap_or_default()
+        };
+        match self.sort {
+            SortKey::Default => {}
+            SortKey::Title => rows.sort_by_cached_key(|&i| text(i, |t| t.title.as_ref())),
+            SortKey::Artist => rows.sort_by_cached_key(|&i| text(i, |t| t.artist.as_ref())),
+            // Numeric columns put the highest and most recent first
+            SortKey::Rating => rows.sort_by_key(|&i| std::cmp::Reverse(self.stats(i).rating)),
+            SortKey::Plays => rows.sort_by_key(|&i| std::cmp::Reverse(self.stats(i).play_count)),
+            SortKey::Skips => rows.sort_by_key(|&i| std::cmp::Reverse(self.stats(i).skip_count)),
+            SortKey::LastPlayed => rows.sort_by_key(|&i| std::cmp::Reverse(self.stats(i).last_played)),
+        }
+        if self.sort_reversed {
+            rows.reverse();
+        }
+    }
+
+    /// Returns the listening statistics of a library track.
+    fn stats(&self, index: usize) -> TrackStats {
+        self.library.stats(&self.tracks[index].path)
+    }
+
+    /// Recomputes the rows shown in the track list from the current view and
+    /// filter.
+    ///
+    /// Playlist entries that cannot be matched to a library track are skipped.
+    /// Smart playlists are re-evaluated, so this must be called whenever the
+    /// library changes.
+    fn refresh_rows(&mut self) {
+        let now = self.clock.system_time();
+        let current = self.rows.get(self.selected).copied();
+        let mut entries = Vec::new();
+        let rows = match &self.view {
+            View::Library => Some((0..self.library_tracks().len()).collect()),
+            View::Recent => Some(self.track_indices(self.library.recently_played(HISTORY_VIEW_LIMIT))),
+            View::MostPlayed => Some(self.track_indices(self.library.most_played(HISTORY_VIEW_LIMIT))),
+            View::Playlist(name) => self.playlists.get(name).map(|playlist| {
+                let matched = playlist.match_tracks(self.library_tracks()).into_iter().enumerate();
+                let rows: Vec<(usize, usize)> = matched.filter_map(|(entry, track)| Some((entry, track?))).collect();
+                entries = rows.iter().map(|&(entry, _)| entry).collect();
+                rows.into_iter().map(|(_, track)| track).collect()
+            }),
+            View::Smart(name) => {
+                let library = &self.library;
+                self.smart.get(name).map(|query| {
+                    smart::evaluate(query, self.library_tracks(), |i| library.facts(&self.tracks[i]), now)
+                })
+            }
+        };
+        let rows: Vec<usize> = match rows {
+            Some(rows) => rows,
+            None => {
+                self.view = View::Library;
+                (0..self.library_tracks().len()).collect()
+            }
+        };
+        let (mut rows, entries): (Vec<usize>, Vec<usize>) = match &self.filter {
+            Some(query) => {
+                let matches = |i: usize| query.matches(&self.library.facts(&self.tracks[i]), now);
+                if entries.is_empty() {
+                    (rows.into_iter().filter(|&i| matches(i)).collect(), entries)
+                } else {
+                    rows.into_iter().zip(entries).filter(|&(i, _)| matches(i)).unzip()
+                }
+            }
+            None => (rows, entries),
+        };
+        // Playlist rows map to entries for editing, so they keep their order
+        if !matches!(self.view, View::Playlist(_)) {
+            self.sort_rows(&mut rows);
+        }
+        self.rows = rows;
+        self.entries = entries;
+        // Keep the selection on the same track when it is still shown
+        match current.and_then(|c| self.rows.iter().position(|&r| r == c)) {
+            Some(row) => self.selected = row,
+            None => self.selected = self.selected.min(self.rows.len().saturating_sub(1)),
+        }
+    }
+
+    /// Switches the track list to another view.
+    fn open_view(&mut self, view: View) {
+        self.view = view;
+        self.rows.clear();
+        self.selected = 0;
+        self.refresh_rows();
+    }
+
+    /// Rebuilds the queue after the playlist it was loaded from changed,
+    /// keeping the current track.
+    ///
+    /// An attached application's queue mirrors the other player's, which is
+    /// left alone.
+    fn sync_queue(&mut self, name: &str) {
+        if self.attached || self.queue.source() != Some(name) {
+            return;
+        }
+        if let Some(playlist) = self.playlists.get(name) {
+            let tracks = playlist.match_tracks(&self.tracks).into_iter().flatten().collect();
+            self.queue.replace(tracks);
+        }
+    }
+
+    /// Returns the name of the playlist shown in the track list.
+    fn view_playlist(&self) -> Option<&str> {
+        match &self.view {
+            View::Playlist(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Sends a play command for a library track and selects it if visible.
+    fn play_track(&mut self, index: usize) {
+        let Some(track) = self.tracks.get(index) else { return };
+        let path = track.path.clone();
+        if let Some(row) = self.rows.iter().position(|&r| r == index) {
+            self.selected = row;
+        }
+        self.send(AppCommand::Play { index, path });
+    }
+
+    /// Loads the current view into the queue and plays the track at `row`.
+    ///
+    /// A playlist shown whole becomes the source of the queue, which then
+    /// follows its changes; a filtered one is queued as a plain list.
+    fn play_row(&mut self, row: usize) {
+        if row >= self.rows.len() {
+            return;
+        }
+        let source = self.view_playlist().filter(|_| self.filter.is_none()).map(str::to_string);
+        self.queue.load(self.rows.clone(), Some(row), source);
+        self.forward_queue();
+        if let Some(index) = self.queue.current() {
+            self.play_track(index);
+        }
+    }
+
+    /// Plays the next (or previous) track in the queue.
+    ///
+    /// When nothing has been queued yet, playback starts from the selection.
+    fn skip(&mut self, forward: bool) {
+        if self.queue.is_empty() {
+            self.play_row(self.selected);
+            return;
+        }
+        let target = if forward { self.queue.next_track() } else { self.queue.prev_track() };
+        if let Some(index) = target {
+            self.play_track(index);
+        }
+    }
+
+    /// Handles the end of the current track by advancing the queue.
+    fn advance(&mut self) {
+        match self.queue.advance() {
+            Some(index) => self.play_track(index),
+            None => self.status = PlaybackStatus::Stopped,
+        }
+    }
+
+    /// Starts playback while stopped: the queue's current track (e.g. restored
+    /// from the last session) or else the selection.
+    ///
+    /// A track restored from the session continues at its saved position.
+    fn resume(&mut self) {
+        match self.queue.current() {
+            Some(index) => {
+                let position = self.position;
+                let restored = self.playing == Some(index) && !position.is_zero();
+                self.play_track(index);
+                if restored {
+                    self.seek_to(position);
+                }
+            }
+            None => self.play_row(self.selected),
+        }
+    }
+
+    /// Pauses the playing track or resumes the paused one.
+    fn toggle_pause(&mut self) {
+        self.status = match self.status {
+            PlaybackStatus::Playing => PlaybackStatus::Paused,
+            PlaybackStatus::Paused => PlaybackStatus::Playing,
+            PlaybackStatus::Stopped => return,
+        };
+        self.send(AppCommand::TogglePlayPause);
+    }
+
+    /// Asks the player to jump to a position in the current track.
+    fn seek_to(&mut self, position: Duration) {
+        let position = match self.total {
+            Some(total) => position.min(total),
+            None => position,
+        };
+        self.position = position;
+        self.send(AppCommand::Seek(position));
+    }
+
+    /// Returns the path of the playing track.
+    fn playing_path(&self) -> Option<PathBuf> {
+        self.playing.and_then(|i| self.tracks.get(i)).map(|t| t.path.clone())
+    }
+
+    /// Remembers or clears the resume position of the track being listened
+    /// to.
+    ///
+    /// # Arguments
+    ///
+    /// * `finished` - Whether the track was played to its end
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the bookmarks changed and need saving.
+    fn remember_position(&mut self, finished: bool) -> bool {
+        let Some(track) = self.listening.and_then(|i| self.tracks.get(i)) else { return false };
+        let path = track.path.clone();
+        if finished {
+            self.bookmarks.clear_resume(&path);
+        } else if self.config.player.resume.applies(&path, self.total.or(track.duration)) {
+            self.bookmarks.set_resume(&path, self.position);
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Captures the state to save when quitting.
+    fn session(&self) -> Session {
+        let path = |index: usize| self.tracks.get(index).map(|t| t.path.clone());
+        Session {
+            track: self.playing.and_then(path),
+            position: self.position,
+            volume: self.volume,
+            queue: self.queue.tracks().iter().filter_map(|&i| path(i)).collect(),
+            queue_position: self.queue.position(),
+            queue_source: self.queue.source().map(str::to_string),
+            shuffle: self.queue.shuffle(),
+            repeat: self.queue.repeat(),
+            view: self.view.session_key(),
+            selected: self.rows.get(self.selected).and_then(|&i| path(i)),
+            sort: self.sort.label().to_string(),
+            sort_reversed: self.sort_reversed,
+        }
+    }
+
+    /// Restores a saved session.
+    ///
+    /// Tracks are looked up by path; ones no longer in the library are
+    /// dropped. Unless `session.resume_playback` is configured, playback is
+    /// not started: the saved track is shown as stopped at its saved position
+    /// and `Space` starts it.
+    fn restore(&mut self, session: Session) {
+        let by_path: std::collections::HashMap<_, _> =
+            self.tracks.iter().enumerate().map(|(i, t)| (t.path.clone(), i)).collect();
+        let index_of = |path: &PathBuf| by_path.get(path).copied();
+
+        self.volume = session.volume;
+        self.send(AppCommand::SetVolume(self.volume));
+
+        let current = session.queue_position.and_then(|p| session.queue.get(p)).and_then(index_of);
+        let queue: Vec<usize> = session.queue.iter().filter_map(index_of).collect();
+        let position = current.and_then(|c| queue.iter().position(|&i| i == c));
+        self.queue.restore(queue, position, session.queue_source, session.shuffle, session.repeat);
+
+        if let Some(index) = session.track.as_ref().and_then(index_of) {
+            self.playing = Some(index);
+            self.position = session.position;
+            self.total = self.tracks[index].duration;
+            if self.config.session.resume_playback {
+                let path = self.tracks[index].path.clone();
+                self.send(AppCommand::Play { index, path });
+                if !session.position.is_zero() {
+                    self.send(AppCommand::Seek(session.position));
+                }
+            }
+        }
+
+        self.sort = SortKey::from_label(&session.sort);
+        self.sort_reversed = session.sort_reversed;
+        self.open_view(View::from_session_key(&session.view));
+        if let Some(row) = session.selected.as_ref().and_then(index_of).and_then(|i| self.rows.iter().position(|&r| r == i)) {
+            self.selected = row;
+        }
+    }
+
+    /// Applies an event from the player thread, recording listening statistics.
+    fn handle_player_event(&mut self, evt: AppEvent) {
+        match evt {
+            AppEvent::TrackStarted { index, duration } => {
+                // A track that is replaced before its end was skipped
+                self.finish_listening(HistoryEvent::Skipped);
+                if let Some(track) = self.tracks.get(index) {
+                    self.library.record_start(&track.path, self.clock.system_time());
+                }
+                self.listening = Some(index);
+                self.playing = Some(index);
+                self.status = PlaybackStatus::Playing;
+                self.position = Duration::from_secs(0);
+                self.total = duration;
+                self.bookmark_selected = 0;
+                // Long-form tracks continue where they were left
+                if let Some(track) = self.tracks.get(index) {
+                    let resume = self.bookmarks.resume_position(&track.path);
+                    if let Some(position) = resume.filter(|_| self.config.player.resume.applies(&track.path, duration.or(track.duration))) {
+                        self.seek_to(position);
+                    }
+                }
+            }
+            AppEvent::Progress { position } => {
+                self.position = position;
+            }
+            AppEvent::TrackEnded => {
+                self.finish_listening(HistoryEvent::Played);
+                // Auto-advance through the queue (library or active playlist)
+                self.advance();
+            }
+            AppEvent::Error { message } => {
+                error!("{}", message);
+                self.listening = None;
+                self.status = PlaybackStatus::Stopped;
+            }
+        }
+    }
+
+    /// Records the end of the current listening session (if any).
+    fn finish_listening(&mut self, event: HistoryEvent) {
+        if self.remember_position(event == HistoryEvent::Played) {
+            self.effects.push(Effect::SaveBookmarks);
+        }
+        let Some(index) = self.listening.take() else { return };
+        let Some(track) = self.tracks.get(index) else { return };
+        let listened = match event {
+            HistoryEvent::Played => self.total.unwrap_or(self.position),
+            HistoryEvent::Skipped => self.position,
+        };
+        let path = track.path.clone();
+        self.library.record(&path, event, listened, self.clock.system_time());
+        self.effects.push(Effect::SaveLibrary);
+        self.refresh_rows();
+    }
+
+    /// Applies the result of a playlist operation: changes are saved,
+    /// failures reported.
+    fn report(&mut self, result: Result<(), crate::playlist::PlaylistError>) {
+        match result {
+            Ok(()) => self.effects.push(Effect::SavePlaylists),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    /// Handles a key press.
+    ///
+    /// Digits typed first form a count for the command that follows. Keys are
+    /// then collected until they form a bound sequence; a key that cannot
+    /// continue the pending sequence ends it and is handled on its own.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if let Some(prompt) = &self.prompt {
+            if prompt.kind == PromptKind::Command {
+                return self.handle_command_key(key);
+            }
+            self.handle_prompt_key(key);
+            return true;
+        }
+        if self.popup_open() {
+            self.handle_popup_key(key);
+            return true;
+        }
+        if self.mode != Mode::Normal {
+            self.handle_mark_key(key);
+            return true;
+        }
+        // Esc abandons a count or an incomplete sequence
+        if key.code == KeyCode::Esc && (self.count.is_some() || !self.pending_keys.is_empty()) {
+            self.count = None;
+            self.pending_keys.clear();
+            return true;
+        }
+        // A count cannot start with 0, which stays free for bindings
+        if let KeyCode::Char(c @ '0'..='9') = key.code {
+            let plain = !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+            if plain && self.pending_keys.is_empty() && (c != '0' || self.count.is_some()) {
+                let digit = c as usize - '0' as usize;
+                self.count = Some((self.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                self.pending_since = self.clock.now();
+                return true;
+            }
+        }
+        self.pending_keys.push(KeySpec::from(key));
+        self.pending_since = self.clock.now();
+        match self.keymap.lookup(self.focus.context(), &self.pending_keys) {
+            Lookup::Action(action) => {
+                self.pending_keys.clear();
+                self.dispatch(action)
+            }
+            Lookup::Ambiguous(_) | Lookup::Pending => true,
+            Lookup::Unbound if self.pending_keys.len() > 1 => {
+                self.pending_keys.pop();
+                self.flush_pending_keys() && self.handle_key(key)
+            }
+            Lookup::Unbound => {
+                self.pending_keys.clear();
+                self.count = None;
+                true
+            }
+        }
+    }
+
+    /// Ends the pending key sequence, performing the action bound to the keys
+    /// typed so far (e.g. `z` when `zz` is also bound).
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn flush_pending_keys(&mut self) -> bool {
+        let keys = std::mem::take(&mut self.pending_keys);
+        match self.keymap.lookup(self.focus.context(), &keys) {
+            Lookup::Ambiguous(action) => self.dispatch(action),
+            _ => true,
+        }
+    }
+
+    /// Ends the pending sequence and count once no further key followed in
+    /// time, so that `z` shuffles on its own.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn flush_pending_input(&mut self) -> bool {
+        if !self.flush_pending_keys() {
+            return false;
+        }
+        let count = self.count.take();
+        self.replay_count(count)
+    }
+
+    /// Performs an action with the pending count.
+    ///
+    /// The digits of a count typed before an action that takes none are
+    /// handled as keys of their own first, for keymaps that bind digits.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn dispatch(&mut self, action: Action) -> bool {
+        let count = self.count.take();
+        if action.takes_count() {
+            return self.perform(action, count);
+        }
+        self.replay_count(count) && self.perform(action, None)
+    }
+
+    /// Performs the actions bound to the digits of an abandoned count.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn replay_count(&mut self, count: Option<usize>) -> bool {
+        let Some(count) = count else { return true };
+        for digit in count.to_string().chars() {
+            let key = KeySpec::new(KeyCode::Char(digit), KeyModifiers::NONE);
+            if let Lookup::Action(action) | Lookup::Ambiguous(action) = self.keymap.lookup(self.focus.context(), &[key]) {
+                if !self.perform(action, None) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns the count and keys typed so far of an incomplete command.
+    fn pending_input(&self) -> String {
+        let count = self.count.map(|n| n.to_string()).unwrap_or_default();
+        count + &keymap::format_sequence(&self.pending_keys)
+    }
+
+    /// Handles the key naming a mark after `m` or `'`; `''` jumps back to
+    /// where the last jump started.
+    fn handle_mark_key(&mut self, key: KeyEvent) {
+        let mode = std::mem::replace(&mut self.mode, Mode::Normal);
+        let KeyCode::Char(name) = key.code else { return };
+        let current = self.rows.get(self.selected).copied();
+        match mode {
+            Mode::SetMark => {
+                if let Some(index) = current {
+                    self.marks.insert(name, index);
+                }
+            }
+            Mode::JumpToMark => {
+                let Some(&index) = self.marks.get(&name) else {
+                    error!("未设置标记 '{}'", name);
+                    return;
+                };
+                match self.rows.iter().position(|&i| i == index) {
+                    Some(row) => {
+                        if let Some(previous) = current {
+                            self.marks.insert('\'', previous);
+                        }
+                        self.selected = row;
+                    }
+                    None => error!("标记 '{}' 的曲目不在当前列表中", name),
+                }
+            }
+            Mode::Normal => {}
+        }
+    }
+
+    /// Returns the sections of the help popup: the bindings of the live
+    /// keymap by context, then the commands.
+    fn help_sections(&self) -> Vec<(&'static str, Vec<(String, &'static str)>)> {
+        let mut sections = self.keymap.help();
+        sections.push(("命令", command::help()));
+        sections
+    }
+
+    /// Returns whether the help popup or the message log is shown.
+    fn popup_open(&self) -> bool {
+        self.help_scroll.is_some() || self.log_scroll.is_some()
+    }
+
+    /// Handles keys while the help popup or the message log is shown:
+    /// motions scroll it, and `Esc`, quit, help or messages close it.
+    fn handle_popup_key(&mut self, key: KeyEvent) {
+        let action = match key.code {
+            KeyCode::Esc => None,
+            KeyCode::PageDown => Some(Action::HalfPageDown),
+            KeyCode::PageUp => Some(Action::HalfPageUp),
+            _ => match self.keymap.lookup(keymap::Context::Tracks, &[KeySpec::from(key)]) {
+                Lookup::Action(action) | Lookup::Ambiguous(action) => Some(action),
+                Lookup::Pending | Lookup::Unbound => return,
+            },
+        };
+        self.scroll_popup(action);
+    }
+
+    /// Returns the number of lines visible in the help popup or message log.
+    fn popup_rows(&self) -> usize {
+        // The popup leaves a margin of one line and has borders
+        match &self.cached_layout {
+            Some((_, height, _, _)) => (*height as usize).saturating_sub(4).max(1),
+            None => DEFAULT_PAGE_ROWS,
+        }
+    }
+
+    /// Scrolls the open popup by a motion, or closes it for `None`, quit,
+    /// help and messages.
+    fn scroll_popup(&mut self, action: Option<Action>) {
+        let visible = self.popup_rows();
+        let lines = match self.log_scroll {
+            Some(_) => MessageLogWidget::content_height(self.notifications.log()),
+            None => HelpWidget::content_height(&self.help_sections()),
+        };
+        let positions = lines.saturating_sub(visible) + 1;
+        let scroll = if self.log_scroll.is_some() { &mut self.log_scroll } else { &mut self.help_scroll };
+        match action {
+            None | Some(Action::Quit | Action::Help | Action::Messages) => *scroll = None,
+            Some(action) => {
+                if let Some(line) = motion(action, None, scroll.unwrap_or(0), positions, visible) {
+                    *scroll = Some(line);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of track rows the track list shows.
+    fn page_rows(&self) -> usize {
+        match &self.cached_layout {
+            Some((_, _, _, layout)) => page_rows(layout, self.compact_mode),
+            None => DEFAULT_PAGE_ROWS,
+        }
+    }
+
+    /// Scrolls the track list just enough to show the selected row.
+    fn scroll_to_selection(&mut self) {
+        self.scroll = scroll_to(self.scroll, self.selected, self.page_rows());
+    }
+
+    /// Scrolls the track list by `rows` (up when negative), moving the
+    /// selection along when it would leave the view.
+    fn scroll_tracks(&mut self, rows: isize) {
+        let page = self.page_rows();
+        let max = self.rows.len().saturating_sub(page);
+        self.scroll = self.scroll.saturating_add_signed(rows).min(max);
+        let last = (self.scroll + page).min(self.rows.len()).saturating_sub(1);
+        self.selected = self.selected.clamp(self.scroll.min(last), last);
+    }
+
+    /// Records a left click and returns whether it completes a double-click.
+    fn double_click(&mut self, column: u16, row: u16) -> bool {
+        let now = self.clock.now();
+        let double = matches!(self.last_click,
+            Some((at, c, r)) if (c, r) == (column, row) && now.duration_since(at) <= DOUBLE_CLICK_INTERVAL);
+        // A third click starts over rather than making another double-click
+        self.last_click = if double { None } else { Some((now, column, row)) };
+        double
+    }
+
+    /// Handles mouse input by hit-testing the layout of the last frame.
+    ///
+    /// The wheel scrolls the pane under the pointer and changes the volume
+    /// over the volume readout. Clicking selects a row (double-clicking
+    /// activates it), seeks on the progress bar and cycles the visualization
+    /// mode; dragging seeks along the progress bar or changes the volume.
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        if self.prompt.is_some() {
+            return;
+        }
+        let (column, row) = (mouse.column, mouse.row);
+        if self.popup_open() {
+            match mouse.kind {
+                MouseEventKind::ScrollDown => self.scroll_popup(Some(Action::Down)),
+                MouseEventKind::ScrollUp => self.scroll_popup(Some(Action::Up)),
+                MouseEventKind::Down(_) => self.scroll_popup(None),
+                _ => {}
+            }
+            return;
+        }
+        let Some((_, _, _, layout)) = &self.cached_layout else { return };
+        let layout = layout.clone();
+
+        match (mouse.kind, self.drag) {
+            (MouseEventKind::Drag(MouseButton::Left), Some(Drag::Seek)) => {
+                self.seek_at(layout.playback_control, column);
+                return;
+            }
+            (MouseEventKind::Drag(MouseButton::Left), Some(Drag::Volume(start, volume))) => {
+                let delta = (column as f32 - start as f32) * VOLUME_PER_COLUMN;
+                self.volume = (volume + delta).clamp(0.0, 2.0);
+                self.send(AppCommand::SetVolume(self.volume));
+                return;
+            }
+            (MouseEventKind::Up(_), _) => {
+                self.drag = None;
+                return;
+            }
+            _ => {}
+        }
+
+        let clicked = mouse.kind == MouseEventKind::Down(MouseButton::Left);
+        let double = clicked && self.double_click(column, row);
+
+        // The bookmarks panel covers the visualization (or the track list)
+        let bookmarks_area = self.bookmarks_visible.then_some(layout.visualization.unwrap_or(layout.track_list));
+        if let Some(area) = bookmarks_area.filter(|&area| contains(area, column, row)) {
+            match mouse.kind {
+                MouseEventKind::ScrollDown => self.perform_bookmark(Action::Down, None),
+                MouseEventKind::ScrollUp => self.perform_bookmark(Action::Up, None),
+                _ if clicked => {
+                    let Some(path) = self.playing_path() else { return };
+                    let len = self.bookmarks.bookmarks(&path).len();
+                    if let Some(entry) = list_row(area, row).filter(|&entry| entry < len) {
+                        self.focus = Focus::Bookmarks;
+                        self.bookmark_selected = entry;
+                        if double {
+                            self.perform_bookmark(Action::Activate, None);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match (layout.region_at(column, row), mouse.kind) {
+            (Some(Region::TrackList), MouseEventKind::ScrollDown) => self.scroll_tracks(WHEEL_ROWS),
+            (Some(Region::TrackList), MouseEventKind::ScrollUp) => self.scroll_tracks(-WHEEL_ROWS),
+            (Some(Region::TrackList), _) if clicked => {
+                let entry = TrackListWidget::row_at(layout.track_list, !self.compact_mode, self.scroll, row);
+                if let Some(entry) = entry.filter(|&entry| entry < self.rows.len()) {
+                    self.focus = Focus::Tracks;
+                    self.selected = entry;
+                    if double {
+                        self.play_row(entry);
+                    }
+                }
+            }
+            (Some(Region::Sidebar), MouseEventKind::ScrollDown) => self.perform_sidebar(Action::Down, None),
+            (Some(Region::Sidebar), MouseEventKind::ScrollUp) => self.perform_sidebar(Action::Up, None),
+            (Some(Region::Sidebar), _) if clicked => {
+                let entry = layout.sidebar.and_then(|area| list_row(area, row));
+                if let Some(entry) = entry.filter(|&entry| entry < self.sidebar_len()) {
+                    self.focus = Focus::Sidebar;
+                    self.sidebar_selected = entry;
+                    if double {
+                        self.perform_sidebar(Action::Activate, None);
+                    }
+                }
+            }
+            (Some(Region::PlaybackControl), kind) => {
+                let area = layout.playback_control;
+                if contains(PlaybackControlWidget::progress_area(area), column, row) {
+                    if clicked && self.total.is_some() {
+                        self.drag = Some(Drag::Seek);
+                        self.seek_at(area, column);
+                    }
+                } else if contains(PlaybackControlWidget::info_area(area), column, row) {
+                    match kind {
+                        MouseEventKind::ScrollUp => {
+                            self.perform(Action::VolumeUp, None);
+                        }
+                        MouseEventKind::ScrollDown => {
+                            self.perform(Action::VolumeDown, None);
+                        }
+                        _ if clicked => self.drag = Some(Drag::Volume(column, self.volume)),
+                        _ => {}
+                    }
+                }
+            }
+            (Some(Region::Visualization), _) if clicked => self.visualization = self.visualization.next(),
+            _ => {}
+        }
+    }
+
+    /// Seeks to the position under a column of the progress bar.
+    fn seek_at(&mut self, playback_control: Rect, column: u16) {
+        if let Some(total) = self.total {
+            let ratio = PlaybackControlWidget::progress_ratio_at(playback_control, column);
+            self.seek_to(total.mul_f64(ratio));
+        }
+    }
+
+    /// Performs an action, dispatching pane-specific ones to the focused pane.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - Action to perform
+    /// * `count` - Count typed before the keys, for actions that take one
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn perform(&mut self, action: Action, count: Option<usize>) -> bool {
+        let times = count.unwrap_or(1);
+        match action {
+            Action::Quit => return false,
+            Action::FocusNext => {
+                self.focus = match self.focus {
+                    Focus::Tracks => Focus::Sidebar,
+                    Focus::Sidebar if self.bookmarks_visible => Focus::Bookmarks,
+                    Focus::Sidebar | Focus::Bookmarks => Focus::Tracks,
+                };
+                if self.focus == Focus::Sidebar {
+                    self.sidebar_visible = true;
+                }
+            }
+            Action::ToggleSidebar => {
+                self.sidebar_visible = !self.sidebar_visible;
+                if !self.sidebar_visible {
+                    self.focus = Focus::Tracks;
+                }
+            }
+            Action::PlayPause if self.status == PlaybackStatus::Stopped => self.resume(),
+            Action::PlayPause => self.toggle_pause(),
+            Action::SeekBack => self.seek_to(self.position.saturating_sub(self.config.player.seek_step * times as u32)),
+            Action::SeekForward => self.seek_to(self.position + self.config.player.seek_step * times as u32),
+            Action::Bookmark => {
+                if self.playing_path().is_some() {
+                    let secs = self.position.as_secs();
+                    let input = format!("{}:{:02}", secs / 60, secs % 60);
+                    self.prompt = Some(Prompt { kind: PromptKind::Bookmark(self.position), input });
+                }
+            }
+            Action::ToggleBookmarks => {
+                self.bookmarks_visible = !self.bookmarks_visible;
+                self.focus = if self.bookmarks_visible { Focus::Bookmarks } else { Focus::Tracks };
+            }
+            Action::NextTheme => self.next_theme(),
+            Action::Help => self.help_scroll = Some(0),
+            Action::Suspend => self.suspend(),
+            Action::Messages => {
+                // The log opens at its newest messages, which no longer need toasts
+                let lines = MessageLogWidget::content_height(self.notifications.log());
+                self.log_scroll = Some(lines.saturating_sub(self.popup_rows()));
+                self.notifications.dismiss(self.clock.now());
+            }
+            Action::CommandLine => {
+                self.prompt = Some(Prompt { kind: PromptKind::Command, input: String::new() });
+            }
+            Action::Shuffle => {
+                let shuffle = !self.queue.shuffle();
+                self.queue.set_shuffle(shuffle);
+            }
+            Action::Repeat => self.queue.set_repeat(self.queue.repeat().next()),
+            Action::Next => (0..times).for_each(|_| self.skip(true)),
+            Action::Prev => (0..times).for_each(|_| self.skip(false)),
+            Action::Search => {
+                let input = self.filter.as_ref().map(|q| q.as_str().to_string()).unwrap_or_default();
+                self.prompt = Some(Prompt { kind: PromptKind::Search, input });
+            }
+            Action::ClearFilter => {
+                if self.filter.is_some() {
+                    self.set_filter(None);
+                }
+            }
+            Action::Sort => {
+                self.sort = self.sort.next();
+                self.refresh_rows();
+            }
+            Action::ReverseSort => {
+                self.sort_reversed = !self.sort_reversed;
+                self.refresh_rows();
+            }
+            Action::SaveSmart => {
+                if self.filter.is_some() {
+                    self.prompt = Some(Prompt { kind: PromptKind::SaveSmart, input: String::new() });
+                } else {
+                    error!("请先用 / 输入搜索条件");
+                }
+            }
+            Action::VolumeUp => {
+                self.volume = (self.volume + self.config.player.volume_step * times as f32).min(2.0);
+                self.send(AppCommand::SetVolume(self.volume));
+            }
+            Action::VolumeDown => {
+                self.volume = (self.volume - self.config.player.volume_step * times as f32).max(0.0);
+                self.send(AppCommand::SetVolume(self.volume));
+            }
+            _ => match self.focus {
+                Focus::Tracks => self.perform_track(action, count),
+                Focus::Sidebar => self.perform_sidebar(action, count),
+                Focus::Bookmarks => self.perform_bookmark(action, count),
+            },
+        }
+        true
+    }
+
+    /// Applies a configuration, replacing the previous one.
+    ///
+    /// Library directories and extensions only take effect on the next scan.
+    pub fn apply_config(&mut self, config: Config) {
+        self.theme = config.ui.theme.clone();
+        self.theme_name = config.ui.theme_name.clone();
+        self.write_rating_tags = config.player.write_tags;
+        self.keymap = Keymap::new(&config.keys);
+        self.notifications.set_timeout(config.ui.toast_timeout);
+        self.pending_keys.clear();
+        self.cached_layout = None;
+        self.config = config;
+    }
+
+    /// Switches to a built-in theme or a theme file in the themes directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the theme does not exist or its file is invalid;
+    /// the current theme is kept.
+    fn set_theme(&mut self, name: &str) -> Result<(), ThemeError> {
+        self.theme = Theme::load(name, &paths::themes_dir())?;
+        self.theme_name = name.to_string();
+        Ok(())
+    }
+
+    /// Switches to the theme after the active one, wrapping around.
+    fn next_theme(&mut self) {
+        let names = theme::available(&paths::themes_dir());
+        let next = match names.iter().position(|name| *name == self.theme_name) {
+            Some(i) => &names[(i + 1) % names.len()],
+            None => &names[0],
+        };
+        match self.set_theme(&next.clone()) {
+            Ok(()) => info!("主题: {}", next),
+            Err(e) => error!("无法切换主题: {}", e),
+        }
+    }
+
+    /// Performs an action while the track list has focus.
+    fn perform_track(&mut self, action: Action, count: Option<usize>) {
+        let page = self.page_rows();
+        if let Some(row) = motion(action, count, self.selected, self.rows.len(), page) {
+            // Half-page moves scroll the list along with the cursor
+            if matches!(action, Action::HalfPageDown | Action::HalfPageUp) {
+                self.scroll = (self.scroll + row).saturating_sub(self.selected);
+            }
+            self.selected = row;
+            return;
+        }
+        match action {
+            Action::CenterPlaying => {
+                let Some(index) = self.playing else { return };
+                match self.rows.iter().position(|&i| i == index) {
+                    Some(row) => {
+                        self.selected = row;
+                        self.scroll = row.saturating_sub(page / 2);
+                    }
+                    None => error!("正在播放的曲目不在当前列表中"),
+                }
+            }
+            Action::SetMark => self.mode = Mode::SetMark,
+            Action::JumpToMark => self.mode = Mode::JumpToMark,
+            Action::Activate => self.play_row(self.selected),
+            Action::AddToPlaylist => {
+                let Some(name) = self.sidebar_playlist(self.sidebar_selected) else {
+                    error!("请先在侧栏中选择一个播放列表");
+                    return;
+                };
+                if let Some(&index) = self.rows.get(self.selected) {
+                    let result = self.playlists.add_tracks(&name, [&self.tracks[index]]);
+                    self.report(result);
+                    self.sync_queue(&name);
+                    if self.view_playlist() == Some(name.as_str()) {
+                        self.refresh_rows();
+                    }
+                }
+            }
+            Action::Delete => {
+                let Some(name) = self.view_playlist().map(str::to_string) else { return };
+                if let Some(entry) = self.playlist_entry(self.selected) {
+                    let result = self.playlists.remove(&name, entry);
+                    self.report(result);
+                    self.sync_queue(&name);
+                    self.refresh_rows();
+                }
+            }
+            Action::Rate(stars) => {
+                if let Some(&index) = self.rows.get(self.selected) {
+                    self.rate_track(index, stars);
+                }
+            }
+            Action::Favorite => {
+                if let Some(&index) = self.rows.get(self.selected) {
+                    let path = self.tracks[index].path.clone();
+                    self.library.toggle_favorite(&path);
+                    self.effects.push(Effect::SaveLibrary);
+                    self.refresh_rows();
+                }
+            }
+            Action::MoveEntryDown => self.move_selected_entry(true),
+            Action::MoveEntryUp => self.move_selected_entry(false),
+            _ => {}
+        }
+    }
+
+    /// Performs an action while the playlist sidebar has focus.
+    fn perform_sidebar(&mut self, action: Action, count: Option<usize>) {
+        let len = self.sidebar_len();
+        if let Some(row) = motion(action, count, self.sidebar_selected, len, len) {
+            self.sidebar_selected = row;
+            return;
+        }
+        match action {
+            Action::Activate => {
+                let view = self.sidebar_entry(self.sidebar_selected).unwrap_or(View::Library);
+                self.open_view(view);
+                self.focus = Focus::Tracks;
+                self.play_row(0);
+            }
+            Action::NewPlaylist => {
+                self.prompt = Some(Prompt { kind: PromptKind::NewPlaylist, input: String::new() });
+            }
+            Action::Rename => match self.sidebar_entry(self.sidebar_selected) {
+                Some(View::Playlist(name)) => {
+                    self.prompt = Some(Prompt { kind: PromptKind::RenamePlaylist(name.clone()), input: name });
+                }
+                Some(View::Smart(name)) => {
+                    self.prompt = Some(Prompt { kind: PromptKind::RenameSmart(name.clone()), input: name });
+                }
+                _ => {}
+            },
+            Action::Delete => {
+                match self.sidebar_entry(self.sidebar_selected) {
+                    Some(View::Playlist(name)) => {
+                        let result = self.playlists.delete(&name);
+                        self.report(result);
+                        if self.queue.source() == Some(name.as_str()) {
+                            self.queue.set_source(None);
+                        }
+                        if self.view_playlist() == Some(name.as_str()) {
+                            self.open_view(View::Library);
+                        }
+                    }
+                    Some(View::Smart(name)) => {
+                        let result = self.smart.delete(&name);
+                        self.report(result);
+                        if self.view == View::Smart(name) {
+                            self.open_view(View::Library);
+                        }
+                    }
+                    _ => return,
+                }
+                self.sidebar_selected = self.sidebar_selected.min(self.sidebar_len() - 1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Performs an action while the bookmarks panel has focus.
+    fn perform_bookmark(&mut self, action: Action, count: Option<usize>) {
+        let Some(path) = self.playing_path() else { return };
+        let len = self.bookmarks.bookmarks(&path).len();
+        if let Some(row) = motion(action, count, self.bookmark_selected, len, len) {
+            self.bookmark_selected = row;
+            return;
+        }
+        match action {
+            Action::Activate => {
+                if let Some(mark) = self.bookmarks.bookmarks(&path).get(self.bookmark_selected) {
+                    let position = mark.position;
+                    self.seek_to(position);
+                }
+            }
+            Action::Delete => {
+                self.bookmarks.remove(&path, self.bookmark_selected);
+                self.effects.push(Effect::SaveBookmarks);
+                self.bookmark_selected = self.bookmark_selected.min(len.saturating_sub(2));
+            }
+            Action::Close => {
+                self.bookmarks_visible = false;
+                self.focus = Focus::Tracks;
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles keys while a text prompt is active.
+    fn handle_prompt_key(&mut self, key: KeyEvent) {
+        let Some(prompt) = self.prompt.as_mut() else { return };
+        match key.code {
+            KeyCode::Esc => {
+                if prompt.kind == PromptKind::Search {
+                    self.set_filter(None);
+                }
+                self.prompt = None;
+            }
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                self.update_search();
+            }
+            KeyCode::Char(c) => {
+                prompt.input.push(c);
+                self.update_search();
+            }
+            KeyCode::Enter => {
+                let Some(Prompt { kind, input }) = self.prompt.take() else { return };
+                if kind == PromptKind::Search {
+                    match parse_filter(&input) {
+                        Ok(filter) => self.set_filter(filter),
+                        Err(e) => error!("搜索条件无效: {}", e),
+                    }
+                    return;
+                }
+                if let PromptKind::Bookmark(position) = kind {
+                    let Some(path) = self.playing_path() else { return };
+                    match self.bookmarks.add(&path, &input, position) {
+                        Ok(()) => {
+                            info!("已添加书签 {}", input.trim());
+                            self.effects.push(Effect::SaveBookmarks);
+                        }
+                        Err(e) => error!("无法添加书签: {}", e),
+                    }
+                    return;
+                }
+                let name = input.trim().to_string();
+                let result = match &kind {
+                    PromptKind::NewPlaylist => self.playlists.create(&name),
+                    PromptKind::RenamePlaylist(old) => self.playlists.rename(old, &name),
+                    PromptKind::SaveSmart => match self.filter.clone() {
+                        Some(query) => self.smart.save(&name, query),
+                        None => return,
+                    },
+                    PromptKind::RenameSmart(old) => self.smart.rename(old, &name),
+                    PromptKind::Search | PromptKind::Bookmark(_) | PromptKind::Command => unreachable!(),
+                };
+                let ok = result.is_ok();
+                self.report(result);
+                if !ok {
+                    return;
+                }
+                match &kind {
+                    PromptKind::RenamePlaylist(old) => {
+                        if self.queue.source() == Some(old.as_str()) {
+                            self.queue.set_source(Some(name.clone()));
+                        }
+                        if self.view_playlist() == Some(old.as_str()) {
+                            self.view = View::Playlist(name.clone());
+                        }
+                    }
+                    PromptKind::RenameSmart(old) if self.view == View::Smart(old.clone()) => {
+                        self.view = View::Smart(name.clone());
+                    }
+                    _ => {}
+                }
+                let entry = match kind {
+                    PromptKind::NewPlaylist | PromptKind::RenamePlaylist(_) => View::Playlist(name),
+                    _ => View::Smart(name),
+                };
+                if let Some(pos) = (0..self.sidebar_len()).find(|&i| self.sidebar_entry(i).as_ref() == Some(&entry)) {
+                    self.sidebar_selected = pos;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles keys while the command line is active: `Tab` completes,
+    /// `↑`/`↓` browse the history and `Enter` runs the command.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the command quits the application.
+    fn handle_command_key(&mut self, key: KeyEvent) -> bool {
+        let Some(prompt) = self.prompt.as_mut() else { return true };
+        if !matches!(key.code, KeyCode::Tab | KeyCode::BackTab) {
+            self.completions = None;
+        }
+        match key.code {
+            KeyCode::Esc => {
+                self.history.reset();
+                self.prompt = None;
+            }
+            // Backspace on an empty line leaves the command line, like vim
+            KeyCode::Backspace if prompt.input.is_empty() => self.prompt = None,
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Up => {
+                if let Some(line) = self.history.older(&prompt.input) {
+                    prompt.input = line.to_string();
+                }
+            }
+            KeyCode::Down => {
+                if let Some(line) = self.history.newer() {
+                    prompt.input = line.to_string();
+                }
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                let forward = key.code == KeyCode::Tab;
+                let (candidates, shown) = match self.completions.take() {
+                    Some((candidates, shown)) if forward => {
+                        let next = (shown + 1) % candidates.len();
+                        (candidates, next)
+                    }
+                    Some((candidates, shown)) => {
+                        let prev = (shown + candidates.len() - 1) % candidates.len();
+                        (candidates, prev)
+                    }
+                    None => {
+                        let input = prompt.input.clone();
+                        let candidates = command::complete(&input, |name, position| {
+                            self.completion_arguments(name, position)
+                        });
+                        if candidates.is_empty() {
+                            return true;
+                        }
+                        let first = if forward { 0 } else { candidates.len() - 1 };
+                        (candidates, first)
+                    }
+                };
+                if let Some(prompt) = self.prompt.as_mut() {
+                    prompt.input = candidates[shown].clone();
+                }
+                self.completions = Some((candidates, shown));
+            }
+            KeyCode::Enter => {
+                let Some(Prompt { input, .. }) = self.prompt.take() else { return true };
+                self.history.push(&input);
+                return match command::parse(&input) {
+                    Ok(command) => self.run_command(command),
+                    Err(command::CommandError::Empty) => true,
+                    Err(e) => {
+                        error!("{}", e);
+                        true
+                    }
+                };
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Returns the names completing an argument of a command.
+    fn completion_arguments(&self, name: &str, position: usize) -> Vec<String> {
+        match (name, position) {
+            ("theme", 1) => theme::available(&paths::themes_dir()),
+            ("save", 2) => self.playlists.names().into_iter().chain(self.smart.names()).map(String::from).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Carries out a command from the command line.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the application should quit.
+    fn run_command(&mut self, command: Command) -> bool {
+        match command {
+            Command::Play(Some(row)) if row <= self.rows.len() => self.play_row(row - 1),
+            Command::Play(Some(row)) => error!("没有第 {} 行", row),
+            Command::Play(None) => match self.status {
+                PlaybackStatus::Stopped => self.resume(),
+                PlaybackStatus::Paused => self.toggle_pause(),
+                PlaybackStatus::Playing => {}
+            },
+            Command::Pause => return self.perform(Action::PlayPause, None),
+            Command::Next(n) => return self.perform(Action::Next, Some(n)),
+            Command::Prev(n) => return self.perform(Action::Prev, Some(n)),
+            Command::Volume(amount) => {
+                let percent = amount.apply((self.volume * 100.0).round() as u32).min(200);
+                self.volume = percent as f32 / 100.0;
+                self.send(AppCommand::SetVolume(self.volume));
+            }
+            Command::Seek(amount) => self.seek_to(amount.apply(self.position)),
+            Command::Theme(name) => {
+                if let Err(e) = self.set_theme(&name) {
+                    error!("无法切换主题: {}", e);
+                }
+            }
+            Command::SavePlaylist(name) => {
+                let playlist = Playlist::from_tracks(self.rows.iter().map(|&i| &self.tracks[i]));
+                match self.playlists.save_as(&name, playlist) {
+                    Ok(()) => {
+                        info!("已保存播放列表 {}", name);
+                        self.effects.push(Effect::SavePlaylists);
+                        self.sync_queue(&name);
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+            Command::SaveSmart(name) => match self.filter.clone() {
+                Some(query) => {
+                    let result = self.smart.save(&name, query);
+                    self.report(result);
+                }
+                None => error!("请先用 / 或 :filter 输入搜索条件"),
+            },
+            Command::Import(path) => self.import_playlist(&path),
+            Command::Export(path) => match PlaylistFormat::from_path(&path) {
+                Some(format) => {
+                    let playlist = Playlist::from_tracks(self.rows.iter().map(|&i| &self.tracks[i]));
+                    self.effects.push(Effect::Export(path, format, playlist));
+                }
+                None => error!("无法导出: {}", PlaylistError::UnknownFormat(path)),
+            },
+            Command::Filter(query) => match parse_filter(&query) {
+                Ok(filter) => self.set_filter(filter),
+                Err(e) => error!("搜索条件无效: {}", e),
+            },
+            Command::Shuffle(shuffle) => {
+                let shuffle = shuffle.unwrap_or(!self.queue.shuffle());
+                self.queue.set_shuffle(shuffle);
+            }
+            Command::Repeat(repeat) => {
+                let repeat = repeat.unwrap_or(self.queue.repeat().next());
+                self.queue.set_repeat(repeat);
+            }
+            Command::Quit => return false,
+        }
+        true
+    }
+
+    /// Imports a playlist file as a named playlist, named after the file,
+    /// and shows it.
+    fn import_playlist(&mut self, path: &Path) {
+        let imported = match playlist::load(path) {
+            Ok(imported) => imported,
+            Err(e) => {
+                error!("无法导入: {}", e);
+                return;
+            }
+        };
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        if self.playlists.get(&name).is_some() {
+            error!("无法导入: {}", PlaylistError::AlreadyExists(name));
+            return;
+        }
+        let matched = imported.match_tracks(self.library_tracks()).iter().flatten().count();
+        let total = imported.entries.len();
+        match self.playlists.save_as(&name, imported) {
+            Ok(()) => {
+                info!("已导入播放列表 {}（曲库中有 {}/{} 首）", name, matched, total);
+                self.effects.push(Effect::SavePlaylists);
+                self.open_view(View::Playlist(name));
+            }
+            Err(e) => error!("无法导入: {}", e),
+        }
+    }
+
+    /// Re-applies the search prompt input as the filter while typing.
+    ///
+    /// Incomplete queries keep the previous filter until they parse.
+    fn update_search(&mut self) {
+        let Some(Prompt { kind: PromptKind::Search, input }) = &self.prompt else { return };
+        if let Ok(filter) = parse_filter(input) {
+            self.set_filter(filter);
+        }
+    }
+
+    /// Replaces the track list filter and refreshes the rows.
+    fn set_filter(&mut self, filter: Option<Query>) {
+        self.filter = filter;
+        self.selected = 0;
+        self.refresh_rows();
+    }
+
+    /// Sets the star rating of a library track, writing it to the file's tags
+    /// when enabled.
+    fn rate_track(&mut self, index: usize, rating: u8) {
+        let path = self.tracks[index].path.clone();
+        self.library.set_rating(&path, rating);
+        self.effects.push(Effect::SaveLibrary);
+        if self.write_rating_tags {
+            self.effects.push(Effect::WriteRating(path, rating));
+        }
+        self.refresh_rows();
+    }
+
+    /// Maps a row of the playlist view to the index of its playlist entry.
+    ///
+    /// Rows skip unmatched entries and those the filter hides, so the two can
+    /// differ.
+    fn playlist_entry(&self, row: usize) -> Option<usize> {
+        self.view_playlist()?;
+        self.entries.get(row).copied()
+    }
+
+    /// Moves the selected entry of the shown playlist one row down or up.
+    fn move_selected_entry(&mut self, down: bool) {
+        let Some(name) = self.view_playlist().map(str::to_string) else { return };
+        let target_row = if down { self.selected + 1 } else { self.selected.wrapping_sub(1) };
+        let (Some(from), Some(to)) = (self.playlist_entry(self.selected), self.playlist_entry(target_row)) else {
+            return;
+        };
+        let result = self.playlists.move_entry(&name, from, to);
+        self.report(result);
+        self.sync_queue(&name);
+        self.refresh_rows();
+        self.selected = target_row;
+    }
+}
+
+/// Returns the cursor position after a cursor motion, or `None` if `action`
+/// does not move the cursor.
+///
+/// A count repeats the motion, except for `Top` and `Bottom` where it names
+/// the row to go to (1-based, like `5G`).
+///
+/// # Arguments
+///
+/// * `action` - Action to apply
+/// * `count` - Count typed before the keys
+/// * `cursor` - Current position
+/// * `len` - Number of rows
+/// * `page` - Number of rows visible at once
+fn motion(action: Action, count: Option<usize>, cursor: usize, len: usize, page: usize) -> Option<usize> {
+    let times = count.unwrap_or(1);
+    let half_page = (page / 2).max(1).saturating_mul(times);
+    let last = len.saturating_sub(1);
+    let row = match action {
+        Action::Up => cursor.saturating_sub(times),
+        Action::Down => cursor.saturating_add(times),
+        Action::HalfPageUp => cursor.saturating_sub(half_page),
+        Action::HalfPageDown => cursor.saturating_add(half_page),
+        Action::Top | Action::Bottom if count.is_some() => times - 1,
+        Action::Top => 0,
+        Action::Bottom => last,
+        _ => return None,
+    };
+    Some(row.min(last))
+}
+
+/// Input to [`update`].
+#[derive(Debug)]
+pub enum Msg {
+    /// A key event from the terminal (only presses are handled)
+    Key(KeyEvent),
+    /// A mouse event from the terminal
+    Mouse(MouseEvent),
+    /// The terminal was resized to (width, height)
+    Resize(u16, u16),
+    /// An event from the player thread
+    Player(AppEvent),
+    /// A message to show (see [`crate::notify`])
+    Notification(Notification),
+    /// The configuration file changed
+    Config(Box<Config>),
+    /// The process was asked to suspend (SIGTSTP)
+    Suspend,
+    /// The process was asked to quit (SIGTERM, SIGINT, SIGHUP)
+    Quit,
+    /// A request from another program, with the channel for the reply (see
+    /// [`crate::remote`])
+    Remote(Request, Sender<Reply>),
+    /// A reply or event from the player the application is attached to
+    /// (see [`App::attached`])
+    Daemon(Reply),
+    /// A deadline from [`App::next_deadline`] has passed
+    Tick,
+}
+
+/// Output of [`update`]: something only the caller can do.
+#[derive(Debug)]
+pub enum Effect {
+    /// Send a command to the player thread
+    Player(AppCommand),
+    /// Send a request to the player the application is attached to
+    Daemon(Request),
+    /// Write a star rating into the tags of an audio file, off the UI
+    /// thread (see [`tags::spawn_rating_writer`])
+    WriteRating(PathBuf, u8),
+    /// Write the listening statistics and history changed in memory (see
+    /// [`App::save_library`])
+    SaveLibrary,
+    /// Write the resume positions and bookmarks (see [`App::save_bookmarks`])
+    SaveBookmarks,
+    /// Write the playlists and smart playlists changed in memory (see
+    /// [`App::save_playlists`])
+    SavePlaylists,
+    /// Write a playlist to a file in the given format (see [`export`])
+    Export(PathBuf, PlaylistFormat, Playlist),
+    /// Answer a request from another program
+    Reply(Sender<Reply>, Reply),
+    /// Tell the subscribers about a change (see [`App::send_event`])
+    Publish(Event),
+    /// Suspend the process to the shell (see [`crate::ui::TerminalGuard::suspend`])
+    Suspend,
+    /// Quit the application
+    Quit,
+    /// Draw the screen again: the state shown has changed
+    Redraw,
+}
+
+/// Applies a message to the application state.
+///
+/// # Arguments
+///
+/// * `app` - Application state
+/// * `msg` - Input to handle
+///
+/// # Returns
+///
+/// Returns the effects to carry out, in order; [`Effect::Redraw`] comes
+/// last if the screen needs drawing.
+pub fn update(app: &mut App, msg: Msg) -> Vec<Effect> {
+    // Key releases and bare mouse movement change nothing on screen
+    let redraw = match &msg {
+        Msg::Key(key) => key.kind == KeyEventKind::Press,
+        Msg::Mouse(mouse) => mouse.kind != MouseEventKind::Moved,
+        Msg::Remote(request, _) => !matches!(request, Request::Status | Request::Queue | Request::Search(_) | Request::Subscribe),
+        Msg::Daemon(reply) => *reply != Reply::Done,
+        _ => true,
+    };
+    let watched = app.watched();
+    // Settings changed here (not by the other player) are passed on to it
+    let options = (app.attached && !matches!(msg, Msg::Daemon(_))).then(|| app.options());
+    let running = match msg {
+        Msg::Key(key) => key.kind != KeyEventKind::Press || app.handle_key(key),
+        Msg::Mouse(mouse) => {
+            app.handle_mouse(mouse);
+            true
+        }
+        Msg::Resize(width, height) => {
+            app.size = Some((width, height));
+            true
+        }
+        Msg::Player(event) => {
+            app.publish_player_event(&event);
+            app.handle_player_event(event);
+            true
+        }
+        Msg::Notification(notification) => {
+            // Toasts expire by the application's clock, counted from arrival
+            let (created, time) = (app.clock.now(), app.clock.system_time());
+            app.notifications.push(Notification { created, time, ..notification });
+            true
+        }
+        Msg::Config(config) => {
+            app.apply_config(*config);
+            true
+        }
+        Msg::Suspend => {
+            app.suspend();
+            true
+        }
+        Msg::Quit => false,
+        Msg::Remote(request, reply) => {
+            app.handle_remote(request, reply);
+            true
+        }
+        Msg::Daemon(reply) => {
+            app.handle_daemon(reply);
+            true
+        }
+        Msg::Tick => app.tick(),
+    };
+    if let Some(before) = watched {
+        app.publish_changes(before);
+    }
+    if let Some(before) = options {
+        app.forward_options(before);
+    }
+    app.relayout();
+    let mut effects = std::mem::take(&mut app.effects);
+    if !running {
+        effects.push(Effect::Quit);
+    } else if redraw {
+        effects.push(Effect::Redraw);
+    }
+    effects
+}
+
+/// Writes a playlist to a file ([`Effect::Export`]), reporting the outcome.
+pub fn export(path: &Path, format: PlaylistFormat, playlist: &Playlist) {
+    match playlist.save(path, format) {
+        Ok(()) => info!("已导出 {} 首到 {}", playlist.entries.len(), path.display()),
+        Err(e) => error!("无法导出: {}", e),
+    }
+}
+
+
+/// Returns the number of track rows the track list shows in a layout.
+fn page_rows(layout: &AppLayout, compact: bool) -> usize {
+    // Borders, and the column header outside compact mode
+    let chrome = if compact { 2 } else { 3 };
+    (layout.track_list.height as usize).saturating_sub(chrome).max(1)
+}
+
+/// Returns the first row to show so that `selected` is visible, scrolling
+/// from `scroll` as little as possible.
+fn scroll_to(scroll: usize, selected: usize, page: usize) -> usize {
+    if selected < scroll {
+        selected
+    } else if selected >= scroll + page {
+        selected + 1 - page
+    } else {
+        scroll
+    }
+}
+
+/// Returns whether a terminal cell lies within an area.
+fn contains(area: Rect, column: u16, row: u16) -> bool {
+    column >= area.x && column < area.x + area.width && row >= area.y && row < area.y + area.height
+}
+
+/// Returns the item of a bordered, unscrolled list shown at a terminal row.
+fn list_row(area: Rect, row: u16) -> Option<usize> {
+    (row > area.y && row + 1 < area.y + area.height).then(|| (row - area.y - 1) as usize)
+}
+
+/// Parses search input into a filter; blank input clears the filter.
+fn parse_filter(input: &str) -> Result<Option<Query>, crate::query::QueryError> {
+    if input.trim().is_empty() {
+        Ok(None)
+    } else {
+        Query::parse(input).map(Some)
+    }
+}
+
+/// Scans directories recursively for audio files.
+///
+/// Searches for files with the given audio extensions and creates Track
+/// instances for each found file, filled from their tags.
+///
+/// # Arguments
+///
+/// * `dirs` - Directory paths to scan
+/// * `extensions` - Lower-case file extensions to include (e.g. `mp3`)
+///
+/// # Returns
+///
+/// Returns a vector of Track instances for all found audio files.
+pub fn scan_directory(dirs: &[PathBuf], extensions: &[String]) -> Vec<Track> {
+    let mut out = Vec::new();
+    for entry in dirs.iter().flat_map(WalkDir::new).filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            let ok = extensions.contains(&ext.to_lowercase());
+            if ok {
+                let mut track = Track {
+                    id: out.len() as u64,
+                    path: path.to_path_buf(),
+                    duration: None,
+                    title: path.file_name().and_then(|s| s.to_str()).map(|s| s.to_string()),
+                    ..Default::default()
+                };
+                // Fill artist, album, genre, year (and title when tagged) from the file
+                match tags::read(path) {
+                    Ok(info) => info.apply_to(&mut track),
+                    Err(e) => error!("无法读取标签 {}: {}", path.display(), e),
+                }
+                out.push(track);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+impl App {
+    /// Creates an application keeping its playlists, statistics and
+    /// bookmarks in a fresh temporary directory, so that tests never write
+    /// to the user's.
+    pub(crate) fn scratch(tracks: Vec<Track>) -> Self {
+        let dir = crate::harness::TempDir::new("app");
+        let mut app = Self::new(tracks).with_data_dir(dir.path());
+        app.scratch_dir = Some(dir);
+        app
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify;
+    use crate::queue::RepeatMode;
+
+    /// Tracks `/test/track<i>.mp3` titled "Track <i>", with ids from 0.
+    fn test_tracks(count: u64) -> Vec<Track> {
+        (0..count)
+            .map(|i| Track {
+                id: i,
+                path: PathBuf::from(format!("/test/track{}.mp3", i)),
+                title: Some(format!("Track {}", i)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Takes the player commands requested so far.
+    fn commands(app: &mut App) -> Vec<AppCommand> {
+        app.effects
+            .drain(..)
+            .filter_map(|effect| match effect {
+                Effect::Player(command) => Some(command),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Carries out the writes asked for so far, as the event loop does.
+    fn write(app: &mut App) {
+        for effect in std::mem::take(&mut app.effects) {
+            match effect {
+                Effect::SaveLibrary => app.save_library(),
+                Effect::SaveBookmarks => app.save_bookmarks(),
+                Effect::SavePlaylists => app.save_playlists(),
+                Effect::Export(path, format, playlist) => export(&path, format, &playlist),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_track_ended_follows_active_playlist() {
+        let tracks = test_tracks(4);
+        let mut app = App::scratch(tracks);
+
+        app.playlists.create("mix").unwrap();
+        app.playlists.add_tracks("mix", [&app.tracks[3], &app.tracks[1]]).unwrap();
+
+        app.open_view(View::Playlist("mix".to_string()));
+        assert_eq!(app.rows, vec![3, 1]);
+        app.play_row(0);
+        app.advance();
+        app.advance();
+
+        let played: Vec<usize> = commands(&mut app)
+            .into_iter()
+            .filter_map(|cmd| match cmd {
+                AppCommand::Play { index, .. } => Some(index),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(played, vec![3, 1], "queue should end after the last playlist entry");
+        assert_eq!(app.status, PlaybackStatus::Stopped);
+        assert_eq!(app.queue.source(), Some("mix"));
+    }
+
+    #[test]
+    fn test_queue_follows_its_playlist() {
+        let mut app = App::scratch(test_tracks(4));
+        app.playlists.create("mix").unwrap();
+        app.playlists.add_tracks("mix", [&app.tracks[0], &app.tracks[1], &app.tracks[2]]).unwrap();
+        app.open_view(View::Playlist("mix".to_string()));
+        app.play_row(1);
+
+        // Reordered, extended and shortened behind the playing track
+        app.selected = 0;
+        app.perform_track(Action::MoveEntryDown, None);
+        assert_eq!(app.queue.tracks(), &[1, 0, 2]);
+        app.sidebar_selected = app.sidebar_views().iter().position(|v| *v == View::Playlist("mix".to_string())).unwrap();
+        app.open_view(View::Library);
+        app.selected = 3;
+        app.perform_track(Action::AddToPlaylist, None);
+        assert_eq!(app.queue.tracks(), &[1, 0, 2, 3]);
+        app.open_view(View::Playlist("mix".to_string()));
+        app.selected = 0;
+        app.perform_track(Action::Delete, None);
+        assert_eq!(app.queue.tracks(), &[1, 0, 2, 3], "the playing track stays queued");
+        app.selected = 1;
+        app.perform_track(Action::Delete, None);
+        assert_eq!((app.queue.tracks(), app.queue.current()), (&[1, 0, 3][..], Some(1)));
+        assert_eq!(app.queue.next_track(), Some(0));
+
+        // A filtered view is queued as it is shown, without following the playlist
+        app.set_filter(Some(Query::parse("-title:3").unwrap()));
+        app.play_row(0);
+        assert_eq!((app.queue.tracks(), app.queue.source()), (&[0][..], None));
+    }
+
+    #[test]
+    fn test_playlist_editing_under_a_filter() {
+        let tracks = test_tracks(4);
+        let mut app = App::scratch(tracks);
+        app.playlists.create("mix").unwrap();
+        app.playlists.add_tracks("mix", app.tracks.iter()).unwrap();
+        app.open_view(View::Playlist("mix".to_string()));
+        app.set_filter(Some(Query::parse("-title:0").unwrap()));
+        assert_eq!(app.rows, vec![1, 2, 3]);
+
+        let order = |app: &App| {
+            let entries = &app.playlists.get("mix").unwrap().entries;
+            entries.iter().map(|e| e.path.file_stem().unwrap().to_string_lossy().into_owned()).collect::<Vec<_>>()
+        };
+        // The hidden first entry does not shift the rows onto the wrong entries
+        app.perform_track(Action::MoveEntryDown, None);
+        assert_eq!(order(&app), ["track0", "track2", "track1", "track3"]);
+        assert_eq!((app.rows.clone(), app.selected), (vec![2, 1, 3], 1));
+        app.selected = 2;
+        app.perform_track(Action::Delete, None);
+        assert_eq!(order(&app), ["track0", "track2", "track1"]);
+    }
+
+    /// Integration test: typing a search filters live and saves as a smart playlist
+    #[test]
+    fn test_search_saves_smart_playlist() {
+        let genres = ["Jazz", "Rock", "Jazz"];
+        let tracks: Vec<Track> = test_tracks(3)
+            .into_iter()
+            .zip(genres)
+            .map(|(track, genre)| Track { genre: Some(genre.to_string()), ..track })
+            .collect();
+        let mut app = App::scratch(tracks);
+
+        let press = |app: &mut App, code: KeyCode| {
+            app.handle_key(KeyEvent::new(code, crossterm::event::KeyModifiers::NONE));
+        };
+        press(&mut app, KeyCode::Char('/'));
+        for c in "genre:jazz".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_eq!(app.rows, vec![0, 2], "filter should apply while typing");
+        press(&mut app, KeyCode::Enter);
+
+        press(&mut app, KeyCode::Char('S'));
+        for c in "jazz".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.rows, vec![0, 1, 2]);
+
+        // New matching tracks appear when the smart playlist is re-evaluated
+        app.tracks[1].genre = Some("jazz".to_string());
+        app.open_view(View::Smart("jazz".to_string()));
+        assert_eq!(app.rows, vec![0, 1, 2]);
+        assert_eq!(app.sidebar_active(), Some(3));
+    }
+
+    #[test]
+    fn test_rating_tags_are_written_off_the_ui_thread() {
+        let track = Track { path: PathBuf::from("/test/track0.mp3"), ..Default::default() };
+        let mut app = App::scratch(vec![track]);
+        app.rate_track(0, 4);
+        assert!(matches!(&app.effects[..], [Effect::SaveLibrary]), "saved, but not into the tags");
+
+        app.effects.clear();
+        app.write_rating_tags = true;
+        app.rate_track(0, 5);
+        assert!(matches!(
+            &app.effects[..],
+            [Effect::SaveLibrary, Effect::WriteRating(path, 5)] if path == Path::new("/test/track0.mp3")
+        ));
+        assert_eq!(app.stats(0).rating, 5);
+    }
+
+    /// Integration test: player events record plays, skips and the history views
+    #[test]
+    fn test_player_events_record_statistics() {
+        let tracks = test_tracks(3);
+        let mut app = App::scratch(tracks);
+
+        let started = |index| AppEvent::TrackStarted { index, duration: Some(Duration::from_secs(200)) };
+        app.handle_player_event(started(2));
+        app.handle_player_event(AppEvent::Progress { position: Duration::from_secs(12) });
+        // Starting another track before the end counts as a skip
+        app.handle_player_event(started(0));
+        app.handle_player_event(AppEvent::TrackEnded);
+        app.handle_player_event(started(0));
+        app.handle_player_event(AppEvent::TrackEnded);
+
+        assert_eq!(app.stats(0).play_count, 2);
+        assert_eq!(app.stats(2).skip_count, 1);
+        assert_eq!(app.library.history()[0].listened, Duration::from_secs(12));
+
+        app.open_view(View::Recent);
+        assert_eq!(app.rows, vec![0, 2]);
+        app.open_view(View::MostPlayed);
+        assert_eq!(app.rows, vec![0]);
+
+        app.open_view(View::Library);
+        app.sort = SortKey::Skips;
+        app.refresh_rows();
+        assert_eq!(app.rows, vec![2, 0, 1]);
+
+        // Ratings and favorites feed sorting and queries
+        app.selected = app.rows.iter().position(|&r| r == 1).unwrap();
+        for c in ['R', '4', 'f'] {
+            app.handle_key(KeyEvent::new(KeyCode::Char(c), crossterm::event::KeyModifiers::NONE));
+        }
+        app.sort = SortKey::Rating;
+        app.refresh_rows();
+        assert_eq!(app.rows[0], 1);
+        app.set_filter(Some(Query::parse("rating:>=4 fav:yes").unwrap()));
+        assert_eq!(app.rows, vec![1]);
+
+        // Nothing is written until the event loop carries out the saves
+        let dir = app.scratch_dir.as_ref().unwrap().path().to_path_buf();
+        assert!(LibraryDb::open(&dir).unwrap().0.history().is_empty());
+        write(&mut app);
+        let (reloaded, _) = LibraryDb::open(&dir).unwrap();
+        assert_eq!(reloaded.stats(&app.tracks[0].path).play_count, 2);
+        assert_eq!(reloaded.stats(&app.tracks[1].path).rating, 4);
+    }
+
+    /// Integration test: long tracks resume where they were left and bookmarks seek
+    #[test]
+    fn test_resume_and_bookmarks_seek() {
+        let tracks = test_tracks(2);
+        let mut app = App::scratch(tracks);
+        let key = |c| KeyEvent::new(c, crossterm::event::KeyModifiers::NONE);
+        let hour = Some(Duration::from_secs(3600));
+
+        // Leaving a long track remembers the position; a short one does not
+        app.handle_player_event(AppEvent::TrackStarted { index: 0, duration: hour });
+        app.handle_player_event(AppEvent::Progress { position: Duration::from_secs(754) });
+        app.handle_player_event(AppEvent::TrackStarted { index: 1, duration: Some(Duration::from_secs(180)) });
+        app.handle_player_event(AppEvent::Progress { position: Duration::from_secs(90) });
+        app.handle_player_event(AppEvent::TrackStarted { index: 0, duration: hour });
+        assert_eq!(app.bookmarks.resume_position(&app.tracks[1].path), None);
+        let seeks: Vec<_> = commands(&mut app);
+        assert!(matches!(seeks[..], [AppCommand::Seek(p)] if p == Duration::from_secs(754)));
+
+        // Bookmark the current position, then jump back to it from the panel
+        app.handle_key(key(KeyCode::Char('b')));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "12:34");
+        app.handle_key(key(KeyCode::Enter));
+        app.handle_key(key(KeyCode::Right));
+        assert_eq!(app.position, Duration::from_secs(764));
+        app.handle_key(key(KeyCode::Char('B')));
+        assert_eq!(app.focus, Focus::Bookmarks);
+        app.handle_key(key(KeyCode::Enter));
+        let seeks: Vec<_> = commands(&mut app);
+        assert_eq!(seeks.len(), 2);
+        assert!(matches!(seeks[1], AppCommand::Seek(p) if p == Duration::from_secs(754)));
+
+        // Finishing the track forgets its resume position but keeps bookmarks
+        app.handle_player_event(AppEvent::TrackEnded);
+        assert!(app.effects.iter().any(|e| matches!(e, Effect::SaveBookmarks)));
+        write(&mut app);
+        let reloaded = BookmarkStore::open(app.scratch_dir.as_ref().unwrap().path()).unwrap();
+        assert_eq!(reloaded.resume_position(&app.tracks[0].path), None);
+        assert_eq!(reloaded.bookmarks(&app.tracks[0].path)[0].name, "12:34");
+    }
+
+    /// Integration test: configured keys, steps and breakpoint drive the app
+    #[test]
+    fn test_config_rebinds_keys_and_steps() {
+        let tracks = vec![Track { id: 0, path: PathBuf::from("/test/a.mp3"), ..Default::default() }];
+        let mut app = App::scratch(tracks);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), crossterm::event::KeyModifiers::NONE);
+        let config = Config::parse(
+            "[player]\nvolume_step = 0.25\nseek_step = 30\n[ui]\ncompact_width = 120\n[keys]\nquit = \"x\"\nvolume_up = \"=\"\n",
+        )
+        .unwrap();
+        app.apply_config(config);
+
+        assert!(app.handle_key(key('q')), "the default quit key is unbound");
+        app.volume = 0.5;
+        app.handle_key(key('='));
+        assert_eq!(app.volume, 0.75);
+        app.handle_key(key('+'));
+        assert_eq!(app.volume, 0.75);
+        app.playing = Some(0);
+        app.handle_key(KeyEvent::new(KeyCode::Right, crossterm::event::KeyModifiers::NONE));
+        assert_eq!(app.position, Duration::from_secs(30));
+        assert!(app.get_layout(100, 30).visualization.is_none());
+        assert!(!app.handle_key(key('x')));
+    }
+
+    /// Integration test: key sequences, pane contexts and generated hints
+    #[test]
+    fn test_key_sequences() {
+        let tracks = test_tracks(5);
+        let mut app = App::scratch(tracks);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), crossterm::event::KeyModifiers::NONE);
+
+        app.handle_key(key('G'));
+        assert_eq!(app.selected, 4);
+        app.handle_key(key('g'));
+        assert_eq!(app.selected, 4, "a lone g waits for the rest of the sequence");
+        app.handle_key(key('g'));
+        assert_eq!(app.selected, 0);
+        // A key that cannot continue the sequence is handled on its own
+        app.handle_key(key('g'));
+        app.handle_key(key('j'));
+        assert_eq!((app.selected, app.pending_keys.len()), (1, 0));
+
+        // A prefix that is also bound runs when the sequence is abandoned
+        let config = Config::parse("[keys]\ntop = \"oo\"\n").unwrap();
+        app.apply_config(config);
+        app.handle_key(key('o'));
+        assert_eq!(app.sort, SortKey::Default);
+        assert!(app.flush_pending_keys());
+        assert_ne!(app.sort, SortKey::Default);
+        app.handle_key(key('o'));
+        app.handle_key(key('o'));
+        assert_eq!(app.selected, 0);
+
+        // Pane bindings take precedence over global ones
+        app.handle_key(key('r'));
+        assert_ne!(app.queue.repeat(), Default::default());
+        app.focus = Focus::Sidebar;
+        app.handle_key(key('r'));
+        assert!(app.prompt.is_none(), "the library view cannot be renamed");
+        assert_eq!(app.keymap.status_hints()[0], ("q".to_string(), "退出"));
+    }
+
+    /// Integration test: counts, half pages, centering and marks
+    #[test]
+    fn test_counts_and_marks() {
+        let tracks = test_tracks(100);
+        let mut app = App::scratch(tracks);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        app.get_layout(120, 30);
+        let page = app.page_rows();
+
+        app.handle_key(key('5'));
+        app.handle_key(key('j'));
+        assert_eq!(app.selected, 5);
+        app.handle_key(key('1'));
+        app.handle_key(key('2'));
+        assert_eq!(app.pending_input(), "12");
+        app.handle_key(key('G'));
+        assert_eq!((app.selected, app.count), (11, None));
+        app.handle_key(ctrl('d'));
+        assert_eq!(app.selected, 11 + page / 2);
+        app.handle_key(ctrl('u'));
+        assert_eq!(app.selected, 11);
+        app.handle_key(key('G'));
+        app.scroll_to_selection();
+        assert_eq!((app.selected, app.scroll), (99, 100 - page));
+
+        // Digits are counts, even right before a motion; ratings take a prefix
+        app.handle_key(key('3'));
+        app.handle_key(key('k'));
+        assert_eq!((app.selected, app.stats(99).rating), (96, 0));
+        app.handle_key(key('3'));
+        app.handle_key(key('j'));
+        app.handle_key(key('R'));
+        app.handle_key(key('4'));
+        assert_eq!((app.selected, app.stats(99).rating), (99, 4));
+
+        // A count before an action that takes none is dropped
+        app.handle_key(key('2'));
+        app.handle_key(key('f'));
+        assert_eq!((app.stats(99).rating, app.stats(99).favorite), (4, true));
+        app.handle_key(key('R'));
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        app.handle_key(key('3'));
+        assert!(app.flush_pending_input());
+        assert_eq!(app.stats(99).rating, 4, "Esc cancels the sequence");
+
+        // Marks remember tracks, and '' jumps back
+        app.handle_key(key('m'));
+        assert_eq!(app.mode, Mode::SetMark);
+        app.handle_key(key('a'));
+        app.handle_key(key('g'));
+        app.handle_key(key('g'));
+        app.handle_key(key('\''));
+        app.handle_key(key('a'));
+        assert_eq!((app.selected, app.mode), (99, Mode::Normal));
+        app.handle_key(key('\''));
+        app.handle_key(key('\''));
+        assert_eq!(app.selected, 0);
+        app.handle_key(key('\''));
+        app.handle_key(key('x'));
+        assert_eq!(app.selected, 0);
+
+        // zz selects the playing track and centers it
+        app.playing = Some(50);
+        app.handle_key(key('z'));
+        app.handle_key(key('z'));
+        assert_eq!((app.selected, app.scroll), (50, 50 - page / 2));
+        assert!(!app.queue.shuffle());
+    }
+
+    /// Integration test: the `:` command line with completion and history
+    #[test]
+    fn test_command_line() {
+        let tracks = test_tracks(3);
+        let mut app = App::scratch(tracks);
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let type_line = |app: &mut App, line: &str| {
+            app.handle_key(key(KeyCode::Char(':')));
+            for c in line.chars() {
+                app.handle_key(key(KeyCode::Char(c)));
+            }
+            app.handle_key(key(KeyCode::Enter))
+        };
+
+        assert!(type_line(&mut app, "vol 80"));
+        assert_eq!(app.volume, 0.8);
+        assert!(type_line(&mut app, "vol +150"));
+        assert_eq!(app.volume, 2.0);
+        assert!(type_line(&mut app, "play 2"));
+        assert_eq!(app.selected, 1);
+        assert!(type_line(&mut app, "shuffle on"));
+        assert!(app.queue.shuffle());
+        assert!(type_line(&mut app, "seek 1:00"));
+        assert_eq!(app.position, Duration::from_secs(60));
+        assert!(type_line(&mut app, "bogus"));
+        assert!(app.prompt.is_none());
+        let commands: Vec<_> = commands(&mut app);
+        assert!(matches!(commands[0], AppCommand::SetVolume(v) if v == 0.8));
+        assert!(commands.iter().any(|c| matches!(c, AppCommand::Seek(p) if *p == Duration::from_secs(60))));
+
+        // Tab cycles through completions, Up recalls earlier lines
+        app.handle_key(key(KeyCode::Char(':')));
+        app.handle_key(key(KeyCode::Char('s')));
+        app.handle_key(key(KeyCode::Tab));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "seek");
+        app.handle_key(key(KeyCode::Tab));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "save");
+        app.handle_key(key(KeyCode::Char(' ')));
+        app.handle_key(key(KeyCode::Char('s')));
+        app.handle_key(key(KeyCode::Tab));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "save smart");
+        app.handle_key(key(KeyCode::Up));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "bogus");
+        app.handle_key(key(KeyCode::Up));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "seek 1:00");
+        app.handle_key(key(KeyCode::Down));
+        app.handle_key(key(KeyCode::Down));
+        assert_eq!(app.prompt.as_ref().unwrap().input, "save smart");
+        app.handle_key(key(KeyCode::Esc));
+        assert!(app.prompt.is_none());
+
+        assert!(!type_line(&mut app, "q"));
+    }
+
+    /// Integration test: `:export` writes the track list and `:import` reads
+    /// it back as a named playlist
+    #[test]
+    fn test_import_and_export_commands() {
+        let tracks: Vec<Track> = test_tracks(3)
+            .into_iter()
+            .map(|track| Track { artist: Some(if track.id == 1 { "Can" } else { "Neu!" }.to_string()), ..track })
+            .collect();
+        let mut app = App::scratch(tracks);
+        let dir = app.scratch_dir.as_ref().unwrap().path().to_path_buf();
+        app.set_filter(Some(Query::parse("artist:can").unwrap()));
+        assert_eq!(app.rows.len(), 1);
+
+        assert!(app.run_command(Command::Export(dir.join("mix.xspf"))));
+        assert!(matches!(&app.effects[..], [Effect::Export(_, PlaylistFormat::Xspf, _)]));
+        write(&mut app);
+        let exported = playlist::load(&dir.join("mix.xspf")).unwrap();
+        assert_eq!(exported.entries.len(), 1);
+        assert_eq!(exported.entries[0].path, PathBuf::from("/test/track1.mp3"));
+
+        app.run_command(Command::Import(dir.join("mix.xspf")));
+        assert_eq!(app.view, View::Playlist("mix".to_string()));
+        assert_eq!(app.rows, vec![1]);
+
+        // Neither an existing name nor an unknown format is taken
+        std::fs::write(dir.join("mix.pls"), "[playlist]\nFile1=/test/2.mp3\nNumberOfEntries=1\n").unwrap();
+        app.run_command(Command::Import(dir.join("mix.pls")));
+        assert_eq!(app.playlists.get("mix").unwrap().entries.len(), 1);
+        assert_eq!(app.playlists.get("mix").unwrap().entries[0].path, PathBuf::from("/test/track1.mp3"));
+        write(&mut app);
+        app.run_command(Command::Export(dir.join("mix.txt")));
+        assert!(app.effects.is_empty());
+    }
+
+    /// Integration test: the help popup follows the keymap and scrolls
+    #[test]
+    fn test_help_popup() {
+        let mut app = App::scratch(Vec::new());
+        app.apply_config(Config::parse("[keys]\nquit = \"ctrl+q\"\n").unwrap());
+        app.get_layout(80, 20);
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+
+        let sections = app.help_sections();
+        assert_eq!(sections[0].0, "全局");
+        assert!(sections[0].1.contains(&("Ctrl-q".to_string(), "退出")));
+        assert_eq!(sections.last().unwrap().0, "命令");
+
+        app.handle_key(key('?'));
+        assert_eq!(app.help_scroll, Some(0));
+        app.handle_key(key('j'));
+        app.handle_key(key('j'));
+        assert_eq!(app.help_scroll, Some(2));
+        app.handle_key(key('G'));
+        let lines = HelpWidget::content_height(&sections);
+        assert_eq!(app.help_scroll, Some(lines - 16));
+        assert!(app.handle_key(key('q')), "keys do not reach the player while help is shown");
+        assert_eq!(app.help_scroll, Some(lines - 16));
+        app.handle_key(key('?'));
+        assert_eq!(app.help_scroll, None);
+    }
+
+    /// Integration test: mouse clicks, wheel and drags hit-tested against the layout
+    #[test]
+    fn test_mouse() {
+        let tracks = test_tracks(100);
+        let mut app = App::scratch(tracks);
+        let layout = app.get_layout(120, 30).clone();
+        let mouse = |kind, column, row| MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE };
+        let click = MouseEventKind::Down(MouseButton::Left);
+
+        // Wheel scrolls the list and drags the selection along
+        let list = layout.track_list;
+        app.handle_mouse(mouse(MouseEventKind::ScrollDown, list.x + 5, list.y + 5));
+        assert_eq!((app.scroll, app.selected), (3, 3));
+        app.handle_mouse(mouse(MouseEventKind::ScrollUp, list.x + 5, list.y + 5));
+        assert_eq!(app.scroll, 0);
+
+        // Click selects, double-click plays; the first row is below the header
+        app.handle_mouse(mouse(click, list.x + 5, list.y + 4));
+        assert_eq!(app.selected, 2);
+        assert!(commands(&mut app).iter().all(|c| !matches!(c, AppCommand::Play { .. })));
+        app.handle_mouse(mouse(click, list.x + 5, list.y + 4));
+        let commands: Vec<_> = commands(&mut app);
+        assert!(matches!(commands[..], [AppCommand::Play { index: 2, .. }]));
+
+        // Click and drag on the progress bar seek
+        app.total = Some(Duration::from_secs(200));
+        let bar = PlaybackControlWidget::progress_area(layout.playback_control);
+        app.handle_mouse(mouse(click, bar.x + 1 + (bar.width - 2) / 2, bar.y + 1));
+        assert_eq!(app.position, Duration::from_secs(100));
+        app.handle_mouse(mouse(MouseEventKind::Drag(MouseButton::Left), bar.x + bar.width, bar.y + 5));
+        assert_eq!(app.position, Duration::from_secs(200));
+        app.handle_mouse(mouse(MouseEventKind::Up(MouseButton::Left), 0, 0));
+        assert_eq!(app.drag, None);
+
+        // Wheel and drag on the volume readout
+        let info = PlaybackControlWidget::info_area(layout.playback_control);
+        app.handle_mouse(mouse(MouseEventKind::ScrollDown, info.x + 20, info.y));
+        assert_eq!(app.volume, 1.0 - app.config.player.volume_step);
+        app.volume = 1.0;
+        app.handle_mouse(mouse(click, info.x + 20, info.y));
+        app.handle_mouse(mouse(MouseEventKind::Drag(MouseButton::Left), info.x + 30, info.y));
+        assert!((app.volume - 1.2).abs() < 1e-6);
+        app.handle_mouse(mouse(MouseEventKind::Up(MouseButton::Left), info.x + 30, info.y));
+
+        // Clicking the visualization cycles its mode
+        let viz = layout.visualization.unwrap();
+        app.handle_mouse(mouse(click, viz.x + 2, viz.y + 2));
+        assert_eq!(app.visualization, VisualizationMode::Bars);
+
+        // The sidebar takes clicks once shown
+        app.sidebar_visible = true;
+        let sidebar = app.get_layout(120, 30).sidebar.unwrap();
+        app.handle_mouse(mouse(click, sidebar.x + 2, sidebar.y + 2));
+        assert_eq!((app.focus, app.sidebar_selected), (Focus::Sidebar, 1));
+    }
+
+    /// Integration test: errors from the player become toasts and log entries
+    #[test]
+    fn test_notifications() {
+        use tracing_subscriber::prelude::*;
+
+        let mut app = App::scratch(Vec::new());
+        app.get_layout(80, 20);
+        let (layer, rx) = notify::layer();
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            app.handle_player_event(AppEvent::Error { message: "无法解码".to_string() });
+        });
+        for notification in rx.try_iter() {
+            update(&mut app, Msg::Notification(notification));
+        }
+        let toasts = app.notifications.toasts(Instant::now());
+        assert_eq!(toasts.len(), 1);
+        assert_eq!((toasts[0].level, toasts[0].message.as_str()), (notify::Level::Error, "无法解码"));
+
+        // The log opens at its end and takes over from the toasts
+        app.handle_key(KeyEvent::new(KeyCode::Char('M'), KeyModifiers::SHIFT));
+        assert_eq!(app.log_scroll, Some(0));
+        assert!(app.notifications.toasts(Instant::now()).is_empty());
+        assert_eq!(app.notifications.log().len(), 1);
+        assert!(app.handle_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE)));
+        assert_eq!(app.log_scroll, None);
+    }
+
+    /// Integration test: Ctrl-Z asks the main loop to suspend
+    #[test]
+    fn test_suspend_request() {
+        let mut app = App::scratch(Vec::new());
+        let effects = update(&mut app, Msg::Key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL)));
+        assert!(matches!(effects[..], [Effect::Suspend, Effect::Redraw]));
+        assert!(!app.queue.shuffle(), "Ctrl-Z is not z");
+    }
+
+    /// Integration test: the loop only wakes up for visible changes
+    #[test]
+    fn test_deadlines_and_redraws() {
+        use crate::clock::ManualClock;
+        use crossterm::event::KeyEventState;
+
+        let clock = ManualClock::new(std::time::UNIX_EPOCH);
+        let track = Track { path: PathBuf::from("/test/a.mp3"), ..Default::default() };
+        let mut app = App::scratch(vec![track]).with_clock(Arc::new(clock.clone()));
+        update(&mut app, Msg::Resize(120, 30));
+        assert_eq!(app.next_deadline(), None, "idle: sleep until the next event");
+
+        // Frames while the visualization is shown and playing
+        update(&mut app, Msg::Player(AppEvent::TrackStarted { index: 0, duration: None }));
+        let frame = Duration::from_secs(1) / app.config.ui.visualization_fps;
+        assert_eq!(app.next_deadline(), Some(clock.now() + frame));
+        clock.advance(frame);
+        let wave = app.wave.clone();
+        assert!(matches!(update(&mut app, Msg::Tick)[..], [Effect::Redraw]));
+        assert_ne!(app.wave, wave);
+        update(&mut app, Msg::Resize(60, 30));
+        assert_eq!(app.next_deadline(), None, "no frames in compact mode");
+
+        // A key sequence prefix waits for its timeout
+        update(&mut app, Msg::Key(KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE)));
+        assert_eq!(app.next_deadline(), Some(clock.now() + KEY_SEQUENCE_TIMEOUT));
+
+        // Releases and bare movement need no redraw
+        let release = KeyEvent { kind: KeyEventKind::Release, state: KeyEventState::NONE, ..KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE) };
+        assert!(update(&mut app, Msg::Key(release)).is_empty());
+        let moved = MouseEvent { kind: MouseEventKind::Moved, column: 1, row: 1, modifiers: KeyModifiers::NONE };
+        assert!(update(&mut app, Msg::Mouse(moved)).is_empty());
+        assert!(matches!(update(&mut app, Msg::Quit)[..], [Effect::Quit]));
+    }
+
+    /// Integration test: switching themes at runtime
+    #[test]
+    fn test_switch_theme() {
+        let mut app = App::scratch(Vec::new());
+
+        app.set_theme("nord").unwrap();
+        assert_eq!(app.theme, Theme::builtin("nord").unwrap());
+        assert!(app.set_theme("no-such-theme").is_err());
+        assert_eq!(app.theme_name, "nord");
+        app.handle_key(KeyEvent::new(KeyCode::Char('t'), crossterm::event::KeyModifiers::NONE));
+        assert_eq!(app.theme_name, "high-contrast");
+        assert_eq!(app.theme.background, ratatui::style::Color::Black);
+    }
+
+    /// Integration test: a saved session restores queue, view and playback state
+    #[test]
+    fn test_session_roundtrip_restores_state() {
+        let tracks: Vec<Track> = test_tracks(4)
+            .into_iter()
+            .map(|track| Track { duration: Some(Duration::from_secs(100 + track.id)), ..track })
+            .collect();
+        let mut app = App::scratch(tracks.clone());
+        app.queue.set_repeat(RepeatMode::All);
+        app.sort = SortKey::Title;
+        app.sort_reversed = true;
+        app.refresh_rows();
+        app.play_row(1);
+        app.handle_player_event(AppEvent::TrackStarted { index: 2, duration: Some(Duration::from_secs(102)) });
+        app.listening = None;
+        app.handle_player_event(AppEvent::Progress { position: Duration::from_secs(42) });
+        app.volume = 0.6;
+        let (session, _) = Session::parse(&app.session().serialize());
+
+        // The library lost a track between runs
+        let remaining: Vec<Track> = tracks.into_iter().filter(|t| t.id != 0).collect();
+        let mut restored = App::scratch(remaining);
+        restored.restore(session);
+
+        assert_eq!(restored.volume, 0.6);
+        assert_eq!(restored.queue.repeat(), RepeatMode::All);
+        assert_eq!(restored.sort, SortKey::Title);
+        assert!(restored.sort_reversed);
+        assert_eq!(restored.playing.map(|i| restored.tracks[i].id), Some(2));
+        assert_eq!(restored.position, Duration::from_secs(42));
+        assert_eq!(restored.status, PlaybackStatus::Stopped);
+        assert_eq!(restored.queue.tracks().len(), 3);
+        assert_eq!(restored.queue.current().map(|i| restored.tracks[i].id), Some(2));
+        assert_eq!(restored.rows.get(restored.selected).map(|&i| restored.tracks[i].id), Some(2));
+
+        // Space starts the restored track
+        restored.handle_key(KeyEvent::new(KeyCode::Char(' '), crossterm::event::KeyModifiers::NONE));
+        let played: Vec<PathBuf> = commands(&mut restored)
+            .into_iter()
+            .filter_map(|cmd| match cmd {
+                AppCommand::Play { path, .. } => Some(path),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(played, vec![PathBuf::from("/test/track2.mp3")]);
+    }
+
+    /// Integration test: the session is saved to the application's own data
+    /// directory
+    #[test]
+    fn test_session_is_saved_to_data_dir() {
+        let mut app = App::scratch(Vec::new());
+        app.volume = 0.3;
+        app.save();
+        let (session, _) = Session::load(app.scratch_dir.as_ref().unwrap().path()).unwrap().unwrap();
+        assert_eq!(session.volume, 0.3);
+    }
+
+    /// Integration test: with `session.resume_playback`, the restored track
+    /// plays on from its saved position
+    #[test]
+    fn test_session_resumes_playback_when_configured() {
+        let tracks = test_tracks(2);
+        let session = Session {
+            track: Some(PathBuf::from("/test/track1.mp3")),
+            position: Duration::from_secs(42),
+            ..Default::default()
+        };
+
+        let mut app = App::scratch(tracks.clone());
+        app.restore(session.clone());
+        assert!(commands(&mut app).iter().all(|c| !matches!(c, AppCommand::Play { .. } | AppCommand::Seek(_))));
+
+        let mut app = App::scratch(tracks);
+        let mut config = Config::default();
+        config.session.resume_playback = true;
+        app.apply_config(config);
+        app.restore(session);
+        let commands = commands(&mut app);
+        assert!(
+            matches!(commands[1..], [AppCommand::Play { index: 1, .. }, AppCommand::Seek(p)] if p == Duration::from_secs(42)),
+            "{:?}",
+            commands
+        );
+    }
+}
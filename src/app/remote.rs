@@ -0,0 +1,385 @@
+//! Requests from other programs (see [`crate::remote`]).
+//!
+//! Requests are carried out with the same operations as the keys and the
+//! command line. Subscribers hear about player events as they arrive and
+//! about every other change by comparing the state before and after each
+//! message.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+use super::{parse_filter, App, Effect};
+use crate::common::{AppCommand, AppEvent, PlaybackStatus};
+use crate::queue::RepeatMode;
+use crate::remote::{Event, Reply, Request, Status, TrackInfo};
+
+/// What subscribers are told about when it changes.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Watched {
+    status: PlaybackStatus,
+    volume: u32,
+    queue: Vec<usize>,
+    options: (bool, RepeatMode),
+}
+
+impl App {
+    /// Returns the playback state as told to other programs.
+    pub fn remote_status(&self) -> Status {
+        Status {
+            state: self.status,
+            track: self.current_track().map(TrackInfo::from),
+            position: self.position,
+            duration: self.total,
+            volume: self.volume_percent(),
+            shuffle: self.queue.shuffle(),
+            repeat: self.queue.repeat(),
+            queue_position: self.queue.position(),
+            queue_length: self.queue.tracks().len(),
+        }
+    }
+
+    /// Returns the volume in percent.
+    fn volume_percent(&self) -> u32 {
+        (self.volume * 100.0).round() as u32
+    }
+
+    /// Carries out a request and answers it ([`Effect::Reply`]); a
+    /// subscription keeps the reply channel for events.
+    pub(super) fn handle_remote(&mut self, request: Request, reply: Sender<Reply>) {
+        let answer = match request {
+            Request::Toggle => {
+                match self.status {
+                    PlaybackStatus::Stopped => self.remote_play(),
+                    _ => self.toggle_pause(),
+                }
+                Reply::Done
+            }
+            Request::Play(None) => {
+                match self.status {
+                    PlaybackStatus::Stopped => self.remote_play(),
+                    PlaybackStatus::Paused => self.toggle_pause(),
+                    PlaybackStatus::Playing => {}
+                }
+                Reply::Done
+            }
+            Request::Play(Some(position)) if position < self.queue.tracks().len() => {
+                if let Some(index) = self.queue.jump(position) {
+                    self.play_track(index);
+                }
+                Reply::Done
+            }
+            Request::Play(Some(position)) => Reply::Error(format!("队列中没有第 {} 首", position)),
+            Request::PlayFile(path) => match self.tracks.iter().position(|t| t.path == path) {
+                Some(index) => {
+                    let queued = self.queue.tracks().iter().position(|&i| i == index);
+                    let position = queued.unwrap_or_else(|| {
+                        self.queue.append(&[index]);
+                        self.queue.tracks().len() - 1
+                    });
+                    self.queue.jump(position);
+                    self.play_track(index);
+                    Reply::Done
+                }
+                None => Reply::Error(format!("不在音乐库中: {}", path.display())),
+            },
+            Request::Pause => {
+                if self.status == PlaybackStatus::Playing {
+                    self.toggle_pause();
+                }
+                Reply::Done
+            }
+            Request::Next | Request::Prev => {
+                self.skip(request == Request::Next);
+                Reply::Done
+            }
+            Request::Volume(amount) => {
+                self.volume = amount.apply(self.volume_percent()).min(200) as f32 / 100.0;
+                self.send(AppCommand::SetVolume(self.volume));
+                Reply::Done
+            }
+            Request::Seek(_) if self.playing.is_none() => Reply::Error("没有正在播放的曲目".to_string()),
+            Request::Seek(amount) => {
+                self.seek_to(amount.apply(self.position));
+                Reply::Done
+            }
+            Request::Shuffle(shuffle) => {
+                self.queue.set_shuffle(shuffle.unwrap_or(!self.queue.shuffle()));
+                Reply::Done
+            }
+            Request::Repeat(repeat) => {
+                self.queue.set_repeat(repeat.unwrap_or(self.queue.repeat().next()));
+                Reply::Done
+            }
+            Request::Enqueue(paths) => self.enqueue(&paths),
+            Request::ClearQueue => {
+                self.queue.clear();
+                Reply::Done
+            }
+            Request::Status => Reply::Status(self.remote_status()),
+            Request::Queue => {
+                Reply::Tracks(self.queue.tracks().iter().map(|&i| TrackInfo::from(&self.tracks[i])).collect())
+            }
+            Request::Search(query) => match parse_filter(&query) {
+                Ok(filter) => {
+                    let now = self.clock.system_time();
+                    let matches = |track: &&_| filter.as_ref().is_none_or(|q| q.matches(&self.library.facts(track), now));
+                    Reply::Tracks(self.tracks.iter().filter(matches).map(TrackInfo::from).collect())
+                }
+                Err(e) => Reply::Error(format!("搜索条件无效: {}", e)),
+            },
+            Request::Subscribe => {
+                self.subscribers.push(reply.clone());
+                Reply::Done
+            }
+        };
+        self.effects.push(Effect::Reply(reply, answer));
+    }
+
+    /// Starts playback while stopped: the current track of the queue, the
+    /// first queued track when none is current yet, or else the selection.
+    fn remote_play(&mut self) {
+        if self.queue.current().is_none() && !self.queue.is_empty() {
+            self.queue.jump(0);
+        }
+        self.resume();
+    }
+
+    /// Adds the library tracks at or below each path to the queue.
+    fn enqueue(&mut self, paths: &[PathBuf]) -> Reply {
+        // Whole queues arrive as lists of files (see `App::attached`)
+        let by_path: HashMap<&Path, usize> = self.tracks.iter().enumerate().map(|(i, t)| (t.path.as_path(), i)).collect();
+        let mut added = Vec::new();
+        for path in paths {
+            if let Some(&index) = by_path.get(path.as_path()) {
+                added.push(index);
+                continue;
+            }
+            let before = added.len();
+            added.extend(self.tracks.iter().enumerate().filter(|(_, t)| t.path.starts_with(path)).map(|(i, _)| i));
+            if added.len() == before {
+                return Reply::Error(format!("不在音乐库中: {}", path.display()));
+            }
+        }
+        self.queue.append(&added);
+        Reply::Done
+    }
+
+    /// Returns what subscribers are told about, or `None` without subscribers.
+    pub(super) fn watched(&self) -> Option<Watched> {
+        (!self.subscribers.is_empty()).then(|| Watched {
+            status: self.status,
+            volume: self.volume_percent(),
+            queue: self.queue.tracks().to_vec(),
+            options: (self.queue.shuffle(), self.queue.repeat()),
+        })
+    }
+
+    /// Tells subscribers about a player event.
+    pub(super) fn publish_player_event(&mut self, event: &AppEvent) {
+        let event = match event {
+            AppEvent::TrackStarted { index, .. } => match self.tracks.get(*index) {
+                Some(track) => Event::TrackStarted(TrackInfo::from(track)),
+                None => return,
+            },
+            AppEvent::Progress { position } => Event::Progress(*position),
+            AppEvent::TrackEnded => Event::TrackEnded,
+            AppEvent::Error { message } => Event::Error(message.clone()),
+        };
+        self.publish(event);
+    }
+
+    /// Tells subscribers what changed since `before`.
+    pub(super) fn publish_changes(&mut self, before: Watched) {
+        let Some(after) = self.watched() else { return };
+        if after.status != before.status {
+            self.publish(Event::State(after.status));
+        }
+        if after.volume != before.volume {
+            self.publish(Event::Volume(after.volume));
+        }
+        if after.queue != before.queue {
+            self.publish(Event::Queue);
+        }
+        if after.options != before.options {
+            self.publish(Event::Options);
+        }
+    }
+
+    /// Tells subscribers about an event ([`Effect::Publish`]).
+    fn publish(&mut self, event: Event) {
+        self.effects.push(Effect::Publish(event));
+    }
+
+    /// Sends an event to every subscriber, forgetting those that hung up.
+    pub fn send_event(&mut self, event: &Event) {
+        self.subscribers.retain(|s| s.send(Reply::Event(event.clone())).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::app::{update, Msg};
+    use crate::command::Amount;
+    use crate::common::Track;
+
+    fn app() -> App {
+        let tracks = (0..4)
+            .map(|i| Track {
+                id: i,
+                path: PathBuf::from(format!("/music/{}/track{}.mp3", if i < 2 { "a" } else { "b" }, i)),
+                title: Some(format!("Track {}", i)),
+                ..Default::default()
+            })
+            .collect();
+        App::scratch(tracks)
+    }
+
+    /// Applies a message, delivering replies and events as the event loop
+    /// does, and returns the player commands.
+    fn run(app: &mut App, msg: Msg) -> Vec<AppCommand> {
+        update(app, msg)
+            .into_iter()
+            .filter_map(|effect| match effect {
+                Effect::Player(command) => Some(command),
+                Effect::Reply(reply, answer) => {
+                    reply.send(answer).ok();
+                    None
+                }
+                Effect::Publish(event) => {
+                    app.send_event(&event);
+                    None
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sends a request and returns its reply and the player commands.
+    fn request(app: &mut App, request: Request) -> (Reply, Vec<AppCommand>) {
+        let (tx, rx) = mpsc::channel();
+        let commands = run(app, Msg::Remote(request, tx));
+        (rx.recv().unwrap(), commands)
+    }
+
+    fn subscribe(app: &mut App) -> Receiver<Reply> {
+        let (tx, rx) = mpsc::channel();
+        run(app, Msg::Remote(Request::Subscribe, tx));
+        assert_eq!(rx.recv().unwrap(), Reply::Done);
+        rx
+    }
+
+    #[test]
+    fn test_transport_requests() {
+        let mut app = app();
+        let enqueue = Request::Enqueue(vec![PathBuf::from("/music/b"), PathBuf::from("/music/a/track0.mp3")]);
+        assert_eq!(request(&mut app, enqueue).0, Reply::Done);
+        assert_eq!(app.queue.tracks(), &[2, 3, 0]);
+
+        // Playback starts at the head of the queue, and toggling pauses and resumes it
+        let (reply, commands) = request(&mut app, Request::Toggle);
+        assert_eq!(reply, Reply::Done);
+        assert!(matches!(commands.as_slice(), [AppCommand::Play { index: 2, .. }]));
+        run(&mut app, Msg::Player(AppEvent::TrackStarted { index: 2, duration: Some(Duration::from_secs(100)) }));
+        assert!(matches!(request(&mut app, Request::Toggle).1.as_slice(), [AppCommand::TogglePlayPause]));
+        assert_eq!(app.status(), PlaybackStatus::Paused);
+        assert!(request(&mut app, Request::Pause).1.is_empty(), "already paused");
+        assert!(matches!(request(&mut app, Request::Play(None)).1.as_slice(), [AppCommand::TogglePlayPause]));
+        assert_eq!(app.status(), PlaybackStatus::Playing);
+
+        assert!(matches!(request(&mut app, Request::Next).1.as_slice(), [AppCommand::Play { index: 3, .. }]));
+        assert!(matches!(request(&mut app, Request::Play(Some(2))).1.as_slice(), [AppCommand::Play { index: 0, .. }]));
+        assert!(matches!(request(&mut app, Request::Play(Some(3))).0, Reply::Error(_)));
+
+        let (_, commands) = request(&mut app, Request::Volume(Amount::Down(25)));
+        assert!(matches!(commands.as_slice(), [AppCommand::SetVolume(v)] if (*v - 0.75).abs() < 1e-6));
+        let (_, commands) = request(&mut app, Request::Seek(Amount::To(Duration::from_secs(500))));
+        assert!(matches!(commands.as_slice(), [AppCommand::Seek(p)] if *p == Duration::from_secs(100)));
+
+        assert!(matches!(request(&mut app, Request::Enqueue(vec![PathBuf::from("/elsewhere")])).0, Reply::Error(_)));
+        assert_eq!(app.queue.tracks().len(), 3, "nothing is added when a path is unknown");
+        let (_, commands) = request(&mut app, Request::PlayFile(PathBuf::from("/music/b/track3.mp3")));
+        assert!(matches!(commands.as_slice(), [AppCommand::Play { index: 3, .. }]));
+        assert_eq!(app.queue.position(), Some(1));
+        let (_, commands) = request(&mut app, Request::PlayFile(PathBuf::from("/music/a/track1.mp3")));
+        assert!(matches!(commands.as_slice(), [AppCommand::Play { index: 1, .. }]));
+        assert_eq!((app.queue.tracks(), app.queue.position()), (&[2, 3, 0, 1][..], Some(3)));
+        assert!(matches!(request(&mut app, Request::PlayFile(PathBuf::from("/elsewhere"))).0, Reply::Error(_)));
+        request(&mut app, Request::Repeat(Some(RepeatMode::One)));
+        request(&mut app, Request::Shuffle(None));
+        assert_eq!((app.queue.shuffle(), app.queue.repeat()), (true, RepeatMode::One));
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut app = app();
+        let Reply::Status(status) = request(&mut app, Request::Status).0 else { panic!("expected a status") };
+        assert_eq!((status.state, status.track, status.volume, status.queue_length), (PlaybackStatus::Stopped, None, 100, 0));
+
+        request(&mut app, Request::Enqueue(vec![PathBuf::from("/music/a")]));
+        request(&mut app, Request::Play(Some(1)));
+        run(&mut app, Msg::Player(AppEvent::TrackStarted { index: 1, duration: None }));
+        run(&mut app, Msg::Player(AppEvent::Progress { position: Duration::from_secs(7) }));
+        let Reply::Status(status) = request(&mut app, Request::Status).0 else { panic!("expected a status") };
+        assert_eq!(status.track.map(|t| t.path), Some(PathBuf::from("/music/a/track1.mp3")));
+        assert_eq!((status.position, status.queue_position), (Duration::from_secs(7), Some(1)));
+
+        let Reply::Tracks(tracks) = request(&mut app, Request::Queue).0 else { panic!("expected tracks") };
+        let paths: Vec<&Path> = tracks.iter().map(|t| t.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/music/a/track0.mp3"), Path::new("/music/a/track1.mp3")]);
+
+        assert_eq!(request(&mut app, Request::ClearQueue).0, Reply::Done);
+        assert!(app.queue.is_empty());
+
+        let Reply::Tracks(found) = request(&mut app, Request::Search("path:/b/ OR title:\"track 0\"".to_string())).0 else {
+            panic!("expected tracks")
+        };
+        assert_eq!(found.iter().map(|t| t.title.as_deref().unwrap()).collect::<Vec<_>>(), ["Track 0", "Track 2", "Track 3"]);
+        assert!(matches!(request(&mut app, Request::Search(" ".to_string())).0, Reply::Tracks(all) if all.len() == 4));
+        assert!(matches!(request(&mut app, Request::Search("year:soon".to_string())).0, Reply::Error(_)));
+    }
+
+    #[test]
+    fn test_subscribers_hear_about_changes() {
+        let mut app = app();
+        let events = subscribe(&mut app);
+        let gone = subscribe(&mut app);
+        drop(gone);
+
+        request(&mut app, Request::Enqueue(vec![PathBuf::from("/music/a")]));
+        request(&mut app, Request::Toggle);
+        run(&mut app, Msg::Player(AppEvent::TrackStarted { index: 0, duration: None }));
+        run(&mut app, Msg::Player(AppEvent::Progress { position: Duration::from_secs(1) }));
+        request(&mut app, Request::Volume(Amount::To(50)));
+        request(&mut app, Request::Shuffle(Some(false)));
+        request(&mut app, Request::Repeat(Some(RepeatMode::All)));
+        run(&mut app, Msg::Player(AppEvent::Error { message: "无法解码".to_string() }));
+
+        let received: Vec<Event> = events
+            .try_iter()
+            .map(|reply| match reply {
+                Reply::Event(event) => event,
+                other => panic!("unexpected reply {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            received,
+            [
+                Event::Queue,
+                Event::TrackStarted(TrackInfo::from(&app.tracks[0])),
+                Event::State(PlaybackStatus::Playing),
+                Event::Progress(Duration::from_secs(1)),
+                Event::Volume(50),
+                Event::Options,
+                Event::Error("无法解码".to_string()),
+                Event::State(PlaybackStatus::Stopped),
+            ]
+        );
+        assert_eq!(app.subscribers.len(), 1, "closed channels are dropped");
+    }
+}
@@ -0,0 +1,266 @@
+//! Drawing of the application state.
+
+use ratatui::layout::Rect;
+use ratatui::widgets::Block;
+use ratatui::Frame;
+
+use super::{page_rows, scroll_to, App, Focus, PromptKind, SortKey, View};
+use crate::common::PlaybackStatus;
+use crate::ui::widgets::{
+    BookmarksWidget, HelpWidget, MessageLogWidget, NowPlayingWidget, PlaybackControlWidget, PlaylistSidebarWidget,
+    PromptWidget, StatusBarWidget, ToastWidget, TrackListWidget, VisualizationWidget,
+};
+
+/// Draws the whole interface for the application state.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to draw on; its size picks the layout
+/// * `app` - Application state
+pub fn render(frame: &mut Frame, app: &App) {
+    let size = frame.size();
+    let compact = size.width < app.config.ui.compact_width;
+
+    // Get layout (uses cache if terminal size unchanged)
+    let layout = app.layout(size);
+    
+    // Paint the theme's background behind all widgets
+    frame.render_widget(Block::default().style(app.theme.style_base()), size);
+    
+    // Render NowPlayingWidget to top area
+    let current_track = app.playing.and_then(|i| app.tracks.get(i));
+    let current_stats = app.playing.filter(|&i| i < app.tracks.len()).map(|i| app.stats(i)).unwrap_or_default();
+    let now_playing_widget = NowPlayingWidget::new(
+        current_track,
+        app.status,
+        &app.theme
+    )
+    .rating(current_stats.rating, current_stats.favorite);
+    frame.render_widget(now_playing_widget, layout.now_playing);
+    
+    // Render PlaylistSidebarWidget to the leftmost middle area (if shown)
+    if let Some(sidebar_area) = layout.sidebar {
+        let labels = app.sidebar_items();
+        let items: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let active = app.sidebar_active();
+        let sidebar_widget = PlaylistSidebarWidget::new(
+            &items,
+            app.sidebar_selected,
+            active,
+            app.focus == Focus::Sidebar,
+            &app.theme
+        );
+        frame.render_widget(sidebar_widget, sidebar_area);
+    }
+    
+    // Render TrackListWidget to middle-left area
+    let mut track_list_title = match &app.view {
+        View::Library => "Track List".to_string(),
+        View::Recent => "Recently Played".to_string(),
+        View::MostPlayed => "Most Played".to_string(),
+        View::Playlist(name) => format!("Playlist: {}", name),
+        View::Smart(name) => format!("Smart: {}", name),
+    };
+    if let Some(filter) = &app.filter {
+        track_list_title.push_str(&format!(" [/{}]", filter));
+    }
+    if app.sort != SortKey::Default {
+        let arrow = if app.sort_reversed { "↑" } else { "↓" };
+        track_list_title.push_str(&format!(" {}{}", arrow, app.sort.label()));
+    }
+    let offset = scroll_to(app.scroll, app.selected, page_rows(&layout, compact));
+    let stats: Vec<_> = (0..app.tracks.len()).map(|i| app.stats(i)).collect();
+    let track_list_widget = TrackListWidget::new(
+        &app.tracks,
+        app.selected,
+        app.playing
+    )
+    .rows(&app.rows)
+    .offset(offset)
+    .title(&track_list_title)
+    .theme(&app.theme);
+    let track_list_widget = if compact {
+        track_list_widget
+    } else {
+        track_list_widget.stats(&stats, app.clock.system_time())
+    };
+    frame.render_widget(track_list_widget, layout.track_list);
+    
+    // The bookmarks panel takes the visualization area, or the track
+    // list in compact mode
+    let bookmarks_area = app.bookmarks_visible.then_some(layout.visualization.unwrap_or(layout.track_list));
+    if let Some(area) = bookmarks_area {
+        let path = app.playing_path().unwrap_or_default();
+        let bookmarks_widget = BookmarksWidget::new(
+            app.bookmarks.bookmarks(&path),
+            app.bookmark_selected,
+            app.focus == Focus::Bookmarks,
+            &app.theme
+        );
+        frame.render_widget(bookmarks_widget, area);
+    } else if let Some(viz_area) = layout.visualization {
+        // Render VisualizationWidget to middle-right area (if not in compact mode)
+        let is_playing = app.status == PlaybackStatus::Playing;
+        let visualization_widget = VisualizationWidget::new(
+            &app.wave,
+            is_playing
+        )
+        .mode(app.visualization)
+        .theme(&app.theme);
+        frame.render_widget(visualization_widget, viz_area);
+    }
+    
+    // Render PlaybackControlWidget to bottom playback control area
+    let playback_control_widget = PlaybackControlWidget::new(
+        app.position,
+        app.total,
+        app.volume,
+        app.status
+    )
+    .modes(app.queue.shuffle(), app.queue.repeat())
+    .theme(&app.theme);
+    frame.render_widget(playback_control_widget, layout.playback_control);
+    
+    // Render the active prompt or the StatusBarWidget to the bottom-most status bar
+    if let Some(prompt) = &app.prompt {
+        let label = match prompt.kind {
+            PromptKind::NewPlaylist => "新建播放列表",
+            PromptKind::RenamePlaylist(_) => "重命名播放列表",
+            PromptKind::Search => "搜索",
+            PromptKind::SaveSmart => "保存智能播放列表",
+            PromptKind::RenameSmart(_) => "重命名智能播放列表",
+            PromptKind::Bookmark(_) => "书签名称",
+            PromptKind::Command => "命令",
+        };
+        let prompt_widget = PromptWidget::new(label, &prompt.input).theme(&app.theme);
+        frame.render_widget(prompt_widget, layout.status_bar);
+    } else {
+        let hints = app.keymap.status_hints();
+        let status_hints: Vec<(&str, &str)> = hints.iter().map(|(keys, label)| (keys.as_str(), *label)).collect();
+        let pending = app.pending_input();
+        let status_bar_widget = StatusBarWidget::new(&status_hints)
+            .mode(app.mode.label())
+            .pending(&pending)
+            .theme(&app.theme);
+        frame.render_widget(status_bar_widget, layout.status_bar);
+    }
+
+    // Toasts cover the middle of the screen
+    let toasts = app.notifications.toasts(app.clock.now());
+    if !toasts.is_empty() {
+        let area = Rect::new(0, layout.track_list.y, size.width, layout.track_list.height);
+        frame.render_widget(ToastWidget::new(&toasts).theme(&app.theme), area);
+    }
+
+    // The help popup and the message log cover everything else
+    if let Some(scroll) = app.help_scroll {
+        let sections = app.help_sections();
+        frame.render_widget(HelpWidget::new(&sections, scroll).theme(&app.theme), size);
+    }
+    if let Some(scroll) = app.log_scroll {
+        let log_widget = MessageLogWidget::new(app.notifications.log(), scroll, app.clock.system_time()).theme(&app.theme);
+        frame.render_widget(log_widget, size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use crate::app::{update, Msg};
+    use crate::common::Track;
+
+    fn track(id: u64, title: &str, secs: u64) -> Track {
+        Track {
+            id,
+            path: PathBuf::from(format!("/test/track{}.mp3", id + 1)),
+            duration: Some(Duration::from_secs(secs)),
+            title: Some(title.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Draws the application on a terminal of the given size.
+    fn draw(app: &mut App, width: u16, height: u16) -> String {
+        update(app, Msg::Resize(width, height));
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| render(f, app)).unwrap();
+        buffer_to_string(terminal.backend().buffer())
+    }
+
+    /// Integration test: Complete UI rendering from App state
+    /// Tests the full rendering pipeline from App state to complete interface
+    #[test]
+    fn test_complete_ui_rendering() {
+        let tracks = vec![track(0, "Test Track 1", 180), track(1, "Test Track 2", 240), track(2, "Test Track 3", 200)];
+        let mut app = App::scratch(tracks);
+        app.playing = Some(1);
+        app.status = PlaybackStatus::Playing;
+        app.position = Duration::from_secs(60);
+        app.total = Some(Duration::from_secs(240));
+        app.volume = 0.75;
+        app.selected = 1;
+        app.wave = vec![10, 20, 30, 40, 50, 60, 70, 80];
+
+        let rendered = draw(&mut app, 100, 30);
+
+        assert!(rendered.contains("▶"), "Now Playing area should contain play icon when playing");
+        for title in ["Test Track 1", "Test Track 2", "Test Track 3"] {
+            assert!(rendered.contains(title), "Track list should contain {}", title);
+        }
+        assert!(rendered.contains("01:00"), "Progress bar should show current time");
+        assert!(rendered.contains("04:00"), "Progress bar should show total time");
+        assert!(rendered.contains("75%"), "Volume indicator should be displayed");
+        assert!(!app.compact_mode);
+        assert!(app.layout(Rect::new(0, 0, 100, 30)).visualization.is_some());
+    }
+
+    /// Integration test: Compact mode rendering
+    /// Tests that UI adapts correctly to narrow terminal width
+    #[test]
+    fn test_compact_mode_rendering() {
+        let mut app = App::scratch(vec![track(0, "Track 1", 180)]);
+        app.playing = Some(0);
+        app.status = PlaybackStatus::Playing;
+
+        let rendered = draw(&mut app, 60, 25);
+
+        assert!(app.compact_mode, "Compact mode should be active for width < 80");
+        assert!(app.layout(Rect::new(0, 0, 60, 25)).visualization.is_none(), "Visualization should be hidden in compact mode");
+        assert!(rendered.contains("Track 1"), "Track list should be visible in compact mode");
+        assert!(rendered.contains("▶"), "Play icon should be visible in compact mode");
+    }
+
+    /// Integration test: Empty track list rendering
+    /// Tests that UI handles empty state gracefully
+    #[test]
+    fn test_empty_track_list_rendering() {
+        let mut app = App::scratch(Vec::new());
+
+        let rendered = draw(&mut app, 80, 25);
+
+        assert!(
+            rendered.contains("Welcome") || rendered.contains("Music Player"),
+            "Should show welcome message when no track is playing"
+        );
+        // Chinese characters are rendered with a trailing blank cell
+        assert!(rendered.contains("未") || rendered.contains("找"), "Should show empty track list message");
+    }
+
+    /// Helper function to convert buffer to string for assertions
+    fn buffer_to_string(buffer: &ratatui::buffer::Buffer) -> String {
+        let mut result = String::new();
+        for y in 0..buffer.area.height {
+            for x in 0..buffer.area.width {
+                result.push_str(buffer.get(x, y).symbol());
+            }
+            result.push('\n');
+        }
+        result
+    }
+}
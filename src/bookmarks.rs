@@ -0,0 +1,274 @@
+//! Resume positions and named bookmarks for long-form audio.
+//!
+//! Audiobooks and podcasts are rarely heard in one sitting, so the player
+//! keeps two kinds of positions per file:
+//! - An automatic resume position, remembered for tracks that qualify under
+//!   [`ResumePolicy`] and cleared once the track has been played to its end
+//! - Named bookmarks set by the user, which can be jumped to at any time
+//!
+//! Both are stored in `bookmarks.tsv` in the data directory, one per line:
+//! `resume`, path and seconds, or `mark`, path, seconds and name (tab-separated,
+//! escaped like the library database).
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::library::{escape, unescape};
+
+/// File name of the bookmark table
+const BOOKMARKS_FILE: &str = "bookmarks.tsv";
+
+/// Positions closer than this to the start are not worth resuming from.
+const MIN_RESUME_POSITION: Duration = Duration::from_secs(10);
+
+/// Errors that can occur while loading or saving bookmarks.
+#[derive(Debug, Error)]
+pub enum BookmarkError {
+    /// Reading or writing the bookmark file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The bookmark file contains a malformed line
+    #[error("{BOOKMARKS_FILE}:{0}: malformed entry")]
+    Parse(usize),
+    /// A bookmark name was empty
+    #[error("bookmark name must not be empty")]
+    EmptyName,
+}
+
+/// Which tracks get an automatic resume position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResumePolicy {
+    /// Tracks at least this long qualify
+    pub min_duration: Duration,
+    /// Tracks under any of these directories qualify regardless of length
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Default for ResumePolicy {
+    fn default() -> Self {
+        Self { min_duration: Duration::from_secs(20 * 60), dirs: Vec::new() }
+    }
+}
+
+impl ResumePolicy {
+    /// Returns `true` if a track should resume where it was left.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the track
+    /// * `duration` - Total duration, if known
+    pub fn applies(&self, path: &Path, duration: Option<Duration>) -> bool {
+        duration.is_some_and(|d| d >= self.min_duration) || self.dirs.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+/// A named position within a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bookmark {
+    /// User-given name
+    pub name: String,
+    /// Position within the file
+    pub position: Duration,
+}
+
+/// Persistent resume positions and named bookmarks.
+#[derive(Debug)]
+pub struct BookmarkStore {
+    dir: PathBuf,
+    resume: HashMap<PathBuf, Duration>,
+    marks: BTreeMap<PathBuf, Vec<Bookmark>>,
+    /// Whether changes are kept in memory only
+    read_only: bool,
+}
+
+impl BookmarkStore {
+    /// Creates an empty store for `dir` without reading it.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), resume: HashMap::new(), marks: BTreeMap::new(), read_only: false }
+    }
+
+    /// Stops writing to the directory: from now on, changes are kept in
+    /// memory only.
+    pub fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    /// Opens the store in `dir`; a missing file yields an empty store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or has a malformed line.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, BookmarkError> {
+        let mut store = Self::new(dir);
+        let content = match fs::read_to_string(store.dir.join(BOOKMARKS_FILE)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(e.into()),
+        };
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let malformed = || BookmarkError::Parse(i + 1);
+            let position = fields
+                .get(2)
+                .and_then(|s| s.parse::<f64>().ok())
+                .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                .ok_or_else(malformed)?;
+            let path = PathBuf::from(unescape(fields[1]));
+            match (fields[0], fields.get(3)) {
+                ("resume", None) => {
+                    store.resume.insert(path, position);
+                }
+                ("mark", Some(name)) => {
+                    store.marks.entry(path).or_default().push(Bookmark { name: unescape(name), position });
+                }
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(store)
+    }
+
+    /// Returns the position to resume a track from, if one was saved.
+    pub fn resume_position(&self, path: &Path) -> Option<Duration> {
+        self.resume.get(path).copied()
+    }
+
+    /// Remembers where a track was left; positions near the start clear it.
+    pub fn set_resume(&mut self, path: &Path, position: Duration) {
+        if position < MIN_RESUME_POSITION {
+            self.resume.remove(path);
+        } else {
+            self.resume.insert(path.to_path_buf(), position);
+        }
+    }
+
+    /// Forgets the resume position of a track (e.g. after it was finished).
+    pub fn clear_resume(&mut self, path: &Path) {
+        self.resume.remove(path);
+    }
+
+    /// Returns the named bookmarks of a file, ordered by position.
+    pub fn bookmarks(&self, path: &Path) -> &[Bookmark] {
+        self.marks.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds a named bookmark; [`BookmarkStore::save`] writes it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is empty.
+    pub fn add(&mut self, path: &Path, name: &str, position: Duration) -> Result<(), BookmarkError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(BookmarkError::EmptyName);
+        }
+        let marks = self.marks.entry(path.to_path_buf()).or_default();
+        let at = marks.partition_point(|m| m.position <= position);
+        marks.insert(at, Bookmark { name: name.to_string(), position });
+        Ok(())
+    }
+
+    /// Removes the bookmark at `index` of a file; [`BookmarkStore::save`]
+    /// writes the change.
+    ///
+    /// Out-of-range indices are ignored.
+    pub fn remove(&mut self, path: &Path, index: usize) {
+        if let Some(marks) = self.marks.get_mut(path) {
+            if index < marks.len() {
+                marks.remove(index);
+            }
+            if marks.is_empty() {
+                self.marks.remove(path);
+            }
+        }
+    }
+
+    /// Writes the store, replacing the previous file atomically (unless the
+    /// store is read-only).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self) -> Result<(), BookmarkError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut out = String::from("# kind\tpath\tseconds\tname\n");
+        let mut resume: Vec<_> = self.resume.iter().collect();
+        resume.sort();
+        for (path, position) in resume {
+            out.push_str(&format!("resume\t{}\t{}\n", escape(&path.to_string_lossy()), position.as_secs_f64()));
+        }
+        for (path, marks) in &self.marks {
+            for mark in marks {
+                out.push_str(&format!(
+                    "mark\t{}\t{}\t{}\n",
+                    escape(&path.to_string_lossy()),
+                    mark.position.as_secs_f64(),
+                    escape(&mark.name),
+                ));
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        let tmp = self.dir.join(format!("{}.tmp", BOOKMARKS_FILE));
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, self.dir.join(BOOKMARKS_FILE))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    #[test]
+    fn test_policy_by_duration_and_dir() {
+        let policy = ResumePolicy { min_duration: Duration::from_secs(600), dirs: vec![PathBuf::from("/pods")] };
+        assert!(policy.applies(Path::new("/music/book.mp3"), Some(Duration::from_secs(3600))));
+        assert!(!policy.applies(Path::new("/music/song.mp3"), Some(Duration::from_secs(200))));
+        assert!(policy.applies(Path::new("/pods/ep1.mp3"), None));
+        assert!(!policy.applies(Path::new("/podsx/ep1.mp3"), None));
+    }
+
+    #[test]
+    fn test_bookmarks_sorted_and_persisted() {
+        let dir = TempDir::new("bookmarks");
+        let book = Path::new("/books/long\tname.mp3");
+
+        let mut store = BookmarkStore::new(dir.path());
+        store.add(book, "Chapter 2", Duration::from_secs(1800)).unwrap();
+        store.add(book, "Chapter 1", Duration::from_secs(60)).unwrap();
+        assert!(matches!(store.add(book, "  ", Duration::ZERO), Err(BookmarkError::EmptyName)));
+        store.set_resume(book, Duration::from_millis(2_000_500));
+        store.set_resume(Path::new("/short.mp3"), Duration::from_secs(3));
+        store.save().unwrap();
+
+        let mut store = BookmarkStore::open(dir.path()).unwrap();
+        let names: Vec<&str> = store.bookmarks(book).iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Chapter 1", "Chapter 2"]);
+        assert_eq!(store.resume_position(book), Some(Duration::from_millis(2_000_500)));
+        assert_eq!(store.resume_position(Path::new("/short.mp3")), None);
+
+        store.remove(book, 0);
+        store.clear_resume(book);
+        store.save().unwrap();
+        let store = BookmarkStore::open(dir.path()).unwrap();
+        assert_eq!(store.bookmarks(book).len(), 1);
+        assert_eq!(store.resume_position(book), None);
+    }
+
+    #[test]
+    fn test_open_rejects_malformed_line() {
+        let dir = TempDir::new("bookmarks");
+        fs::write(dir.join(BOOKMARKS_FILE), "mark\t/a.mp3\t12\n").unwrap();
+        assert!(matches!(BookmarkStore::open(dir.path()), Err(BookmarkError::Parse(1))));
+    }
+}
@@ -0,0 +1,102 @@
+//! Time as seen by the application.
+//!
+//! The application never asks the system for the time directly: it reads a
+//! [`Clock`], which is the [`SystemClock`] when running and a [`ManualClock`]
+//! in tests, where time only passes when the test says so.
+//!
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use tools_rs::clock::{Clock, ManualClock};
+//!
+//! let clock = ManualClock::new(UNIX_EPOCH);
+//! let start = clock.now();
+//! clock.advance(Duration::from_secs(3));
+//! assert_eq!(clock.now() - start, Duration::from_secs(3));
+//! assert_eq!(clock.system_time(), UNIX_EPOCH + Duration::from_secs(3));
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the current monotonic time, for timeouts and durations.
+    fn now(&self) -> Instant;
+
+    /// Returns the current wall-clock time, for timestamps shown or saved.
+    fn system_time(&self) -> SystemTime;
+}
+
+/// The system's clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when advanced.
+///
+/// Clones share the same time, so a test can keep one and hand another to
+/// the application.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    start: Instant,
+    epoch: SystemTime,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    /// Creates a clock standing at a wall-clock time.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - Wall-clock time the clock starts at
+    pub fn new(epoch: SystemTime) -> Self {
+        Self { start: Instant::now(), epoch, elapsed: Arc::new(Mutex::new(Duration::ZERO)) }
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Returns how far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.epoch + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_manual_clock_clones_share_time() {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let shared: Arc<dyn Clock> = Arc::new(clock.clone());
+        let start = shared.now();
+        clock.advance(Duration::from_millis(1500));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(shared.now() - start, Duration::from_secs(2));
+        assert_eq!(shared.system_time(), UNIX_EPOCH + Duration::from_secs(2));
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+    }
+}
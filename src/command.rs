@@ -0,0 +1,467 @@
+//! Ex-style command line (`:play 12`, `:vol 80`, `:seek +30`).
+//!
+//! Commands are typed after `:` and parsed into a [`Command`], which the
+//! player carries out through the same state changes and player commands as
+//! the keys. [`complete`] offers tab completion of command names and
+//! arguments, and [`CommandHistory`] recalls earlier command lines.
+//!
+//! | Command | Effect |
+//! |---|---|
+//! | `play [N]` | Play row `N` of the track list, or resume playback |
+//! | `pause` | Pause or resume playback |
+//! | `next [N]`, `prev [N]` | Skip `N` tracks forward/back |
+//! | `vol N`, `vol +N`, `vol -N` | Set or change the volume in percent (0-200) |
+//! | `seek POS`, `seek +S`, `seek -S` | Seek to a position or by an offset (`90`, `1:30`) |
+//! | `theme NAME` | Switch the color theme |
+//! | `save playlist NAME` | Save the track list as a named playlist |
+//! | `save smart NAME` | Save the filter as a smart playlist |
+//! | `import PATH` | Import a playlist file (`.m3u`, `.m3u8`, `.pls`, `.xspf`) as a named playlist |
+//! | `export PATH` | Write the track list to a playlist file, in the format of its extension |
+//! | `filter [QUERY]` | Filter the track list (no query clears the filter) |
+//! | `shuffle [on\|off]` | Set or toggle shuffle |
+//! | `repeat [off\|all\|one]` | Set or cycle the repeat mode |
+//! | `quit` | Quit the player |
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::queue::RepeatMode;
+
+/// Command names, their arguments and descriptions, for completion and help.
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    ("play", "[N]", "播放第 N 行，或继续播放"),
+    ("pause", "", "暂停/继续播放"),
+    ("next", "[N]", "向后跳过 N 首"),
+    ("prev", "[N]", "向前跳过 N 首"),
+    ("vol", "N|+N|-N", "设置或调整音量（百分比）"),
+    ("seek", "POS|+S|-S", "跳到指定位置或前后移动"),
+    ("theme", "NAME", "切换主题"),
+    ("save", "playlist|smart NAME", "保存为播放列表/智能播放列表"),
+    ("import", "PATH", "导入播放列表文件"),
+    ("export", "PATH", "将曲目列表导出为 .m3u8/.pls/.xspf 等文件"),
+    ("filter", "[QUERY]", "过滤曲目列表"),
+    ("shuffle", "[on|off]", "随机播放"),
+    ("repeat", "[off|all|one]", "循环模式"),
+    ("quit", "", "退出"),
+];
+
+/// Maximum number of command lines kept in the history
+const HISTORY_LIMIT: usize = 100;
+
+/// Errors that can occur while parsing a command line.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CommandError {
+    /// The command line is blank
+    #[error("empty command")]
+    Empty,
+    /// No command has this name
+    #[error("unknown command ':{0}'")]
+    Unknown(String),
+    /// An argument is missing, extra or malformed
+    #[error(":{command}: expected {expected}")]
+    Usage {
+        /// Name of the command
+        command: &'static str,
+        /// The arguments the command takes
+        expected: &'static str,
+    },
+}
+
+/// A value that is set or changed by an amount (`80`, `+10`, `-10`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Amount<T> {
+    /// Set to this value
+    To(T),
+    /// Increase by this amount
+    Up(T),
+    /// Decrease by this amount
+    Down(T),
+}
+
+impl Amount<u32> {
+    /// Applies the amount to a current value, saturating at zero.
+    pub fn apply(self, current: u32) -> u32 {
+        match self {
+            Self::To(value) => value,
+            Self::Up(delta) => current.saturating_add(delta),
+            Self::Down(delta) => current.saturating_sub(delta),
+        }
+    }
+}
+
+impl Amount<Duration> {
+    /// Applies the amount to a current position, saturating at zero and at
+    /// the largest duration.
+    pub fn apply(self, current: Duration) -> Duration {
+        match self {
+            Self::To(position) => position,
+            Self::Up(delta) => current.saturating_add(delta),
+            Self::Down(delta) => current.saturating_sub(delta),
+        }
+    }
+}
+
+/// A parsed command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Play a row of the track list (1-based), or resume playback
+    Play(Option<usize>),
+    /// Pause or resume playback
+    Pause,
+    /// Skip tracks forward
+    Next(usize),
+    /// Skip tracks back
+    Prev(usize),
+    /// Set or change the volume, in percent
+    Volume(Amount<u32>),
+    /// Seek to a position or by an offset
+    Seek(Amount<Duration>),
+    /// Switch the color theme
+    Theme(String),
+    /// Save the track list as a named playlist
+    SavePlaylist(String),
+    /// Save the filter as a smart playlist
+    SaveSmart(String),
+    /// Import a playlist file into the named playlists
+    Import(PathBuf),
+    /// Write the track list to a playlist file
+    Export(PathBuf),
+    /// Filter the track list; an empty query clears the filter
+    Filter(String),
+    /// Set shuffle, or toggle it
+    Shuffle(Option<bool>),
+    /// Set the repeat mode, or cycle it
+    Repeat(Option<RepeatMode>),
+    /// Quit the player
+    Quit,
+}
+
+/// Parses a command line (without the leading `:`).
+///
+/// Command names may be abbreviated to any unambiguous prefix (`:q`, `:th`).
+///
+/// # Errors
+///
+/// Returns an error if the command is unknown or its arguments are invalid.
+pub fn parse(input: &str) -> Result<Command, CommandError> {
+    let input = input.trim().trim_start_matches(':').trim_start();
+    let (word, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let rest = rest.trim();
+    if word.is_empty() {
+        return Err(CommandError::Empty);
+    }
+    let (name, expected, _) = lookup(word).ok_or_else(|| CommandError::Unknown(word.to_string()))?;
+    let usage = || CommandError::Usage { command: name, expected };
+    let args: Vec<&str> = rest.split_whitespace().collect();
+    let command = match (name, args.as_slice()) {
+        ("play", []) => Command::Play(None),
+        ("play", [row]) => Command::Play(Some(row.parse().ok().filter(|&n| n > 0).ok_or_else(usage)?)),
+        ("pause", []) => Command::Pause,
+        ("next" | "prev", []) => if name == "next" { Command::Next(1) } else { Command::Prev(1) },
+        ("next" | "prev", [n]) => {
+            let n = n.parse().ok().filter(|&n| n > 0).ok_or_else(usage)?;
+            if name == "next" { Command::Next(n) } else { Command::Prev(n) }
+        }
+        ("vol", [amount]) => Command::Volume(parse_amount(amount, |s| s.parse().ok()).ok_or_else(usage)?),
+        ("seek", [amount]) => Command::Seek(parse_amount(amount, parse_position).ok_or_else(usage)?),
+        ("theme", [name]) => Command::Theme(name.to_string()),
+        ("save", [kind, ..]) if args.len() > 1 => {
+            let name = rest[kind.len()..].trim().to_string();
+            match *kind {
+                "playlist" => Command::SavePlaylist(name),
+                "smart" => Command::SaveSmart(name),
+                _ => return Err(usage()),
+            }
+        }
+        // The path is the rest of the line, spaces included
+        ("import", [_, ..]) => Command::Import(PathBuf::from(rest)),
+        ("export", [_, ..]) => Command::Export(PathBuf::from(rest)),
+        ("filter", _) => Command::Filter(rest.to_string()),
+        ("shuffle", []) => Command::Shuffle(None),
+        ("shuffle", ["on"]) => Command::Shuffle(Some(true)),
+        ("shuffle", ["off"]) => Command::Shuffle(Some(false)),
+        ("repeat", []) => Command::Repeat(None),
+        ("repeat", [mode]) => Command::Repeat(Some(mode.parse().map_err(|_| usage())?)),
+        ("quit", []) => Command::Quit,
+        _ => return Err(usage()),
+    };
+    Ok(command)
+}
+
+/// Returns the help lines: each command with its arguments, and its description.
+pub fn help() -> Vec<(String, &'static str)> {
+    COMMANDS
+        .iter()
+        .map(|(name, args, description)| (format!(":{} {}", name, args).trim_end().to_string(), *description))
+        .collect()
+}
+
+/// Finds the command a name or unambiguous prefix stands for.
+fn lookup(word: &str) -> Option<(&'static str, &'static str, &'static str)> {
+    if let Some(&command) = COMMANDS.iter().find(|(name, _, _)| *name == word) {
+        return Some(command);
+    }
+    let mut matches = COMMANDS.iter().filter(|(name, _, _)| name.starts_with(word));
+    let first = matches.next()?;
+    matches.next().is_none().then_some(*first)
+}
+
+/// Parses `N`, `+N` or `-N` with a parser for the value.
+pub fn parse_amount<T>(raw: &str, value: impl Fn(&str) -> Option<T>) -> Option<Amount<T>> {
+    if let Some(delta) = raw.strip_prefix('+') {
+        value(delta).map(Amount::Up)
+    } else if let Some(delta) = raw.strip_prefix('-') {
+        value(delta).map(Amount::Down)
+    } else {
+        value(raw).map(Amount::To)
+    }
+}
+
+/// Parses a position given as seconds, `m:ss` or `h:mm:ss`.
+pub fn parse_position(raw: &str) -> Option<Duration> {
+    let mut secs = 0u64;
+    for (i, part) in raw.split(':').enumerate() {
+        if i > 2 || part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        secs = secs.checked_mul(60)?.checked_add(part.parse().ok()?)?;
+    }
+    Some(Duration::from_secs(secs))
+}
+
+/// Completes the last word of a command line.
+///
+/// The first word completes to command names; later words to the fixed
+/// arguments of the command and to the names returned by `arguments`, which
+/// is given the command name and the position of the word (1 for the first
+/// argument).
+///
+/// # Returns
+///
+/// Returns the completed command lines in order, or nothing if no candidate
+/// starts with the word.
+pub fn complete(input: &str, arguments: impl Fn(&str, usize) -> Vec<String>) -> Vec<String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let (done, word) = match words.split_last() {
+        Some((last, done)) if !input.ends_with(char::is_whitespace) => (done, *last),
+        _ => (words.as_slice(), ""),
+    };
+    let candidates: Vec<String> = match done.first() {
+        None => COMMANDS.iter().map(|(name, _, _)| name.to_string()).collect(),
+        Some(first) => {
+            let Some((name, _, _)) = lookup(first) else { return Vec::new() };
+            let fixed: &[&str] = match (name, done.len()) {
+                ("save", 1) => &["playlist", "smart"],
+                ("shuffle", 1) => &["on", "off"],
+                ("repeat", 1) => &["off", "all", "one"],
+                _ => &[],
+            };
+            let mut candidates: Vec<String> = fixed.iter().map(|s| s.to_string()).collect();
+            candidates.extend(arguments(name, done.len()));
+            candidates
+        }
+    };
+    let prefix = &input[..input.len() - word.len()];
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(|candidate| format!("{}{}", prefix, candidate))
+        .collect()
+}
+
+/// Earlier command lines, recalled with the arrow keys.
+#[derive(Clone, Debug, Default)]
+pub struct CommandHistory {
+    entries: Vec<String>,
+    /// Entry being shown while browsing, counted from the oldest
+    cursor: Option<usize>,
+    /// Input typed before browsing started
+    draft: String,
+}
+
+impl CommandHistory {
+    /// Creates an empty history.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the entries, oldest first.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Records a command line and ends browsing.
+    ///
+    /// Blank lines and repeats of the previous line are not recorded.
+    pub fn push(&mut self, line: &str) {
+        self.cursor = None;
+        let line = line.trim();
+        if line.is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_LIMIT {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Steps back to the previous entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - Input shown now, restored when stepping past the newest entry
+    ///
+    /// # Returns
+    ///
+    /// Returns the entry to show, or `None` at the oldest entry.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let cursor = match self.cursor {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(cursor) => cursor - 1,
+        };
+        self.cursor = Some(cursor);
+        Some(&self.entries[cursor])
+    }
+
+    /// Steps forward to the next entry, or back to the draft after the newest.
+    ///
+    /// # Returns
+    ///
+    /// Returns the input to show, or `None` when not browsing.
+    pub fn newer(&mut self) -> Option<&str> {
+        let cursor = self.cursor?;
+        if cursor + 1 < self.entries.len() {
+            self.cursor = Some(cursor + 1);
+            Some(&self.entries[cursor + 1])
+        } else {
+            self.cursor = None;
+            Some(&self.draft)
+        }
+    }
+
+    /// Ends browsing without recording anything.
+    pub fn reset(&mut self) {
+        self.cursor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse("play 12"), Ok(Command::Play(Some(12))));
+        assert_eq!(parse(":play"), Ok(Command::Play(None)));
+        assert_eq!(parse("vol 80"), Ok(Command::Volume(Amount::To(80))));
+        assert_eq!(parse("vol -5"), Ok(Command::Volume(Amount::Down(5))));
+        assert_eq!(parse("seek +30"), Ok(Command::Seek(Amount::Up(Duration::from_secs(30)))));
+        assert_eq!(parse("seek 1:02:03"), Ok(Command::Seek(Amount::To(Duration::from_secs(3723)))));
+        assert_eq!(parse("theme nord"), Ok(Command::Theme("nord".to_string())));
+        assert_eq!(parse("save playlist road trip"), Ok(Command::SavePlaylist("road trip".to_string())));
+        assert_eq!(parse("save smart jazz"), Ok(Command::SaveSmart("jazz".to_string())));
+        assert_eq!(parse("import ~/lists/road trip.m3u"), Ok(Command::Import(PathBuf::from("~/lists/road trip.m3u"))));
+        assert_eq!(parse("exp /tmp/mix.xspf"), Ok(Command::Export(PathBuf::from("/tmp/mix.xspf"))));
+        assert_eq!(parse("filter artist:Miles year:>1960"), Ok(Command::Filter("artist:Miles year:>1960".to_string())));
+        assert_eq!(parse("filter"), Ok(Command::Filter(String::new())));
+        assert_eq!(parse("shuffle off"), Ok(Command::Shuffle(Some(false))));
+        assert_eq!(parse("repeat one"), Ok(Command::Repeat(Some(RepeatMode::One))));
+        assert_eq!(parse("next 3"), Ok(Command::Next(3)));
+        assert_eq!(parse("q"), Ok(Command::Quit));
+        assert_eq!(parse("th gruvbox"), Ok(Command::Theme("gruvbox".to_string())));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("  "), Err(CommandError::Empty));
+        assert_eq!(parse("fly"), Err(CommandError::Unknown("fly".to_string())));
+        // "p" could be play, pause or prev
+        assert_eq!(parse("p"), Err(CommandError::Unknown("p".to_string())));
+        assert_eq!(parse("play 0").unwrap_err().to_string(), ":play: expected [N]");
+        assert!(parse("vol loud").is_err());
+        assert!(parse("seek 1:xx").is_err());
+        assert!(parse("save playlist").is_err());
+        assert!(parse("save mix x").is_err());
+        assert_eq!(parse("import").unwrap_err().to_string(), ":import: expected PATH");
+        assert!(parse("quit now").is_err());
+    }
+
+    #[test]
+    fn test_amounts_apply() {
+        assert_eq!(Amount::Down(30).apply(20u32), 0);
+        assert_eq!(Amount::Up(Duration::from_secs(30)).apply(Duration::from_secs(5)), Duration::from_secs(35));
+        assert_eq!(Amount::To(Duration::ZERO).apply(Duration::from_secs(5)), Duration::ZERO);
+        let Ok(Command::Seek(far)) = parse("seek +18446744073709551615") else { panic!("not a seek") };
+        assert_eq!(far.apply(Duration::from_secs(5)), Duration::MAX);
+    }
+
+    #[test]
+    fn test_complete() {
+        let themes = |command: &str, position: usize| match (command, position) {
+            ("theme", 1) => vec!["nord".to_string(), "gruvbox".to_string()],
+            _ => Vec::new(),
+        };
+        assert_eq!(complete("th", themes), vec!["theme"]);
+        assert_eq!(complete("p", themes), vec!["play", "pause", "prev"]);
+        assert_eq!(complete("theme ", themes), vec!["theme nord", "theme gruvbox"]);
+        assert_eq!(complete("theme g", themes), vec!["theme gruvbox"]);
+        assert_eq!(complete("save pl", themes), vec!["save playlist"]);
+        assert_eq!(complete("repeat ", themes), vec!["repeat off", "repeat all", "repeat one"]);
+        assert!(complete("fly ", themes).is_empty());
+        assert!(complete("theme x", themes).is_empty());
+    }
+
+    #[test]
+    fn test_help_lists_every_command() {
+        let help = help();
+        assert_eq!(help.len(), COMMANDS.len());
+        assert_eq!(help[0], (":play [N]".to_string(), "播放第 N 行，或继续播放"));
+        assert!(help.iter().any(|(usage, _)| usage == ":quit"));
+    }
+
+    #[test]
+    fn test_history_browsing() {
+        let mut history = CommandHistory::new();
+        assert_eq!(history.older("x"), None);
+        history.push("vol 80");
+        history.push("play 3");
+        history.push("play 3");
+        history.push(" ");
+        assert_eq!(history.entries(), ["vol 80", "play 3"]);
+
+        assert_eq!(history.older("th"), Some("play 3"));
+        assert_eq!(history.older("ignored"), Some("vol 80"));
+        assert_eq!(history.older("ignored"), None);
+        assert_eq!(history.newer(), Some("play 3"));
+        assert_eq!(history.newer(), Some("th"));
+        assert_eq!(history.newer(), None);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_history_is_bounded(lines in proptest::collection::vec("[a-z]{1,4}", 0..300)) {
+            let mut history = CommandHistory::new();
+            for line in &lines {
+                history.push(line);
+            }
+            prop_assert!(history.entries().len() <= HISTORY_LIMIT);
+            if let Some(last) = lines.last() {
+                prop_assert_eq!(history.entries().last(), Some(last));
+            }
+        }
+
+        #[test]
+        fn prop_seek_position_roundtrip(secs in 0u64..360_000) {
+            let text = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+            prop_assert_eq!(parse_position(&text), Some(Duration::from_secs(secs)));
+        }
+    }
+}
@@ -7,7 +7,7 @@ use std::path::PathBuf;
 use std::time::Duration;
 
 /// Represents a music track with metadata.
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Track {
     /// Unique identifier for the track
     #[allow(dead_code)]
@@ -19,6 +19,17 @@ pub struct Track {
     pub duration: Option<Duration>,
     /// Display title for the track (if available, otherwise use filename)
     pub title: Option<String>,
+    /// Performing artist from the file's tags (if available)
+    pub artist: Option<String>,
+    /// Album name from the file's tags (if available)
+    pub album: Option<String>,
+    /// Genre from the file's tags (if available)
+    pub genre: Option<String>,
+    /// Release year from the file's tags (if available)
+    pub year: Option<u32>,
+    /// Star rating (1-5) from the file's tags (if available); the player's
+    /// own rating takes over once the track is in the library database
+    pub rating: Option<u8>,
 }
 
 /// Represents the current playback state.
@@ -34,6 +45,7 @@ pub enum PlaybackStatus {
 }
 
 /// Commands sent from the UI to the player thread.
+#[derive(Debug)]
 pub enum AppCommand {
     /// Start playing a specific track
     Play {
@@ -46,9 +58,14 @@ pub enum AppCommand {
     TogglePlayPause,
     /// Set the playback volume (0.0 to 2.0, where 1.0 is 100%)
     SetVolume(f32),
+    /// Jump to a position within the current track
+    Seek(Duration),
+    /// Stop playback and end the player thread
+    Shutdown,
 }
 
 /// Events sent from the player thread to the UI.
+#[derive(Debug)]
 pub enum AppEvent {
     /// A track has started playing
     TrackStarted {
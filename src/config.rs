@@ -0,0 +1,594 @@
+//! User configuration loaded from `config.toml`.
+//!
+//! The file lives in the config directory (see [`default_path`]) and is
+//! entirely optional: missing sections and keys keep their defaults. Unknown
+//! keys and invalid values are rejected with an error naming the offending
+//! key, so typos do not go unnoticed.
+//!
+//! ```toml
+//! [library]
+//! roots = ["~/Music"]
+//! extensions = ["mp3", "flac", "ogg", "wav"]
+//!
+//! [player]
+//! volume_step = 0.05
+//! seek_step = 10            # seconds
+//! tick_rate = 200           # milliseconds between position updates
+//! resume_min_minutes = 20
+//! resume_dirs = ["~/Podcasts"]
+//! write_tags = false
+//! pause_on_suspend = false # pause while suspended with Ctrl-Z
+//!
+//! [ui]
+//! compact_width = 80
+//! sidebar = false
+//! toast_timeout = 4         # seconds
+//! visualization_fps = 30    # frames per second while playing
+//! theme = "nord"            # built-in or themes/<name>.toml
+//!
+//! [ui.colors]               # overrides on top of the theme
+//! primary = "cyan"
+//! highlight = "#ffd700"
+//!
+//! [session]
+//! resume_playback = false   # keep playing where the last run stopped
+//!
+//! [remote]
+//! mpd_port = 6600           # MPD protocol on localhost (off when unset)
+//! # HTTP API and web remote (off when unset); to reach it from a phone,
+//! # listen on 0.0.0.0 and set a token, then open /?token=<token> once
+//! http_address = "127.0.0.1:8080"
+//! http_token = "change-me"
+//!
+//! [keys]                    # see the keymap module for action names
+//! play_pause = "p"
+//! quit = "ctrl+q"
+//! top = ["gg", "home"]
+//!
+//! [keys.sidebar]            # bindings for one pane only
+//! rename = "R"
+//! ```
+//!
+//! [`ConfigWatcher`] reloads the file when it changes while the player runs.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::bookmarks::ResumePolicy;
+use crate::keymap::{self, Action, Binding, Context};
+use crate::paths;
+use crate::ui::theme::{Theme, ThemeError};
+
+/// File name of the configuration file
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Errors that can occur while loading the configuration.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// Reading the file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The file is not valid TOML
+    #[error("{CONFIG_FILE}: {0}")]
+    Syntax(String),
+    /// A key is unknown or has an invalid value
+    #[error("{CONFIG_FILE}: {key}: {message}")]
+    Invalid {
+        /// Dotted path of the key (e.g. `player.volume_step`)
+        key: String,
+        /// What is wrong with it
+        message: String,
+    },
+}
+
+/// `[library]`: where music is found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryConfig {
+    /// Directories scanned for tracks (the working directory when empty)
+    pub roots: Vec<PathBuf>,
+    /// File extensions treated as audio, lower-case without the dot
+    pub extensions: Vec<String>,
+}
+
+/// `[player]`: playback behaviour.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerConfig {
+    /// Volume change per key press
+    pub volume_step: f32,
+    /// Distance jumped by the seek keys
+    pub seek_step: Duration,
+    /// Interval between position updates from the player while playing
+    pub tick_rate: Duration,
+    /// Which tracks resume where they were left
+    pub resume: ResumePolicy,
+    /// Whether ratings are also written into the files' tags
+    pub write_tags: bool,
+    /// Whether playback pauses while the player is suspended to the shell
+    pub pause_on_suspend: bool,
+}
+
+/// `[ui]`: appearance.
+#[derive(Clone, Debug)]
+pub struct UiConfig {
+    /// Terminals narrower than this use the compact layout
+    pub compact_width: u16,
+    /// Whether the playlist sidebar is shown on startup
+    pub sidebar: bool,
+    /// How long notifications are shown (errors stay twice as long)
+    pub toast_timeout: Duration,
+    /// Frames per second of the visualization while it is shown and playing
+    pub visualization_fps: u32,
+    /// Name of the theme the colors start from
+    pub theme_name: String,
+    /// Colors: the named theme with the `[ui.colors]` overrides
+    pub theme: Theme,
+}
+
+/// `[session]`: what is restored on startup.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionConfig {
+    /// Whether the restored track starts playing, instead of waiting paused
+    pub resume_playback: bool,
+}
+
+/// `[remote]`: control from other programs, read on startup only.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RemoteConfig {
+    /// Port the MPD protocol server listens on at localhost (off when `None`)
+    pub mpd_port: Option<u16>,
+    /// Address the HTTP server listens on (off when `None`)
+    pub http_address: Option<SocketAddr>,
+    /// Token the HTTP API requires, if any
+    pub http_token: Option<String>,
+}
+
+/// The complete configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Library settings
+    pub library: LibraryConfig,
+    /// Player settings
+    pub player: PlayerConfig,
+    /// UI settings
+    pub ui: UiConfig,
+    /// Session settings
+    pub session: SessionConfig,
+    /// Remote control settings
+    pub remote: RemoteConfig,
+    /// Key bindings replacing the defaults of their actions
+    pub keys: Vec<Binding>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            library: LibraryConfig {
+                roots: Vec::new(),
+                extensions: ["mp3", "flac", "ogg", "wav"].map(String::from).to_vec(),
+            },
+            player: PlayerConfig {
+                volume_step: 0.05,
+                seek_step: Duration::from_secs(10),
+                tick_rate: Duration::from_millis(200),
+                resume: ResumePolicy::default(),
+                write_tags: false,
+                pause_on_suspend: false,
+            },
+            ui: UiConfig {
+                compact_width: 80,
+                sidebar: false,
+                toast_timeout: Duration::from_secs(4),
+                visualization_fps: 30,
+                theme_name: "dark".to_string(),
+                theme: Theme::default(),
+            },
+            session: SessionConfig::default(),
+            remote: RemoteConfig::default(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file at `path`; a missing file yields defaults.
+    ///
+    /// Custom themes are looked up in the `themes` directory next to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not valid TOML or has
+    /// an unknown key or invalid value.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let themes_dir = path.parent().unwrap_or(Path::new("")).join("themes");
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse_with_themes(&content, &themes_dir),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parses the contents of a configuration file, looking up custom themes
+    /// in [`paths::themes_dir`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first unknown key or invalid value.
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        Self::parse_with_themes(content, &paths::themes_dir())
+    }
+
+    fn parse_with_themes(content: &str, themes_dir: &Path) -> Result<Self, ConfigError> {
+        let table: Table = content.parse().map_err(|e: toml::de::Error| ConfigError::Syntax(e.to_string()))?;
+        let mut config = Self::default();
+        for (section, value) in &table {
+            let entries = expect_table(section, value)?;
+            match section.as_str() {
+                "library" => config.parse_library(entries)?,
+                "player" => config.parse_player(entries)?,
+                "ui" => config.parse_ui(entries, themes_dir)?,
+                "session" => config.parse_session(entries)?,
+                "remote" => config.parse_remote(entries)?,
+                "keys" => config.parse_keys(entries)?,
+                _ => return Err(invalid(section, "unknown section")),
+            }
+        }
+        Ok(config)
+    }
+
+    fn parse_library(&mut self, entries: &Table) -> Result<(), ConfigError> {
+        for (name, value) in entries {
+            let key = format!("library.{}", name);
+            match name.as_str() {
+                "roots" => self.library.roots = expect_paths(&key, value)?,
+                "extensions" => {
+                    let extensions = expect_strings(&key, value)?;
+                    if extensions.is_empty() {
+                        return Err(invalid(&key, "at least one extension is required"));
+                    }
+                    self.library.extensions =
+                        extensions.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect();
+                }
+                _ => return Err(invalid(&key, "unknown key")),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_player(&mut self, entries: &Table) -> Result<(), ConfigError> {
+        for (name, value) in entries {
+            let key = format!("player.{}", name);
+            let player = &mut self.player;
+            match name.as_str() {
+                "volume_step" => player.volume_step = expect_number(&key, value, 0.01, 0.5)? as f32,
+                "seek_step" => player.seek_step = Duration::from_secs_f64(expect_number(&key, value, 1.0, 600.0)?),
+                "tick_rate" => player.tick_rate = Duration::from_millis(expect_number(&key, value, 16.0, 1000.0)? as u64),
+                "resume_min_minutes" => {
+                    player.resume.min_duration = Duration::from_secs_f64(expect_number(&key, value, 0.0, 1440.0)? * 60.0);
+                }
+                "resume_dirs" => player.resume.dirs = expect_paths(&key, value)?,
+                "write_tags" => player.write_tags = expect_bool(&key, value)?,
+                "pause_on_suspend" => player.pause_on_suspend = expect_bool(&key, value)?,
+                _ => return Err(invalid(&key, "unknown key")),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_ui(&mut self, entries: &Table, themes_dir: &Path) -> Result<(), ConfigError> {
+        // The theme goes first so that the color overrides apply on top of it
+        if let Some(value) = entries.get("theme") {
+            let name = expect_string("ui.theme", value)?;
+            self.ui.theme = Theme::load(name, themes_dir).map_err(|e: ThemeError| invalid("ui.theme", &e.to_string()))?;
+            self.ui.theme_name = name.to_string();
+        }
+        for (name, value) in entries {
+            let key = format!("ui.{}", name);
+            match name.as_str() {
+                "compact_width" => self.ui.compact_width = expect_number(&key, value, 0.0, 1000.0)? as u16,
+                "sidebar" => self.ui.sidebar = expect_bool(&key, value)?,
+                "toast_timeout" => self.ui.toast_timeout = Duration::from_secs_f64(expect_number(&key, value, 1.0, 60.0)?),
+                "visualization_fps" => self.ui.visualization_fps = expect_number(&key, value, 1.0, 60.0)? as u32,
+                "theme" => {}
+                "colors" => {
+                    for (color, value) in expect_table(&key, value)? {
+                        let key = format!("{}.{}", key, color);
+                        self.ui.theme.set_color(color, value).map_err(|message| invalid(&key, &message))?;
+                    }
+                }
+                _ => return Err(invalid(&key, "unknown key")),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_session(&mut self, entries: &Table) -> Result<(), ConfigError> {
+        for (name, value) in entries {
+            let key = format!("session.{}", name);
+            match name.as_str() {
+                "resume_playback" => self.session.resume_playback = expect_bool(&key, value)?,
+                _ => return Err(invalid(&key, "unknown key")),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_remote(&mut self, entries: &Table) -> Result<(), ConfigError> {
+        for (name, value) in entries {
+            let key = format!("remote.{}", name);
+            match name.as_str() {
+                "mpd_port" => self.remote.mpd_port = Some(expect_number(&key, value, 1.0, 65535.0)? as u16),
+                "http_address" => {
+                    let address = expect_string(&key, value)?;
+                    let address = address.parse().map_err(|_| invalid(&key, "expected an address like \"127.0.0.1:8080\""))?;
+                    self.remote.http_address = Some(address);
+                }
+                "http_token" => {
+                    let token = expect_string(&key, value)?;
+                    if token.is_empty() || !token.bytes().all(|b| b.is_ascii_graphic()) {
+                        return Err(invalid(&key, "expected printable characters without spaces"));
+                    }
+                    self.remote.http_token = Some(token.to_string());
+                }
+                _ => return Err(invalid(&key, "unknown key")),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_keys(&mut self, entries: &Table) -> Result<(), ConfigError> {
+        for (name, value) in entries {
+            let key = format!("keys.{}", name);
+            match (name.parse::<Context>(), value) {
+                // `[keys.<context>]` binds actions in that pane only
+                (Ok(context), Value::Table(actions)) => {
+                    for (action, value) in actions {
+                        self.parse_binding(&format!("{}.{}", key, action), action, value, Some(context))?;
+                    }
+                }
+                _ => self.parse_binding(&key, name, value, None)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_binding(&mut self, key: &str, action: &str, value: &Value, context: Option<Context>) -> Result<(), ConfigError> {
+        let action: Action = action.parse().map_err(|_| invalid(key, "unknown action"))?;
+        let context = context.unwrap_or_else(|| action.default_context());
+        let sequences = match value {
+            Value::String(s) => vec![s.as_str()],
+            _ => expect_strings(key, value)?,
+        };
+        if sequences.is_empty() {
+            return Err(invalid(key, "at least one key is required"));
+        }
+        for sequence in sequences {
+            let keys = keymap::parse_sequence(sequence).map_err(|e| invalid(key, &e))?;
+            self.keys.push(Binding { context, keys, action });
+        }
+        Ok(())
+    }
+}
+
+/// Reloads the configuration file when it changes.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching `path` from its current state.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = modified_time(&path);
+        Self { path, modified }
+    }
+
+    /// Returns the path being watched.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks whether the file changed since the last call.
+    ///
+    /// # Returns
+    ///
+    /// Returns the reloaded configuration (or the error loading it) if the
+    /// file was modified, created or deleted, and `None` otherwise.
+    pub fn poll(&mut self) -> Option<Result<Config, ConfigError>> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Config::load(&self.path))
+    }
+}
+
+/// Returns the modification time of a file, or `None` if it does not exist.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn invalid(key: &str, message: &str) -> ConfigError {
+    ConfigError::Invalid { key: key.to_string(), message: message.to_string() }
+}
+
+fn expect_table<'a>(key: &str, value: &'a Value) -> Result<&'a Table, ConfigError> {
+    value.as_table().ok_or_else(|| invalid(key, "expected a table"))
+}
+
+fn expect_bool(key: &str, value: &Value) -> Result<bool, ConfigError> {
+    value.as_bool().ok_or_else(|| invalid(key, "expected true or false"))
+}
+
+fn expect_string<'a>(key: &str, value: &'a Value) -> Result<&'a str, ConfigError> {
+    value.as_str().ok_or_else(|| invalid(key, "expected a string"))
+}
+
+/// Reads an integer or float within `min..=max`.
+fn expect_number(key: &str, value: &Value, min: f64, max: f64) -> Result<f64, ConfigError> {
+    let number = match value {
+        Value::Integer(n) => *n as f64,
+        Value::Float(n) => *n,
+        _ => return Err(invalid(key, "expected a number")),
+    };
+    if !(min..=max).contains(&number) {
+        return Err(invalid(key, &format!("must be between {} and {}", min, max)));
+    }
+    Ok(number)
+}
+
+fn expect_strings<'a>(key: &str, value: &'a Value) -> Result<Vec<&'a str>, ConfigError> {
+    let array = value.as_array().ok_or_else(|| invalid(key, "expected an array of strings"))?;
+    array.iter().map(|v| v.as_str().ok_or_else(|| invalid(key, "expected an array of strings"))).collect()
+}
+
+/// Reads an array of paths, expanding a leading `~/` to the home directory.
+fn expect_paths(key: &str, value: &Value) -> Result<Vec<PathBuf>, ConfigError> {
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    Ok(expect_strings(key, value)?
+        .into_iter()
+        .map(|s| match s.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(s),
+        })
+        .collect())
+}
+
+/// Returns the default location of the configuration file.
+pub fn default_path() -> PathBuf {
+    paths::config_dir().join(CONFIG_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use crate::keymap::KeySpec;
+    use crossterm::event::KeyCode;
+    use ratatui::style::Color;
+
+    #[test]
+    fn test_parse_full_config() {
+        let config = Config::parse(
+            r##"
+            [library]
+            roots = ["/music", "/more"]
+            extensions = [".MP3", "opus"]
+
+            [player]
+            volume_step = 0.1
+            seek_step = 30
+            tick_rate = 100
+            resume_min_minutes = 5
+            resume_dirs = ["/pods"]
+            write_tags = true
+            pause_on_suspend = true
+
+            [ui]
+            compact_width = 100
+            sidebar = true
+            toast_timeout = 8
+            visualization_fps = 12
+            theme = "nord"
+            colors = { primary = "light-blue", highlight = "#ffd700", border = 240 }
+
+            [session]
+            resume_playback = true
+
+            [remote]
+            mpd_port = 6601
+            http_address = "127.0.0.1:8081"
+            http_token = "s3cret"
+
+            [keys]
+            play_pause = "p"
+            quit = "ctrl+q"
+            top = ["gg", "home"]
+
+            [keys.sidebar]
+            rename = "R"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.library.roots, vec![PathBuf::from("/music"), PathBuf::from("/more")]);
+        assert_eq!(config.library.extensions, vec!["mp3", "opus"]);
+        assert_eq!(config.player.volume_step, 0.1);
+        assert_eq!(config.player.seek_step, Duration::from_secs(30));
+        assert_eq!(config.player.tick_rate, Duration::from_millis(100));
+        assert_eq!(config.player.resume.min_duration, Duration::from_secs(300));
+        assert_eq!(config.player.resume.dirs, vec![PathBuf::from("/pods")]);
+        assert!(config.player.write_tags);
+        assert!(config.player.pause_on_suspend);
+        assert_eq!(config.ui.compact_width, 100);
+        assert!(config.ui.sidebar);
+        assert_eq!(config.ui.toast_timeout, Duration::from_secs(8));
+        assert_eq!(config.ui.visualization_fps, 12);
+        assert_eq!(config.ui.theme.primary, Color::LightBlue);
+        assert_eq!(config.ui.theme.highlight, Color::Rgb(0xff, 0xd7, 0x00));
+        assert_eq!(config.ui.theme.border, Color::Indexed(240));
+        assert_eq!(config.ui.theme_name, "nord");
+        assert_eq!(config.ui.theme.background, Theme::builtin("nord").unwrap().background);
+        assert!(config.session.resume_playback);
+        assert_eq!(config.remote.mpd_port, Some(6601));
+        assert_eq!(config.remote.http_address, Some(([127, 0, 0, 1], 8081).into()));
+        assert_eq!(config.remote.http_token.as_deref(), Some("s3cret"));
+        let binding = |action: Action| config.keys.iter().find(|b| b.action == action).unwrap();
+        assert_eq!(binding(Action::PlayPause).keys, vec!["p".parse::<KeySpec>().unwrap()]);
+        assert_eq!(binding(Action::Quit).keys[0].to_string(), "ctrl+q");
+        assert_eq!(config.keys.iter().filter(|b| b.action == Action::Top).count(), 2);
+        assert_eq!(binding(Action::Top).keys.len(), 2);
+        assert_eq!(binding(Action::Rename).context, Context::Sidebar);
+        assert_eq!(binding(Action::Rename).keys[0].code, KeyCode::Char('R'));
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let error = |content: &str| Config::parse(content).unwrap_err().to_string();
+        assert_eq!(error("[player]\nvolume_step = 3\n"), "config.toml: player.volume_step: must be between 0.01 and 0.5");
+        assert_eq!(error("[player]\ntick = 100\n"), "config.toml: player.tick: unknown key");
+        assert_eq!(error("[ui.colors]\nprimary = \"octarine\"\n"), "config.toml: ui.colors.primary: expected a color name, 0-255 or #rrggbb");
+        assert_eq!(error("[keys]\nfly = \"f\"\n"), "config.toml: keys.fly: unknown action");
+        assert_eq!(error("[ui]\ntheme = \"no-such-theme\"\n"), "config.toml: ui.theme: unknown theme 'no-such-theme'");
+        assert_eq!(error("[keys]\nquit = \"hyper+q\"\n"), "config.toml: keys.quit: unknown modifier 'hyper'");
+        assert_eq!(error("[keys.tracks]\nfly = \"f\"\n"), "config.toml: keys.tracks.fly: unknown action");
+        assert_eq!(error("[keys]\ntop = []\n"), "config.toml: keys.top: at least one key is required");
+        assert_eq!(error("[library]\nextensions = []\n"), "config.toml: library.extensions: at least one extension is required");
+        assert!(matches!(Config::parse("[player\n"), Err(ConfigError::Syntax(_))));
+        assert_eq!(error("[remote]\nmpd_port = 0\n"), "config.toml: remote.mpd_port: must be between 1 and 65535");
+        assert_eq!(
+            error("[remote]\nhttp_address = \"8080\"\n"),
+            "config.toml: remote.http_address: expected an address like \"127.0.0.1:8080\""
+        );
+        assert_eq!(
+            error("[remote]\nhttp_token = \"two words\"\n"),
+            "config.toml: remote.http_token: expected printable characters without spaces"
+        );
+        assert!(Config::parse("").unwrap().keys.is_empty());
+        assert_eq!(Config::parse("").unwrap().remote.mpd_port, None);
+    }
+
+    #[test]
+    fn test_watcher_reloads_on_change() {
+        let dir = TempDir::new("config");
+        let path = dir.join(CONFIG_FILE);
+
+        let mut watcher = ConfigWatcher::new(&path);
+        assert!(watcher.poll().is_none());
+        fs::write(&path, "[ui]\ncompact_width = 60\n").unwrap();
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.ui.compact_width, 60);
+        assert!(watcher.poll().is_none());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(watcher.poll().unwrap().unwrap().ui.compact_width, 80);
+    }
+}
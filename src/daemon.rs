@@ -0,0 +1,68 @@
+//! Running without a terminal.
+//!
+//! Started with `--daemon`, the player scans the library and plays without
+//! a UI, logs to [`crate::paths::log_file`] only and is controlled through
+//! [`crate::remote`]: the socket, and the MPD and HTTP servers when they are
+//! configured. A terminal started meanwhile attaches to it as a client (see
+//! [`crate::app::App::attached`]).
+//!
+//! The daemon announces itself in a [`PidFile`], so that service managers
+//! and scripts can signal it; SIGTERM makes it save the session and quit.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file holding the ID of the running process.
+///
+/// The file is removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the ID of this process to a file, replacing one left behind
+    /// by a process that did not quit cleanly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be written.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, format!("{}\n", std::process::id()))?;
+        Ok(Self { path: path.to_path_buf() })
+    }
+
+    /// Returns the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    #[test]
+    fn test_pid_file_is_written_and_removed() {
+        let dir = TempDir::new("daemon");
+        let path = dir.join("run").join("tools-rs.pid");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "1\n").unwrap();
+
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(fs::read_to_string(pid_file.path()).unwrap(), format!("{}\n", std::process::id()));
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
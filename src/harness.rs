@@ -0,0 +1,623 @@
+//! Deterministic end-to-end harness for the application loop.
+//!
+//! [`Harness`] runs the same loop as the binary — messages, [`update`],
+//! effects, [`render`] on [`Effect::Redraw`], ticks at
+//! [`App::next_deadline`] — but against a [`TestBackend`], a script of
+//! terminal events instead of the input thread, a [`FakePlayer`] speaking the
+//! [`AppCommand`]/[`AppEvent`] protocol instead of the audio thread, and a
+//! [`ManualClock`] that only moves while the script waits. Waiting jumps from
+//! one deadline or player report to the next, so the same script always
+//! produces the same screen, however long it waits. Writes to the data
+//! directory and exports are recorded (see [`Harness::writes`]) rather than
+//! carried out.
+//!
+//! ```
+//! use std::path::PathBuf;
+//! use tools_rs::common::Track;
+//! use tools_rs::harness::Harness;
+//!
+//! let tracks = (1..=3)
+//!     .map(|i| Track { id: i, path: PathBuf::from(format!("/music/{}.mp3", i)), title: Some(format!("Song {}", i)), ..Default::default() })
+//!     .collect();
+//! let mut h = Harness::new(tracks, 100, 30);
+//! h.press("j j enter").run();
+//! assert_eq!(h.app().current_track().and_then(|t| t.title.as_deref()), Some("Song 3"));
+//! h.assert_contains("Song 3");
+//! ```
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers, MouseEvent};
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::Terminal;
+use tracing::subscriber::DefaultGuard;
+use tracing_subscriber::prelude::*;
+use unicode_width::UnicodeWidthStr;
+
+use crate::app::{render, update, App, Effect, Msg};
+use crate::clock::{Clock, ManualClock};
+use crate::common::{AppCommand, AppEvent, Track};
+use crate::config::Config;
+use crate::keymap;
+use crate::notify::{self, Notification};
+
+/// Wall-clock time the harness clock starts at (2023-11-14 22:13:20 UTC)
+pub const EPOCH_SECS: u64 = 1_700_000_000;
+
+/// Distinguishes temporary directories created by the same process
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh directory under the system's temporary directory, removed with
+/// everything in it when dropped.
+///
+/// Names combine the process ID with a counter, so tests running in
+/// parallel in one process never share a directory.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Word identifying the user of the directory in its name
+    ///
+    /// # Panics
+    ///
+    /// Panics if the directory cannot be created.
+    pub fn new(name: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("tools-rs-{}-{}-{}", name, std::process::id(), id));
+        std::fs::remove_dir_all(&path).ok();
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of an entry inside the directory.
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+/// Runs the application loop against a scripted terminal and a fake player.
+///
+/// Script input with [`press`](Self::press), [`key`](Self::key),
+/// [`event`](Self::event) and [`wait`](Self::wait), then play it with
+/// [`run`](Self::run) and inspect the screen and the application.
+pub struct Harness {
+    app: App,
+    terminal: Terminal<TestBackend>,
+    clock: ManualClock,
+    input: ScriptedInput,
+    player: FakePlayer,
+    /// Notifications raised on this thread, as the binary gets them from `tracing`
+    notifications: Receiver<Notification>,
+    _subscriber: DefaultGuard,
+    messages: Vec<Msg>,
+    writes: Vec<Effect>,
+    suspends: usize,
+    quit: bool,
+    _data_dir: TempDir,
+}
+
+impl Harness {
+    /// Creates a harness with the default configuration and empty
+    /// playlists, statistics and bookmarks in a temporary directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - Library tracks; their durations are what the fake player reports
+    /// * `width` - Terminal width in cells
+    /// * `height` - Terminal height in cells
+    pub fn new(tracks: Vec<Track>, width: u16, height: u16) -> Self {
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(EPOCH_SECS));
+        let data_dir = TempDir::new("harness");
+        let durations = tracks.iter().map(|t| t.duration).collect();
+        let player = FakePlayer::new(durations, Config::default().player.tick_rate);
+        let app = App::new(tracks).with_clock(Arc::new(clock.clone())).with_data_dir(data_dir.path());
+        let (layer, notifications) = notify::layer();
+        let subscriber = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+        Self {
+            app,
+            terminal: Terminal::new(TestBackend::new(width, height)).unwrap(),
+            input: ScriptedInput::default(),
+            clock,
+            player,
+            notifications,
+            _subscriber: subscriber,
+            messages: vec![Msg::Resize(width, height)],
+            writes: Vec::new(),
+            suspends: 0,
+            quit: false,
+            _data_dir: data_dir,
+        }
+    }
+
+    /// Replaces the configuration, as if the config file had been edited.
+    pub fn config(mut self, config: Config) -> Self {
+        self.messages.push(Msg::Config(Box::new(config)));
+        self
+    }
+
+    /// Returns the application state.
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+
+    /// Returns the fake player, to inspect the commands it received.
+    pub fn player(&self) -> &FakePlayer {
+        &self.player
+    }
+
+    /// Returns the fake player, to make it send events of its own.
+    pub fn player_mut(&mut self) -> &mut FakePlayer {
+        &mut self.player
+    }
+
+    /// Returns the clock the application and the player run on.
+    pub fn clock(&self) -> &ManualClock {
+        &self.clock
+    }
+
+    /// Scripts a key sequence written as in the config file (`j j enter`,
+    /// `gg`, `ctrl+z`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sequence cannot be parsed.
+    pub fn press(&mut self, keys: &str) -> &mut Self {
+        let keys = keymap::parse_sequence(keys).unwrap_or_else(|e| panic!("invalid key sequence '{}': {}", keys, e));
+        for key in keys {
+            self.key(KeyEvent::new(key.code, key.modifiers));
+        }
+        self
+    }
+
+    /// Scripts typing text, e.g. into a prompt.
+    pub fn type_text(&mut self, text: &str) -> &mut Self {
+        for c in text.chars() {
+            self.key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        }
+        self
+    }
+
+    /// Scripts a key press.
+    pub fn key(&mut self, key: KeyEvent) -> &mut Self {
+        let key = KeyEvent { kind: KeyEventKind::Press, state: KeyEventState::NONE, ..key };
+        self.event(Event::Key(key))
+    }
+
+    /// Scripts a mouse event.
+    pub fn mouse(&mut self, mouse: MouseEvent) -> &mut Self {
+        self.event(Event::Mouse(mouse))
+    }
+
+    /// Scripts a terminal event.
+    pub fn event(&mut self, event: Event) -> &mut Self {
+        self.input.push(event);
+        self
+    }
+
+    /// Scripts a pause: the clock moves on, ticks and player progress included.
+    pub fn wait(&mut self, duration: Duration) -> &mut Self {
+        self.input.wait(duration);
+        self
+    }
+
+    /// Plays the script to its end, then handles whatever the player sent in
+    /// response and draws the final screen.
+    ///
+    /// Notifications raised while handling the script are shown like in the
+    /// binary, as long as the harness is used on the thread that created it.
+    ///
+    /// Stops early once the application quits.
+    pub fn run(&mut self) -> &mut Self {
+        loop {
+            self.process();
+            if self.quit {
+                break;
+            }
+            match self.input.steps.front_mut() {
+                Some(Step::Event(_)) => {
+                    if let Some(Step::Event(event)) = self.input.steps.pop_front() {
+                        self.messages.extend(Msg::from_event(event));
+                    }
+                }
+                Some(Step::Wait(remaining)) => {
+                    // Sleep until the wait ends, the application's next deadline or the player's next report
+                    let now = self.clock.now();
+                    let deadline = self.app.next_deadline().map(|at| at.saturating_duration_since(now));
+                    let step = [Some(*remaining), deadline, self.player.next_report()].into_iter().flatten().min().unwrap_or_default();
+                    *remaining -= step;
+                    if remaining.is_zero() {
+                        self.input.steps.pop_front();
+                    }
+                    self.clock.advance(step);
+                    self.player.advance(step);
+                    if self.app.next_deadline().is_some_and(|at| at <= self.clock.now()) {
+                        self.messages.push(Msg::Tick);
+                    }
+                }
+                None => {
+                    self.messages.extend(self.notifications.try_iter().map(Msg::Notification));
+                    if self.player.events.is_empty() && self.messages.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+        self
+    }
+
+    /// Returns whether the application has quit.
+    pub fn is_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Returns the writes the application asked for, in order:
+    /// [`Effect::SaveLibrary`], [`Effect::SaveBookmarks`],
+    /// [`Effect::SavePlaylists`] and [`Effect::Export`].
+    pub fn writes(&self) -> &[Effect] {
+        &self.writes
+    }
+
+    /// Returns how many times the application asked to be suspended.
+    pub fn suspends(&self) -> usize {
+        self.suspends
+    }
+
+    /// Returns the screen as last drawn.
+    pub fn buffer(&self) -> &Buffer {
+        self.terminal.backend().buffer()
+    }
+
+    /// Returns a line of the screen, without trailing blanks.
+    ///
+    /// Wide characters take up one `char`, as they would when copied from a
+    /// terminal.
+    pub fn line(&self, y: u16) -> String {
+        let buffer = self.buffer();
+        let mut line = String::new();
+        let mut skip = 0;
+        for x in 0..buffer.area.width {
+            let symbol = buffer.get(x, y).symbol();
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            line.push_str(symbol);
+            skip = symbol.width().saturating_sub(1);
+        }
+        line.trim_end().to_string()
+    }
+
+    /// Returns the screen, one [`line`](Self::line) per row.
+    pub fn screen(&self) -> String {
+        (0..self.buffer().area.height).map(|y| self.line(y)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Asserts that the screen shows some text.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the screen if the text is not found.
+    pub fn assert_contains(&self, text: &str) {
+        let screen = self.screen();
+        assert!(screen.contains(text), "screen does not contain {:?}:\n{}", text, screen);
+    }
+
+    /// Asserts that the screen matches a snapshot.
+    ///
+    /// The snapshot lists the rows from the top; trailing blanks, a leading
+    /// newline and rows left out at the bottom are not compared.
+    ///
+    /// # Panics
+    ///
+    /// Panics with both screens if a row differs.
+    pub fn assert_screen(&self, expected: &str) {
+        let expected = expected.strip_prefix('\n').unwrap_or(expected);
+        for (y, want) in expected.lines().enumerate() {
+            let got = self.line(y as u16);
+            assert!(
+                got == want.trim_end(),
+                "row {} differs:\n  got:  {:?}\n  want: {:?}\nscreen:\n{}\nsnapshot:\n{}",
+                y, got, want.trim_end(), self.screen(), expected,
+            );
+        }
+    }
+
+    /// Runs one turn of the loop: handles the pending messages and draws if
+    /// the screen changed.
+    fn process(&mut self) {
+        self.messages.extend(self.player.events.drain(..).map(Msg::Player));
+        self.messages.extend(self.notifications.try_iter().map(Msg::Notification));
+
+        let mut redraw = false;
+        for msg in std::mem::take(&mut self.messages) {
+            for effect in update(&mut self.app, msg) {
+                match effect {
+                    Effect::Player(command) => self.player.handle(command),
+                    // The harness is never attached to another player
+                    Effect::Daemon(_) => {}
+                    // Nor does it touch the tracks' files
+                    Effect::WriteRating(..) => {}
+                    effect @ (Effect::SaveLibrary | Effect::SaveBookmarks | Effect::SavePlaylists | Effect::Export(..)) => {
+                        self.writes.push(effect);
+                    }
+                    Effect::Reply(reply, answer) => {
+                        reply.send(answer).ok();
+                    }
+                    Effect::Publish(event) => self.app.send_event(&event),
+                    Effect::Suspend => {
+                        self.suspends += 1;
+                        redraw = true;
+                    }
+                    Effect::Quit => {
+                        self.quit = true;
+                        return;
+                    }
+                    Effect::Redraw => redraw = true,
+                }
+            }
+        }
+        if redraw {
+            let app = &self.app;
+            self.terminal.draw(|frame| render(frame, app)).unwrap();
+        }
+    }
+}
+
+/// A step of a [`ScriptedInput`].
+#[derive(Debug)]
+enum Step {
+    Event(Event),
+    Wait(Duration),
+}
+
+/// Terminal events played from a script, with pauses in between.
+#[derive(Debug, Default)]
+struct ScriptedInput {
+    steps: VecDeque<Step>,
+}
+
+impl ScriptedInput {
+    /// Appends an event.
+    fn push(&mut self, event: Event) {
+        self.steps.push_back(Step::Event(event));
+    }
+
+    /// Appends a pause.
+    fn wait(&mut self, duration: Duration) {
+        self.steps.push_back(Step::Wait(duration));
+    }
+}
+
+/// A player that plays nothing but follows the player thread's protocol:
+/// `Play` answers with `TrackStarted`, `Seek` with `Progress`, and while a
+/// track plays it reports at a fixed interval: `Progress`, or `TrackEnded`
+/// once the track is over.
+#[derive(Debug)]
+pub struct FakePlayer {
+    durations: Vec<Option<Duration>>,
+    received: Vec<AppCommand>,
+    events: VecDeque<AppEvent>,
+    current: Option<usize>,
+    paused: bool,
+    position: Duration,
+    volume: f32,
+    failure: Option<String>,
+    interval: Duration,
+    /// Time left until the next report while playing
+    until_report: Duration,
+}
+
+impl FakePlayer {
+    /// Creates a stopped player.
+    ///
+    /// # Arguments
+    ///
+    /// * `durations` - Duration of each track by index; tracks without one never end
+    /// * `interval` - Time between reports while playing
+    pub fn new(durations: Vec<Option<Duration>>, interval: Duration) -> Self {
+        Self {
+            durations,
+            received: Vec::new(),
+            events: VecDeque::new(),
+            current: None,
+            paused: false,
+            position: Duration::ZERO,
+            volume: 1.0,
+            failure: None,
+            interval,
+            until_report: interval,
+        }
+    }
+
+    /// Carries out a command from the application.
+    pub fn handle(&mut self, command: AppCommand) {
+        match &command {
+            AppCommand::Play { index, path } => match self.failure.take() {
+                Some(message) => self.events.push_back(AppEvent::Error { message: format!("{}: {}", message, path.display()) }),
+                None => {
+                    self.current = Some(*index);
+                    self.paused = false;
+                    self.position = Duration::ZERO;
+                    self.until_report = self.interval;
+                    let duration = self.durations.get(*index).copied().flatten();
+                    self.events.push_back(AppEvent::TrackStarted { index: *index, duration });
+                }
+            },
+            AppCommand::TogglePlayPause => {
+                if self.current.is_some() {
+                    self.paused = !self.paused;
+                }
+            }
+            AppCommand::SetVolume(volume) => self.volume = *volume,
+            AppCommand::Seek(position) => {
+                if self.current.is_some() {
+                    self.position = *position;
+                    self.events.push_back(AppEvent::Progress { position: *position });
+                }
+            }
+            AppCommand::Shutdown => self.current = None,
+        }
+        self.received.push(command);
+    }
+
+    /// Returns the time until the next report, or `None` while nothing plays.
+    pub fn next_report(&self) -> Option<Duration> {
+        (self.current.is_some() && !self.paused).then_some(self.until_report)
+    }
+
+    /// Lets time pass: a playing track moves on, reporting its position
+    /// whenever a report is due.
+    pub fn advance(&mut self, elapsed: Duration) {
+        let Some(index) = self.current else { return };
+        if self.paused {
+            return;
+        }
+        self.position += elapsed;
+        self.until_report = self.until_report.saturating_sub(elapsed);
+        if !self.until_report.is_zero() {
+            return;
+        }
+        self.until_report = self.interval;
+        match self.durations.get(index).copied().flatten() {
+            Some(duration) if self.position >= duration => {
+                self.current = None;
+                self.position = Duration::ZERO;
+                self.events.push_back(AppEvent::TrackEnded);
+            }
+            _ => self.events.push_back(AppEvent::Progress { position: self.position }),
+        }
+    }
+
+    /// Sends an event of the player's own (e.g. an [`AppEvent::Error`]).
+    pub fn emit(&mut self, event: AppEvent) {
+        self.events.push_back(event);
+    }
+
+    /// Makes the next `Play` fail with an error message instead of starting.
+    pub fn fail_next(&mut self, message: impl Into<String>) {
+        self.failure = Some(message.into());
+    }
+
+    /// Returns the commands received so far, oldest first.
+    pub fn received(&self) -> &[AppCommand] {
+        &self.received
+    }
+
+    /// Returns the index of the loaded track (if any).
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Returns whether the loaded track is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns the position within the loaded track.
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    /// Returns the volume last set.
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracks() -> Vec<Track> {
+        (0..3)
+            .map(|i| Track {
+                id: i,
+                path: PathBuf::from(format!("/music/{}.mp3", i + 1)),
+                duration: Some(Duration::from_secs(10 * (i + 1))),
+                title: Some(format!("Song {}", i + 1)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_keys_play_the_selected_track() {
+        let mut h = Harness::new(tracks(), 60, 16);
+        h.press("j j enter").run();
+
+        assert!(matches!(h.player().received(), [AppCommand::Play { index: 2, .. }]));
+        assert_eq!(h.app().current_track().map(|t| t.id), Some(2));
+        h.assert_screen("
+┌Now Playing───────────────────────────────────────────────┐
+│                         ▶ Song 3                         │
+└──────────────────────────────────────────────────────────┘
+┌Track List────────────────────────────────────────────────┐
+│  1.   Song 1                                             │
+│  2.   Song 2                                             │
+│  3. ▶ Song 3                                             │
+");
+        // Only the selected row is highlighted
+        let highlight = crate::ui::theme::Theme::default().highlight;
+        assert_eq!(h.buffer().get(3, 6).fg, highlight);
+        assert_ne!(h.buffer().get(3, 5).fg, highlight);
+    }
+
+    #[test]
+    fn test_clock_drives_progress_and_auto_advance() {
+        let mut h = Harness::new(tracks(), 60, 16);
+        h.press("enter").wait(Duration::from_secs(4)).run();
+        assert_eq!(h.app().position(), Duration::from_secs(4));
+        h.assert_contains("00:04 / 00:10");
+
+        // Pausing stops the clock for the player
+        h.press("space").wait(Duration::from_secs(30)).press("space").run();
+        assert_eq!(h.player().position(), Duration::from_secs(4));
+
+        // The first track ends after ten seconds of playing and the next one starts
+        h.wait(Duration::from_secs(6)).run();
+        assert_eq!(h.app().current_track().map(|t| t.id), Some(1));
+        assert_eq!(h.player().current(), Some(1));
+        assert_eq!(h.clock().elapsed(), Duration::from_secs(40));
+        // The finished play is saved by the loop, after the resume position is cleared
+        assert!(matches!(h.writes(), [Effect::SaveBookmarks, Effect::SaveLibrary]));
+    }
+
+    #[test]
+    fn test_errors_suspend_and_quit() {
+        let mut h = Harness::new(tracks(), 60, 16);
+        h.player_mut().fail_next("无法打开文件");
+        h.press("enter").run();
+        h.assert_contains("无法打开文件: /music/1.mp3");
+        assert_eq!(h.player().current(), None);
+
+        h.press("ctrl+z").run();
+        assert_eq!(h.suspends(), 1);
+        assert!(!h.is_quit());
+        h.press("q j").run();
+        assert!(h.is_quit());
+        assert_eq!(h.app().selected_track().map(|t| t.id), Some(0), "nothing runs after quitting");
+    }
+}
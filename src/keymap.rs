@@ -0,0 +1,828 @@
+//! Key bindings: the actions of the player and the keys that trigger them.
+//!
+//! Every key press is looked up in a [`Keymap`] under the [`Context`] of the
+//! focused pane, falling back to the global bindings. Bindings may be key
+//! sequences such as `gg`; while a sequence is incomplete the lookup reports
+//! [`Lookup::Pending`]. The status-bar hints and the help screen are generated
+//! from the same keymap, so they always show the keys actually bound.
+//!
+//! Overrides come from the `[keys]` section of the config file, where each
+//! action is bound to a key, a sequence or an array of them:
+//!
+//! ```toml
+//! [keys]
+//! play_pause = "p"
+//! top = ["g g", "home"]
+//!
+//! [keys.sidebar]
+//! rename = "R"
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Pane in which a binding applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// Everywhere, unless the focused pane binds the key itself
+    Global,
+    /// The track list
+    Tracks,
+    /// The playlist sidebar
+    Sidebar,
+    /// The bookmarks panel
+    Bookmarks,
+}
+
+impl Context {
+    /// All contexts, in help-screen order
+    pub const ALL: [Context; 4] = [Context::Global, Context::Tracks, Context::Sidebar, Context::Bookmarks];
+
+    /// Returns the name used in the config file (`[keys.<name>]`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Tracks => "tracks",
+            Self::Sidebar => "sidebar",
+            Self::Bookmarks => "bookmarks",
+        }
+    }
+
+    /// Returns the heading shown in the help screen.
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Global => "全局",
+            Self::Tracks => "曲目列表",
+            Self::Sidebar => "播放列表侧栏",
+            Self::Bookmarks => "书签",
+        }
+    }
+}
+
+impl FromStr for Context {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|c| c.name() == s).ok_or(())
+    }
+}
+
+/// Something the user can do with a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Quit the player
+    Quit,
+    /// Toggle play/pause (or start the restored track)
+    PlayPause,
+    /// Next track
+    Next,
+    /// Previous track
+    Prev,
+    /// Increase the volume
+    VolumeUp,
+    /// Decrease the volume
+    VolumeDown,
+    /// Seek forward
+    SeekForward,
+    /// Seek back
+    SeekBack,
+    /// Filter the track list with a query
+    Search,
+    /// Clear the filter
+    ClearFilter,
+    /// Save the filter as a smart playlist
+    SaveSmart,
+    /// Cycle the sort column
+    Sort,
+    /// Reverse the sort order
+    ReverseSort,
+    /// Toggle shuffle
+    Shuffle,
+    /// Cycle the repeat mode
+    Repeat,
+    /// Show/hide the playlist sidebar
+    ToggleSidebar,
+    /// Move focus to the next pane
+    FocusNext,
+    /// Bookmark the current position
+    Bookmark,
+    /// Show/hide the bookmarks panel
+    ToggleBookmarks,
+    /// Switch to the next color theme
+    NextTheme,
+    /// Open the `:` command line
+    CommandLine,
+    /// Show the help popup
+    Help,
+    /// Show the message log
+    Messages,
+    /// Suspend the player to the shell
+    Suspend,
+    /// Move the cursor up
+    Up,
+    /// Move the cursor down
+    Down,
+    /// Move the cursor to the first row
+    Top,
+    /// Move the cursor to the last row
+    Bottom,
+    /// Move the cursor down half a page
+    HalfPageDown,
+    /// Move the cursor up half a page
+    HalfPageUp,
+    /// Select the playing track and scroll it to the middle of the list
+    CenterPlaying,
+    /// Remember the selected track under the next key typed
+    SetMark,
+    /// Jump to the track remembered under the next key typed
+    JumpToMark,
+    /// Play or open the item under the cursor
+    Activate,
+    /// Delete the item under the cursor
+    Delete,
+    /// Add the selected track to the highlighted playlist
+    AddToPlaylist,
+    /// Move the selected playlist entry up
+    MoveEntryUp,
+    /// Move the selected playlist entry down
+    MoveEntryDown,
+    /// Rate the selected track (0 clears the rating)
+    Rate(u8),
+    /// Mark/unmark the selected track as a favorite
+    Favorite,
+    /// Create a playlist
+    NewPlaylist,
+    /// Rename the highlighted playlist
+    Rename,
+    /// Close the focused panel
+    Close,
+}
+
+impl Action {
+    /// All actions, in help-screen order
+    pub const ALL: &'static [Action] = &[
+        Action::Quit,
+        Action::PlayPause,
+        Action::Next,
+        Action::Prev,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::SeekForward,
+        Action::SeekBack,
+        Action::Search,
+        Action::ClearFilter,
+        Action::SaveSmart,
+        Action::Sort,
+        Action::ReverseSort,
+        Action::Shuffle,
+        Action::Repeat,
+        Action::ToggleSidebar,
+        Action::FocusNext,
+        Action::Bookmark,
+        Action::ToggleBookmarks,
+        Action::NextTheme,
+        Action::CommandLine,
+        Action::Help,
+        Action::Messages,
+        Action::Suspend,
+        Action::Up,
+        Action::Down,
+        Action::Top,
+        Action::Bottom,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::CenterPlaying,
+        Action::SetMark,
+        Action::JumpToMark,
+        Action::Activate,
+        Action::Delete,
+        Action::AddToPlaylist,
+        Action::MoveEntryUp,
+        Action::MoveEntryDown,
+        Action::Rate(0),
+        Action::Rate(1),
+        Action::Rate(2),
+        Action::Rate(3),
+        Action::Rate(4),
+        Action::Rate(5),
+        Action::Favorite,
+        Action::NewPlaylist,
+        Action::Rename,
+        Action::Close,
+    ];
+
+    /// Returns the context of the action's default bindings.
+    pub fn default_context(self) -> Context {
+        DEFAULT_BINDINGS
+            .iter()
+            .find(|(_, _, action)| *action == self)
+            .map_or(Context::Global, |(context, _, _)| *context)
+    }
+
+    /// Returns `true` if a count typed before the keys (e.g. `5j`) repeats
+    /// the action or, for [`Action::Top`] and [`Action::Bottom`], picks the row.
+    pub fn takes_count(self) -> bool {
+        matches!(
+            self,
+            Self::Up
+                | Self::Down
+                | Self::Top
+                | Self::Bottom
+                | Self::HalfPageDown
+                | Self::HalfPageUp
+                | Self::Next
+                | Self::Prev
+                | Self::VolumeUp
+                | Self::VolumeDown
+                | Self::SeekForward
+                | Self::SeekBack
+        )
+    }
+
+    /// Returns a one-line description for the help screen.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Quit => "退出",
+            Self::PlayPause => "播放/暂停",
+            Self::Next => "下一曲",
+            Self::Prev => "上一曲",
+            Self::VolumeUp => "增大音量",
+            Self::VolumeDown => "减小音量",
+            Self::SeekForward => "快进",
+            Self::SeekBack => "快退",
+            Self::Search => "搜索/过滤曲目",
+            Self::ClearFilter => "清除过滤条件",
+            Self::SaveSmart => "将过滤条件保存为智能播放列表",
+            Self::Sort => "切换排序列",
+            Self::ReverseSort => "反转排序",
+            Self::Shuffle => "随机播放开关",
+            Self::Repeat => "切换循环模式",
+            Self::ToggleSidebar => "显示/隐藏播放列表侧栏",
+            Self::FocusNext => "切换焦点",
+            Self::Bookmark => "在当前位置添加书签",
+            Self::ToggleBookmarks => "显示/隐藏书签",
+            Self::NextTheme => "切换主题",
+            Self::CommandLine => "输入命令",
+            Self::Help => "显示帮助",
+            Self::Messages => "显示消息记录",
+            Self::Suspend => "挂起到后台 (fg 恢复)",
+            Self::Up => "上移",
+            Self::Down => "下移",
+            Self::Top => "跳到第一行",
+            Self::Bottom => "跳到最后一行",
+            Self::HalfPageDown => "向下翻半页",
+            Self::HalfPageUp => "向上翻半页",
+            Self::CenterPlaying => "定位到正在播放的曲目并居中",
+            Self::SetMark => "设置标记",
+            Self::JumpToMark => "跳到标记",
+            Self::Activate => "播放/打开",
+            Self::Delete => "删除",
+            Self::AddToPlaylist => "添加到侧栏选中的播放列表",
+            Self::MoveEntryUp => "上移播放列表条目",
+            Self::MoveEntryDown => "下移播放列表条目",
+            Self::Rate(0) => "清除评分",
+            Self::Rate(_) => "评分",
+            Self::Favorite => "收藏/取消收藏",
+            Self::NewPlaylist => "新建播放列表",
+            Self::Rename => "重命名",
+            Self::Close => "关闭面板",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Quit => "quit",
+            Self::PlayPause => "play_pause",
+            Self::Next => "next",
+            Self::Prev => "prev",
+            Self::VolumeUp => "volume_up",
+            Self::VolumeDown => "volume_down",
+            Self::SeekForward => "seek_forward",
+            Self::SeekBack => "seek_back",
+            Self::Search => "search",
+            Self::ClearFilter => "clear_filter",
+            Self::SaveSmart => "save_smart",
+            Self::Sort => "sort",
+            Self::ReverseSort => "reverse_sort",
+            Self::Shuffle => "shuffle",
+            Self::Repeat => "repeat",
+            Self::ToggleSidebar => "sidebar",
+            Self::FocusNext => "focus",
+            Self::Bookmark => "bookmark",
+            Self::ToggleBookmarks => "bookmarks",
+            Self::NextTheme => "theme",
+            Self::CommandLine => "command_line",
+            Self::Help => "help",
+            Self::Messages => "messages",
+            Self::Suspend => "suspend",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::HalfPageDown => "half_page_down",
+            Self::HalfPageUp => "half_page_up",
+            Self::CenterPlaying => "center_playing",
+            Self::SetMark => "set_mark",
+            Self::JumpToMark => "jump_to_mark",
+            Self::Activate => "activate",
+            Self::Delete => "delete",
+            Self::AddToPlaylist => "add_to_playlist",
+            Self::MoveEntryUp => "move_up",
+            Self::MoveEntryDown => "move_down",
+            Self::Rate(n) => return write!(f, "rate_{}", n),
+            Self::Favorite => "favorite",
+            Self::NewPlaylist => "new_playlist",
+            Self::Rename => "rename",
+            Self::Close => "close",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter().copied().find(|action| action.to_string() == s).ok_or(())
+    }
+}
+
+/// A key with modifiers, written like `ctrl+d`, `space` or `G`.
+///
+/// Shift is folded into the character for character keys, so `G` and
+/// `shift+g` are the same key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeySpec {
+    /// Key code
+    pub code: KeyCode,
+    /// Modifiers other than Shift on character keys
+    pub modifiers: KeyModifiers,
+}
+
+impl KeySpec {
+    /// Creates a key, folding Shift into character keys.
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                Self { code: KeyCode::Char(c.to_ascii_uppercase()), modifiers: modifiers - KeyModifiers::SHIFT }
+            }
+            // Terminals disagree on reporting Shift with upper-case characters and BackTab
+            KeyCode::Char(_) | KeyCode::BackTab => Self { code, modifiers: modifiers - KeyModifiers::SHIFT },
+            _ => Self { code, modifiers },
+        }
+    }
+
+    /// Returns the short label shown in hints and the help screen (e.g. `↑`, `Ctrl-d`).
+    pub fn label(&self) -> String {
+        let mut out = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            out.push_str("Ctrl-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            out.push_str("Alt-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            out.push_str("Shift-");
+        }
+        match self.code {
+            KeyCode::Char(' ') => out.push_str("Space"),
+            KeyCode::Char(c) => out.push(c),
+            KeyCode::Up => out.push('↑'),
+            KeyCode::Down => out.push('↓'),
+            KeyCode::Left => out.push('←'),
+            KeyCode::Right => out.push('→'),
+            KeyCode::Enter => out.push_str("Enter"),
+            KeyCode::Esc => out.push_str("Esc"),
+            KeyCode::Tab => out.push_str("Tab"),
+            KeyCode::BackTab => out.push_str("Shift-Tab"),
+            KeyCode::Backspace => out.push_str("Backspace"),
+            KeyCode::Delete => out.push_str("Del"),
+            KeyCode::Home => out.push_str("Home"),
+            KeyCode::End => out.push_str("End"),
+            KeyCode::PageUp => out.push_str("PgUp"),
+            KeyCode::PageDown => out.push_str("PgDn"),
+            KeyCode::F(n) => out.push_str(&format!("F{}", n)),
+            code => out.push_str(&format!("{:?}", code)),
+        }
+        out
+    }
+}
+
+impl From<KeyEvent> for KeySpec {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A trailing "+" is the key itself, not a separator
+        let (mods, name) = match s.strip_suffix('+') {
+            Some(mods) => (mods, "+"),
+            None => match s.rfind('+') {
+                Some(i) => (&s[..i], &s[i + 1..]),
+                None => ("", s),
+            },
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        for m in mods.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match m.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier '{}'", m)),
+            };
+        }
+        let code = match name.to_lowercase().as_str() {
+            "space" => KeyCode::Char(' '),
+            "enter" | "return" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            lower => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=12) => KeyCode::F(n),
+                _ => {
+                    let mut chars = name.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => KeyCode::Char(c),
+                        _ => return Err(format!("unknown key '{}'", name)),
+                    }
+                }
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [(KeyModifiers::CONTROL, "ctrl"), (KeyModifiers::ALT, "alt"), (KeyModifiers::SHIFT, "shift")] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            KeyCode::BackTab => write!(f, "backtab"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            code => write!(f, "{}", format!("{:?}", code).to_lowercase()),
+        }
+    }
+}
+
+/// Parses a key sequence: keys separated by spaces (`g g`, `ctrl+w j`), or a
+/// run of characters written together (`gg`).
+///
+/// # Errors
+///
+/// Returns a message naming the first key that cannot be parsed.
+pub fn parse_sequence(s: &str) -> Result<Vec<KeySpec>, String> {
+    let tokens: Vec<&str> = s.split_whitespace().collect();
+    match tokens.as_slice() {
+        [] => Err("empty key sequence".to_string()),
+        [token] => match token.parse() {
+            Ok(key) => Ok(vec![key]),
+            // "gg" is shorthand for "g g"
+            Err(e) if token.contains('+') || token.chars().count() > 8 => Err(e),
+            Err(e) if token.chars().all(|c| c.is_alphanumeric()) && !is_key_name(token) => {
+                token.chars().map(|c| c.to_string().parse()).collect::<Result<_, _>>().map_err(|_: String| e)
+            }
+            Err(e) => Err(e),
+        },
+        tokens => tokens.iter().map(|t| t.parse()).collect(),
+    }
+}
+
+/// Returns `true` for words that look like (misspelled) key names rather than
+/// a run of characters; only runs of one repeated character are shorthand.
+fn is_key_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    let first = chars.next();
+    !chars.all(|c| Some(c) == first)
+}
+
+/// Formats a key sequence for display (e.g. `gg`, `Ctrl-w j`).
+pub fn format_sequence(keys: &[KeySpec]) -> String {
+    let labels: Vec<String> = keys.iter().map(KeySpec::label).collect();
+    if labels.iter().all(|l| l.chars().count() == 1) {
+        labels.concat()
+    } else {
+        labels.join(" ")
+    }
+}
+
+/// A key sequence bound to an action in a context.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    /// Pane in which the binding applies
+    pub context: Context,
+    /// Keys to press, in order
+    pub keys: Vec<KeySpec>,
+    /// Action triggered
+    pub action: Action,
+}
+
+/// Result of looking up the keys pressed so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    /// The keys trigger this action
+    Action(Action),
+    /// The keys trigger this action, but are also the start of a longer
+    /// sequence; the action runs if no further key follows in time
+    Ambiguous(Action),
+    /// The keys are the start of a sequence
+    Pending,
+    /// Nothing is bound to the keys
+    Unbound,
+}
+
+/// Default bindings: context, key sequence and action.
+pub const DEFAULT_BINDINGS: &[(Context, &str, Action)] = &[
+    (Context::Global, "q", Action::Quit),
+    (Context::Global, "space", Action::PlayPause),
+    (Context::Global, "]", Action::Next),
+    (Context::Global, "[", Action::Prev),
+    (Context::Global, "+", Action::VolumeUp),
+    (Context::Global, "-", Action::VolumeDown),
+    (Context::Global, "right", Action::SeekForward),
+    (Context::Global, "left", Action::SeekBack),
+    (Context::Global, "/", Action::Search),
+    (Context::Global, "esc", Action::ClearFilter),
+    (Context::Global, "S", Action::SaveSmart),
+    (Context::Global, "o", Action::Sort),
+    (Context::Global, "O", Action::ReverseSort),
+    (Context::Global, "z", Action::Shuffle),
+    (Context::Global, "p", Action::ToggleSidebar),
+    (Context::Global, "tab", Action::FocusNext),
+    (Context::Global, "b", Action::Bookmark),
+    (Context::Global, "B", Action::ToggleBookmarks),
+    (Context::Global, "t", Action::NextTheme),
+    (Context::Global, ":", Action::CommandLine),
+    (Context::Global, "?", Action::Help),
+    (Context::Global, "M", Action::Messages),
+    (Context::Global, "ctrl+z", Action::Suspend),
+    (Context::Global, "up", Action::Up),
+    (Context::Global, "k", Action::Up),
+    (Context::Global, "down", Action::Down),
+    (Context::Global, "j", Action::Down),
+    (Context::Global, "g g", Action::Top),
+    (Context::Global, "home", Action::Top),
+    (Context::Global, "G", Action::Bottom),
+    (Context::Global, "end", Action::Bottom),
+    (Context::Global, "enter", Action::Activate),
+    (Context::Global, "d", Action::Delete),
+    (Context::Global, "delete", Action::Delete),
+    (Context::Tracks, "ctrl+d", Action::HalfPageDown),
+    (Context::Tracks, "ctrl+u", Action::HalfPageUp),
+    (Context::Tracks, "z z", Action::CenterPlaying),
+    (Context::Tracks, "m", Action::SetMark),
+    (Context::Tracks, "'", Action::JumpToMark),
+    (Context::Tracks, "r", Action::Repeat),
+    (Context::Tracks, "a", Action::AddToPlaylist),
+    (Context::Tracks, "K", Action::MoveEntryUp),
+    (Context::Tracks, "J", Action::MoveEntryDown),
+    // Digits alone type counts (`3j`), so ratings take a prefix
+    (Context::Tracks, "R 0", Action::Rate(0)),
+    (Context::Tracks, "R 1", Action::Rate(1)),
+    (Context::Tracks, "R 2", Action::Rate(2)),
+    (Context::Tracks, "R 3", Action::Rate(3)),
+    (Context::Tracks, "R 4", Action::Rate(4)),
+    (Context::Tracks, "R 5", Action::Rate(5)),
+    (Context::Tracks, "f", Action::Favorite),
+    (Context::Sidebar, "n", Action::NewPlaylist),
+    (Context::Sidebar, "r", Action::Rename),
+    (Context::Bookmarks, "esc", Action::Close),
+];
+
+/// Actions shown in the status bar: the actions whose keys are joined by the
+/// separator, and the label.
+pub const STATUS_HINTS: &[(&[Action], &str, &str)] = &[
+    (&[Action::Quit], "/", "退出"),
+    (&[Action::Help], "/", "帮助"),
+    (&[Action::Up, Action::Down], "/", "导航"),
+    (&[Action::Activate], "/", "播放"),
+    (&[Action::PlayPause], "/", "暂停"),
+    (&[Action::Prev, Action::Next], "/", "上/下一曲"),
+    (&[Action::VolumeUp, Action::VolumeDown], "/", "音量"),
+    (&[Action::FocusNext], "/", "播放列表"),
+    (&[Action::Search], "/", "搜索"),
+    (&[Action::Rate(0), Action::Rate(5)], "-", "评分"),
+    (&[Action::Favorite], "/", "收藏"),
+    (&[Action::SeekBack, Action::SeekForward], "/", "快退/快进"),
+    (&[Action::Bookmark, Action::ToggleBookmarks], "/", "书签"),
+    (&[Action::NextTheme], "/", "主题"),
+];
+
+/// Maps key sequences to actions.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl Keymap {
+    /// Creates the default keymap with `overrides` applied.
+    ///
+    /// An action with overrides loses all its default bindings, and default
+    /// bindings of the same keys in the same context are dropped.
+    pub fn new(overrides: &[Binding]) -> Self {
+        let mut bindings = overrides.to_vec();
+        for (context, keys, action) in DEFAULT_BINDINGS {
+            let keys = parse_sequence(keys).expect("default bindings are valid");
+            let replaced = overrides
+                .iter()
+                .any(|o| o.action == *action || (o.context == *context && o.keys == keys));
+            if !replaced {
+                bindings.push(Binding { context: *context, keys, action: *action });
+            }
+        }
+        Self { bindings }
+    }
+
+    /// Returns all bindings, overrides first.
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Looks up the keys pressed so far in a context and the global bindings.
+    pub fn lookup(&self, context: Context, keys: &[KeySpec]) -> Lookup {
+        let applies = |b: &&Binding| b.context == context || b.context == Context::Global;
+        // Bindings of the focused pane take precedence over global ones
+        let exact = self
+            .bindings
+            .iter()
+            .filter(applies)
+            .filter(|b| b.keys == keys)
+            .min_by_key(|b| b.context == Context::Global)
+            .map(|b| b.action);
+        let longer = self
+            .bindings
+            .iter()
+            .filter(applies)
+            .any(|b| b.keys.len() > keys.len() && b.keys.starts_with(keys));
+        match (exact, longer) {
+            (Some(action), false) => Lookup::Action(action),
+            (Some(action), true) => Lookup::Ambiguous(action),
+            (None, true) => Lookup::Pending,
+            (None, false) => Lookup::Unbound,
+        }
+    }
+
+    /// Returns the first key sequence bound to an action, if any.
+    pub fn keys_for(&self, action: Action) -> Option<&[KeySpec]> {
+        self.bindings.iter().find(|b| b.action == action).map(|b| b.keys.as_slice())
+    }
+
+    /// Builds the status-bar hints from [`STATUS_HINTS`].
+    ///
+    /// Hints whose actions are all unbound are left out.
+    pub fn status_hints(&self) -> Vec<(String, &'static str)> {
+        STATUS_HINTS
+            .iter()
+            .filter_map(|(actions, separator, label)| {
+                let keys: Vec<String> =
+                    actions.iter().filter_map(|a| self.keys_for(*a)).map(format_sequence).collect();
+                (!keys.is_empty()).then(|| (keys.join(separator), *label))
+            })
+            .collect()
+    }
+
+    /// Builds the help screen: for each context with bindings, its title and
+    /// one line per action with all its keys.
+    pub fn help(&self) -> Vec<(&'static str, Vec<(String, &'static str)>)> {
+        Context::ALL
+            .into_iter()
+            .filter_map(|context| {
+                let lines: Vec<(String, &'static str)> = Action::ALL
+                    .iter()
+                    .filter_map(|&action| {
+                        let keys: Vec<String> = self
+                            .bindings
+                            .iter()
+                            .filter(|b| b.context == context && b.action == action)
+                            .map(|b| format_sequence(&b.keys))
+                            .collect();
+                        (!keys.is_empty()).then(|| (keys.join(", "), action.description()))
+                    })
+                    .collect();
+                (!lines.is_empty()).then_some((context.title(), lines))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> KeySpec {
+        KeySpec::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_key_spec_parse_and_fold_shift() {
+        let spec: KeySpec = "ctrl+d".parse().unwrap();
+        assert_eq!(spec, KeySpec::from(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL)));
+        assert_ne!(spec, key('d'));
+        assert_eq!("shift+b".parse::<KeySpec>().unwrap(), KeySpec::from(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT)));
+        assert_eq!("B".parse::<KeySpec>().unwrap(), KeySpec::from(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::NONE)));
+        assert_eq!("+".parse::<KeySpec>().unwrap(), key('+'));
+        assert_eq!("alt++".parse::<KeySpec>().unwrap().to_string(), "alt++");
+        assert_eq!("F5".parse::<KeySpec>().unwrap().code, KeyCode::F(5));
+        assert_eq!("pageDown".parse::<KeySpec>().unwrap().to_string(), "pagedown");
+        assert_eq!("ctrl+u".parse::<KeySpec>().unwrap().label(), "Ctrl-u");
+        assert!("hyper+q".parse::<KeySpec>().is_err());
+        for (_, keys, _) in DEFAULT_BINDINGS {
+            let parsed = parse_sequence(keys).unwrap();
+            assert_eq!(parsed.iter().map(|k| k.to_string()).collect::<Vec<_>>().join(" "), *keys);
+        }
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        assert_eq!(parse_sequence("gg").unwrap(), vec![key('g'), key('g')]);
+        assert_eq!(parse_sequence("g g").unwrap(), vec![key('g'), key('g')]);
+        assert_eq!(parse_sequence("ctrl+w j").unwrap().len(), 2);
+        assert_eq!(parse_sequence("up").unwrap(), vec![KeySpec::new(KeyCode::Up, KeyModifiers::NONE)]);
+        assert!(parse_sequence("upp").is_err());
+        assert!(parse_sequence("").is_err());
+        assert_eq!(format_sequence(&parse_sequence("g g").unwrap()), "gg");
+        assert_eq!(format_sequence(&parse_sequence("ctrl+w j").unwrap()), "Ctrl-w j");
+    }
+
+    #[test]
+    fn test_action_names_roundtrip() {
+        for action in Action::ALL {
+            assert_eq!(action.to_string().parse::<Action>(), Ok(*action));
+            assert!(!action.description().is_empty());
+        }
+        assert_eq!("rate_4".parse::<Action>(), Ok(Action::Rate(4)));
+        assert!("rate_9".parse::<Action>().is_err());
+        assert_eq!(Action::Rename.default_context(), Context::Sidebar);
+    }
+
+    #[test]
+    fn test_lookup_sequences_and_contexts() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('q')]), Lookup::Action(Action::Quit));
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('g')]), Lookup::Pending);
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('g'), key('g')]), Lookup::Action(Action::Top));
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('r')]), Lookup::Action(Action::Repeat));
+        assert_eq!(keymap.lookup(Context::Sidebar, &[key('r')]), Lookup::Action(Action::Rename));
+        assert_eq!(keymap.lookup(Context::Sidebar, &[key('a')]), Lookup::Unbound);
+        let esc = [KeySpec::new(KeyCode::Esc, KeyModifiers::NONE)];
+        assert_eq!(keymap.lookup(Context::Bookmarks, &esc), Lookup::Action(Action::Close));
+        assert_eq!(keymap.lookup(Context::Tracks, &esc), Lookup::Action(Action::ClearFilter));
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('z')]), Lookup::Ambiguous(Action::Shuffle));
+        assert_eq!(keymap.lookup(Context::Sidebar, &[key('z')]), Lookup::Action(Action::Shuffle));
+        let ctrl_d = "ctrl+d".parse::<KeySpec>().unwrap();
+        assert_eq!(keymap.lookup(Context::Tracks, &[ctrl_d]), Lookup::Action(Action::HalfPageDown));
+    }
+
+    #[test]
+    fn test_overrides_replace_defaults() {
+        let overrides = [
+            Binding { context: Context::Global, keys: vec![key('p')], action: Action::PlayPause },
+            Binding { context: Context::Global, keys: vec![key('z'), key('z')], action: Action::Top },
+        ];
+        let keymap = Keymap::new(&overrides);
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('p')]), Lookup::Action(Action::PlayPause));
+        assert_eq!(keymap.lookup(Context::Tracks, &[key(' ')]), Lookup::Unbound);
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('g')]), Lookup::Unbound);
+        assert_eq!(keymap.lookup(Context::Tracks, &[key('z')]), Lookup::Ambiguous(Action::Shuffle));
+        assert_eq!(keymap.keys_for(Action::ToggleSidebar), None);
+
+        let hints = keymap.status_hints();
+        assert!(hints.contains(&("p".to_string(), "暂停")));
+        assert!(hints.contains(&("↑/↓".to_string(), "导航")));
+        assert!(hints.contains(&("R0-R5".to_string(), "评分")));
+        let help = keymap.help();
+        assert_eq!(help[0].0, "全局");
+        assert!(help[0].1.contains(&("zz".to_string(), "跳到第一行")));
+        assert!(help[0].1.contains(&("↑, k".to_string(), "上移")));
+    }
+}
@@ -8,7 +8,41 @@
 //! - `ui`: User interface components and layout system
 //! - `common`: Shared data structures and types
 //! - `player`: Audio playback engine
+//! - `playlist`: Playlist import/export and named playlists
+//! - `queue`: Playback queue followed by auto-advance
+//! - `paths`: XDG locations of the player's files
+//! - `tags`: Metadata reading from audio file tags
+//! - `query`: Query language for search and smart playlists
+//! - `library`: Library database of listening statistics, ratings and history
+//! - `session`: Session state saved on quit and restored on startup
+//! - `bookmarks`: Resume positions and named bookmarks for long-form audio
+//! - `config`: User configuration file with hot-reload
+//! - `keymap`: Actions, key bindings and key sequences
+//! - `command`: Ex-style command line with completion and history
+//! - `notify`: Toast notifications and the message log, fed by `tracing`
+//! - `app`: Application state machine: update and render
+//! - `clock`: Clock abstraction for deterministic time in tests
+//! - `harness`: End-to-end test harness with scripted input and a fake player
+//! - `remote`: Remote control from other programs over a Unix socket
+//! - `daemon`: Running without a terminal: the PID file
 
 pub mod ui;
 pub mod common;
 pub mod player;
+pub mod playlist;
+pub mod queue;
+pub mod paths;
+pub mod tags;
+pub mod query;
+pub mod library;
+pub mod session;
+pub mod bookmarks;
+pub mod config;
+pub mod keymap;
+pub mod command;
+pub mod notify;
+pub mod app;
+pub mod clock;
+pub mod harness;
+pub mod remote;
+pub mod daemon;
@@ -0,0 +1,674 @@
+//! Library database: listening statistics, ratings and history.
+//!
+//! The player records what is listened to so that it can be shown, sorted on
+//! and queried:
+//! - Per-track statistics (play count, skip count, last-played time, star
+//!   rating and favorite flag), saved as `library.tsv` in the data directory
+//! - A listening history log with one line per play or skip, appended to
+//!   `history.log` and trimmed to the most recent [`MAX_HISTORY`] entries
+//!
+//! Tracks are identified by their path, so statistics survive rescans and
+//! changes in library order.
+//!
+//! # File Formats
+//!
+//! Both files are tab-separated text with times as Unix seconds:
+//! - `library.tsv`: path, plays, skips, last played, rating, favorite (`0`/`1`)
+//! - `history.log`: time, `played` or `skipped`, seconds listened, path
+//!
+//! Tabs, newlines and backslashes in paths are escaped with a backslash.
+//!
+//! Malformed lines are skipped and reported rather than failing the whole
+//! load, so that one bad line never costs the rest of the statistics.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::common::Track;
+use crate::query::Facts;
+
+/// File name of the statistics table
+const STATS_FILE: &str = "library.tsv";
+/// File name of the listening history log
+const HISTORY_FILE: &str = "history.log";
+/// Number of history entries kept; older ones are dropped
+pub const MAX_HISTORY: usize = 10_000;
+/// Entries appended beyond [`MAX_HISTORY`] before the log is trimmed
+const HISTORY_SLACK: usize = 1_000;
+/// Listening sessions recorded before the statistics table is rewritten
+const SAVE_EVERY: usize = 10;
+
+/// Errors that can occur while loading or saving the library database.
+#[derive(Debug, Error)]
+pub enum LibraryError {
+    /// Reading or writing a database file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// A database file contains a malformed line
+    #[error("{file}:{line}: malformed entry")]
+    Parse {
+        /// File name of the database file
+        file: &'static str,
+        /// 1-based line number
+        line: usize,
+    },
+}
+
+/// Listening statistics of a single track.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrackStats {
+    /// Number of plays that reached the end of the track
+    pub play_count: u32,
+    /// Number of times the track was skipped before its end
+    pub skip_count: u32,
+    /// When the track was last started
+    pub last_played: Option<SystemTime>,
+    /// Star rating from 0 to 5 (0 means unrated)
+    pub rating: u8,
+    /// Whether the track is marked as a favorite
+    pub favorite: bool,
+}
+
+/// How a listening session ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryEvent {
+    /// The track played to its end
+    Played,
+    /// The user moved on before the end
+    Skipped,
+}
+
+impl HistoryEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Played => "played",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// One entry of the listening history.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// When the session ended
+    pub time: SystemTime,
+    /// How the session ended
+    pub event: HistoryEvent,
+    /// How long the track was listened to
+    pub listened: Duration,
+    /// Path of the track
+    pub path: PathBuf,
+}
+
+/// Persistent per-track statistics and listening history.
+#[derive(Debug)]
+pub struct LibraryDb {
+    dir: PathBuf,
+    stats: HashMap<PathBuf, TrackStats>,
+    history: Vec<HistoryEntry>,
+    /// Listening sessions recorded since the statistics table was written
+    unsaved: usize,
+    /// Listening sessions recorded since the history log was written
+    unlogged: usize,
+    /// Whether the history was trimmed since the log was written
+    trimmed: bool,
+    /// Whether ratings or favorites changed since the statistics table was
+    /// written
+    edited: bool,
+    /// Whether changes are kept in memory only
+    read_only: bool,
+}
+
+impl LibraryDb {
+    /// Creates an empty database for `dir` without reading it.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            stats: HashMap::new(),
+            history: Vec::new(),
+            unsaved: 0,
+            unlogged: 0,
+            trimmed: false,
+            edited: false,
+            read_only: false,
+        }
+    }
+
+    /// Stops writing to the directory: from now on, changes are kept in
+    /// memory only (e.g. while another process owns the files).
+    pub fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    /// Opens the database in `dir`, loading the statistics and history.
+    ///
+    /// Missing files are treated as empty. Malformed lines are skipped; the
+    /// statistics table drops them the next time it is written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the database and a [`LibraryError::Parse`] for each skipped line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be read. The caller should then keep
+    /// the database from writing (see [`LibraryDb::set_read_only`]), or it
+    /// would replace the unread files.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<(Self, Vec<LibraryError>), LibraryError> {
+        let mut db = Self::new(dir);
+        let mut skipped = Vec::new();
+        if let Some(content) = read_optional(&db.dir.join(STATS_FILE))? {
+            for (line_no, line) in data_lines(&content) {
+                let fields: Vec<&str> = line.split('\t').collect();
+                match parse_stats(&fields) {
+                    Some(parsed) => {
+                        db.stats.insert(PathBuf::from(unescape(fields[0])), parsed);
+                    }
+                    None => skipped.push(LibraryError::Parse { file: STATS_FILE, line: line_no }),
+                }
+            }
+        }
+        if let Some(content) = read_optional(&db.dir.join(HISTORY_FILE))? {
+            for (line_no, line) in data_lines(&content) {
+                match parse_history(line) {
+                    Some(entry) => db.history.push(entry),
+                    None => skipped.push(LibraryError::Parse { file: HISTORY_FILE, line: line_no }),
+                }
+            }
+            let excess = db.history.len().saturating_sub(MAX_HISTORY);
+            db.history.drain(..excess);
+        }
+        Ok((db, skipped))
+    }
+
+    /// Returns the statistics of a track (all zero if it was never played).
+    pub fn stats(&self, path: &Path) -> TrackStats {
+        self.stats.get(path).copied().unwrap_or_default()
+    }
+
+    /// Returns the listening history, oldest first (at most [`MAX_HISTORY`]
+    /// entries, plus those recorded since it was last trimmed).
+    pub fn history(&self) -> &[HistoryEntry] {
+        &self.history
+    }
+
+    /// Builds the query facts of a track from its statistics.
+    pub fn facts<'a>(&self, track: &'a Track) -> Facts<'a> {
+        let stats = self.stats(&track.path);
+        Facts {
+            play_count: stats.play_count,
+            skip_count: stats.skip_count,
+            last_played: stats.last_played,
+            rating: stats.rating,
+            favorite: stats.favorite,
+            ..Facts::new(track)
+        }
+    }
+
+    /// Records that a track started playing.
+    pub fn record_start(&mut self, path: &Path, now: SystemTime) {
+        self.stats.entry(path.to_path_buf()).or_default().last_played = Some(now);
+    }
+
+    /// Records the end of a listening session and adds it to the history,
+    /// which is trimmed once it grows well past [`MAX_HISTORY`] entries.
+    ///
+    /// Nothing is written until the next [`LibraryDb::flush`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the track
+    /// * `event` - Whether the track was played to the end or skipped
+    /// * `listened` - How long the track was listened to
+    /// * `now` - Time the session ended
+    pub fn record(&mut self, path: &Path, event: HistoryEvent, listened: Duration, now: SystemTime) {
+        let stats = self.stats.entry(path.to_path_buf()).or_default();
+        match event {
+            HistoryEvent::Played => stats.play_count += 1,
+            HistoryEvent::Skipped => stats.skip_count += 1,
+        }
+        self.history.push(HistoryEntry { time: now, event, listened, path: path.to_path_buf() });
+        self.unsaved += 1;
+        self.unlogged += 1;
+        if self.history.len() > MAX_HISTORY + HISTORY_SLACK {
+            let excess = self.history.len() - MAX_HISTORY;
+            self.history.drain(..excess);
+            self.trimmed = true;
+        }
+    }
+
+    /// Writes what changed since the last write (unless the database is
+    /// read-only).
+    ///
+    /// Recorded sessions are appended to the history log, which is replaced
+    /// instead once the history was trimmed. The statistics table is only
+    /// rewritten after ratings or favorites changed, or every few sessions
+    /// (and by [`LibraryDb::save`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the history log or statistics cannot be written;
+    /// what was not written is tried again by the next flush.
+    pub fn flush(&mut self) -> Result<(), LibraryError> {
+        if self.read_only {
+            return Ok(());
+        }
+        if self.unlogged > 0 || self.trimmed {
+            fs::create_dir_all(&self.dir)?;
+            if self.trimmed {
+                self.write_history()?;
+            } else {
+                let mut out = String::new();
+                for entry in &self.history[self.history.len().saturating_sub(self.unlogged)..] {
+                    out.push_str(&format_history(entry));
+                    out.push('\n');
+                }
+                let mut log = OpenOptions::new().create(true).append(true).open(self.dir.join(HISTORY_FILE))?;
+                log.write_all(out.as_bytes())?;
+            }
+            self.unlogged = 0;
+            self.trimmed = false;
+        }
+        if self.edited || self.unsaved >= SAVE_EVERY {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the history log with the entries in memory.
+    fn write_history(&self) -> Result<(), LibraryError> {
+        let mut out = String::new();
+        for entry in &self.history {
+            out.push_str(&format_history(entry));
+            out.push('\n');
+        }
+        let tmp = self.dir.join(format!("{}.tmp", HISTORY_FILE));
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, self.dir.join(HISTORY_FILE))?;
+        Ok(())
+    }
+
+    /// Sets the star rating of a track (clamped to 5; 0 clears it).
+    ///
+    /// The statistics table is rewritten by the next [`LibraryDb::flush`].
+    pub fn set_rating(&mut self, path: &Path, rating: u8) {
+        self.stats.entry(path.to_path_buf()).or_default().rating = rating.min(5);
+        self.edited = true;
+    }
+
+    /// Adopts a rating found in a track's tags, for tracks the database does
+    /// not know yet. Once a track has an entry, the database's rating wins,
+    /// so ratings cleared in the player stay cleared.
+    ///
+    /// Nothing is written until the next [`LibraryDb::save`].
+    pub fn seed_rating(&mut self, path: &Path, rating: u8) {
+        if !self.stats.contains_key(path) {
+            self.stats.insert(path.to_path_buf(), TrackStats { rating: rating.min(5), ..TrackStats::default() });
+        }
+    }
+
+    /// Flips the favorite flag of a track.
+    ///
+    /// The statistics table is rewritten by the next [`LibraryDb::flush`].
+    ///
+    /// # Returns
+    ///
+    /// Returns the new flag.
+    pub fn toggle_favorite(&mut self, path: &Path) -> bool {
+        let stats = self.stats.entry(path.to_path_buf()).or_default();
+        stats.favorite = !stats.favorite;
+        self.edited = true;
+        stats.favorite
+    }
+
+    /// Writes the statistics table, replacing the previous file atomically
+    /// (unless the database is read-only).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&mut self) -> Result<(), LibraryError> {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut entries: Vec<_> = self.stats.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        let mut out = String::from("# path\tplays\tskips\tlast_played\trating\tfavorite\n");
+        for (path, stats) in entries {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                escape(&path.to_string_lossy()),
+                stats.play_count,
+                stats.skip_count,
+                stats.last_played.map(unix_secs).map(|s| s.to_string()).unwrap_or_default(),
+                stats.rating,
+                u8::from(stats.favorite),
+            ));
+        }
+        fs::create_dir_all(&self.dir)?;
+        let tmp = self.dir.join(format!("{}.tmp", STATS_FILE));
+        fs::write(&tmp, out)?;
+        fs::rename(tmp, self.dir.join(STATS_FILE))?;
+        self.unsaved = 0;
+        self.edited = false;
+        Ok(())
+    }
+
+    /// Returns the most recently played tracks, newest first, without repeats.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Maximum number of paths to return
+    pub fn recently_played(&self, limit: usize) -> Vec<&Path> {
+        let mut seen = std::collections::HashSet::new();
+        self.history
+            .iter()
+            .rev()
+            .map(|e| e.path.as_path())
+            .filter(|p| seen.insert(*p))
+            .take(limit)
+            .collect()
+    }
+
+    /// Returns the tracks with the most completed plays, most played first.
+    ///
+    /// Tracks that were never played to the end are left out; ties are
+    /// ordered by path.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Maximum number of paths to return
+    pub fn most_played(&self, limit: usize) -> Vec<&Path> {
+        let mut played: Vec<_> = self.stats.iter().filter(|(_, s)| s.play_count > 0).collect();
+        played.sort_by(|a, b| b.1.play_count.cmp(&a.1.play_count).then_with(|| a.0.cmp(b.0)));
+        played.into_iter().take(limit).map(|(p, _)| p.as_path()).collect()
+    }
+}
+
+/// Formats the time since `then` compactly (`now`, `5m`, `3h`, `2d`, `6w`, `1y`).
+pub fn format_age(then: SystemTime, now: SystemTime) -> String {
+    let secs = now.duration_since(then).unwrap_or_default().as_secs();
+    match secs {
+        0..=59 => "now".to_string(),
+        60..=3_599 => format!("{}m", secs / 60),
+        3_600..=86_399 => format!("{}h", secs / 3_600),
+        86_400..=1_209_599 => format!("{}d", secs / 86_400),
+        1_209_600..=31_535_999 => format!("{}w", secs / 604_800),
+        _ => format!("{}y", secs / 31_536_000),
+    }
+}
+
+/// Reads a file, returning `None` if it does not exist.
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Iterates over non-empty, non-comment lines with 1-based line numbers.
+fn data_lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+}
+
+/// Parses the numeric columns of a statistics line.
+///
+/// The rating and favorite columns are optional so that tables written before
+/// they existed still load.
+fn parse_stats(fields: &[&str]) -> Option<TrackStats> {
+    if fields.len() < 4 {
+        return None;
+    }
+    Some(TrackStats {
+        play_count: fields[1].parse().ok()?,
+        skip_count: fields[2].parse().ok()?,
+        last_played: match fields[3] {
+            "" => None,
+            secs => Some(UNIX_EPOCH.checked_add(Duration::from_secs(secs.parse().ok()?))?),
+        },
+        rating: match fields.get(4) {
+            Some(rating) => rating.parse::<u8>().ok().filter(|&r| r <= 5)?,
+            None => 0,
+        },
+        favorite: match fields.get(5).copied() {
+            Some("1") => true,
+            Some("0") | None => false,
+            Some(_) => return None,
+        },
+    })
+}
+
+fn parse_history(line: &str) -> Option<HistoryEntry> {
+    let mut fields = line.splitn(4, '\t');
+    let time = UNIX_EPOCH.checked_add(Duration::from_secs(fields.next()?.parse().ok()?))?;
+    let event = match fields.next()? {
+        "played" => HistoryEvent::Played,
+        "skipped" => HistoryEvent::Skipped,
+        _ => return None,
+    };
+    let listened = Duration::from_secs(fields.next()?.parse().ok()?);
+    let path = PathBuf::from(unescape(fields.next()?));
+    Some(HistoryEntry { time, event, listened, path })
+}
+
+fn format_history(entry: &HistoryEntry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        unix_secs(entry.time),
+        entry.event.as_str(),
+        entry.listened.as_secs(),
+        escape(&entry.path.to_string_lossy()),
+    )
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Escapes tabs, newlines and backslashes.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reverses [`escape`].
+pub(crate) fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use proptest::prelude::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_record_and_reload() {
+        let dir = TempDir::new("library");
+        let a = Path::new("/music/a.flac");
+        let b = Path::new("/music/b\tc.mp3");
+
+        let mut db = LibraryDb::new(dir.path());
+        db.record_start(a, at(100));
+        db.record(a, HistoryEvent::Played, Duration::from_secs(300), at(400));
+        db.record_start(b, at(400));
+        db.record(b, HistoryEvent::Skipped, Duration::from_secs(5), at(405));
+        assert!(!dir.join(HISTORY_FILE).exists(), "nothing is written before a flush");
+        db.flush().unwrap();
+        db.record_start(a, at(405));
+        db.record(a, HistoryEvent::Played, Duration::from_secs(300), at(705));
+        db.flush().unwrap();
+        // Plays are appended to the history; the table waits for more or a save
+        assert_eq!(fs::read_to_string(dir.join(HISTORY_FILE)).unwrap().lines().count(), 3);
+        assert!(!dir.join(STATS_FILE).exists());
+        db.save().unwrap();
+
+        let (db, skipped) = LibraryDb::open(dir.path()).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(
+            db.stats(a),
+            TrackStats { play_count: 2, skip_count: 0, last_played: Some(at(405)), ..TrackStats::default() }
+        );
+        assert_eq!(db.stats(b).skip_count, 1);
+        assert_eq!(db.history().len(), 3);
+        assert_eq!(db.history()[1].path, b);
+        assert_eq!(db.recently_played(10), vec![a, b]);
+        assert_eq!(db.most_played(10), vec![a]);
+    }
+
+    #[test]
+    fn test_open_skips_malformed_lines() {
+        let dir = TempDir::new("library");
+        fs::write(dir.join(STATS_FILE), "# header\n/a.mp3\tmany\t0\t\n/b.mp3\t4\t1\t99999999999999999999\n/c.mp3\t7\t0\t\n")
+            .unwrap();
+        // A time too large for `SystemTime` is as malformed as any other
+        fs::write(dir.join(HISTORY_FILE), "18446744073709551615\tplayed\t3\t/c.mp3\n10\tplayed\t3\t/c.mp3\n").unwrap();
+
+        let (db, skipped) = LibraryDb::open(dir.path()).unwrap();
+        let lines: Vec<_> = skipped
+            .iter()
+            .map(|e| match e {
+                LibraryError::Parse { file, line } => (*file, *line),
+                other => panic!("{}", other),
+            })
+            .collect();
+        assert_eq!(lines, [(STATS_FILE, 2), (STATS_FILE, 3), (HISTORY_FILE, 1)]);
+        assert_eq!(db.stats(Path::new("/c.mp3")).play_count, 7);
+        assert_eq!(db.history().len(), 1);
+    }
+
+    #[test]
+    fn test_read_only_database_keeps_files() {
+        let dir = TempDir::new("library");
+        fs::write(dir.join(STATS_FILE), "/a.mp3\t7\t0\t\n").unwrap();
+        let mut db = LibraryDb::new(dir.path());
+        db.set_read_only();
+        db.set_rating(Path::new("/b.mp3"), 3);
+        db.record(Path::new("/b.mp3"), HistoryEvent::Played, Duration::from_secs(1), at(1));
+        db.flush().unwrap();
+        db.save().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join(STATS_FILE)).unwrap(), "/a.mp3\t7\t0\t\n");
+        assert!(!dir.join(HISTORY_FILE).exists());
+        assert_eq!(db.stats(Path::new("/b.mp3")).rating, 3);
+    }
+
+    #[test]
+    fn test_history_is_trimmed() {
+        let dir = TempDir::new("library");
+        let mut db = LibraryDb::new(dir.path());
+        let path = Path::new("/a.mp3");
+        for i in 0..=(MAX_HISTORY + HISTORY_SLACK) as u64 {
+            db.record(path, HistoryEvent::Played, Duration::from_secs(1), at(i));
+        }
+        assert_eq!(db.history().len(), MAX_HISTORY);
+        assert_eq!(db.history()[0].time, at((HISTORY_SLACK + 1) as u64));
+        assert_eq!(db.stats(path).play_count as usize, MAX_HISTORY + HISTORY_SLACK + 1);
+        db.flush().unwrap();
+
+        let (reopened, _) = LibraryDb::open(dir.path()).unwrap();
+        assert_eq!(reopened.history(), db.history());
+        assert_eq!(reopened.stats(path).play_count as usize, MAX_HISTORY + HISTORY_SLACK + 1);
+    }
+
+    #[test]
+    fn test_rating_and_favorite_persist() {
+        let dir = TempDir::new("library");
+        let path = Path::new("/music/a.flac");
+
+        let mut db = LibraryDb::new(dir.path());
+        db.set_rating(path, 9);
+        assert!(db.toggle_favorite(path));
+        db.flush().unwrap();
+        let (db, _) = LibraryDb::open(dir.path()).unwrap();
+        assert_eq!(db.stats(path).rating, 5);
+        assert!(db.stats(path).favorite);
+
+        // Tables without the rating columns still load
+        fs::write(dir.join(STATS_FILE), "/old.mp3\t3\t0\t\n").unwrap();
+        let (db, _) = LibraryDb::open(dir.path()).unwrap();
+        assert_eq!(db.stats(Path::new("/old.mp3")).play_count, 3);
+        assert_eq!(db.stats(Path::new("/old.mp3")).rating, 0);
+    }
+
+    #[test]
+    fn test_tag_ratings_only_seed_unknown_tracks() {
+        let dir = TempDir::new("library");
+        let (known, new) = (Path::new("/known.flac"), Path::new("/new.flac"));
+        let mut db = LibraryDb::new(dir.path());
+        db.set_rating(known, 0);
+
+        db.seed_rating(known, 4);
+        db.seed_rating(new, 4);
+        assert_eq!(db.stats(known).rating, 0, "a rating cleared in the player stays cleared");
+        assert_eq!(db.stats(new).rating, 4);
+        db.save().unwrap();
+        assert_eq!(LibraryDb::open(dir.path()).unwrap().0.stats(new).rating, 4);
+    }
+
+    #[test]
+    fn test_facts_include_stats() {
+        let track = Track { path: PathBuf::from("/a.mp3"), ..Track::default() };
+        let mut db = LibraryDb::new(std::env::temp_dir());
+        db.record_start(&track.path, at(50));
+        let facts = db.facts(&track);
+        assert_eq!(facts.last_played, Some(at(50)));
+        assert_eq!(facts.play_count, 0);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(at(0), at(30)), "now");
+        assert_eq!(format_age(at(0), at(150)), "2m");
+        assert_eq!(format_age(at(0), at(3 * 86_400)), "3d");
+        assert_eq!(format_age(at(0), at(21 * 86_400)), "3w");
+        assert_eq!(format_age(at(0), at(800 * 86_400)), "2y");
+    }
+
+    proptest! {
+        #[test]
+        fn prop_escape_roundtrip(text in ".*") {
+            let escaped = escape(&text);
+            prop_assert!(!escaped.contains('\t') && !escaped.contains('\n'));
+            prop_assert_eq!(unescape(&escaped), text);
+        }
+    }
+}
@@ -14,162 +14,171 @@
 //! cargo run
 //! ```
 //!
+//! Library directories, file extensions, player and UI options, the color
+//! theme (built-in or a file in `themes/`) and keybindings can be set in `$XDG_CONFIG_HOME/tools-rs/config.toml` (see
+//! `tools_rs::config`). Changes to the file apply while the player runs;
+//! library directories and extensions are read on startup only.
+//!
 //! # Keyboard Controls
 //!
+//! The default bindings are listed below; any action can be rebound in the
+//! `[keys]` section of the config file (see `tools_rs::keymap`), and the
+//! status bar always shows the keys actually bound.
+//!
 //! - `q`: Quit
 //! - `↑/↓` or `j/k`: Navigate track list
+//! - `gg`/`G` or `Home`/`End`: Jump to the first/last row
+//! - `Ctrl-d`/`Ctrl-u`: Move half a page down/up
+//! - `zz`: Select the playing track and center it (`z` alone shuffles after a pause)
+//! - `m<x>`/`'<x>`: Set mark `x` on the selected track / jump to it (`''` jumps back)
 //! - `Enter`: Play selected track
-//! - `Space`: Toggle play/pause
+//! - `Space`: Toggle play/pause (or start the restored track)
 //! - `[/]`: Previous/next track
 //! - `+/-`: Increase/decrease volume
-
+//! - `Tab`: Switch focus between the track list and the playlist sidebar
+//! - `p`: Show/hide the playlist sidebar
+//! - `a`: Add the selected track to the playlist highlighted in the sidebar
+//! - `d`, `J/K`: Remove / move down / move up the selected playlist entry
+//! - `R 0`-`R 5`: Rate the selected track (`R 0` clears the rating)
+//! - `f`: Mark/unmark the selected track as a favorite
+//! - `o`/`O`: Cycle the sort column / reverse the sort order
+//! - `←/→`: Seek back/forward (10 seconds by default)
+//! - `b`: Bookmark the current position of the playing track
+//! - `B`: Show/hide the bookmarks of the playing track (`Enter` jumps, `d` deletes)
+//! - `t`: Switch to the next color theme
+//! - `z`: Toggle shuffle
+//! - `r`: Cycle repeat (off, all, one)
+//! - `/`: Filter the track list with a query (e.g. `genre:jazz year:>=1960`)
+//! - `Esc`: Clear the filter
+//! - `S`: Save the current filter as a smart playlist
+//! - `?`: Show all key bindings and commands
+//! - `M`: Show the message log
+//! - `Ctrl-Z`: Suspend to the shell (`fg` returns); playback continues unless
+//!   `pause_on_suspend` is set
+//! - `:`: Command line (`:play 12`, `:vol 80`, `:seek +30`, `:theme nord`,
+//!   `:save playlist road-trip`, `:import /lists/road-trip.m3u`, `:export mix.xspf`,
+//!   `:filter artist:Miles`; see `tools_rs::command`),
+//!   with `Tab` completion and `↑/↓` history
+//!
+//! A count typed before a motion repeats it (`5j`, `3]`) or picks the row
+//! (`12G`); digits always start a count, which is why ratings take the `R`
+//! prefix.
+//! The status bar shows the input mode and the count or keys typed so far.
+//!
+//! In the playlist sidebar, `Enter` loads the highlighted playlist into the
+//! queue, `n` creates, `r` renames and `d` deletes a playlist. The sidebar also
+//! offers "Recently played" and "Most played" views; smart playlists are listed
+//! after the named ones and are re-evaluated whenever they are shown.
+//!
+//! Play counts, skips, ratings, favorites and the listening history are
+//! recorded in the library database in the XDG data directory. Start with
+//! `--write-tags` to also write ratings into the files' tags.
+//!
+//! The session (current track and position, volume, queue, shuffle/repeat,
+//! view and sort) is saved on quit (including on SIGTERM, SIGINT and SIGHUP)
+//! and restored on the next launch. Long tracks (20 minutes or more) resume
+//! where they were left.
+//!
+//! While running, the player can be controlled from scripts and other
+//! terminals through a Unix socket at `$XDG_RUNTIME_DIR/tools-rs.sock`,
+//! one JSON command per line (see `tools_rs::remote`).
+//!
+//! The same commands are available from the shell: `tools-rs ctl toggle`,
+//! `next`, `prev`, `vol +5`, `seek 1:30`, `enqueue <paths>`, `status
+//! --format '{artist} - {title}'` and more (`tools-rs ctl` lists them). They
+//! exit with an error when no player is running.
+//!
+//! With `mpd_port` set in the `[remote]` section, the player also speaks a
+//! subset of the MPD protocol on that port of localhost, so that MPD clients
+//! such as ncmpcpp can show and control playback (see
+//! `tools_rs::remote::mpd`). With `http_address` set, it serves a web
+//! remote and a JSON API there, e.g. at `http://<host>:8080/` for a phone on
+//! the same network when it listens on `0.0.0.0:8080`; `http_token` then
+//! keeps others on the network out (see `tools_rs::remote::http`). The
+//! `[remote]` section is read on startup only.
+//!
+//! `tools-rs --daemon` plays without a terminal, e.g. as a service: it logs
+//! to `tools-rs.log` in `$XDG_STATE_HOME/tools-rs`, writes its process ID to
+//! `$XDG_RUNTIME_DIR/tools-rs.pid`, and saves the session and quits on
+//! SIGTERM. It is controlled only through the socket, MPD and HTTP. Starting
+//! `tools-rs` in a terminal while a player is running attaches to it: the UI
+//! shows and controls that player's playback and queue instead of playing
+//! itself, and quitting it leaves the player running (see `tools_rs::daemon`).
+//!
+//! Errors and other messages appear as toasts that disappear by themselves
+//! and stay in the message log. They are also written, along with the rest
+//! of the diagnostics, to `$XDG_STATE_HOME/tools-rs/tools-rs.log` (the level
+//! can be set with `RUST_LOG`).
+//!
+//! # Mouse Controls
+//!
+//! - Wheel: Scroll the track list, sidebar or bookmarks under the pointer;
+//!   over the volume readout, change the volume
+//! - Click: Select a track, playlist or bookmark; double-click plays the
+//!   track, opens the playlist or jumps to the bookmark
+//! - Click or drag on the progress bar: Seek
+//! - Drag on the volume readout: Change the volume
+//! - Click on the visualization: Cycle its mode (wave, bars, level)
+
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
+use anyhow::Context;
 use anyhow::Result;
-use crossterm::event::{self, Event as CEvent, KeyCode, KeyEventKind};
-use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use tracing::error;
-use walkdir::WalkDir;
-
-mod ui;
-mod player;
-mod common;
-
-use common::{AppEvent, AppCommand, PlaybackStatus, Track};
-use ui::theme::Theme;
-use ui::layout::{LayoutManager, AppLayout};
-
-/// Main application state.
-struct App {
-    /// List of all available tracks
-    tracks: Vec<Track>,
-    /// Index of currently selected track
-    selected: usize,
-    /// Index of currently playing track (if any)
-    playing: Option<usize>,
-    /// Current playback status
-    status: PlaybackStatus,
-    /// Current playback position
-    position: Duration,
-    /// Total duration of current track
-    total: Option<Duration>,
-    /// Current volume level (0.0 to 2.0)
-    volume: f32,
-    /// Channel sender for player commands
-    cmd_tx: Sender<AppCommand>,
-    /// Channel receiver for player events
-    evt_rx: Receiver<AppEvent>,
-    /// Timestamp of last tick for timing
-    last_tick: Instant,
-    /// Waveform data for visualization
-    wave: Vec<u64>,
-    /// UI color theme
-    theme: Theme,
-    /// Whether UI is in compact mode
-    compact_mode: bool,
-    /// Cached layout to avoid recalculation
-    cached_layout: Option<(u16, u16, AppLayout)>,
-}
-
-impl App {
-    /// Creates a new application instance.
-    ///
-    /// # Arguments
-    ///
-    /// * `tracks` - List of available audio tracks
-    /// * `cmd_tx` - Channel sender for player commands
-    /// * `evt_rx` - Channel receiver for player events
-    fn new(tracks: Vec<Track>, cmd_tx: Sender<AppCommand>, evt_rx: Receiver<AppEvent>) -> Self {
-        Self {
-            tracks,
-            selected: 0,
-            playing: None,
-            status: PlaybackStatus::Stopped,
-            position: Duration::from_secs(0),
-            total: None,
-            volume: 1.0,
-            cmd_tx,
-            evt_rx,
-            last_tick: Instant::now(),
-            wave: vec![0; 80],
-            theme: Theme::default(),
-            compact_mode: false,
-            cached_layout: None,
-        }
-    }
-
-    /// Gets the layout for the current terminal size, using cache if available.
-    ///
-    /// This method implements layout caching to avoid recalculating the layout
-    /// on every frame when the terminal size hasn't changed.
-    ///
-    /// # Arguments
-    ///
-    /// * `width` - Current terminal width
-    /// * `height` - Current terminal height
-    ///
-    /// # Returns
-    ///
-    /// Returns a reference to the calculated layout.
-    fn get_layout(&mut self, width: u16, height: u16) -> &AppLayout {
-        // Check if we have a cached layout for this size
-        let needs_recalc = match &self.cached_layout {
-            Some((cached_w, cached_h, _)) => *cached_w != width || *cached_h != height,
-            None => true,
-        };
-
-        if needs_recalc {
-            let size = ratatui::layout::Rect::new(0, 0, width, height);
-            let layout_manager = LayoutManager::new(size);
-            let layout = layout_manager.calculate_layout();
-            self.cached_layout = Some((width, height, layout));
-        }
-
-        // Safe to unwrap because we just set it
-        &self.cached_layout.as_ref().unwrap().2
-    }
-}
-
-/// Scans a directory recursively for audio files.
-///
-/// Searches for files with supported audio extensions (mp3, flac, ogg, wav)
-/// and creates Track instances for each found file.
-///
-/// # Arguments
+use tracing::{error, info, warn};
+
+use tools_rs::app::{self, App, Effect, Msg};
+use tools_rs::common::AppCommand;
+use tools_rs::config::{self, Config, ConfigWatcher};
+#[cfg(unix)]
+use tools_rs::daemon::PidFile;
+use tools_rs::notify::{self, Notification};
+use tools_rs::remote::http::HttpServer;
+use tools_rs::remote::{RemoteError, Request};
+use tools_rs::remote::mpd::{self, MpdServer};
+use tools_rs::{paths, player, tags, ui};
+
+/// Time between checks of the config file for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Sends `tracing` output to the log file instead of the terminal, which
+/// belongs to the UI, and forwards the player's messages to the UI as
+/// notifications.
 ///
-/// * `dir` - Directory path to scan
+/// The log file level defaults to `info` and can be changed with `RUST_LOG`.
+/// Without a writable log file, messages only reach the UI.
 ///
 /// # Returns
 ///
-/// Returns a vector of Track instances for all found audio files.
-fn scan_directory(dir: PathBuf) -> Vec<Track> {
-    let mut out = Vec::new();
-    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if !path.is_file() {
-            continue;
-        }
-        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            let ok = matches!(ext.to_lowercase().as_str(), "mp3" | "flac" | "ogg" | "wav");
-            if ok {
-                out.push(Track {
-                    id: out.len() as u64,
-                    path: path.to_path_buf(),
-                    duration: None,
-                    title: path.file_name().and_then(|s| s.to_str()).map(|s| s.to_string()),
-                });
-            }
-        }
-    }
-    out
+/// Returns the channel the notifications arrive on.
+fn init_logging() -> Receiver<Notification> {
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::EnvFilter;
+
+    let path = paths::log_file();
+    let file = path.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::OpenOptions::new().create(true).append(true).open(&path));
+    let file_layer = file.ok().map(|file| {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        tracing_subscriber::fmt::layer()
+            .with_writer(std::sync::Mutex::new(file))
+            .with_ansi(false)
+            .with_filter(filter)
+    });
+    let (notification_layer, rx) = notify::layer();
+    tracing_subscriber::registry().with(file_layer).with(notification_layer).init();
+    rx
 }
 
 /// Main application entry point.
 ///
 /// Initializes the application, sets up the terminal UI, starts the player thread,
-/// and runs the main event loop.
+/// and runs the main event loop. When a player is already running (e.g. one
+/// started with `--daemon`), the UI attaches to it instead of playing itself.
 ///
 /// # Returns
 ///
@@ -182,497 +191,412 @@ fn scan_directory(dir: PathBuf) -> Vec<Track> {
 /// - Player thread cannot be started
 /// - Terminal operations fail during runtime
 fn run() -> Result<()> {
-    tracing_subscriber::fmt().with_env_filter(tracing_subscriber::EnvFilter::from_default_env()).init();
-    let cwd = std::env::current_dir()?;
-    let tracks = scan_directory(cwd);
-    let (cmd_tx, cmd_rx) = mpsc::channel();
-    let (evt_tx, evt_rx) = mpsc::channel();
-    let _player_handle = player::start(cmd_rx, evt_tx)?;
-
-    enable_raw_mode()?;
-    let mut terminal = ui::init_terminal()?;
-    let tick_rate = Duration::from_millis(200);
-
-    let mut app = App::new(tracks, cmd_tx.clone(), evt_rx);
-
-    loop {
-        // Process all pending player events
-        while let Ok(evt) = app.evt_rx.try_recv() {
-            match evt {
-                AppEvent::TrackStarted { index, duration } => {
-                    app.playing = Some(index);
-                    app.status = PlaybackStatus::Playing;
-                    app.position = Duration::from_secs(0);
-                    app.total = duration;
-                }
-                AppEvent::Progress { position } => {
-                    app.position = position;
-                }
-                AppEvent::TrackEnded => {
-                    // Auto-advance to next track
-                    if let Some(i) = app.playing {
-                        let next = if i + 1 < app.tracks.len() { i + 1 } else { i };
-                        app.selected = next;
-                        let t = app.tracks[next].clone();
-                        app.cmd_tx.send(AppCommand::Play { index: next, path: t.path }).ok();
-                    }
-                }
-                AppEvent::Error { message } => {
-                    error!("{}", message);
-                    app.status = PlaybackStatus::Stopped;
+    let notification_rx = init_logging();
+    let (config_watcher, config, write_tags_flag) = load_config()?;
+    let roots = library_roots(&config)?;
+    let tracks = app::scan_directory(&roots, &config.library.extensions);
+
+    // Everything the UI reacts to arrives on one channel
+    let (tx, rx) = mpsc::channel();
+    spawn_signal_handler(tx.clone()).context("无法注册信号处理")?;
+    app::forward(notification_rx, tx.clone(), Msg::Notification);
+    spawn_config_watcher(config_watcher, write_tags_flag, tx.clone());
+
+    let (mut app, backend, _servers) = match attach(tx.clone()) {
+        Some(requests) => (App::start(tracks, config).attached(), Backend::Attached(requests), None),
+        None => {
+            let backend = Backend::start(&config, tx.clone())?;
+            let remote = start_remote(tx.clone()).map_err(|e| error!("无法启动远程控制: {}", e)).ok();
+            let servers = Servers::start(remote, &config, &roots, tx.clone());
+            (App::start(tracks, config), backend, Some(servers))
+        }
+    };
+
+    ui::install_panic_hook();
+    let mut terminal = ui::TerminalGuard::new()?;
+    let size = terminal.size()?;
+    tx.send(Msg::Resize(size.width, size.height)).ok();
+    app::spawn_input(tx);
+
+    event_loop(&mut app, &rx, &backend, Some(&mut terminal))?;
+    app.save();
+    backend.shutdown();
+    Ok(())
+}
+
+/// Runs the player without a terminal until SIGTERM (see `tools_rs::daemon`).
+///
+/// # Errors
+///
+/// Returns an error if the configuration cannot be loaded, another player
+/// is running, or the socket, the PID file or the player thread cannot be
+/// set up.
+#[cfg(unix)]
+fn daemon() -> Result<()> {
+    let notification_rx = init_logging();
+    let (config_watcher, config, write_tags_flag) = load_config()?;
+    let roots = library_roots(&config)?;
+
+    let (tx, rx) = mpsc::channel();
+    spawn_signal_handler(tx.clone()).context("无法注册信号处理")?;
+    // Messages are only logged, but also kept for the message log of the app
+    app::forward(notification_rx, tx.clone(), Msg::Notification);
+    spawn_config_watcher(config_watcher, write_tags_flag, tx.clone());
+    // Without the socket there would be no way to control the daemon
+    let remote = start_remote(tx.clone()).context("无法启动远程控制")?;
+    let _servers = Servers::start(Some(remote), &config, &roots, tx.clone());
+    let pid_file = PidFile::create(&paths::pid_file()).context("无法写入 PID 文件")?;
+
+    let tracks = app::scan_directory(&roots, &config.library.extensions);
+    let backend = Backend::start(&config, tx)?;
+    info!("守护进程已启动 (PID {}，{})", std::process::id(), pid_file.path().display());
+    let mut app = App::start(tracks, config);
+    event_loop(&mut app, &rx, &backend, None)?;
+    app.save();
+    backend.shutdown();
+    info!("守护进程已退出");
+    Ok(())
+}
+
+/// Running without a terminal needs Unix sockets to be controlled.
+#[cfg(not(unix))]
+fn daemon() -> Result<()> {
+    anyhow::bail!("--daemon is only available on Unix")
+}
+
+/// Loads the configuration file, with `--write-tags` applied.
+///
+/// # Returns
+///
+/// Returns the watcher of the file, the configuration and whether
+/// `--write-tags` was given.
+///
+/// # Errors
+///
+/// Returns an error if the file exists but cannot be read or parsed.
+fn load_config() -> Result<(ConfigWatcher, Config, bool)> {
+    let config_watcher = ConfigWatcher::new(config::default_path());
+    let mut config = Config::load(config_watcher.path())
+        .with_context(|| format!("无法加载配置 {}", config_watcher.path().display()))?;
+    let write_tags_flag = std::env::args().any(|arg| arg == "--write-tags");
+    config.player.write_tags |= write_tags_flag;
+    Ok((config_watcher, config, write_tags_flag))
+}
+
+/// Returns the configured library directories, or the current directory
+/// when there are none.
+fn library_roots(config: &Config) -> Result<Vec<PathBuf>> {
+    Ok(match config.library.roots.as_slice() {
+        [] => vec![std::env::current_dir()?],
+        roots => roots.to_vec(),
+    })
+}
+
+/// Starts a thread checking the config file for changes once every
+/// [`CONFIG_POLL_INTERVAL`] and sending the new configuration; a broken file
+/// is reported and keeps the current settings.
+///
+/// # Arguments
+///
+/// * `watcher` - Watcher of the config file
+/// * `write_tags` - Whether `--write-tags` was given, which overrides the file
+/// * `tx` - Channel the configuration is sent on
+fn spawn_config_watcher(mut watcher: ConfigWatcher, write_tags: bool, tx: Sender<Msg>) {
+    thread::spawn(move || loop {
+        thread::sleep(CONFIG_POLL_INTERVAL);
+        match watcher.poll() {
+            Some(Ok(mut config)) => {
+                config.player.write_tags |= write_tags;
+                if tx.send(Msg::Config(Box::new(config))).is_err() {
+                    break;
                 }
             }
+            Some(Err(e)) => error!("{}", e),
+            None => {}
         }
+    });
+}
+
+/// Where playback happens.
+enum Backend {
+    /// The player thread of this process
+    Player {
+        commands: Sender<AppCommand>,
+        thread: JoinHandle<()>,
+    },
+    /// Another player the application is attached to (see [`App::attached`])
+    Attached(Sender<Request>),
+}
+
+impl Backend {
+    /// Starts the player thread, with its events sent on `tx`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread cannot be started.
+    fn start(config: &Config, tx: Sender<Msg>) -> Result<Self> {
+        let (commands, cmd_rx) = mpsc::channel();
+        let (evt_tx, evt_rx) = mpsc::channel();
+        let thread = player::start(cmd_rx, evt_tx, config.player.tick_rate)?;
+        app::forward(evt_rx, tx, Msg::Player);
+        Ok(Self::Player { commands, thread })
+    }
 
-        terminal.draw(|f| {
-            let size = f.size();
-            
-            // Update compact mode based on terminal size
-            app.compact_mode = size.width < 80;
-            
-            // Get layout (uses cache if terminal size unchanged)
-            let layout = app.get_layout(size.width, size.height).clone();
-            
-            // Render NowPlayingWidget to top area
-            let current_track = app.playing.and_then(|i| app.tracks.get(i));
-            let now_playing_widget = ui::widgets::NowPlayingWidget::new(
-                current_track,
-                app.status,
-                &app.theme
-            );
-            f.render_widget(now_playing_widget, layout.now_playing);
-            
-            // Render TrackListWidget to middle-left area
-            let track_list_widget = ui::widgets::TrackListWidget::new(
-                &app.tracks,
-                app.selected,
-                app.playing
-            );
-            f.render_widget(track_list_widget, layout.track_list);
-            
-            // Render VisualizationWidget to middle-right area (if not in compact mode)
-            if let Some(viz_area) = layout.visualization {
-                let is_playing = app.status == PlaybackStatus::Playing;
-                let visualization_widget = ui::widgets::VisualizationWidget::new(
-                    &app.wave,
-                    is_playing
-                );
-                f.render_widget(visualization_widget, viz_area);
+    /// Stops the player thread and waits for it; an attached player keeps
+    /// running.
+    fn shutdown(self) {
+        if let Self::Player { commands, thread } = self {
+            commands.send(AppCommand::Shutdown).ok();
+            if thread.join().is_err() {
+                error!("播放线程异常退出");
             }
-            
-            // Render PlaybackControlWidget to bottom playback control area
-            let playback_control_widget = ui::widgets::PlaybackControlWidget::new(
-                app.position,
-                app.total,
-                app.volume,
-                app.status
-            );
-            f.render_widget(playback_control_widget, layout.playback_control);
-            
-            // Render StatusBarWidget to bottom-most status bar
-            let status_hints = [
-                ("q", "退出"),
-                ("↑/↓", "导航"),
-                ("Enter", "播放"),
-                ("Space", "暂停"),
-                ("[/]", "上/下一曲"),
-                ("+/-", "音量"),
-            ];
-            let status_bar_widget = ui::widgets::StatusBarWidget::new(&status_hints);
-            f.render_widget(status_bar_widget, layout.status_bar);
-        })?;
-
-        // Poll for keyboard input with timeout
-        let timeout = tick_rate.saturating_sub(app.last_tick.elapsed());
-        if event::poll(timeout)? {
-            if let CEvent::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    // Handle keyboard commands
-                    match key.code {
-                        KeyCode::Char('q') => break,
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            if app.selected + 1 < app.tracks.len() { app.selected += 1; }
-                        }
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            if app.selected > 0 { app.selected -= 1; }
-                        }
-                        KeyCode::Enter => {
-                            let t = app.tracks[app.selected].clone();
-                            app.cmd_tx.send(AppCommand::Play { index: app.selected, path: t.path }).ok();
-                        }
-                        KeyCode::Char(' ') => {
-                            app.cmd_tx.send(AppCommand::TogglePlayPause).ok();
-                        }
-                        KeyCode::Char(']') => {
-                            let next = if app.selected + 1 < app.tracks.len() { app.selected + 1 } else { app.selected };
-                            app.selected = next;
-                            let t = app.tracks[next].clone();
-                            app.cmd_tx.send(AppCommand::Play { index: next, path: t.path }).ok();
-                        }
-                        KeyCode::Char('[') => {
-                            let prev = if app.selected > 0 { app.selected - 1 } else { 0 };
-                            app.selected = prev;
-                            let t = app.tracks[prev].clone();
-                            app.cmd_tx.send(AppCommand::Play { index: prev, path: t.path }).ok();
-                        }
-                        KeyCode::Char('+') => {
-                            app.volume = (app.volume + 0.05).min(2.0);
-                            app.cmd_tx.send(AppCommand::SetVolume(app.volume)).ok();
-                        }
-                        KeyCode::Char('-') => {
-                            app.volume = (app.volume - 0.05).max(0.0);
-                            app.cmd_tx.send(AppCommand::SetVolume(app.volume)).ok();
+        }
+    }
+}
+
+/// Runs the application until it quits.
+///
+/// Sleeps until a message arrives or the next deadline passes, handles
+/// everything that is waiting and then draws once, if there is a terminal.
+///
+/// # Errors
+///
+/// Returns an error if drawing or suspending fails.
+fn event_loop(
+    app: &mut App,
+    rx: &Receiver<Msg>,
+    backend: &Backend,
+    mut terminal: Option<&mut ui::TerminalGuard>,
+) -> Result<()> {
+    let (ratings, rating_writer) = tags::spawn_rating_writer();
+    'main: loop {
+        // Sleep until something happens or the next deadline passes
+        let received = match app.next_deadline() {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let first = match received {
+            Ok(msg) => msg,
+            Err(RecvTimeoutError::Timeout) => Msg::Tick,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        // Handle everything that is already waiting before drawing once
+        let mut redraw = false;
+        let pending: Vec<Msg> = rx.try_iter().collect();
+        for msg in std::iter::once(first).chain(pending) {
+            for effect in app::update(app, msg) {
+                match (effect, backend) {
+                    (Effect::Player(command), Backend::Player { commands, .. }) => {
+                        commands.send(command).ok();
+                    }
+                    (Effect::Daemon(request), Backend::Attached(requests)) => {
+                        requests.send(request).ok();
+                    }
+                    (Effect::Player(_) | Effect::Daemon(_), _) => {}
+                    (Effect::WriteRating(path, rating), _) => {
+                        ratings.send((path, rating)).ok();
+                    }
+                    (Effect::SaveLibrary, _) => app.save_library(),
+                    (Effect::SaveBookmarks, _) => app.save_bookmarks(),
+                    (Effect::SavePlaylists, _) => app.save_playlists(),
+                    (Effect::Export(path, format, playlist), _) => app::export(&path, format, &playlist),
+                    (Effect::Reply(reply, answer), _) => {
+                        reply.send(answer).ok();
+                    }
+                    (Effect::Publish(event), _) => app.send_event(&event),
+                    (Effect::Suspend, _) => {
+                        if let Some(terminal) = terminal.as_deref_mut() {
+                            terminal.suspend()?;
+                            redraw = true;
                         }
-                        _ => {}
                     }
+                    (Effect::Quit, _) => break 'main,
+                    (Effect::Redraw, _) => redraw = true,
                 }
             }
         }
-        // Update visualization waveform data
-        let sample = if app.status == PlaybackStatus::Playing {
-            // Generate simulated waveform based on playback position
-            let t = app.position.as_secs_f64();
-            let v = ((t * 6.0).sin() * 0.5 + 0.5) * 100.0 * app.volume.min(1.0) as f64;
-            v.clamp(0.0, 100.0) as u64
-        } else {
-            0
-        };
-        
-        // Maintain rolling window of waveform samples
-        if !app.wave.is_empty() {
-            app.wave.remove(0);
+        if let Some(terminal) = terminal.as_deref_mut().filter(|_| redraw) {
+            terminal.draw(|frame| app::render(frame, app))?;
         }
-        app.wave.push(sample);
-        app.last_tick = Instant::now();
     }
+    // Let the ratings still being written reach the files
+    drop(ratings);
+    if rating_writer.join().is_err() {
+        error!("评分写入线程异常退出");
+    }
+    Ok(())
+}
+
+/// Starts the remote-control server on [`paths::socket_file`].
+///
+/// # Returns
+///
+/// Returns the server, which removes the socket when dropped.
+///
+/// # Errors
+///
+/// Returns an error if another player answers on the socket or it cannot
+/// be created.
+#[cfg(unix)]
+fn start_remote(tx: Sender<Msg>) -> Result<RemoteServer, RemoteError> {
+    RemoteServer::start(&paths::socket_file(), tx)
+}
 
-    disable_raw_mode()?;
-    ui::restore_terminal(&mut terminal)?;
+/// Remote control needs Unix sockets.
+#[cfg(not(unix))]
+fn start_remote(_tx: Sender<Msg>) -> Result<RemoteServer, RemoteError> {
     Ok(())
 }
 
-fn main() {
-    if let Err(e) = run() { eprintln!("{}", e); }
+/// Attaches to the player answering on [`paths::socket_file`], if any.
+///
+/// # Returns
+///
+/// Returns the channel requests for that player are sent on.
+#[cfg(unix)]
+fn attach(tx: Sender<Msg>) -> Option<Sender<Request>> {
+    tools_rs::remote::ipc::attach(&paths::socket_file(), tx).ok()
 }
 
-#[cfg(test)]
-mod integration_tests {
-    use super::*;
-    use ratatui::backend::TestBackend;
-    use ratatui::Terminal;
+/// Attaching needs Unix sockets.
+#[cfg(not(unix))]
+fn attach(_tx: Sender<Msg>) -> Option<Sender<Request>> {
+    None
+}
 
-    /// Integration test: Complete UI rendering from App state
-    /// Tests the full rendering pipeline from App state to complete interface
-    /// Validates: All requirements
-    #[test]
-    fn test_complete_ui_rendering() {
-        // Create test tracks
-        let tracks = vec![
-            Track {
-                id: 0,
-                path: PathBuf::from("/test/track1.mp3"),
-                duration: Some(Duration::from_secs(180)),
-                title: Some("Test Track 1".to_string()),
-            },
-            Track {
-                id: 1,
-                path: PathBuf::from("/test/track2.mp3"),
-                duration: Some(Duration::from_secs(240)),
-                title: Some("Test Track 2".to_string()),
-            },
-            Track {
-                id: 2,
-                path: PathBuf::from("/test/track3.mp3"),
-                duration: Some(Duration::from_secs(200)),
-                title: Some("Test Track 3".to_string()),
-            },
-        ];
-
-        // Create channels for testing
-        let (cmd_tx, _cmd_rx) = mpsc::channel();
-        let (_evt_tx, evt_rx) = mpsc::channel();
-
-        // Create app with test data
-        let mut app = App::new(tracks, cmd_tx, evt_rx);
-        app.playing = Some(1);
-        app.status = PlaybackStatus::Playing;
-        app.position = Duration::from_secs(60);
-        app.total = Some(Duration::from_secs(240));
-        app.volume = 0.75;
-        app.selected = 1;
-        app.wave = vec![10, 20, 30, 40, 50, 60, 70, 80];
-
-        // Create a test backend with sufficient size
-        let backend = TestBackend::new(100, 30);
-        let mut terminal = Terminal::new(backend).unwrap();
-
-        // Render the UI
-        terminal
-            .draw(|f| {
-                let size = f.size();
-                
-                // Update compact mode based on terminal size
-                app.compact_mode = size.width < 80;
-                
-                // Calculate layout using LayoutManager
-                let layout_manager = ui::layout::LayoutManager::new(size);
-                let layout = layout_manager.calculate_layout();
-                
-                // Render NowPlayingWidget to top area
-                let current_track = app.playing.and_then(|i| app.tracks.get(i));
-                let now_playing_widget = ui::widgets::NowPlayingWidget::new(
-                    current_track,
-                    app.status,
-                    &app.theme
-                );
-                f.render_widget(now_playing_widget, layout.now_playing);
-                
-                // Render TrackListWidget to middle-left area
-                let track_list_widget = ui::widgets::TrackListWidget::new(
-                    &app.tracks,
-                    app.selected,
-                    app.playing
-                );
-                f.render_widget(track_list_widget, layout.track_list);
-                
-                // Render VisualizationWidget to middle-right area (if not in compact mode)
-                if let Some(viz_area) = layout.visualization {
-                    let is_playing = app.status == PlaybackStatus::Playing;
-                    let visualization_widget = ui::widgets::VisualizationWidget::new(
-                        &app.wave,
-                        is_playing
-                    );
-                    f.render_widget(visualization_widget, viz_area);
-                }
-                
-                // Render PlaybackControlWidget to bottom playback control area
-                let playback_control_widget = ui::widgets::PlaybackControlWidget::new(
-                    app.position,
-                    app.total,
-                    app.volume,
-                    app.status
-                );
-                f.render_widget(playback_control_widget, layout.playback_control);
-                
-                // Render StatusBarWidget to bottom-most status bar
-                let status_hints = [
-                    ("q", "退出"),
-                    ("↑/↓", "导航"),
-                    ("Enter", "播放"),
-                    ("Space", "暂停"),
-                    ("[/]", "上/下一曲"),
-                    ("+/-", "音量"),
-                ];
-                let status_bar_widget = ui::widgets::StatusBarWidget::new(&status_hints);
-                f.render_widget(status_bar_widget, layout.status_bar);
-            })
-            .unwrap();
-
-        // Get the rendered buffer
-        let buffer = terminal.backend().buffer();
-        let rendered = buffer_to_string(buffer);
-
-        // Verify all regions are rendered correctly
-        
-        // 1. Now Playing area should contain the playing track title
-        assert!(
-            rendered.contains("Test Track 2"),
-            "Now Playing area should contain the playing track title"
-        );
-        
-        // 2. Now Playing area should contain play icon
-        assert!(
-            rendered.contains("▶"),
-            "Now Playing area should contain play icon when playing"
-        );
-        
-        // 3. Track list should contain all tracks
-        assert!(
-            rendered.contains("Test Track 1"),
-            "Track list should contain Track 1"
-        );
-        assert!(
-            rendered.contains("Test Track 2"),
-            "Track list should contain Track 2"
-        );
-        assert!(
-            rendered.contains("Test Track 3"),
-            "Track list should contain Track 3"
-        );
-        
-        // 4. Progress bar should show time information
-        assert!(
-            rendered.contains("01:00") || rendered.contains("1:00"),
-            "Progress bar should show current time (01:00)"
-        );
-        assert!(
-            rendered.contains("04:00") || rendered.contains("4:00"),
-            "Progress bar should show total time (04:00)"
-        );
-        
-        // 5. Volume indicator should be displayed
-        assert!(
-            rendered.contains("75%") || rendered.contains("Volume"),
-            "Volume indicator should be displayed"
-        );
-        
-        // 6. Status bar should contain keyboard shortcuts
-        assert!(
-            rendered.contains("退出") || rendered.contains("q"),
-            "Status bar should contain quit shortcut"
-        );
-        
-        // 7. Visualization should be rendered (since width >= 80)
-        assert!(
-            rendered.contains("Visualization") || !app.compact_mode,
-            "Visualization should be rendered in non-compact mode"
-        );
-    }
+/// The socket server on Unix.
+#[cfg(unix)]
+type RemoteServer = tools_rs::remote::ipc::Server;
+/// The socket server on Unix.
+#[cfg(not(unix))]
+type RemoteServer = ();
+
+/// The remote-control servers, which stop listening when dropped.
+struct Servers {
+    _remote: Option<RemoteServer>,
+    _mpd: Option<MpdServer>,
+    _http: Option<HttpServer>,
+}
 
-    /// Integration test: Compact mode rendering
-    /// Tests that UI adapts correctly to narrow terminal width
-    #[test]
-    fn test_compact_mode_rendering() {
-        // Create minimal test data
-        let tracks = vec![
-            Track {
-                id: 0,
-                path: PathBuf::from("/test/track1.mp3"),
-                duration: Some(Duration::from_secs(180)),
-                title: Some("Track 1".to_string()),
-            },
-        ];
-
-        let (cmd_tx, _cmd_rx) = mpsc::channel();
-        let (_evt_tx, evt_rx) = mpsc::channel();
-
-        let mut app = App::new(tracks, cmd_tx, evt_rx);
-        app.playing = Some(0);
-        app.status = PlaybackStatus::Playing;
-
-        // Create a narrow terminal (width < 80 for compact mode)
-        let backend = TestBackend::new(60, 25);
-        let mut terminal = Terminal::new(backend).unwrap();
-
-        terminal
-            .draw(|f| {
-                let size = f.size();
-                app.compact_mode = size.width < 80;
-                
-                let layout_manager = ui::layout::LayoutManager::new(size);
-                let layout = layout_manager.calculate_layout();
-                
-                // Verify compact mode is active
-                assert!(app.compact_mode, "Compact mode should be active for width < 80");
-                assert!(layout.visualization.is_none(), "Visualization should be hidden in compact mode");
-                
-                // Render all widgets
-                let current_track = app.playing.and_then(|i| app.tracks.get(i));
-                let now_playing_widget = ui::widgets::NowPlayingWidget::new(
-                    current_track,
-                    app.status,
-                    &app.theme
-                );
-                f.render_widget(now_playing_widget, layout.now_playing);
-                
-                let track_list_widget = ui::widgets::TrackListWidget::new(
-                    &app.tracks,
-                    app.selected,
-                    app.playing
-                );
-                f.render_widget(track_list_widget, layout.track_list);
-                
-                let playback_control_widget = ui::widgets::PlaybackControlWidget::new(
-                    app.position,
-                    app.total,
-                    app.volume,
-                    app.status
-                );
-                f.render_widget(playback_control_widget, layout.playback_control);
-                
-                let status_hints = [("q", "Quit")];
-                let status_bar_widget = ui::widgets::StatusBarWidget::new(&status_hints);
-                f.render_widget(status_bar_widget, layout.status_bar);
-            })
-            .unwrap();
-
-        let buffer = terminal.backend().buffer();
-        let rendered = buffer_to_string(buffer);
-
-        // Verify core functionality is still accessible in compact mode
-        assert!(
-            rendered.contains("Track 1"),
-            "Track list should be visible in compact mode"
-        );
-        assert!(
-            rendered.contains("▶"),
-            "Play icon should be visible in compact mode"
-        );
+impl Servers {
+    /// Starts the servers configured in `[remote]` next to the socket
+    /// server; failures are logged.
+    fn start(remote: Option<RemoteServer>, config: &Config, roots: &[PathBuf], tx: Sender<Msg>) -> Self {
+        Self {
+            _remote: remote,
+            _mpd: config.remote.mpd_port.and_then(|port| start_mpd(port, roots, tx.clone())),
+            _http: config.remote.http_address.and_then(|addr| start_http(addr, config.remote.http_token.clone(), tx)),
+        }
     }
+}
 
-    /// Integration test: Empty track list rendering
-    /// Tests that UI handles empty state gracefully
-    #[test]
-    fn test_empty_track_list_rendering() {
-        let tracks = vec![];
-        let (cmd_tx, _cmd_rx) = mpsc::channel();
-        let (_evt_tx, evt_rx) = mpsc::channel();
-
-        let app = App::new(tracks, cmd_tx, evt_rx);
-
-        let backend = TestBackend::new(80, 25);
-        let mut terminal = Terminal::new(backend).unwrap();
-
-        terminal
-            .draw(|f| {
-                let size = f.size();
-                let layout_manager = ui::layout::LayoutManager::new(size);
-                let layout = layout_manager.calculate_layout();
-                
-                // Render with empty track list
-                let now_playing_widget = ui::widgets::NowPlayingWidget::new(
-                    None,
-                    app.status,
-                    &app.theme
-                );
-                f.render_widget(now_playing_widget, layout.now_playing);
-                
-                let track_list_widget = ui::widgets::TrackListWidget::new(
-                    &app.tracks,
-                    app.selected,
-                    app.playing
-                );
-                f.render_widget(track_list_widget, layout.track_list);
-            })
-            .unwrap();
-
-        let buffer = terminal.backend().buffer();
-        let rendered = buffer_to_string(buffer);
-
-        // Verify welcome message is shown when no track is playing
-        assert!(
-            rendered.contains("Welcome") || rendered.contains("Music Player"),
-            "Should show welcome message when no track is playing"
-        );
-        
-        // Verify empty track list message
-        // Note: Chinese characters may be rendered with spaces
-        assert!(
-            rendered.contains("未") || rendered.contains("找"),
-            "Should show empty track list message"
-        );
+/// Starts the MPD protocol server on a port of localhost, with URIs
+/// relative to the directory containing all library roots.
+///
+/// # Returns
+///
+/// Returns the server, or `None` if the port cannot be bound.
+fn start_mpd(port: u16, roots: &[PathBuf], tx: Sender<Msg>) -> Option<MpdServer> {
+    MpdServer::start(port, mpd::music_dir(roots), tx)
+        .map_err(|e| error!("无法在端口 {} 启动 MPD 服务: {}", port, e))
+        .ok()
+}
+
+/// Starts the HTTP server with the web remote, warning when it is open to
+/// the network without a token.
+///
+/// # Returns
+///
+/// Returns the server, or `None` if the address cannot be bound.
+fn start_http(addr: SocketAddr, token: Option<String>, tx: Sender<Msg>) -> Option<HttpServer> {
+    if token.is_none() && !addr.ip().is_loopback() {
+        warn!("HTTP 服务在 {} 上没有设置 http_token, 网络上的任何人都能控制播放", addr);
     }
+    HttpServer::start(addr, token, tx).map_err(|e| error!("无法在 {} 启动 HTTP 服务: {}", addr, e)).ok()
+}
 
-    /// Helper function to convert buffer to string for assertions
-    fn buffer_to_string(buffer: &ratatui::buffer::Buffer) -> String {
-        let mut result = String::new();
-        for y in 0..buffer.area.height {
-            for x in 0..buffer.area.width {
-                let cell = buffer.get(x, y);
-                result.push_str(cell.symbol());
+/// Starts a thread turning the signals that ask the player to quit
+/// (SIGTERM, SIGINT, SIGHUP) into [`Msg::Quit`], so that the session is
+/// saved and the terminal restored as on a normal quit, and SIGTSTP from
+/// outside (e.g. `kill -TSTP`) into [`Msg::Suspend`], so that the terminal
+/// is restored first (see [`ui::TerminalGuard::suspend`]).
+///
+/// # Errors
+///
+/// Returns an error if the handlers cannot be registered.
+#[cfg(unix)]
+fn spawn_signal_handler(tx: Sender<Msg>) -> std::io::Result<()> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+
+    let mut signals = signal_hook::iterator::Signals::new([SIGTERM, SIGINT, SIGHUP, SIGTSTP])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            let msg = if signal == SIGTSTP { Msg::Suspend } else { Msg::Quit };
+            if tx.send(msg).is_err() {
+                break;
             }
-            result.push('\n');
         }
-        result
+    });
+    Ok(())
+}
+
+/// Without Unix signals, Ctrl-C reaches the UI as a key in raw mode.
+#[cfg(not(unix))]
+fn spawn_signal_handler(_tx: Sender<Msg>) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Runs a `tools-rs ctl` subcommand against the running player and prints
+/// the answer.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid, no player is running or
+/// the player refuses the request.
+#[cfg(unix)]
+fn ctl(args: &[String]) -> Result<()> {
+    let output = tools_rs::remote::ctl::run(args, &paths::socket_file())?;
+    print!("{}", output);
+    if !output.is_empty() && !output.ends_with('\n') {
+        println!();
+    }
+    Ok(())
+}
+
+/// Remote control needs Unix sockets.
+#[cfg(not(unix))]
+fn ctl(_args: &[String]) -> Result<()> {
+    anyhow::bail!("tools-rs ctl is only available on Unix")
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.split_first() {
+        Some((command, rest)) if command == "ctl" => ctl(rest),
+        _ if args.iter().any(|arg| arg == "--daemon") => daemon(),
+        _ => run(),
+    };
+    if let Err(e) = result {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Integration test: termination and stop signals become messages instead of killing the process
+    #[cfg(unix)]
+    #[test]
+    fn test_signals_become_messages() {
+        let (tx, rx) = mpsc::channel();
+        spawn_signal_handler(tx).unwrap();
+        assert!(rx.try_recv().is_err());
+        signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+        assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Msg::Quit)));
+        signal_hook::low_level::raise(signal_hook::consts::SIGTSTP).unwrap();
+        assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Msg::Suspend)));
     }
 }
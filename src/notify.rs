@@ -0,0 +1,264 @@
+//! Notifications shown to the user: transient toasts and a message log.
+//!
+//! Messages reach the player through `tracing`: [`layer`] returns a
+//! [`NotificationLayer`] that forwards the player's own `info!`, `warn!` and
+//! `error!` events over a channel, so no call site needs to know about the
+//! UI. The receiving side feeds them into [`Notifications`], which keeps the
+//! log and decides which toasts are still shown.
+//!
+//! ```no_run
+//! use tracing_subscriber::prelude::*;
+//!
+//! let (layer, rx) = tools_rs::notify::layer();
+//! tracing_subscriber::registry().with(layer).init();
+//! tracing::error!("无法保存书签");
+//! assert_eq!(rx.recv().unwrap().message, "无法保存书签");
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant, SystemTime};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Messages kept in the log; older ones are dropped
+pub const LOG_CAPACITY: usize = 500;
+
+/// Toasts shown at the same time; older ones make way for newer ones
+pub const MAX_TOASTS: usize = 3;
+
+/// Target prefix of the events forwarded by [`NotificationLayer`]
+const TARGET_PREFIX: &str = "tools_rs";
+
+/// Severity of a notification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Something worth knowing (e.g. a playlist was saved)
+    Info,
+    /// Something went partly wrong
+    Warning,
+    /// Something failed
+    Error,
+}
+
+impl Level {
+    /// Returns the label shown in toasts and the message log.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Info => "信息",
+            Self::Warning => "警告",
+            Self::Error => "错误",
+        }
+    }
+}
+
+impl From<&tracing::Level> for Level {
+    fn from(level: &tracing::Level) -> Self {
+        match *level {
+            tracing::Level::ERROR => Self::Error,
+            tracing::Level::WARN => Self::Warning,
+            _ => Self::Info,
+        }
+    }
+}
+
+/// A message for the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// Severity
+    pub level: Level,
+    /// Text of the message
+    pub message: String,
+    /// When the message was raised, for the log
+    pub time: SystemTime,
+    /// When the message was raised, for expiring its toast
+    pub created: Instant,
+}
+
+impl Notification {
+    /// Creates a notification raised now.
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self { level, message: message.into(), time: SystemTime::now(), created: Instant::now() }
+    }
+}
+
+/// The message log and the toasts currently shown.
+#[derive(Debug)]
+pub struct Notifications {
+    log: VecDeque<Notification>,
+    timeout: Duration,
+    dismissed: Option<Instant>,
+}
+
+impl Notifications {
+    /// Creates an empty log.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a toast is shown; errors stay twice as long
+    pub fn new(timeout: Duration) -> Self {
+        Self { log: VecDeque::new(), timeout, dismissed: None }
+    }
+
+    /// Changes how long toasts are shown.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Adds a notification to the log, showing it as a toast.
+    pub fn push(&mut self, notification: Notification) {
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(notification);
+    }
+
+    /// Returns the logged notifications, oldest first.
+    pub fn log(&self) -> &VecDeque<Notification> {
+        &self.log
+    }
+
+    /// Returns the toasts shown at `now`, oldest first: at most
+    /// [`MAX_TOASTS`] recent notifications that have not timed out or been
+    /// dismissed.
+    pub fn toasts(&self, now: Instant) -> Vec<&Notification> {
+        let mut toasts: Vec<&Notification> = self.log
+            .iter()
+            .rev()
+            .take_while(|n| self.dismissed.is_none_or(|at| n.created > at))
+            .filter(|n| now.saturating_duration_since(n.created) < self.lifetime(n.level))
+            .take(MAX_TOASTS)
+            .collect();
+        toasts.reverse();
+        toasts
+    }
+
+    /// Returns when the next of the toasts shown at `now` times out, or
+    /// `None` if none are shown.
+    pub fn next_expiry(&self, now: Instant) -> Option<Instant> {
+        self.toasts(now).iter().map(|n| n.created + self.lifetime(n.level)).min()
+    }
+
+    /// Hides all toasts shown at `now`; the messages stay in the log.
+    pub fn dismiss(&mut self, now: Instant) {
+        self.dismissed = Some(now);
+    }
+
+    /// Returns how long a toast of a level is shown.
+    fn lifetime(&self, level: Level) -> Duration {
+        match level {
+            Level::Error => self.timeout * 2,
+            Level::Info | Level::Warning => self.timeout,
+        }
+    }
+}
+
+/// A `tracing` layer forwarding the player's events of level `INFO` and
+/// above as notifications.
+#[derive(Debug)]
+pub struct NotificationLayer {
+    tx: Sender<Notification>,
+}
+
+/// Creates a [`NotificationLayer`] and the channel its notifications arrive on.
+pub fn layer() -> (NotificationLayer, Receiver<Notification>) {
+    let (tx, rx) = mpsc::channel();
+    (NotificationLayer { tx }, rx)
+}
+
+impl<S: Subscriber> Layer<S> for NotificationLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > tracing::Level::INFO || !metadata.target().starts_with(TARGET_PREFIX) {
+            return;
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        // The receiver is gone once the UI has shut down
+        self.tx.send(Notification::new(metadata.level().into(), visitor.0)).ok();
+    }
+}
+
+/// Collects the `message` field of an event.
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use tracing_subscriber::prelude::*;
+
+    fn at(created: Instant, level: Level, message: &str) -> Notification {
+        Notification { created, ..Notification::new(level, message) }
+    }
+
+    #[test]
+    fn test_toasts_expire_and_dismiss() {
+        let start = Instant::now();
+        let mut notifications = Notifications::new(Duration::from_secs(4));
+        notifications.push(at(start, Level::Error, "e"));
+        notifications.push(at(start, Level::Info, "i"));
+        let messages = |n: &Notifications, secs| {
+            n.toasts(start + Duration::from_secs(secs)).iter().map(|t| t.message.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(messages(&notifications, 1), ["e", "i"]);
+        assert_eq!(messages(&notifications, 5), ["e"], "errors stay longer");
+        assert!(messages(&notifications, 9).is_empty());
+        assert_eq!(notifications.next_expiry(start), Some(start + Duration::from_secs(4)));
+        assert_eq!(notifications.next_expiry(start + Duration::from_secs(5)), Some(start + Duration::from_secs(8)));
+        assert_eq!(notifications.next_expiry(start + Duration::from_secs(9)), None);
+
+        notifications.dismiss(start + Duration::from_secs(1));
+        assert!(messages(&notifications, 1).is_empty());
+        notifications.push(at(start + Duration::from_secs(2), Level::Warning, "w"));
+        assert_eq!(messages(&notifications, 2), ["w"]);
+        assert_eq!(notifications.log().len(), 3);
+    }
+
+    #[test]
+    fn test_layer_forwards_player_events() {
+        let (layer, rx) = layer();
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            tracing::error!("无法保存: {}", 42);
+            tracing::warn!(target: "tools_rs::player", "slow");
+            tracing::debug!("ignored");
+            tracing::error!(target: "rodio", "not ours");
+        });
+        let received: Vec<_> = rx.try_iter().map(|n| (n.level, n.message)).collect();
+        assert_eq!(received, [(Level::Error, "无法保存: 42".to_string()), (Level::Warning, "slow".to_string())]);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_log_is_capped_and_toasts_limited(count in 0usize..700) {
+            let now = Instant::now();
+            let mut notifications = Notifications::new(Duration::from_secs(4));
+            for i in 0..count {
+                notifications.push(at(now, Level::Info, &i.to_string()));
+            }
+            prop_assert_eq!(notifications.log().len(), count.min(LOG_CAPACITY));
+            let toasts = notifications.toasts(now);
+            prop_assert_eq!(toasts.len(), count.min(MAX_TOASTS));
+            if let Some(last) = toasts.last() {
+                prop_assert_eq!(&last.message, &(count - 1).to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Locations of the player's files on disk.
+//!
+//! Directories follow the XDG Base Directory specification, falling back to
+//! the conventional locations under `$HOME` when the variables are unset.
+
+use std::env;
+use std::path::PathBuf;
+
+/// Application directory name used under the XDG base directories.
+pub const APP_DIR: &str = "tools-rs";
+
+/// Returns the directory for persistent player data (playlists, library data).
+///
+/// Resolves to `$XDG_DATA_HOME/tools-rs`, or `~/.local/share/tools-rs` when
+/// `XDG_DATA_HOME` is unset or empty.
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Returns the directory for user configuration.
+///
+/// Resolves to `$XDG_CONFIG_HOME/tools-rs`, or `~/.config/tools-rs` when
+/// `XDG_CONFIG_HOME` is unset or empty.
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Returns the directory for state that is not worth keeping (the log file).
+///
+/// Resolves to `$XDG_STATE_HOME/tools-rs`, or `~/.local/state/tools-rs` when
+/// `XDG_STATE_HOME` is unset or empty.
+pub fn state_dir() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Returns the log file inside the state directory.
+pub fn log_file() -> PathBuf {
+    state_dir().join("tools-rs.log")
+}
+
+/// Returns the remote-control socket of a running player.
+///
+/// Resolves to `$XDG_RUNTIME_DIR/tools-rs.sock`, or to `tools-rs.sock` in
+/// the state directory when `XDG_RUNTIME_DIR` is unset or empty.
+pub fn socket_file() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join(format!("{}.sock", APP_DIR)),
+        None => state_dir().join(format!("{}.sock", APP_DIR)),
+    }
+}
+
+/// Returns the file a player started with `--daemon` writes its process ID
+/// to, next to [`socket_file`].
+pub fn pid_file() -> PathBuf {
+    socket_file().with_extension("pid")
+}
+
+/// Returns the directory for custom theme files inside the config directory.
+pub fn themes_dir() -> PathBuf {
+    config_dir().join("themes")
+}
+
+/// Returns the directory for named playlists inside the data directory.
+pub fn playlists_dir() -> PathBuf {
+    data_dir().join("playlists")
+}
+
+/// Resolves an XDG base directory, joining the application directory name.
+fn xdg_dir(var: &str, home_fallback: &str) -> PathBuf {
+    let base = match env::var_os(var).filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(home_fallback),
+    };
+    base.join(APP_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xdg_dir_falls_back_to_home() {
+        // Variables of their own, so that other tests reading the real ones are unaffected
+        env::set_var("TOOLS_RS_TEST_XDG_SET", "/xdg/data");
+        env::set_var("TOOLS_RS_TEST_XDG_EMPTY", "");
+        let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+
+        assert_eq!(xdg_dir("TOOLS_RS_TEST_XDG_SET", ".local/share"), PathBuf::from("/xdg/data/tools-rs"));
+        assert_eq!(xdg_dir("TOOLS_RS_TEST_XDG_EMPTY", ".config"), home.join(".config/tools-rs"));
+        assert_eq!(xdg_dir("TOOLS_RS_TEST_XDG_UNSET", ".local/state"), home.join(".local/state/tools-rs"));
+    }
+}
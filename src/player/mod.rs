@@ -12,11 +12,13 @@
 //! # Thread Safety
 //!
 //! The player runs in its own thread to avoid blocking the UI. All communication
-//! is done through thread-safe channels.
+//! is done through thread-safe channels. The thread sleeps until a command
+//! arrives; only while a track plays does it wake up to report the position.
 
 use std::fs::File;
 use std::io::BufReader;
-use std::sync::mpsc::{Receiver, Sender};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
@@ -34,10 +36,12 @@ use crate::common::{AppCommand, AppEvent};
 ///
 /// * `cmd_rx` - Channel receiver for receiving playback commands
 /// * `evt_tx` - Channel sender for sending playback events
+/// * `progress_interval` - Time between [`AppEvent::Progress`] reports while playing
 ///
 /// # Returns
 ///
-/// Returns a `JoinHandle` for the spawned player thread.
+/// Returns a `JoinHandle` for the spawned player thread. The thread ends on
+/// [`AppCommand::Shutdown`] or when `cmd_rx` is disconnected.
 ///
 /// # Errors
 ///
@@ -47,68 +51,128 @@ use crate::common::{AppCommand, AppEvent};
 ///
 /// ```no_run
 /// use std::sync::mpsc;
+/// use std::time::Duration;
 /// use tools_rs::player;
 ///
 /// let (cmd_tx, cmd_rx) = mpsc::channel();
 /// let (evt_tx, evt_rx) = mpsc::channel();
-/// let handle = player::start(cmd_rx, evt_tx).unwrap();
+/// let handle = player::start(cmd_rx, evt_tx, Duration::from_millis(200)).unwrap();
 /// ```
-pub fn start(cmd_rx: Receiver<AppCommand>, evt_tx: Sender<AppEvent>) -> Result<JoinHandle<()>> {
+pub fn start(cmd_rx: Receiver<AppCommand>, evt_tx: Sender<AppEvent>, progress_interval: Duration) -> Result<JoinHandle<()>> {
     let handle = thread::spawn(move || {
         let (_stream, stream_handle) = OutputStream::try_default().unwrap();
         let mut sink: Option<Sink> = None;
         let mut volume = 1.0f32;
-        let mut start_at: Option<Instant> = None;
-        let mut paused_acc = Duration::from_secs(0);
+        // Path of the loaded track, needed to reopen it when seeking
+        let mut current: Option<PathBuf> = None;
+        // Position = offset + time since playback last (re)started; None while paused
+        let mut offset = Duration::from_secs(0);
+        let mut resumed_at: Option<Instant> = None;
+        let mut next_report = Instant::now();
 
         loop {
-            while let Ok(cmd) = cmd_rx.try_recv() {
-                match cmd {
-                    AppCommand::Play { index, path } => {
-                        if let Some(s) = sink.take() { s.stop(); }
-                        let file = match File::open(&path) { Ok(f) => f, Err(_) => { let _ = evt_tx.send(AppEvent::Error { message: format!("无法打开文件: {}", path.display()) }); continue; } };
-                        let source = match Decoder::new(BufReader::new(file)) { Ok(s) => s, Err(_) => { let _ = evt_tx.send(AppEvent::Error { message: format!("不支持的格式: {}", path.display()) }); continue; } };
-                        let duration = source.total_duration();
-                        let s = Sink::try_new(&stream_handle).unwrap();
-                        s.set_volume(volume);
-                        s.append(source);
-                        sink = Some(s);
-                        start_at = Some(Instant::now());
-                        paused_acc = Duration::from_secs(0);
-                        let _ = evt_tx.send(AppEvent::TrackStarted { index, duration });
-                    }
-                    AppCommand::TogglePlayPause => {
-                        if let Some(s) = &sink {
-                            if s.is_paused() {
-                                s.play();
-                                if let Some(t0) = start_at { start_at = Some(Instant::now() - (t0.elapsed() - paused_acc)); }
-                            } else {
-                                s.pause();
-                                if let Some(t0) = start_at { paused_acc = t0.elapsed(); }
-                            }
+            // Wait for a command; while a track plays, only until the next report is due
+            let playing = sink.as_ref().is_some_and(|s| !s.is_paused());
+            let received = if playing {
+                cmd_rx.recv_timeout(next_report.saturating_duration_since(Instant::now()))
+            } else {
+                cmd_rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            };
+            let cmd = match received {
+                Ok(cmd) => cmd,
+                Err(RecvTimeoutError::Timeout) => {
+                    next_report = Instant::now() + progress_interval;
+                    if let Some(s) = &sink {
+                        if s.empty() {
+                            let _ = evt_tx.send(AppEvent::TrackEnded);
+                            sink = None;
+                            current = None;
+                            resumed_at = None;
+                            offset = Duration::from_secs(0);
+                        } else {
+                            let p = offset.saturating_add(resumed_at.map_or(Duration::from_secs(0), |t0| t0.elapsed()));
+                            let _ = evt_tx.send(AppEvent::Progress { position: p });
                         }
                     }
-                    AppCommand::SetVolume(v) => {
-                        volume = v;
-                        if let Some(s) = &sink { s.set_volume(volume); }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            match cmd {
+                AppCommand::Play { index, path } => {
+                    if let Some(s) = sink.take() { s.stop(); }
+                    let Some(source) = open_source(&path, &evt_tx) else { continue };
+                    let duration = source.total_duration();
+                    let s = Sink::try_new(&stream_handle).unwrap();
+                    s.set_volume(volume);
+                    s.append(source);
+                    sink = Some(s);
+                    current = Some(path);
+                    offset = Duration::from_secs(0);
+                    resumed_at = Some(Instant::now());
+                    let _ = evt_tx.send(AppEvent::TrackStarted { index, duration });
+                }
+                AppCommand::TogglePlayPause => {
+                    if let Some(s) = &sink {
+                        if s.is_paused() {
+                            s.play();
+                            resumed_at = Some(Instant::now());
+                        } else {
+                            s.pause();
+                            if let Some(t0) = resumed_at.take() { offset = offset.saturating_add(t0.elapsed()); }
+                        }
                     }
                 }
-            }
-
-            if let Some(s) = &sink {
-                if s.empty() {
-                    let _ = evt_tx.send(AppEvent::TrackEnded);
-                    sink = None;
-                    start_at = None;
-                    paused_acc = Duration::from_secs(0);
-                } else if let Some(t0) = start_at {
-                    let p = t0.elapsed();
-                    let _ = evt_tx.send(AppEvent::Progress { position: p });
+                AppCommand::SetVolume(v) => {
+                    volume = v;
+                    if let Some(s) = &sink { s.set_volume(volume); }
                 }
+                AppCommand::Seek(position) => {
+                    // rodio 0.17 cannot seek a playing source, so reopen the
+                    // file and skip ahead, keeping the pause state
+                    let (Some(old), Some(path)) = (&sink, &current) else { continue };
+                    let paused = old.is_paused();
+                    let Some(source) = open_source(path, &evt_tx) else { continue };
+                    let s = Sink::try_new(&stream_handle).unwrap();
+                    s.set_volume(volume);
+                    if paused { s.pause(); }
+                    s.append(source.skip_duration(position));
+                    if let Some(old) = sink.replace(s) { old.stop(); }
+                    offset = position;
+                    resumed_at = if paused { None } else { Some(Instant::now()) };
+                    let _ = evt_tx.send(AppEvent::Progress { position });
+                }
+                AppCommand::Shutdown => break,
             }
-
-            thread::sleep(Duration::from_millis(200));
         }
+        if let Some(s) = sink { s.stop(); }
     });
     Ok(handle)
 }
+
+/// Opens and decodes an audio file, reporting failures to the UI.
+fn open_source(path: &Path, evt_tx: &Sender<AppEvent>) -> Option<Decoder<BufReader<File>>> {
+    let file = match File::open(path) { Ok(f) => f, Err(_) => { let _ = evt_tx.send(AppEvent::Error { message: format!("无法打开文件: {}", path.display()) }); return None; } };
+    match Decoder::new(BufReader::new(file)) { Ok(s) => Some(s), Err(_) => { let _ = evt_tx.send(AppEvent::Error { message: format!("不支持的格式: {}", path.display()) }); None } }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_open_source_reports_missing_and_undecodable_files() {
+        let dir = TempDir::new("player");
+        let (tx, rx) = mpsc::channel();
+
+        assert!(open_source(&dir.join("missing.mp3"), &tx).is_none());
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Error { message }) if message.contains("missing.mp3")));
+
+        let garbage = dir.join("garbage.mp3");
+        std::fs::write(&garbage, b"not audio at all").unwrap();
+        assert!(open_source(&garbage, &tx).is_none());
+        assert!(matches!(rx.try_recv(), Ok(AppEvent::Error { message }) if message.contains("garbage.mp3")));
+    }
+}
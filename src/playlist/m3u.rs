@@ -0,0 +1,126 @@
+//! M3U and M3U8 playlist support.
+//!
+//! Both variants share the same syntax: one location per line, with optional
+//! `#EXTM3U` header and `#EXTINF:<seconds>,<title>` lines describing the entry
+//! that follows. Other `#` lines are treated as comments. Output is always
+//! written as UTF-8 with an extended header.
+
+use std::path::Path;
+use std::time::Duration;
+
+use super::{relative_location, resolve_location, Playlist, PlaylistEntry, PlaylistError};
+
+/// Parses M3U/M3U8 content.
+///
+/// # Errors
+///
+/// Returns an error if an `#EXTINF` line has an invalid duration.
+pub fn parse(content: &str, base_dir: &Path) -> Result<Playlist, PlaylistError> {
+    let mut playlist = Playlist::default();
+    let mut pending: Option<(Option<Duration>, Option<String>)> = None;
+
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line = raw_line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            pending = Some(parse_extinf(info, idx + 1)?);
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let (duration, title) = pending.take().unwrap_or((None, None));
+        playlist.entries.push(PlaylistEntry { path: resolve_location(line, base_dir), title, duration });
+    }
+
+    Ok(playlist)
+}
+
+/// Parses the part of an `#EXTINF` line after the colon.
+///
+/// The duration may be `-1` (unknown) and may be followed by attributes such
+/// as `tvg-id="..."` before the comma separating the title.
+fn parse_extinf(info: &str, line: usize) -> Result<(Option<Duration>, Option<String>), PlaylistError> {
+    let (head, title) = match info.split_once(',') {
+        Some((head, title)) => (head, Some(title.trim().to_string()).filter(|t| !t.is_empty())),
+        None => (info, None),
+    };
+    let secs_str = head.split_whitespace().next().unwrap_or("");
+    let secs: f64 = secs_str.parse().map_err(|_| PlaylistError::Parse {
+        line,
+        message: format!("invalid #EXTINF duration '{}'", secs_str),
+    })?;
+    // Negative (`-1`), infinite and absurdly large durations are unknown
+    let duration = Duration::try_from_secs_f64(secs).ok();
+    Ok((duration, title))
+}
+
+/// Serializes a playlist as extended M3U.
+pub fn write(playlist: &Playlist, base_dir: &Path) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for entry in &playlist.entries {
+        if entry.title.is_some() || entry.duration.is_some() {
+            let secs = entry.duration.map(|d| d.as_secs() as i64).unwrap_or(-1);
+            out.push_str(&format!("#EXTINF:{},{}\n", secs, entry.title.as_deref().unwrap_or("")));
+        }
+        out.push_str(&relative_location(&entry.path, base_dir).to_string_lossy());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_extended_m3u() {
+        let content = "#EXTM3U\n\
+                       #EXTINF:245,Miles Davis - So What\n\
+                       jazz/so_what.flac\n\
+                       # a comment\n\
+                       \n\
+                       /abs/plain.mp3\n\
+                       #EXTINF:-1 tvg-id=\"x\",Radio\n\
+                       http://example.com/stream\n";
+        let playlist = parse(content, Path::new("/music")).unwrap();
+
+        assert_eq!(playlist.entries.len(), 3);
+        assert_eq!(playlist.entries[0].path, PathBuf::from("/music/jazz/so_what.flac"));
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("Miles Davis - So What"));
+        assert_eq!(playlist.entries[0].duration, Some(Duration::from_secs(245)));
+        assert_eq!(playlist.entries[1], PlaylistEntry::new("/abs/plain.mp3"));
+        assert_eq!(playlist.entries[2].path, PathBuf::from("http://example.com/stream"));
+        assert_eq!(playlist.entries[2].duration, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_duration() {
+        let err = parse("#EXTINF:abc,Title\na.mp3\n", Path::new("/")).unwrap_err();
+        assert!(matches!(err, PlaylistError::Parse { line: 1, .. }));
+        let playlist = parse("#EXTINF:inf,x\na.mp3\n", Path::new("/")).unwrap();
+        assert_eq!(playlist.entries[0].duration, None);
+    }
+
+    #[test]
+    fn test_write_roundtrip() {
+        let playlist = Playlist {
+            entries: vec![
+                PlaylistEntry {
+                    path: PathBuf::from("/music/a/one.mp3"),
+                    title: Some("One".to_string()),
+                    duration: Some(Duration::from_secs(61)),
+                },
+                PlaylistEntry::new("/other/two.ogg"),
+            ],
+        };
+        let text = write(&playlist, Path::new("/music"));
+        assert!(text.starts_with("#EXTM3U\n#EXTINF:61,One\na/one.mp3\n"));
+        assert!(text.contains("\n/other/two.ogg\n"));
+        assert_eq!(parse(&text, Path::new("/music")).unwrap(), playlist);
+    }
+}
@@ -0,0 +1,430 @@
+//! Playlist import and export.
+//!
+//! This module reads and writes the playlist formats used by other players so
+//! playlists can be shared in both directions:
+//! - `m3u`: M3U and M3U8 playlists, including `#EXTINF` metadata
+//! - `pls`: PLS (INI-style) playlists
+//! - `xspf`: XSPF (XML Shareable Playlist Format) playlists
+//! - `store`: Named playlists saved in the player's data directory
+//! - `smart`: Smart playlists defined by queries and re-evaluated on demand
+//!
+//! # Path Resolution
+//!
+//! Relative entries are resolved against the directory containing the playlist
+//! file, and `file://` URIs are decoded to local paths. Parsed entries can then
+//! be matched against the library with [`Playlist::match_tracks`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use tools_rs::playlist::{self, PlaylistFormat};
+//!
+//! let playlist = playlist::load(Path::new("road-trip.m3u8")).unwrap();
+//! playlist.save(Path::new("road-trip.xspf"), PlaylistFormat::Xspf).unwrap();
+//! ```
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::common::Track;
+
+pub mod m3u;
+pub mod pls;
+pub mod smart;
+pub mod store;
+pub mod xspf;
+
+/// Supported playlist file formats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaylistFormat {
+    /// Legacy M3U playlist (`.m3u`)
+    M3u,
+    /// UTF-8 M3U playlist (`.m3u8`)
+    M3u8,
+    /// PLS playlist (`.pls`)
+    Pls,
+    /// XML Shareable Playlist Format (`.xspf`)
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// Detects the playlist format from a file extension (case-insensitive).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "m3u" => Some(Self::M3u),
+            "m3u8" => Some(Self::M3u8),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+
+    /// Returns the canonical file extension for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::M3u => "m3u",
+            Self::M3u8 => "m3u8",
+            Self::Pls => "pls",
+            Self::Xspf => "xspf",
+        }
+    }
+}
+
+/// Errors that can occur while reading or writing playlists.
+#[derive(Debug, Error)]
+pub enum PlaylistError {
+    /// The playlist file could not be read or written
+    #[error("playlist I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file extension does not correspond to a known playlist format
+    #[error("unknown playlist format: {0}")]
+    UnknownFormat(PathBuf),
+    /// The playlist content is malformed
+    #[error("malformed playlist at line {line}: {message}")]
+    Parse {
+        /// 1-based line number where the problem was found
+        line: usize,
+        /// Description of the problem
+        message: String,
+    },
+    /// No playlist with this name exists in the store
+    #[error("playlist '{0}' does not exist")]
+    NotFound(String),
+    /// A playlist with this name already exists in the store
+    #[error("playlist '{0}' already exists")]
+    AlreadyExists(String),
+    /// The name cannot be used as a playlist file name
+    #[error("invalid playlist name '{0}'")]
+    InvalidName(String),
+    /// An entry index is outside the playlist
+    #[error("entry {index} is out of range for a playlist of {len} entries")]
+    IndexOutOfRange {
+        /// Requested entry index
+        index: usize,
+        /// Number of entries in the playlist
+        len: usize,
+    },
+    /// A file of a playlist store could not be loaded
+    #[error("{}: {source}", path.display())]
+    Load {
+        /// The file that was skipped
+        path: PathBuf,
+        /// Why it could not be loaded
+        source: Box<PlaylistError>,
+    },
+}
+
+/// A single entry of a playlist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaylistEntry {
+    /// Resolved location of the audio file
+    pub path: PathBuf,
+    /// Display title stored in the playlist (if any)
+    pub title: Option<String>,
+    /// Duration stored in the playlist (if any)
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+    /// Creates an entry for a path without any metadata.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), title: None, duration: None }
+    }
+}
+
+impl From<&Track> for PlaylistEntry {
+    fn from(track: &Track) -> Self {
+        Self { path: track.path.clone(), title: track.title.clone(), duration: track.duration }
+    }
+}
+
+/// An ordered list of playlist entries.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Playlist {
+    /// Entries in playback order
+    pub entries: Vec<PlaylistEntry>,
+}
+
+impl Playlist {
+    /// Builds a playlist from library tracks, e.g. the current queue or a selection.
+    pub fn from_tracks<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Self {
+        Self { entries: tracks.into_iter().map(PlaylistEntry::from).collect() }
+    }
+
+    /// Matches every entry against the library.
+    ///
+    /// Entries are matched by their normalized path first. Entries whose path is
+    /// not in the library fall back to a file name match, which is accepted only
+    /// when exactly one library track has that file name; this keeps playlists
+    /// created on another machine usable.
+    ///
+    /// # Returns
+    ///
+    /// Returns one element per entry holding the index of the matching track in
+    /// `library`, or `None` when the entry could not be matched.
+    pub fn match_tracks(&self, library: &[Track]) -> Vec<Option<usize>> {
+        use std::collections::HashMap;
+
+        let mut by_path: HashMap<PathBuf, usize> = HashMap::new();
+        let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+        for (idx, track) in library.iter().enumerate() {
+            by_path.entry(normalize(&track.path)).or_insert(idx);
+            if let Some(name) = track.path.file_name().and_then(|n| n.to_str()) {
+                by_name.entry(name.to_lowercase()).or_default().push(idx);
+            }
+        }
+
+        self.entries
+            .iter()
+            .map(|entry| {
+                if let Some(&idx) = by_path.get(&normalize(&entry.path)) {
+                    return Some(idx);
+                }
+                let name = entry.path.file_name()?.to_str()?.to_lowercase();
+                match by_name.get(&name).map(Vec::as_slice) {
+                    Some([only]) => Some(*only),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Serializes the playlist in the given format.
+    ///
+    /// Paths inside `base_dir` are written relative to it so the playlist stays
+    /// valid when the directory tree is moved together with it.
+    pub fn serialize(&self, format: PlaylistFormat, base_dir: &Path) -> String {
+        match format {
+            PlaylistFormat::M3u | PlaylistFormat::M3u8 => m3u::write(self, base_dir),
+            PlaylistFormat::Pls => pls::write(self, base_dir),
+            PlaylistFormat::Xspf => xspf::write(self, base_dir),
+        }
+    }
+
+    /// Writes the playlist to `path` in the given format.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path, format: PlaylistFormat) -> Result<(), PlaylistError> {
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        fs::write(path, self.serialize(format, base_dir))?;
+        Ok(())
+    }
+}
+
+/// Parses playlist content in a known format.
+///
+/// # Arguments
+///
+/// * `content` - Raw playlist text
+/// * `format` - Format of the content
+/// * `base_dir` - Directory used to resolve relative entries
+///
+/// # Errors
+///
+/// Returns an error if the content is malformed.
+pub fn parse(content: &str, format: PlaylistFormat, base_dir: &Path) -> Result<Playlist, PlaylistError> {
+    match format {
+        PlaylistFormat::M3u | PlaylistFormat::M3u8 => m3u::parse(content, base_dir),
+        PlaylistFormat::Pls => pls::parse(content, base_dir),
+        PlaylistFormat::Xspf => xspf::parse(content, base_dir),
+    }
+}
+
+/// Loads a playlist file, detecting its format from the extension.
+///
+/// Legacy `.m3u` and `.pls` files that are not valid UTF-8 are decoded as
+/// Latin-1; `.m3u8` and `.xspf` files must be UTF-8.
+///
+/// # Errors
+///
+/// Returns an error if the format is unknown, the file cannot be read, or the
+/// content is malformed.
+pub fn load(path: &Path) -> Result<Playlist, PlaylistError> {
+    let format =
+        PlaylistFormat::from_path(path).ok_or_else(|| PlaylistError::UnknownFormat(path.to_path_buf()))?;
+    let bytes = fs::read(path)?;
+    let content = match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) if matches!(format, PlaylistFormat::M3u | PlaylistFormat::Pls) => {
+            e.into_bytes().iter().map(|&b| b as char).collect()
+        }
+        Err(e) => {
+            let valid = &e.as_bytes()[..e.utf8_error().valid_up_to()];
+            let line = valid.iter().filter(|&&b| b == b'\n').count() + 1;
+            return Err(PlaylistError::Parse { line, message: "invalid UTF-8".to_string() });
+        }
+    };
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(&content, format, base_dir)
+}
+
+/// Resolves a playlist location (plain path or `file://` URI) to a path.
+///
+/// Relative locations are joined onto `base_dir`; the result is lexically
+/// normalized so `..` components from the playlist do not leak into matching.
+pub(crate) fn resolve_location(location: &str, base_dir: &Path) -> PathBuf {
+    let raw = match location.strip_prefix("file://") {
+        // `file://host/path` is treated like `file:///path`
+        Some(rest) => PathBuf::from(percent_decode(&rest[rest.find('/').unwrap_or(0)..])),
+        // Remote streams are kept verbatim; normalizing would collapse the `//`
+        None if location.contains("://") => return PathBuf::from(location),
+        None => PathBuf::from(location),
+    };
+    if raw.is_absolute() {
+        normalize(&raw)
+    } else {
+        normalize(&base_dir.join(raw))
+    }
+}
+
+/// Formats a path for writing, relative to `base_dir` when it lies inside it.
+pub(crate) fn relative_location(path: &Path, base_dir: &Path) -> PathBuf {
+    if base_dir.as_os_str().is_empty() {
+        return path.to_path_buf();
+    }
+    match path.strip_prefix(base_dir) {
+        Ok(rel) => rel.to_path_buf(),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Lexically normalizes a path by removing `.` and resolving `..` components.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push("..");
+                }
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+/// Decodes `%XX` escapes in a URI component.
+pub(crate) fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            // Bytes, not `&input[..]`: the two after `%` may split a character
+            if let (Some(high), Some(low)) = (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+                out.push(high << 4 | low);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Returns the value of a hexadecimal digit.
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|d| d as u8)
+}
+
+/// Encodes a path as a URI path, escaping everything except unreserved characters and `/`.
+pub(crate) fn percent_encode(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for b in input.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(id: u64, path: &str) -> Track {
+        Track { id, path: PathBuf::from(path), duration: None, title: None, ..Track::default() }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(PlaylistFormat::from_path(Path::new("a.M3U")), Some(PlaylistFormat::M3u));
+        assert_eq!(PlaylistFormat::from_path(Path::new("a.m3u8")), Some(PlaylistFormat::M3u8));
+        assert_eq!(PlaylistFormat::from_path(Path::new("a.pls")), Some(PlaylistFormat::Pls));
+        assert_eq!(PlaylistFormat::from_path(Path::new("a.xspf")), Some(PlaylistFormat::Xspf));
+        assert_eq!(PlaylistFormat::from_path(Path::new("a.mp3")), None);
+    }
+
+    #[test]
+    fn test_resolve_location() {
+        let base = Path::new("/music/lists");
+        assert_eq!(resolve_location("../a/b.mp3", base), PathBuf::from("/music/a/b.mp3"));
+        assert_eq!(resolve_location("/abs/c.flac", base), PathBuf::from("/abs/c.flac"));
+        assert_eq!(
+            resolve_location("file:///abs/with%20space.ogg", base),
+            PathBuf::from("/abs/with space.ogg")
+        );
+        assert_eq!(resolve_location("./d.wav", base), PathBuf::from("/music/lists/d.wav"));
+    }
+
+    #[test]
+    fn test_percent_roundtrip() {
+        let original = "/music/Miles Davis/So What (1959).flac";
+        assert_eq!(percent_decode(&percent_encode(original)), original);
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("/music/%aé.mp3"), "/music/%aé.mp3");
+        assert_eq!(percent_decode("%e9%41"), "\u{fffd}A");
+    }
+
+    #[test]
+    fn test_match_tracks_by_path_and_unique_name() {
+        let library = vec![
+            track(0, "/music/a/one.mp3"),
+            track(1, "/music/b/two.mp3"),
+            track(2, "/music/c/two.mp3"),
+            track(3, "/music/c/three.mp3"),
+        ];
+        let playlist = Playlist {
+            entries: vec![
+                PlaylistEntry::new("/music/b/../a/one.mp3"),
+                PlaylistEntry::new("/elsewhere/three.mp3"),
+                PlaylistEntry::new("/elsewhere/two.mp3"),
+                PlaylistEntry::new("/music/c/two.mp3"),
+                PlaylistEntry::new("/missing.mp3"),
+            ],
+        };
+        assert_eq!(playlist.match_tracks(&library), vec![Some(0), Some(3), None, Some(2), None]);
+    }
+
+    #[test]
+    fn test_load_decodes_latin1_only_in_legacy_formats() {
+        let dir = crate::harness::TempDir::new("playlist");
+        let m3u = b"/music/caf\xe9.mp3\n";
+        fs::write(dir.join("old.m3u"), m3u).unwrap();
+        fs::write(dir.join("old.pls"), b"[playlist]\nFile1=/music/caf\xe9.mp3\nNumberOfEntries=1\n").unwrap();
+        for name in ["old.m3u", "old.pls"] {
+            let playlist = load(&dir.join(name)).unwrap();
+            assert_eq!(playlist.entries[0].path, PathBuf::from("/music/café.mp3"), "{}", name);
+        }
+
+        fs::write(dir.join("new.m3u8"), [&b"/music/a.mp3\n"[..], m3u].concat()).unwrap();
+        let error = load(&dir.join("new.m3u8")).unwrap_err();
+        assert!(matches!(error, PlaylistError::Parse { line: 2, .. }), "{}", error);
+        let xspf = b"<?xml version=\"1.0\"?>\n<playlist><trackList><track><location>caf\xe9.mp3</location></track></trackList></playlist>\n";
+        fs::write(dir.join("new.xspf"), xspf).unwrap();
+        assert!(matches!(load(&dir.join("new.xspf")), Err(PlaylistError::Parse { .. })));
+    }
+}
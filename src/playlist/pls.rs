@@ -0,0 +1,145 @@
+//! PLS playlist support.
+//!
+//! PLS is an INI-style format with a single `[playlist]` section containing
+//! numbered `FileN`, `TitleN` and `LengthN` keys. Entries are ordered by their
+//! number rather than by their position in the file.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{relative_location, resolve_location, Playlist, PlaylistEntry, PlaylistError};
+
+/// Parses PLS content.
+///
+/// # Errors
+///
+/// Returns an error if a key has an invalid entry number or a `TitleN` /
+/// `LengthN` key has no matching `FileN`.
+pub fn parse(content: &str, base_dir: &Path) -> Result<Playlist, PlaylistError> {
+    #[derive(Default)]
+    struct Raw {
+        file: Option<String>,
+        title: Option<String>,
+        length: Option<i64>,
+        line: usize,
+    }
+
+    let mut raw: BTreeMap<u32, Raw> = BTreeMap::new();
+
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = raw_line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('[') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(PlaylistError::Parse { line: line_no, message: format!("expected key=value, got '{}'", line) });
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+
+        let (field, number) = if let Some(n) = key.strip_prefix("file") {
+            ("file", n)
+        } else if let Some(n) = key.strip_prefix("title") {
+            ("title", n)
+        } else if let Some(n) = key.strip_prefix("length") {
+            ("length", n)
+        } else {
+            // NumberOfEntries, Version and unknown keys carry no entry data
+            continue;
+        };
+        let number: u32 = number.parse().map_err(|_| PlaylistError::Parse {
+            line: line_no,
+            message: format!("invalid entry number in key '{}'", key),
+        })?;
+
+        let slot = raw.entry(number).or_insert_with(|| Raw { line: line_no, ..Raw::default() });
+        match field {
+            "file" => slot.file = Some(value.to_string()),
+            "title" => slot.title = Some(value.to_string()).filter(|t| !t.is_empty()),
+            _ => slot.length = value.parse().ok(),
+        }
+    }
+
+    let mut playlist = Playlist::default();
+    for (number, entry) in raw {
+        let Some(file) = entry.file else {
+            return Err(PlaylistError::Parse { line: entry.line, message: format!("entry {} has no File{} key", number, number) });
+        };
+        playlist.entries.push(PlaylistEntry {
+            path: resolve_location(&file, base_dir),
+            title: entry.title,
+            duration: entry.length.filter(|&l| l >= 0).map(|l| Duration::from_secs(l as u64)),
+        });
+    }
+    Ok(playlist)
+}
+
+/// Serializes a playlist as PLS version 2.
+pub fn write(playlist: &Playlist, base_dir: &Path) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (idx, entry) in playlist.entries.iter().enumerate() {
+        let n = idx + 1;
+        out.push_str(&format!("File{}={}\n", n, relative_location(&entry.path, base_dir).to_string_lossy()));
+        if let Some(title) = &entry.title {
+            out.push_str(&format!("Title{}={}\n", n, title));
+        }
+        let length = entry.duration.map(|d| d.as_secs() as i64).unwrap_or(-1);
+        out.push_str(&format!("Length{}={}\n", n, length));
+    }
+    out.push_str(&format!("NumberOfEntries={}\nVersion=2\n", playlist.entries.len()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_orders_by_number() {
+        let content = "[playlist]\n\
+                       File2=b.mp3\n\
+                       Title2=Second\n\
+                       Length2=-1\n\
+                       File1=/abs/a.flac\n\
+                       Title1=First\n\
+                       Length1=125\n\
+                       NumberOfEntries=2\n\
+                       Version=2\n";
+        let playlist = parse(content, Path::new("/music")).unwrap();
+
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(playlist.entries[0].path, PathBuf::from("/abs/a.flac"));
+        assert_eq!(playlist.entries[0].duration, Some(Duration::from_secs(125)));
+        assert_eq!(playlist.entries[1].path, PathBuf::from("/music/b.mp3"));
+        assert_eq!(playlist.entries[1].title.as_deref(), Some("Second"));
+        assert_eq!(playlist.entries[1].duration, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_title_without_file() {
+        let err = parse("[playlist]\nTitle1=Orphan\n", Path::new("/")).unwrap_err();
+        assert!(matches!(err, PlaylistError::Parse { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_write_roundtrip() {
+        let playlist = Playlist {
+            entries: vec![
+                PlaylistEntry {
+                    path: PathBuf::from("/music/one.mp3"),
+                    title: Some("One".to_string()),
+                    duration: Some(Duration::from_secs(10)),
+                },
+                PlaylistEntry::new("/music/sub/two.wav"),
+            ],
+        };
+        let text = write(&playlist, Path::new("/music"));
+        assert!(text.contains("File1=one.mp3\nTitle1=One\nLength1=10\n"));
+        assert!(text.contains("File2=sub/two.wav\nLength2=-1\n"));
+        assert!(text.ends_with("NumberOfEntries=2\nVersion=2\n"));
+        assert_eq!(parse(&text, Path::new("/music")).unwrap(), playlist);
+    }
+}
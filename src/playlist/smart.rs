@@ -0,0 +1,242 @@
+//! Smart playlists defined by queries.
+//!
+//! A smart playlist stores a [`Query`] instead of a fixed list of tracks and is
+//! re-evaluated against the library whenever it is shown, so its contents
+//! follow new tracks, tag changes and listening data automatically. Each one is
+//! saved as `<name>.smart` (the query text) next to the named playlists, by
+//! [`SmartPlaylistStore::flush`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::store::validate_name;
+use super::PlaylistError;
+use crate::common::Track;
+use crate::query::{Facts, Query};
+
+/// File extension of saved smart playlists
+const EXTENSION: &str = "smart";
+
+/// Collection of smart playlists backed by a directory.
+#[derive(Debug)]
+pub struct SmartPlaylistStore {
+    dir: PathBuf,
+    playlists: BTreeMap<String, Query>,
+    /// Smart playlists changed since they were written
+    unsaved: BTreeSet<String>,
+    /// Names whose files are to be removed (deleted or renamed playlists)
+    removed: BTreeSet<String>,
+}
+
+impl SmartPlaylistStore {
+    /// Creates an empty store for `dir` without reading it.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), playlists: BTreeMap::new(), unsaved: BTreeSet::new(), removed: BTreeSet::new() }
+    }
+
+    /// Opens the store in `dir`, loading every `.smart` file it contains.
+    ///
+    /// Files that cannot be read or hold an invalid query are skipped, so
+    /// that one bad file does not hide the other playlists.
+    ///
+    /// # Returns
+    ///
+    /// Returns the store and a [`PlaylistError::Load`] for each skipped file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be listed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<(Self, Vec<PlaylistError>), PlaylistError> {
+        let mut store = Self::new(dir);
+        let mut skipped = Vec::new();
+        if store.dir.is_dir() {
+            for entry in fs::read_dir(&store.dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+                match load(&path) {
+                    Ok(query) => {
+                        store.playlists.insert(name.to_string(), query);
+                    }
+                    Err(e) => skipped.push(PlaylistError::Load { path, source: Box::new(e) }),
+                }
+            }
+        }
+        Ok((store, skipped))
+    }
+
+    /// Returns the smart playlist names in sorted order.
+    pub fn names(&self) -> Vec<&str> {
+        self.playlists.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the number of smart playlists.
+    pub fn len(&self) -> usize {
+        self.playlists.len()
+    }
+
+    /// Returns `true` if the store holds no smart playlists.
+    pub fn is_empty(&self) -> bool {
+        self.playlists.is_empty()
+    }
+
+    /// Returns the query of a smart playlist.
+    pub fn get(&self, name: &str) -> Option<&Query> {
+        self.playlists.get(name)
+    }
+
+    /// Creates or replaces a smart playlist; the next flush writes it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid.
+    pub fn save(&mut self, name: &str, query: Query) -> Result<(), PlaylistError> {
+        validate_name(name)?;
+        self.playlists.insert(name.to_string(), query);
+        self.removed.remove(name);
+        self.unsaved.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Renames a smart playlist; its file is moved by the next flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `from` does not exist, or `to` is invalid or taken.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), PlaylistError> {
+        validate_name(to)?;
+        if from == to {
+            return Ok(());
+        }
+        if self.playlists.contains_key(to) {
+            return Err(PlaylistError::AlreadyExists(to.to_string()));
+        }
+        let query = self.playlists.remove(from).ok_or_else(|| PlaylistError::NotFound(from.to_string()))?;
+        self.unsaved.remove(from);
+        self.removed.insert(from.to_string());
+        self.save(to, query)
+    }
+
+    /// Deletes a smart playlist; its file is removed by the next flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the playlist does not exist.
+    pub fn delete(&mut self, name: &str) -> Result<(), PlaylistError> {
+        self.playlists.remove(name).ok_or_else(|| PlaylistError::NotFound(name.to_string()))?;
+        self.unsaved.remove(name);
+        self.removed.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Writes the changes since the last flush: the files of deleted and
+    /// renamed smart playlists are removed, and changed ones are written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be removed or written; what was not
+    /// done is tried again by the next flush.
+    pub fn flush(&mut self) -> Result<(), PlaylistError> {
+        while let Some(name) = self.removed.first().cloned() {
+            let path = self.path_for(&name);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            self.removed.remove(&name);
+        }
+        while let Some(name) = self.unsaved.first().cloned() {
+            if let Some(query) = self.playlists.get(&name) {
+                fs::create_dir_all(&self.dir)?;
+                fs::write(self.path_for(&name), format!("{}\n", query.as_str()))?;
+            }
+            self.unsaved.remove(&name);
+        }
+        Ok(())
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, EXTENSION))
+    }
+}
+
+/// Reads the query of a smart playlist file.
+fn load(path: &Path) -> Result<Query, PlaylistError> {
+    let text = fs::read_to_string(path)?;
+    Query::parse(text.trim()).map_err(|e| PlaylistError::Parse { line: 1, message: e.to_string() })
+}
+
+/// Evaluates a query over the library.
+///
+/// # Arguments
+///
+/// * `query` - Query to evaluate
+/// * `tracks` - Library tracks
+/// * `facts` - Builds the facts for a track index (metadata plus listening data)
+/// * `now` - Reference time for `played` ages
+///
+/// # Returns
+///
+/// Returns the indices of the matching tracks in library order.
+pub fn evaluate<'a>(
+    query: &Query,
+    tracks: &'a [Track],
+    facts: impl Fn(usize) -> Facts<'a>,
+    now: SystemTime,
+) -> Vec<usize> {
+    (0..tracks.len()).filter(|&i| query.matches(&facts(i), now)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    #[test]
+    fn test_save_reload_and_evaluate() {
+        let dir = TempDir::new("smart");
+        let mut store = SmartPlaylistStore::new(dir.path());
+        store.save("jazz", Query::parse("genre:jazz year:>=1960").unwrap()).unwrap();
+        store.save("old", Query::parse("year:<1960").unwrap()).unwrap();
+        store.flush().unwrap();
+        store.rename("old", "classics").unwrap();
+        store.flush().unwrap();
+        assert!(!dir.join("old.smart").exists());
+
+        let (reopened, skipped) = SmartPlaylistStore::open(dir.path()).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(reopened.names(), vec!["classics", "jazz"]);
+
+        let tracks = vec![
+            Track { genre: Some("Jazz".to_string()), year: Some(1959), ..Track::default() },
+            Track { genre: Some("Jazz".to_string()), year: Some(1964), ..Track::default() },
+            Track { genre: Some("Rock".to_string()), year: Some(1971), ..Track::default() },
+        ];
+        let query = reopened.get("jazz").unwrap();
+        let rows = evaluate(query, &tracks, |i| Facts::new(&tracks[i]), SystemTime::now());
+        assert_eq!(rows, vec![1]);
+
+        store.delete("jazz").unwrap();
+        store.flush().unwrap();
+        assert_eq!(SmartPlaylistStore::open(dir.path()).unwrap().0.names(), vec!["classics"]);
+    }
+
+    #[test]
+    fn test_open_skips_bad_files() {
+        let dir = TempDir::new("smart");
+        fs::write(dir.join("broken.smart"), "mood:happy\n").unwrap();
+        fs::write(dir.join("binary.smart"), b"\xff\xfe").unwrap();
+        fs::write(dir.join("jazz.smart"), "genre:jazz\n").unwrap();
+
+        let (store, skipped) = SmartPlaylistStore::open(dir.path()).unwrap();
+        assert_eq!(store.names(), vec!["jazz"]);
+        let mut messages: Vec<String> = skipped.iter().map(|e| e.to_string()).collect();
+        messages.sort();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("binary.smart"), "{:?}", messages);
+        assert!(messages[1].contains("broken.smart") && messages[1].contains("mood"), "{:?}", messages);
+    }
+}
@@ -0,0 +1,355 @@
+//! Named playlists saved in the player's data directory.
+//!
+//! Each playlist is stored as `<name>.m3u8` inside the store directory, so the
+//! files can be opened directly by other players. Mutations change the
+//! in-memory copy, the source of truth while running, and are written to disk
+//! by [`PlaylistStore::flush`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+use super::{m3u, Playlist, PlaylistEntry, PlaylistError, PlaylistFormat};
+use crate::common::Track;
+
+/// Collection of named playlists backed by a directory.
+#[derive(Debug)]
+pub struct PlaylistStore {
+    dir: PathBuf,
+    playlists: BTreeMap<String, Playlist>,
+    /// Playlists changed since they were written
+    unsaved: BTreeSet<String>,
+    /// Names whose files are to be removed (deleted or renamed playlists)
+    removed: BTreeSet<String>,
+}
+
+impl PlaylistStore {
+    /// Creates an empty store for `dir` without reading it.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), playlists: BTreeMap::new(), unsaved: BTreeSet::new(), removed: BTreeSet::new() }
+    }
+
+    /// Opens the store in `dir`, loading every `.m3u8` file it contains.
+    ///
+    /// A missing directory yields an empty store; it is created on the first
+    /// write. Playlists that cannot be read are skipped and left untouched on
+    /// disk: their names stay taken, so that creating or renaming a playlist
+    /// never overwrites them.
+    ///
+    /// # Returns
+    ///
+    /// Returns the store and a [`PlaylistError::Load`] for each skipped file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be listed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<(Self, Vec<PlaylistError>), PlaylistError> {
+        let dir = dir.into();
+        let mut playlists = BTreeMap::new();
+        let mut skipped = Vec::new();
+        if dir.is_dir() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if PlaylistFormat::from_path(&path) != Some(PlaylistFormat::M3u8) {
+                    continue;
+                }
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    let loaded =
+                        fs::read_to_string(&path).map_err(PlaylistError::from).and_then(|c| m3u::parse(&c, &dir));
+                    match loaded {
+                        Ok(playlist) => {
+                            playlists.insert(name.to_string(), playlist);
+                        }
+                        Err(e) => skipped.push(PlaylistError::Load { path, source: Box::new(e) }),
+                    }
+                }
+            }
+        }
+        Ok((Self { dir, playlists, unsaved: BTreeSet::new(), removed: BTreeSet::new() }, skipped))
+    }
+
+    /// Returns the playlist names in sorted order.
+    pub fn names(&self) -> Vec<&str> {
+        self.playlists.keys().map(String::as_str).collect()
+    }
+
+    /// Returns the number of playlists.
+    pub fn len(&self) -> usize {
+        self.playlists.len()
+    }
+
+    /// Returns `true` if the store holds no playlists.
+    pub fn is_empty(&self) -> bool {
+        self.playlists.is_empty()
+    }
+
+    /// Returns the playlist with the given name.
+    pub fn get(&self, name: &str) -> Option<&Playlist> {
+        self.playlists.get(name)
+    }
+
+    /// Creates a new, empty playlist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or already taken.
+    pub fn create(&mut self, name: &str) -> Result<(), PlaylistError> {
+        validate_name(name)?;
+        if self.is_taken(name) {
+            return Err(PlaylistError::AlreadyExists(name.to_string()));
+        }
+        self.playlists.insert(name.to_string(), Playlist::default());
+        self.changed(name);
+        Ok(())
+    }
+
+    /// Creates or replaces a playlist with the given content.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or belongs to a file that
+    /// could not be loaded.
+    pub fn save_as(&mut self, name: &str, playlist: Playlist) -> Result<(), PlaylistError> {
+        validate_name(name)?;
+        if !self.playlists.contains_key(name) && self.is_taken(name) {
+            return Err(PlaylistError::AlreadyExists(name.to_string()));
+        }
+        self.playlists.insert(name.to_string(), playlist);
+        self.changed(name);
+        Ok(())
+    }
+
+    /// Renames a playlist; its file is moved by the next flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `from` does not exist, or `to` is invalid or taken.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), PlaylistError> {
+        validate_name(to)?;
+        if from == to {
+            return Ok(());
+        }
+        if self.is_taken(to) {
+            return Err(PlaylistError::AlreadyExists(to.to_string()));
+        }
+        let playlist = self.playlists.remove(from).ok_or_else(|| PlaylistError::NotFound(from.to_string()))?;
+        self.playlists.insert(to.to_string(), playlist);
+        self.forget(from);
+        self.changed(to);
+        Ok(())
+    }
+
+    /// Deletes a playlist; its file is removed by the next flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the playlist does not exist.
+    pub fn delete(&mut self, name: &str) -> Result<(), PlaylistError> {
+        self.playlists.remove(name).ok_or_else(|| PlaylistError::NotFound(name.to_string()))?;
+        self.forget(name);
+        Ok(())
+    }
+
+    /// Appends tracks to the end of a playlist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the playlist does not exist.
+    pub fn add_tracks<'a>(
+        &mut self,
+        name: &str,
+        tracks: impl IntoIterator<Item = &'a Track>,
+    ) -> Result<(), PlaylistError> {
+        let playlist = self.get_mut(name)?;
+        playlist.entries.extend(tracks.into_iter().map(PlaylistEntry::from));
+        self.changed(name);
+        Ok(())
+    }
+
+    /// Removes the entry at `index` from a playlist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the playlist does not exist or the index is out of
+    /// range.
+    pub fn remove(&mut self, name: &str, index: usize) -> Result<(), PlaylistError> {
+        let playlist = self.get_mut(name)?;
+        check_index(index, playlist.entries.len())?;
+        playlist.entries.remove(index);
+        self.changed(name);
+        Ok(())
+    }
+
+    /// Moves the entry at `from` so that it ends up at position `to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the playlist does not exist or either index is out
+    /// of range.
+    pub fn move_entry(&mut self, name: &str, from: usize, to: usize) -> Result<(), PlaylistError> {
+        let playlist = self.get_mut(name)?;
+        let len = playlist.entries.len();
+        check_index(from, len)?;
+        check_index(to, len)?;
+        let entry = playlist.entries.remove(from);
+        playlist.entries.insert(to, entry);
+        self.changed(name);
+        Ok(())
+    }
+
+    /// Writes the changes since the last flush: the files of deleted and
+    /// renamed playlists are removed, and changed playlists are written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be removed or written; what was not
+    /// done is tried again by the next flush.
+    pub fn flush(&mut self) -> Result<(), PlaylistError> {
+        while let Some(name) = self.removed.first().cloned() {
+            let path = self.path_for(&name);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            self.removed.remove(&name);
+        }
+        while let Some(name) = self.unsaved.first().cloned() {
+            self.persist(&name)?;
+            self.unsaved.remove(&name);
+        }
+        Ok(())
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Playlist, PlaylistError> {
+        self.playlists.get_mut(name).ok_or_else(|| PlaylistError::NotFound(name.to_string()))
+    }
+
+    /// Returns whether a name belongs to a loaded playlist or to a file
+    /// that could not be loaded.
+    fn is_taken(&self, name: &str) -> bool {
+        self.playlists.contains_key(name) || (!self.removed.contains(name) && self.path_for(name).exists())
+    }
+
+    /// Marks a playlist to be written by the next flush.
+    fn changed(&mut self, name: &str) {
+        self.removed.remove(name);
+        self.unsaved.insert(name.to_string());
+    }
+
+    /// Marks the file of a playlist that is gone to be removed by the next
+    /// flush.
+    fn forget(&mut self, name: &str) {
+        self.unsaved.remove(name);
+        self.removed.insert(name.to_string());
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, PlaylistFormat::M3u8.extension()))
+    }
+
+    fn persist(&self, name: &str) -> Result<(), PlaylistError> {
+        fs::create_dir_all(&self.dir)?;
+        let playlist = self.playlists.get(name).ok_or_else(|| PlaylistError::NotFound(name.to_string()))?;
+        playlist.save(&self.path_for(name), PlaylistFormat::M3u8)
+    }
+}
+
+/// Rejects names that cannot be used as a file stem.
+pub(crate) fn validate_name(name: &str) -> Result<(), PlaylistError> {
+    let trimmed = name.trim();
+    if trimmed.is_empty() || trimmed != name || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+        return Err(PlaylistError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn check_index(index: usize, len: usize) -> Result<(), PlaylistError> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(PlaylistError::IndexOutOfRange { index, len })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    fn temp_store() -> (TempDir, PlaylistStore) {
+        let dir = TempDir::new("store");
+        let (store, _) = PlaylistStore::open(dir.path()).unwrap();
+        (dir, store)
+    }
+
+    fn track(id: u64, path: &str) -> Track {
+        Track { id, path: PathBuf::from(path), duration: None, title: Some(format!("T{}", id)), ..Track::default() }
+    }
+
+    #[test]
+    fn test_create_add_reorder_and_reload() {
+        let (dir, mut store) = temp_store();
+        let tracks = [track(0, "/m/a.mp3"), track(1, "/m/b.mp3"), track(2, "/m/c.mp3")];
+
+        store.create("road trip").unwrap();
+        store.add_tracks("road trip", tracks.iter()).unwrap();
+        store.move_entry("road trip", 2, 0).unwrap();
+        store.remove("road trip", 1).unwrap();
+        assert!(!dir.join("road trip.m3u8").exists(), "nothing is written before a flush");
+        store.flush().unwrap();
+
+        let (reopened, skipped) = PlaylistStore::open(dir.path()).unwrap();
+        assert!(skipped.is_empty());
+        let paths: Vec<_> = reopened.get("road trip").unwrap().entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(paths, vec![PathBuf::from("/m/c.mp3"), PathBuf::from("/m/b.mp3")]);
+    }
+
+    #[test]
+    fn test_rename_and_delete() {
+        let (dir, mut store) = temp_store();
+        store.create("old").unwrap();
+        store.create("other").unwrap();
+
+        assert!(matches!(store.rename("old", "other"), Err(PlaylistError::AlreadyExists(_))));
+        store.flush().unwrap();
+        store.rename("old", "new").unwrap();
+        assert_eq!(store.names(), vec!["new", "other"]);
+        store.flush().unwrap();
+        assert!(dir.join("new.m3u8").exists());
+        assert!(!dir.join("old.m3u8").exists());
+
+        store.delete("new").unwrap();
+        // The name is free again before the file is gone
+        store.create("new").unwrap();
+        store.delete("new").unwrap();
+        store.flush().unwrap();
+        assert!(!dir.join("new.m3u8").exists());
+        assert!(matches!(store.delete("new"), Err(PlaylistError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_invalid_names_and_indices() {
+        let (_dir, mut store) = temp_store();
+        for name in ["", " padded", ".hidden", "a/b"] {
+            assert!(matches!(store.create(name), Err(PlaylistError::InvalidName(_))), "{:?}", name);
+        }
+        store.create("x").unwrap();
+        assert!(matches!(store.remove("x", 0), Err(PlaylistError::IndexOutOfRange { index: 0, len: 0 })));
+    }
+
+    #[test]
+    fn test_open_skips_unreadable_playlists() {
+        let dir = TempDir::new("store");
+        fs::write(dir.join("good.m3u8"), "/m/a.mp3\n").unwrap();
+        fs::write(dir.join("latin1.m3u8"), b"/m/caf\xe9.mp3\n").unwrap();
+
+        let (mut store, skipped) = PlaylistStore::open(dir.path()).unwrap();
+        assert_eq!(store.names(), vec!["good"]);
+        assert!(matches!(&skipped[..], [PlaylistError::Load { path, .. }] if path.ends_with("latin1.m3u8")));
+
+        // The skipped file keeps its name until it is fixed or removed by hand
+        assert!(matches!(store.create("latin1"), Err(PlaylistError::AlreadyExists(_))));
+        assert!(matches!(store.save_as("latin1", Playlist::default()), Err(PlaylistError::AlreadyExists(_))));
+        assert!(matches!(store.rename("good", "latin1"), Err(PlaylistError::AlreadyExists(_))));
+        assert_eq!(fs::read(dir.join("latin1.m3u8")).unwrap(), b"/m/caf\xe9.mp3\n");
+    }
+}
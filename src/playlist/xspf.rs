@@ -0,0 +1,227 @@
+//! XSPF playlist support.
+//!
+//! XSPF is an XML format where each `<track>` element holds a `<location>` URI
+//! and optional `<title>`, `<creator>` and `<duration>` (milliseconds). Only
+//! this subset is read; the reader is a small tag scanner rather than a full
+//! XML parser, which is sufficient for the files written by common players.
+
+use std::path::Path;
+use std::time::Duration;
+
+use super::{
+    percent_decode, percent_encode, relative_location, resolve_location, Playlist, PlaylistEntry,
+    PlaylistError,
+};
+
+/// Parses XSPF content.
+///
+/// When a track has both `<creator>` and `<title>`, the entry title is stored
+/// as `"creator - title"`, matching how `#EXTINF` titles are usually written.
+///
+/// # Errors
+///
+/// Returns an error if the document has no `<trackList>`, a `<track>` is not
+/// closed, or a track has no `<location>`.
+pub fn parse(content: &str, base_dir: &Path) -> Result<Playlist, PlaylistError> {
+    let Some(list_start) = content.find("<trackList") else {
+        return Err(PlaylistError::Parse { line: 1, message: "missing <trackList> element".to_string() });
+    };
+
+    let mut playlist = Playlist::default();
+    let mut cursor = list_start;
+    // Line of the latest track, counted on from the previous one
+    let mut line = 1;
+    let mut counted = 0;
+    while let Some(offset) = find_open_tag(&content[cursor..], "track") {
+        let start = cursor + offset;
+        line += content[counted..start].matches('\n').count();
+        counted = start;
+        let Some(end_offset) = content[start..].find("</track>") else {
+            return Err(PlaylistError::Parse { line, message: "unclosed <track> element".to_string() });
+        };
+        let body = &content[start..start + end_offset];
+        cursor = start + end_offset + "</track>".len();
+
+        let Some(location) = element_text(body, "location") else {
+            return Err(PlaylistError::Parse { line, message: "<track> without <location>".to_string() });
+        };
+        let location = if location.starts_with("file://") || location.contains("://") {
+            location
+        } else {
+            percent_decode(&location)
+        };
+
+        let title = match (element_text(body, "creator"), element_text(body, "title")) {
+            (Some(creator), Some(title)) => Some(format!("{} - {}", creator, title)),
+            (None, title) => title,
+            (Some(_), None) => None,
+        };
+        let duration = element_text(body, "duration")
+            .and_then(|ms| ms.trim().parse::<u64>().ok())
+            .map(Duration::from_millis);
+
+        playlist.entries.push(PlaylistEntry { path: resolve_location(&location, base_dir), title, duration });
+    }
+    Ok(playlist)
+}
+
+/// Serializes a playlist as XSPF version 1.
+pub fn write(playlist: &Playlist, base_dir: &Path) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for entry in &playlist.entries {
+        let location = relative_location(&entry.path, base_dir);
+        let location = location.to_string_lossy();
+        let uri = if location.contains("://") {
+            location.into_owned()
+        } else if location.starts_with('/') {
+            format!("file://{}", percent_encode(&location))
+        } else {
+            percent_encode(&location)
+        };
+
+        out.push_str("    <track>\n");
+        out.push_str(&format!("      <location>{}</location>\n", escape(&uri)));
+        if let Some(title) = &entry.title {
+            out.push_str(&format!("      <title>{}</title>\n", escape(title)));
+        }
+        if let Some(duration) = entry.duration {
+            out.push_str(&format!("      <duration>{}</duration>\n", duration.as_millis()));
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+/// Finds the start of `<name>` or `<name ...>` (but not `<nameSuffix>`).
+fn find_open_tag(haystack: &str, name: &str) -> Option<usize> {
+    let needle = format!("<{}", name);
+    let mut from = 0;
+    while let Some(pos) = haystack[from..].find(&needle) {
+        let at = from + pos;
+        match haystack[at + needle.len()..].chars().next() {
+            Some('>') | Some(' ') | Some('\t') | Some('\n') | Some('\r') => return Some(at),
+            _ => from = at + needle.len(),
+        }
+    }
+    None
+}
+
+/// Returns the unescaped, trimmed text content of the first `<name>` element.
+fn element_text(body: &str, name: &str) -> Option<String> {
+    let start = find_open_tag(body, name)?;
+    let content_start = start + body[start..].find('>')? + 1;
+    let close = format!("</{}>", name);
+    let content_end = content_start + body[content_start..].find(&close)?;
+    let text = body[content_start..content_end].trim();
+    let text = match text.strip_prefix("<![CDATA[").and_then(|t| t.strip_suffix("]]>")) {
+        Some(cdata) => cdata.to_string(),
+        None => unescape(text),
+    };
+    Some(text).filter(|t| !t.is_empty())
+}
+
+/// Escapes the XML special characters.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Decodes predefined and numeric XML entities.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else { break };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|n| n.ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_tracks() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Road Trip</title>
+  <trackList>
+    <track>
+      <location>file:///music/Miles%20Davis/So%20What.flac</location>
+      <creator>Miles Davis</creator>
+      <title>So What</title>
+      <duration>562000</duration>
+    </track>
+    <track><location>rel/Tom &amp; Jerry.mp3</location><title>A &lt;b&gt; &#233;</title></track>
+  </trackList>
+</playlist>"#;
+        let playlist = parse(content, Path::new("/lists")).unwrap();
+
+        assert_eq!(playlist.entries.len(), 2);
+        assert_eq!(playlist.entries[0].path, PathBuf::from("/music/Miles Davis/So What.flac"));
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("Miles Davis - So What"));
+        assert_eq!(playlist.entries[0].duration, Some(Duration::from_millis(562000)));
+        assert_eq!(playlist.entries[1].path, PathBuf::from("/lists/rel/Tom & Jerry.mp3"));
+        assert_eq!(playlist.entries[1].title.as_deref(), Some("A <b> é"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("<playlist/>", Path::new("/")).is_err());
+        let missing = "<trackList>\n<track><title>x</title></track></trackList>";
+        assert!(matches!(parse(missing, Path::new("/")), Err(PlaylistError::Parse { line: 2, .. })));
+        let later = "<trackList>\n<track><location>a.mp3</location></track>\n\n<track>\n</track></trackList>";
+        assert!(matches!(parse(later, Path::new("/")), Err(PlaylistError::Parse { line: 4, .. })));
+    }
+
+    #[test]
+    fn test_write_roundtrip() {
+        let playlist = Playlist {
+            entries: vec![
+                PlaylistEntry {
+                    path: PathBuf::from("/music/a & b.mp3"),
+                    title: Some("Rock & Roll".to_string()),
+                    duration: Some(Duration::from_secs(3)),
+                },
+                PlaylistEntry::new("/elsewhere/c d.ogg"),
+            ],
+        };
+        let text = write(&playlist, Path::new("/music"));
+        assert!(text.contains("<location>a%20%26%20b.mp3</location>"));
+        assert!(text.contains("<title>Rock &amp; Roll</title>"));
+        assert!(text.contains("<duration>3000</duration>"));
+        assert!(text.contains("<location>file:///elsewhere/c%20d.ogg</location>"));
+        assert_eq!(parse(&text, Path::new("/music")).unwrap(), playlist);
+    }
+}
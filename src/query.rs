@@ -0,0 +1,688 @@
+//! Query language for search and smart playlists.
+//!
+//! Queries combine field terms with boolean operators, for example:
+//!
+//! ```text
+//! genre:jazz AND year:>=1960 AND rating:>=4 AND NOT played:<30d
+//! artist:"Miles Davis" OR artist:coltrane
+//! blue -live
+//! ```
+//!
+//! # Syntax
+//!
+//! - `field:value` matches a field; text fields match substrings case-insensitively
+//! - `field:=value`, `field:!=value`, `field:<value`, `field:<=value`, `field:>value`,
+//!   `field:>=value` compare exactly or numerically
+//! - A bare word matches title, artist, album, genre or path
+//! - `AND` (or just a space), `OR`, `NOT` (or a leading `-`) and parentheses
+//!   combine terms; `NOT` binds tightest, then `AND`, then `OR`
+//! - Values containing spaces are quoted: `album:"Kind of Blue"`
+//!
+//! # Fields
+//!
+//! | Field | Kind | Example |
+//! |-------|------|---------|
+//! | `title`, `artist`, `album`, `genre`, `path` | text | `artist:davis` |
+//! | `year`, `rating` (`stars`), `plays` (`playcount`), `skips` | number | `year:>=1960` |
+//! | `duration` (`length`) | seconds, `5m`, `3:30` | `duration:>10m` |
+//! | `played` | age (`45s`, `10m`, `6h`, `30d`, `2w`, `1y`) or `never` | `played:<30d` |
+//! | `favorite` (`fav`) | `yes`/`no` | `fav:yes` |
+//!
+//! `played:<30d` means "last played less than 30 days ago"; tracks that were
+//! never played only match `played:never`.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use thiserror::Error;
+
+use crate::common::Track;
+
+/// Deepest nesting of parentheses and `NOT`s accepted
+const MAX_DEPTH: usize = 64;
+
+/// Most words, operators and parentheses accepted in a query, which bounds
+/// the depth of chained `AND`s and `OR`s
+const MAX_TOKENS: usize = 1024;
+
+/// Longest query accepted, in bytes, checked before the query is tokenized
+const MAX_LENGTH: usize = 8 * 1024;
+
+/// Facts about a track that queries are evaluated against.
+///
+/// Combines the track's own metadata with the listening data kept by the
+/// player; fields the player does not know about keep their defaults.
+#[derive(Clone, Debug)]
+pub struct Facts<'a> {
+    /// The track and its tag metadata
+    pub track: &'a Track,
+    /// Number of completed plays
+    pub play_count: u32,
+    /// Number of times the track was skipped
+    pub skip_count: u32,
+    /// When the track was last played
+    pub last_played: Option<SystemTime>,
+    /// Star rating from 0 to 5 (0 means unrated)
+    pub rating: u8,
+    /// Whether the track is marked as a favorite
+    pub favorite: bool,
+}
+
+impl<'a> Facts<'a> {
+    /// Creates facts for a track without any listening data.
+    pub fn new(track: &'a Track) -> Self {
+        Self { track, play_count: 0, skip_count: 0, last_played: None, rating: 0, favorite: false }
+    }
+}
+
+/// Errors reported while parsing a query.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QueryError {
+    /// The query ended where a term was expected
+    #[error("unexpected end of query")]
+    UnexpectedEnd,
+    /// A token appeared where it is not allowed
+    #[error("unexpected '{token}' at column {column}")]
+    Unexpected {
+        /// The offending token
+        token: String,
+        /// 1-based column of the token
+        column: usize,
+    },
+    /// A quoted value was not closed
+    #[error("unclosed quote starting at column {0}")]
+    UnclosedQuote(usize),
+    /// The field name is not known
+    #[error("unknown field '{0}'")]
+    UnknownField(String),
+    /// The value or operator does not fit the field
+    #[error("invalid value '{value}' for field '{field}'")]
+    InvalidValue {
+        /// Field name as written
+        field: String,
+        /// Operator and value as written
+        value: String,
+    },
+    /// Parentheses or `NOT`s are nested deeper than [`MAX_DEPTH`]
+    #[error("query nested too deeply at column {0}")]
+    TooDeep(usize),
+    /// The query is longer than [`MAX_LENGTH`] bytes or has more than
+    /// [`MAX_TOKENS`] tokens
+    #[error("query too long")]
+    TooLong,
+}
+
+/// Fields that can be queried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Path,
+    Year,
+    Rating,
+    Plays,
+    Skips,
+    Duration,
+    Played,
+    Favorite,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "title" => Self::Title,
+            "artist" => Self::Artist,
+            "album" => Self::Album,
+            "genre" => Self::Genre,
+            "path" | "file" => Self::Path,
+            "year" => Self::Year,
+            "rating" | "stars" => Self::Rating,
+            "plays" | "playcount" => Self::Plays,
+            "skips" | "skipcount" => Self::Skips,
+            "duration" | "length" => Self::Duration,
+            "played" | "lastplayed" => Self::Played,
+            "favorite" | "fav" => Self::Favorite,
+            _ => return None,
+        })
+    }
+
+    fn is_text(self) -> bool {
+        matches!(self, Self::Title | Self::Artist | Self::Album | Self::Genre | Self::Path)
+    }
+}
+
+/// Comparison operators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    /// `:` without an explicit operator
+    Match,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn compare<T: PartialOrd>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Self::Match | Self::Eq => lhs == rhs,
+            Self::Ne => lhs != rhs,
+            Self::Lt => lhs < rhs,
+            Self::Le => lhs <= rhs,
+            Self::Gt => lhs > rhs,
+            Self::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// Right-hand side of a term.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    /// Lowercased text
+    Text(String),
+    /// Number (years, counts, ratings, seconds)
+    Number(f64),
+    /// Time since an event
+    Age(Duration),
+    /// `played:never`
+    Never,
+    /// Yes/no flag
+    Flag(bool),
+}
+
+/// Parsed query expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    /// Matches every track (the empty query)
+    All,
+    /// Bare word matched against all text fields
+    Any(String),
+    Term(Field, Op, Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A parsed query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    source: String,
+    expr: Expr,
+}
+
+impl Query {
+    /// Parses a query string.
+    ///
+    /// An empty (or whitespace-only) query matches every track.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first problem found.
+    pub fn parse(source: &str) -> Result<Self, QueryError> {
+        if source.len() > MAX_LENGTH {
+            return Err(QueryError::TooLong);
+        }
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0, depth: 0 };
+        let expr = if parser.tokens.is_empty() { Expr::All } else { parser.parse_or()? };
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(QueryError::Unexpected { token: token.text.clone(), column: token.column });
+        }
+        Ok(Self { source: source.trim().to_string(), expr })
+    }
+
+    /// Returns the query text as written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the query against a track.
+    ///
+    /// # Arguments
+    ///
+    /// * `facts` - Metadata and listening data of the track
+    /// * `now` - Reference time for `played` ages
+    pub fn matches(&self, facts: &Facts, now: SystemTime) -> bool {
+        eval(&self.expr, facts, now)
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A lexical token.
+#[derive(Clone, Debug)]
+struct Token {
+    /// Token text with quotes removed
+    text: String,
+    /// Whether any part of the token was quoted (quoted words are never keywords)
+    quoted: bool,
+    /// 1-based column where the token starts
+    column: usize,
+}
+
+/// Splits a query into words and parentheses, honoring double quotes.
+///
+/// # Errors
+///
+/// Returns [`QueryError::TooLong`] as soon as there are more than
+/// [`MAX_TOKENS`] tokens.
+fn tokenize(source: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    // Characters are numbered as they are read, which gives the columns
+    let mut chars = source.chars().enumerate().peekable();
+
+    while let Some(&(idx, c)) = chars.peek() {
+        let column = idx + 1;
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if tokens.len() == MAX_TOKENS {
+            return Err(QueryError::TooLong);
+        }
+        if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(Token { text: c.to_string(), quoted: false, column });
+            continue;
+        }
+
+        let mut text = String::new();
+        let mut quoted = false;
+        while let Some(&(at, c)) = chars.peek() {
+            if c.is_whitespace() || c == '(' || c == ')' {
+                break;
+            }
+            chars.next();
+            if c == '"' {
+                quoted = true;
+                let start = at + 1;
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => text.push(c),
+                        None => return Err(QueryError::UnclosedQuote(start)),
+                    }
+                }
+            } else {
+                text.push(c);
+            }
+        }
+        tokens.push(Token { text, quoted, column });
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser over the token list.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Parentheses and `NOT`s currently open
+    depth: usize,
+}
+
+impl Parser {
+    /// Enters a nested expression starting at a token.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueryError::TooDeep`] past [`MAX_DEPTH`] levels, which keeps
+    /// hostile queries from overflowing the stack.
+    fn nest(&mut self, token: &Token) -> Result<(), QueryError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(QueryError::TooDeep(token.column));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        self.tokens.get(self.pos).is_some_and(|t| !t.quoted && t.text == keyword)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.parse_and()?;
+        while self.peek_keyword("OR") {
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            if self.peek_keyword("AND") {
+                self.pos += 1;
+            } else if self.pos >= self.tokens.len() || self.peek_keyword("OR") || self.peek_keyword(")") {
+                break;
+            }
+            // Adjacent terms are joined with an implicit AND
+            let rhs = self.parse_unary()?;
+            lhs = Expr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(QueryError::UnexpectedEnd)?;
+        if !token.quoted && token.text == "NOT" {
+            self.pos += 1;
+            self.nest(&token)?;
+            let inner = self.parse_unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Not(Box::new(inner)));
+        }
+        if !token.quoted && token.text.len() > 1 && token.text.starts_with('-') {
+            self.pos += 1;
+            let inner = Token { text: token.text[1..].to_string(), column: token.column + 1, ..token };
+            return Ok(Expr::Not(Box::new(parse_term(&inner)?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(QueryError::UnexpectedEnd)?;
+        self.pos += 1;
+        if token.quoted {
+            return parse_term(&token);
+        }
+        match token.text.as_str() {
+            "(" => {
+                self.nest(&token)?;
+                let expr = self.parse_or()?;
+                self.depth -= 1;
+                if !self.peek_keyword(")") {
+                    return match self.tokens.get(self.pos) {
+                        Some(t) => Err(QueryError::Unexpected { token: t.text.clone(), column: t.column }),
+                        None => Err(QueryError::UnexpectedEnd),
+                    };
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            ")" | "AND" | "OR" => Err(QueryError::Unexpected { token: token.text, column: token.column }),
+            _ => parse_term(&token),
+        }
+    }
+}
+
+/// Parses a single word into a field term or a bare-word match.
+fn parse_term(token: &Token) -> Result<Expr, QueryError> {
+    let Some((name, rest)) = token.text.split_once(':') else {
+        return Ok(Expr::Any(token.text.to_lowercase()));
+    };
+    let field = Field::from_name(name).ok_or_else(|| QueryError::UnknownField(name.to_string()))?;
+    let invalid = || QueryError::InvalidValue { field: name.to_string(), value: rest.to_string() };
+
+    let (op, raw) = [(">=", Op::Ge), ("<=", Op::Le), ("!=", Op::Ne), (">", Op::Gt), ("<", Op::Lt), ("=", Op::Eq)]
+        .iter()
+        .find_map(|(prefix, op)| rest.strip_prefix(prefix).map(|v| (*op, v)))
+        .unwrap_or((Op::Match, rest));
+    if raw.is_empty() {
+        return Err(invalid());
+    }
+
+    let value = match field {
+        f if f.is_text() => {
+            if !matches!(op, Op::Match | Op::Eq | Op::Ne) {
+                return Err(invalid());
+            }
+            Value::Text(raw.to_lowercase())
+        }
+        Field::Year | Field::Rating | Field::Plays | Field::Skips => {
+            Value::Number(raw.parse().map_err(|_| invalid())?)
+        }
+        Field::Duration => Value::Number(parse_seconds(raw).ok_or_else(invalid)?),
+        Field::Played if raw.eq_ignore_ascii_case("never") => match op {
+            Op::Match | Op::Eq => Value::Never,
+            _ => return Err(invalid()),
+        },
+        Field::Played => {
+            let age = parse_age(raw).ok_or_else(invalid)?;
+            // `played:30d` reads as "played within the last 30 days"
+            return Ok(Expr::Term(field, if op == Op::Match { Op::Lt } else { op }, Value::Age(age)));
+        }
+        _ => match raw.to_lowercase().as_str() {
+            "yes" | "true" | "1" => Value::Flag(true),
+            "no" | "false" | "0" => Value::Flag(false),
+            _ => return Err(invalid()),
+        },
+    };
+    Ok(Expr::Term(field, op, value))
+}
+
+/// Parses a duration given as seconds, `5m`, `90s`, `1h` or `m:ss`.
+fn parse_seconds(raw: &str) -> Option<f64> {
+    if let Some((min, sec)) = raw.split_once(':') {
+        let min: f64 = min.parse().ok()?;
+        let sec: f64 = sec.parse().ok()?;
+        return Some(min * 60.0 + sec);
+    }
+    match raw.parse::<f64>() {
+        Ok(secs) => Some(secs),
+        Err(_) => parse_age(raw).map(|d| d.as_secs_f64()),
+    }
+}
+
+/// Parses an age such as `45s`, `10m`, `6h`, `30d`, `2w` or `1y`.
+fn parse_age(raw: &str) -> Option<Duration> {
+    let split = raw.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (num, unit) = raw.split_at(split);
+    let num: f64 = num.parse().ok()?;
+    let unit_secs = match unit.to_lowercase().as_str() {
+        "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        "d" => 86_400.0,
+        "w" => 7.0 * 86_400.0,
+        "y" => 365.0 * 86_400.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(num * unit_secs).ok()
+}
+
+/// Evaluates an expression against a track.
+fn eval(expr: &Expr, facts: &Facts, now: SystemTime) -> bool {
+    match expr {
+        Expr::All => true,
+        Expr::Any(word) => [Field::Title, Field::Artist, Field::Album, Field::Genre, Field::Path]
+            .iter()
+            .any(|&f| text_of(facts, f).is_some_and(|t| t.to_lowercase().contains(word.as_str()))),
+        Expr::Not(inner) => !eval(inner, facts, now),
+        Expr::And(lhs, rhs) => eval(lhs, facts, now) && eval(rhs, facts, now),
+        Expr::Or(lhs, rhs) => eval(lhs, facts, now) || eval(rhs, facts, now),
+        Expr::Term(field, op, value) => eval_term(*field, *op, value, facts, now),
+    }
+}
+
+fn eval_term(field: Field, op: Op, value: &Value, facts: &Facts, now: SystemTime) -> bool {
+    match value {
+        Value::Text(needle) => {
+            let text = text_of(facts, field).map(|t| t.to_lowercase()).unwrap_or_default();
+            match op {
+                Op::Match => text.contains(needle.as_str()),
+                Op::Eq => text == *needle,
+                _ => text != *needle,
+            }
+        }
+        Value::Number(rhs) => {
+            let lhs = match field {
+                Field::Year => facts.track.year.map(f64::from),
+                Field::Rating => Some(f64::from(facts.rating)),
+                Field::Plays => Some(f64::from(facts.play_count)),
+                Field::Skips => Some(f64::from(facts.skip_count)),
+                _ => facts.track.duration.map(|d| d.as_secs_f64()),
+            };
+            lhs.is_some_and(|lhs| op.compare(lhs, *rhs))
+        }
+        Value::Age(age) => facts
+            .last_played
+            .map(|t| now.duration_since(t).unwrap_or(Duration::ZERO))
+            .is_some_and(|elapsed| op.compare(elapsed, *age)),
+        Value::Never => facts.last_played.is_none(),
+        Value::Flag(flag) => op.compare(facts.favorite, *flag),
+    }
+}
+
+/// Returns the text of a text field.
+fn text_of<'a>(facts: &'a Facts, field: Field) -> Option<std::borrow::Cow<'a, str>> {
+    let track = facts.track;
+    match field {
+        Field::Title => track.title.as_deref().map(Into::into),
+        Field::Artist => track.artist.as_deref().map(Into::into),
+        Field::Album => track.album.as_deref().map(Into::into),
+        Field::Genre => track.genre.as_deref().map(Into::into),
+        Field::Path => Some(track.path.to_string_lossy()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::path::PathBuf;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    fn jazz_track() -> Track {
+        Track {
+            id: 1,
+            path: PathBuf::from("/music/jazz/so_what.flac"),
+            duration: Some(Duration::from_secs(562)),
+            title: Some("So What".to_string()),
+            artist: Some("Miles Davis".to_string()),
+            album: Some("Kind of Blue".to_string()),
+            genre: Some("Jazz".to_string()),
+            year: Some(1959),
+            rating: None,
+        }
+    }
+
+    fn check(query: &str, facts: &Facts, now: SystemTime) -> bool {
+        Query::parse(query).unwrap().matches(facts, now)
+    }
+
+    #[test]
+    fn test_text_and_numeric_terms() {
+        let track = jazz_track();
+        let facts = Facts { rating: 4, ..Facts::new(&track) };
+        let now = SystemTime::now();
+
+        assert!(check("genre:jazz", &facts, now));
+        assert!(check("artist:\"miles davis\"", &facts, now));
+        assert!(check("album:=\"kind of blue\"", &facts, now));
+        assert!(!check("album:=kind", &facts, now));
+        assert!(check("year:<1960 AND rating:>=4", &facts, now));
+        assert!(!check("year:>=1960", &facts, now));
+        assert!(check("duration:>9m duration:<9:30", &facts, now));
+        assert!(check("what", &facts, now));
+        assert!(check("", &facts, now));
+    }
+
+    #[test]
+    fn test_boolean_operators_and_precedence() {
+        let track = jazz_track();
+        let facts = Facts::new(&track);
+        let now = SystemTime::now();
+
+        assert!(check("genre:rock OR genre:jazz", &facts, now));
+        assert!(!check("genre:rock OR genre:jazz AND year:2000", &facts, now));
+        assert!(check("(genre:rock OR genre:jazz) AND NOT year:2000", &facts, now));
+        assert!(!check("davis -blue", &facts, now));
+        assert!(check("NOT NOT jazz", &facts, now));
+    }
+
+    #[test]
+    fn test_played_ages_and_never() {
+        let track = jazz_track();
+        let now = SystemTime::now();
+        let recent = Facts { last_played: Some(now - 3 * DAY), ..Facts::new(&track) };
+        let old = Facts { last_played: Some(now - 90 * DAY), ..Facts::new(&track) };
+        let never = Facts::new(&track);
+
+        let query = "genre:jazz AND NOT played:<30d";
+        assert!(!check(query, &recent, now));
+        assert!(check(query, &old, now));
+        assert!(check(query, &never, now));
+        assert!(check("played:7d", &recent, now));
+        assert!(check("played:>1y", &Facts { last_played: Some(now - 400 * DAY), ..Facts::new(&track) }, now));
+        assert!(check("played:never", &never, now) && !check("played:never", &old, now));
+    }
+
+    #[test]
+    fn test_favorite_and_counts() {
+        let track = jazz_track();
+        let facts = Facts { favorite: true, play_count: 12, skip_count: 1, ..Facts::new(&track) };
+        let now = SystemTime::now();
+
+        assert!(check("fav:yes plays:>10 skips:<=1", &facts, now));
+        assert!(!check("favorite:no", &facts, now));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Query::parse("mood:happy"), Err(QueryError::UnknownField("mood".to_string())));
+        assert!(matches!(Query::parse("year:abc"), Err(QueryError::InvalidValue { .. })));
+        assert!(matches!(Query::parse("title:>x"), Err(QueryError::InvalidValue { .. })));
+        assert!(matches!(Query::parse("played:<soon"), Err(QueryError::InvalidValue { .. })));
+        assert!(matches!(Query::parse("played:<1000000000000y"), Err(QueryError::InvalidValue { .. })));
+        assert_eq!(Query::parse("jazz AND"), Err(QueryError::UnexpectedEnd));
+        assert_eq!(Query::parse("(jazz"), Err(QueryError::UnexpectedEnd));
+        assert!(matches!(Query::parse("jazz)"), Err(QueryError::Unexpected { column: 5, .. })));
+        assert_eq!(Query::parse("artist:\"miles"), Err(QueryError::UnclosedQuote(8)));
+        assert_eq!(Query::parse(&"(".repeat(1000)), Err(QueryError::TooDeep(65)));
+        assert_eq!(Query::parse(&"NOT ".repeat(1000)), Err(QueryError::TooDeep(257)));
+        assert_eq!(Query::parse(&"jazz ".repeat(MAX_TOKENS + 1)), Err(QueryError::TooLong));
+        assert!(matches!(Query::parse("é (jazz))"), Err(QueryError::Unexpected { column: 9, .. })));
+        assert_eq!(Query::parse("a\"b\"\"c"), Err(QueryError::UnclosedQuote(5)));
+        let nested = format!("{}jazz{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert!(Query::parse(&nested).is_ok());
+    }
+
+    #[test]
+    fn test_huge_queries_are_rejected_quickly() {
+        // As large as a request body the remote APIs accept
+        let started = std::time::Instant::now();
+        assert_eq!(Query::parse(&" ".repeat(64 * 1024)), Err(QueryError::TooLong));
+        assert_eq!(Query::parse(&"é".repeat(32 * 1024)), Err(QueryError::TooLong));
+        assert_eq!(Query::parse(&"a ".repeat(MAX_LENGTH / 2)), Err(QueryError::TooLong));
+        assert!(Query::parse(&format!("{}jazz", " ".repeat(MAX_LENGTH - 4))).is_ok());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(100))]
+
+        #[test]
+        fn prop_not_inverts_any_term(year in 1900u32..2030, bound in 1900u32..2030, op_idx in 0usize..6) {
+            let ops = ["=", "!=", "<", "<=", ">", ">="];
+            let track = Track { year: Some(year), ..Track::default() };
+            let facts = Facts::new(&track);
+            let now = SystemTime::now();
+            let term = format!("year:{}{}", ops[op_idx], bound);
+
+            let positive = check(&term, &facts, now);
+            let negative = check(&format!("NOT {}", term), &facts, now);
+            prop_assert_ne!(positive, negative);
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Playback queue.
+//!
+//! The queue holds the ordered list of tracks that playback advances through,
+//! together with the position of the current track. It is filled from the view
+//! a track was started from (the whole library or a named playlist), so that
+//! auto-advance and next/previous follow that context. A queue loaded from a
+//! playlist is rebuilt when the playlist changes (see [`Queue::replace`]).
+//!
+//! Shuffle and repeat are settings of the queue that survive reloading it:
+//! shuffling reorders the queued tracks (keeping the current track first),
+//! and repeat decides what happens at the end of the queue or of a track.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What playback does when a track or the whole queue ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// Stop at the end of the queue
+    #[default]
+    Off,
+    /// Start over at the end of the queue
+    All,
+    /// Replay the current track
+    One,
+}
+
+impl RepeatMode {
+    /// Returns the next mode in the cycle Off → All → One → Off.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+}
+
+impl fmt::Display for RepeatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::All => "all",
+            Self::One => "one",
+        })
+    }
+}
+
+impl FromStr for RepeatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "all" => Ok(Self::All),
+            "one" => Ok(Self::One),
+            other => Err(format!("unknown repeat mode '{}'", other)),
+        }
+    }
+}
+
+/// Ordered list of library track indices with a cursor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Queue {
+    /// Library track indices in playback order
+    tracks: Vec<usize>,
+    /// Tracks in the order they were loaded, kept while shuffled
+    original: Vec<usize>,
+    /// Position of the current track within `tracks`
+    position: Option<usize>,
+    /// Name of the playlist the queue was loaded from (`None` for the library)
+    source: Option<String>,
+    /// Whether the tracks are played in random order
+    shuffle: bool,
+    /// What happens at the end of a track or the queue
+    repeat: RepeatMode,
+}
+
+impl Queue {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the queue contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracks` - Library track indices in playback order
+    /// * `position` - Position of the current track (clamped to the queue)
+    /// * `source` - Name of the originating playlist, `None` for the library
+    ///
+    /// When shuffle is on, the tracks are shuffled with the one at
+    /// `position` moved to the front.
+    pub fn load(&mut self, tracks: Vec<usize>, position: Option<usize>, source: Option<String>) {
+        self.position = position.filter(|&p| p < tracks.len());
+        self.original = tracks.clone();
+        self.tracks = tracks;
+        self.source = source;
+        if self.shuffle {
+            self.reshuffle();
+        }
+    }
+
+    /// Restores a saved queue as-is, including its settings.
+    ///
+    /// Unlike [`load`](Self::load) this does not shuffle: `tracks` is taken as
+    /// the playback order, and also becomes the order restored when shuffle is
+    /// turned off.
+    pub fn restore(
+        &mut self,
+        tracks: Vec<usize>,
+        position: Option<usize>,
+        source: Option<String>,
+        shuffle: bool,
+        repeat: RepeatMode,
+    ) {
+        self.shuffle = false;
+        self.load(tracks, position, source);
+        self.shuffle = shuffle;
+        self.repeat = repeat;
+    }
+
+    /// Replaces the tracks with those of the changed source playlist,
+    /// keeping the current track current.
+    ///
+    /// The current track stays queued even if it left the playlist, so that
+    /// playback goes on with the tracks that followed it. While shuffled,
+    /// queued tracks keep their order and added ones are shuffled in behind
+    /// the rest.
+    pub fn replace(&mut self, mut tracks: Vec<usize>) {
+        let current = self.position.zip(self.current());
+        if let Some((position, track)) = current.filter(|&(_, track)| !tracks.contains(&track)) {
+            tracks.insert(position.min(tracks.len()), track);
+        }
+        self.original = tracks.clone();
+        if !self.shuffle {
+            self.position = current.map(|(position, track)| nearest_position(&tracks, track, position));
+            self.tracks = tracks;
+            return;
+        }
+        // Copies of each track still to be placed
+        let mut left: HashMap<usize, usize> = HashMap::new();
+        for &track in &tracks {
+            *left.entry(track).or_default() += 1;
+        }
+        if let Some((_, track)) = current {
+            left.entry(track).and_modify(|n| *n -= 1);
+        }
+        let mut kept = Vec::with_capacity(tracks.len());
+        let mut position = None;
+        for (i, &track) in self.tracks.iter().enumerate() {
+            if current.is_some_and(|(p, _)| p == i) {
+                position = Some(kept.len());
+                kept.push(track);
+            } else if let Some(n) = left.get_mut(&track).filter(|n| **n > 0) {
+                *n -= 1;
+                kept.push(track);
+            }
+        }
+        let start = kept.len();
+        for track in tracks {
+            if let Some(n) = left.get_mut(&track).filter(|n| **n > 0) {
+                *n -= 1;
+                kept.push(track);
+            }
+        }
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        shuffle_slice(&mut kept[start..], seed);
+        self.tracks = kept;
+        self.position = position;
+    }
+
+    /// Adds tracks to the end of the queue.
+    ///
+    /// While shuffled, the tracks are also added to the end of the loaded
+    /// order, so they stay queued when shuffle is turned off.
+    pub fn append(&mut self, tracks: &[usize]) {
+        self.tracks.extend_from_slice(tracks);
+        self.original.extend_from_slice(tracks);
+    }
+
+    /// Removes all tracks from the queue, keeping the shuffle and repeat settings.
+    pub fn clear(&mut self) {
+        *self = Self { shuffle: self.shuffle, repeat: self.repeat, ..Self::default() };
+    }
+
+    /// Returns `true` if the tracks are played in random order.
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Turns shuffle on or off.
+    ///
+    /// Turning it on shuffles the queue behind the current track; turning it
+    /// off restores the loaded order with the current track kept current.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle == self.shuffle {
+            return;
+        }
+        self.shuffle = shuffle;
+        if shuffle {
+            self.reshuffle();
+        } else {
+            let current = self.current();
+            self.tracks = self.original.clone();
+            self.position = current.and_then(|c| self.tracks.iter().position(|&t| t == c));
+        }
+    }
+
+    /// Returns the repeat mode.
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    /// Sets the repeat mode.
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    /// Returns the queued track indices.
+    pub fn tracks(&self) -> &[usize] {
+        &self.tracks
+    }
+
+    /// Returns the position of the current track within the queue.
+    pub fn position(&self) -> Option<usize> {
+        self.position
+    }
+
+    /// Returns the name of the playlist the queue was loaded from.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Updates the source name, e.g. after the playlist was renamed.
+    pub fn set_source(&mut self, source: Option<String>) {
+        self.source = source;
+    }
+
+    /// Returns `true` if the queue has no tracks.
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Returns the library index of the current track.
+    pub fn current(&self) -> Option<usize> {
+        self.position.and_then(|p| self.tracks.get(p).copied())
+    }
+
+    /// Advances to the next track and returns its library index.
+    ///
+    /// Returns `None` (leaving the position unchanged) at the end of the queue,
+    /// unless repeat is on, in which case the queue starts over.
+    pub fn next_track(&mut self) -> Option<usize> {
+        let next = self.position.map_or(0, |p| p + 1);
+        if next < self.tracks.len() {
+            self.position = Some(next);
+            self.current()
+        } else if self.repeat != RepeatMode::Off && !self.tracks.is_empty() {
+            self.position = Some(0);
+            self.current()
+        } else {
+            None
+        }
+    }
+
+    /// Moves to the previous track and returns its library index.
+    ///
+    /// Returns `None` (leaving the position unchanged) at the start of the
+    /// queue, unless repeat is on, in which case it wraps to the end.
+    pub fn prev_track(&mut self) -> Option<usize> {
+        match self.position {
+            Some(p) if p > 0 => {
+                self.position = Some(p - 1);
+                self.current()
+            }
+            Some(_) if self.repeat != RepeatMode::Off => {
+                self.position = Some(self.tracks.len() - 1);
+                self.current()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the track to play after the current one ended on its own.
+    ///
+    /// Like [`next_track`](Self::next_track), except that [`RepeatMode::One`]
+    /// replays the current track.
+    pub fn advance(&mut self) -> Option<usize> {
+        match (self.repeat, self.current()) {
+            (RepeatMode::One, Some(current)) => Some(current),
+            _ => self.next_track(),
+        }
+    }
+
+    /// Shuffles the tracks, moving the current track to the front.
+    fn reshuffle(&mut self) {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        if let Some(p) = self.position {
+            self.tracks.swap(0, p);
+            self.position = Some(0);
+        }
+        let start = usize::from(self.position.is_some());
+        shuffle_slice(&mut self.tracks[start..], seed);
+    }
+
+    /// Jumps to a queue position and returns the library index there.
+    pub fn jump(&mut self, position: usize) -> Option<usize> {
+        if position < self.tracks.len() {
+            self.position = Some(position);
+        }
+        self.current()
+    }
+}
+
+/// Returns the position of the copy of `track` nearest to `position`, should
+/// the tracks hold it more than once.
+fn nearest_position(tracks: &[usize], track: usize, position: usize) -> usize {
+    let copies = tracks.iter().enumerate().filter(|&(_, &t)| t == track);
+    copies.min_by_key(|&(i, _)| i.abs_diff(position)).map_or(position, |(i, _)| i)
+}
+
+/// Shuffles a slice in place (Fisher-Yates with a xorshift generator).
+fn shuffle_slice(items: &mut [usize], seed: u64) {
+    let mut state = seed | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_next_and_prev_stop_at_bounds() {
+        let mut queue = Queue::new();
+        queue.load(vec![4, 2, 7], Some(0), None);
+
+        assert_eq!(queue.current(), Some(4));
+        assert_eq!(queue.next_track(), Some(2));
+        assert_eq!(queue.next_track(), Some(7));
+        assert_eq!(queue.next_track(), None);
+        assert_eq!(queue.current(), Some(7));
+        assert_eq!(queue.prev_track(), Some(2));
+        assert_eq!(queue.prev_track(), Some(4));
+        assert_eq!(queue.prev_track(), None);
+    }
+
+    #[test]
+    fn test_load_clamps_position_and_keeps_source() {
+        let mut queue = Queue::new();
+        queue.load(vec![1], Some(5), Some("mix".to_string()));
+        assert_eq!(queue.position(), None);
+        assert_eq!(queue.source(), Some("mix"));
+        assert_eq!(queue.next_track(), Some(1));
+
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.jump(0), None);
+
+        queue.append(&[3, 8]);
+        assert_eq!(queue.tracks(), &[3, 8]);
+        assert_eq!(queue.position(), None);
+        assert_eq!(queue.next_track(), Some(3));
+    }
+
+    #[test]
+    fn test_repeat_modes() {
+        let mut queue = Queue::new();
+        queue.load(vec![4, 2], Some(1), None);
+        assert_eq!(queue.advance(), None);
+
+        queue.set_repeat(RepeatMode::All);
+        assert_eq!(queue.advance(), Some(4));
+        assert_eq!(queue.prev_track(), Some(2));
+
+        queue.set_repeat(RepeatMode::One);
+        assert_eq!(queue.advance(), Some(2));
+        assert_eq!(queue.next_track(), Some(4), "skipping still moves on");
+        assert_eq!("one".parse::<RepeatMode>(), Ok(RepeatMode::One));
+        assert_eq!(RepeatMode::One.next(), RepeatMode::Off);
+    }
+
+    #[test]
+    fn test_shuffle_off_restores_order() {
+        let mut queue = Queue::new();
+        queue.set_shuffle(true);
+        queue.load((0..20).collect(), Some(7), None);
+        assert_eq!(queue.current(), Some(7));
+        assert_eq!(queue.position(), Some(0));
+
+        queue.next_track();
+        let current = queue.current();
+        queue.append(&[20]);
+        queue.set_shuffle(false);
+        assert_eq!(queue.tracks(), (0..21).collect::<Vec<_>>().as_slice());
+        assert_eq!(queue.current(), current);
+
+        queue.set_shuffle(true);
+        queue.clear();
+        assert!(queue.shuffle(), "clearing keeps the setting");
+    }
+
+    #[test]
+    fn test_replace_keeps_the_current_track() {
+        let mut queue = Queue::new();
+        queue.load(vec![1, 2, 3, 4], Some(1), Some("mix".to_string()));
+        queue.replace(vec![5, 1, 2, 4]);
+        assert_eq!((queue.tracks(), queue.current()), (&[5, 1, 2, 4][..], Some(2)));
+        assert_eq!(queue.next_track(), Some(4), "the removed track is not played");
+
+        // The playing track stays, and what followed it follows it
+        queue.replace(vec![5, 1, 2]);
+        assert_eq!((queue.tracks(), queue.current()), (&[5, 1, 2, 4][..], Some(4)));
+        queue.jump(1);
+        queue.replace(vec![5, 2, 7]);
+        assert_eq!((queue.tracks(), queue.current()), (&[5, 1, 2, 7][..], Some(1)));
+        assert_eq!(queue.next_track(), Some(2));
+        queue.replace(vec![2, 9, 2]);
+        assert_eq!(queue.position(), Some(2), "the nearest copy stays current");
+        assert_eq!(queue.source(), Some("mix"));
+    }
+
+    #[test]
+    fn test_replace_while_shuffled() {
+        let mut queue = Queue::new();
+        queue.set_shuffle(true);
+        queue.load((0..10).collect(), Some(3), None);
+        queue.next_track();
+        let current = queue.current().unwrap();
+        let order: Vec<usize> = queue.tracks().to_vec();
+
+        let mut changed: Vec<usize> = (0..10).filter(|&t| t != 5 || t == current).collect();
+        changed.extend([10, 11]);
+        queue.replace(changed.clone());
+        assert_eq!(queue.current(), Some(current));
+        let kept: Vec<usize> = order.iter().copied().filter(|&t| t != 5 || t == current).collect();
+        assert_eq!(&queue.tracks()[..kept.len()], kept.as_slice(), "queued tracks keep their order");
+        let mut added = queue.tracks()[kept.len()..].to_vec();
+        added.sort_unstable();
+        assert_eq!(added, [10, 11]);
+
+        queue.set_shuffle(false);
+        assert_eq!(queue.tracks(), changed.as_slice());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_shuffle_is_permutation(len in 0usize..50, position in 0usize..50, seed: u64) {
+            let mut items: Vec<usize> = (0..len).collect();
+            shuffle_slice(&mut items, seed);
+            items.sort_unstable();
+            prop_assert_eq!(items, (0..len).collect::<Vec<_>>());
+
+            let mut queue = Queue::new();
+            queue.set_shuffle(true);
+            queue.load((100..100 + len).collect(), Some(position), None);
+            prop_assert_eq!(queue.current(), (position < len).then_some(100 + position));
+        }
+    }
+}
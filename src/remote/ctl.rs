@@ -0,0 +1,312 @@
+//! Client subcommands controlling a running player (`tools-rs ctl ...`).
+//!
+//! Each invocation sends one [`Request`] over the remote-control socket and
+//! prints what the player answers:
+//!
+//! ```sh
+//! tools-rs ctl toggle
+//! tools-rs ctl vol +5
+//! tools-rs ctl seek 1:30
+//! tools-rs ctl status --format '{artist} - {title}'
+//! tools-rs ctl enqueue ~/Music/album/*.flac
+//! ```
+//!
+//! The status format takes the placeholders `{title}`, `{artist}`,
+//! `{album}`, `{path}`, `{state}`, `{position}`, `{duration}`, `{volume}`,
+//! `{shuffle}` and `{repeat}`; fields the track does not have are left empty.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::{RemoteError, Reply, Request, Status, TrackInfo};
+use crate::command;
+use crate::common::PlaybackStatus;
+
+/// Summary of the subcommands, shown with usage errors.
+pub const USAGE: &str = "\
+usage: tools-rs ctl <command> [arguments]
+
+commands:
+  toggle                  pause or resume playback
+  play [position]         start playback, or play an entry of the queue (1-based)
+  pause                   pause playback
+  next, prev              skip to the next or previous track
+  vol <N|+N|-N>           set or change the volume in percent
+  seek <pos|+N|-N>        seek to a position (seconds, m:ss) or by seconds
+  shuffle [on|off]        set or toggle shuffle
+  repeat [off|all|one]    set or cycle repeat
+  enqueue <path>...       add files or directories of the library to the queue
+  clear                   empty the queue
+  queue                   list the queued tracks
+  status [--format fmt]   show what is playing";
+
+/// Status format used without `--format`.
+pub const DEFAULT_FORMAT: &str = "{artist} - {title}\n[{state}] {position}/{duration}\nvolume: {volume}%  repeat: {repeat}  shuffle: {shuffle}";
+
+/// Errors that can occur while running a subcommand.
+#[derive(Debug, Error)]
+pub enum CtlError {
+    /// The arguments do not form a subcommand
+    #[error("{0}\n\n{USAGE}")]
+    Usage(String),
+    /// A path given to `enqueue` does not exist
+    #[error("{0}: no such file or directory")]
+    NoSuchPath(PathBuf),
+    /// No player listens on the socket
+    #[error("tools-rs is not running (nothing listens on {0})")]
+    NotRunning(PathBuf),
+    /// The connection to the player failed
+    #[error("{0}")]
+    Remote(#[from] RemoteError),
+    /// The player refused the request
+    #[error("{0}")]
+    Refused(String),
+}
+
+/// A parsed subcommand: the request to send and how to show the reply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    /// Request for the player
+    pub request: Request,
+    /// Format of the status (for `status`)
+    pub format: String,
+}
+
+/// Parses the arguments after `ctl`.
+///
+/// Relative paths given to `enqueue` are made absolute against the working
+/// directory, since the player may have been started elsewhere.
+///
+/// # Errors
+///
+/// Returns [`CtlError::Usage`] for unknown subcommands or invalid arguments,
+/// and [`CtlError::NoSuchPath`] for paths to enqueue that do not exist.
+pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Invocation, CtlError> {
+    let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+    let usage = |message: &str| CtlError::Usage(message.to_string());
+    let mut format = DEFAULT_FORMAT.to_string();
+    let request = match args.as_slice() {
+        [] => return Err(usage("missing command")),
+        ["toggle"] => Request::Toggle,
+        ["play"] => Request::Play(None),
+        ["play", position] => {
+            let position: usize = position.parse().ok().filter(|&p| p > 0).ok_or_else(|| usage("play: expected a position from 1"))?;
+            Request::Play(Some(position - 1))
+        }
+        ["pause"] => Request::Pause,
+        ["next"] => Request::Next,
+        ["prev"] => Request::Prev,
+        ["vol", amount] => {
+            Request::Volume(command::parse_amount(amount, |s| s.parse().ok()).ok_or_else(|| usage("vol: expected N, +N or -N"))?)
+        }
+        ["seek", amount] => Request::Seek(
+            command::parse_amount(amount, command::parse_position).ok_or_else(|| usage("seek: expected m:ss, +N or -N"))?,
+        ),
+        ["shuffle"] => Request::Shuffle(None),
+        ["shuffle", "on"] => Request::Shuffle(Some(true)),
+        ["shuffle", "off"] => Request::Shuffle(Some(false)),
+        ["repeat"] => Request::Repeat(None),
+        ["repeat", mode] => Request::Repeat(Some(mode.parse().map_err(|_| usage("repeat: expected off, all or one"))?)),
+        ["enqueue", paths @ ..] if !paths.is_empty() => {
+            let paths = paths
+                .iter()
+                .map(|path| match std::path::absolute(path) {
+                    Ok(absolute) if absolute.exists() => Ok(absolute),
+                    _ => Err(CtlError::NoSuchPath(PathBuf::from(path))),
+                })
+                .collect::<Result<_, _>>()?;
+            Request::Enqueue(paths)
+        }
+        ["clear"] => Request::ClearQueue,
+        ["queue"] => Request::Queue,
+        ["status"] => Request::Status,
+        ["status", "--format" | "-f", template] => {
+            format = template.to_string();
+            Request::Status
+        }
+        [name, ..] => return Err(usage(&format!("invalid command or arguments: {}", name))),
+    };
+    Ok(Invocation { request, format })
+}
+
+/// Runs a subcommand against the player listening on a socket.
+///
+/// # Arguments
+///
+/// * `args` - Arguments after `ctl`
+/// * `socket` - Socket of the running player
+///
+/// # Returns
+///
+/// Returns the text to print, empty for requests that only act.
+///
+/// # Errors
+///
+/// Returns [`CtlError::NotRunning`] if no player listens on the socket,
+/// [`CtlError::Refused`] if the player refused the request, or another
+/// error for invalid arguments or a failed connection.
+#[cfg(unix)]
+pub fn run<S: AsRef<str>>(args: &[S], socket: &std::path::Path) -> Result<String, CtlError> {
+    let invocation = parse(args)?;
+    let mut client = super::ipc::Client::connect(socket).map_err(|_| CtlError::NotRunning(socket.to_path_buf()))?;
+    match client.request(&invocation.request)? {
+        Reply::Done | Reply::Event(_) => Ok(String::new()),
+        Reply::Status(status) => Ok(format_status(&status, &invocation.format)),
+        Reply::Tracks(tracks) => Ok(format_queue(&tracks)),
+        Reply::Error(message) => Err(CtlError::Refused(message)),
+    }
+}
+
+/// Fills the placeholders of a status format.
+pub fn format_status(status: &Status, format: &str) -> String {
+    let track = status.track.clone().unwrap_or_default();
+    let state = match status.state {
+        PlaybackStatus::Playing => "playing",
+        PlaybackStatus::Paused => "paused",
+        PlaybackStatus::Stopped => "stopped",
+    };
+    let fields = [
+        ("{title}", title(&track)),
+        ("{artist}", track.artist.clone().unwrap_or_default()),
+        ("{album}", track.album.clone().unwrap_or_default()),
+        ("{path}", track.path.display().to_string()),
+        ("{state}", state.to_string()),
+        ("{position}", format_time(status.position)),
+        ("{duration}", status.duration.or(track.duration).map(format_time).unwrap_or_default()),
+        ("{volume}", status.volume.to_string()),
+        ("{shuffle}", if status.shuffle { "on" } else { "off" }.to_string()),
+        ("{repeat}", status.repeat.to_string()),
+    ];
+    fields.iter().fold(format.to_string(), |text, (placeholder, value)| text.replace(placeholder, value))
+}
+
+/// Lists queued tracks, one numbered line each.
+fn format_queue(tracks: &[TrackInfo]) -> String {
+    tracks
+        .iter()
+        .enumerate()
+        .map(|(i, track)| match &track.artist {
+            Some(artist) => format!("{}. {} - {}\n", i + 1, artist, title(track)),
+            None => format!("{}. {}\n", i + 1, title(track)),
+        })
+        .collect()
+}
+
+/// Returns the title of a track, or its file name without one.
+fn title(track: &TrackInfo) -> String {
+    track
+        .title
+        .clone()
+        .or_else(|| track.path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_default()
+}
+
+/// Formats a time as `m:ss`.
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Amount;
+    use crate::harness::TempDir;
+    use crate::queue::RepeatMode;
+
+    fn parse_request(args: &[&str]) -> Request {
+        parse(args).unwrap().request
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        assert_eq!(parse_request(&["toggle"]), Request::Toggle);
+        assert_eq!(parse_request(&["play", "3"]), Request::Play(Some(2)));
+        assert_eq!(parse_request(&["vol", "+5"]), Request::Volume(Amount::Up(5)));
+        assert_eq!(parse_request(&["vol", "-5"]), Request::Volume(Amount::Down(5)));
+        assert_eq!(parse_request(&["seek", "1:30"]), Request::Seek(Amount::To(Duration::from_secs(90))));
+        assert_eq!(parse_request(&["repeat", "all"]), Request::Repeat(Some(RepeatMode::All)));
+        assert_eq!(parse_request(&["shuffle", "off"]), Request::Shuffle(Some(false)));
+
+        let invocation = parse(&["status", "--format", "{artist} - {title}"]).unwrap();
+        assert_eq!(invocation, Invocation { request: Request::Status, format: "{artist} - {title}".to_string() });
+        assert_eq!(parse(&["status"]).unwrap().format, DEFAULT_FORMAT);
+
+        let here = std::env::current_dir().unwrap();
+        assert_eq!(parse_request(&["enqueue", "src"]), Request::Enqueue(vec![here.join("src")]));
+        assert!(matches!(parse(&["enqueue", "no/such/file.mp3"]), Err(CtlError::NoSuchPath(_))));
+
+        for args in [&[][..], &["dance"], &["play", "0"], &["vol", "loud"], &["seek"], &["enqueue"], &["repeat", "twice"]] {
+            assert!(matches!(parse(args), Err(CtlError::Usage(_))), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_format_status() {
+        let status = Status {
+            state: PlaybackStatus::Paused,
+            track: Some(TrackInfo {
+                path: PathBuf::from("/music/So What.flac"),
+                title: Some("So What".to_string()),
+                artist: Some("Miles Davis".to_string()),
+                ..Default::default()
+            }),
+            position: Duration::from_secs(95),
+            duration: Some(Duration::from_secs(562)),
+            volume: 80,
+            shuffle: false,
+            repeat: RepeatMode::All,
+            queue_position: Some(0),
+            queue_length: 5,
+        };
+        assert_eq!(format_status(&status, "{artist} - {title}"), "Miles Davis - So What");
+        assert_eq!(
+            format_status(&status, DEFAULT_FORMAT),
+            "Miles Davis - So What\n[paused] 1:35/9:22\nvolume: 80%  repeat: all  shuffle: off"
+        );
+
+        let stopped = Status { state: PlaybackStatus::Stopped, track: None, duration: None, ..status };
+        assert_eq!(format_status(&stopped, "{state}: {album}{title} {duration}"), "stopped:  ");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_without_a_player() {
+        let dir = TempDir::new("ctl");
+        let socket = dir.join("tools-rs.sock");
+        assert!(matches!(run(&["next"], &socket), Err(CtlError::NotRunning(_))));
+        assert!(matches!(run(&["dance"], &socket), Err(CtlError::Usage(_))), "arguments are checked first");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_prints_replies() {
+        use std::sync::mpsc;
+        use crate::app::Msg;
+
+        let dir = TempDir::new("ctl");
+        let socket = dir.join("tools-rs.sock");
+        let (tx, rx) = mpsc::channel();
+        let _server = super::super::ipc::Server::start(&socket, tx).unwrap();
+        std::thread::spawn(move || {
+            for msg in rx {
+                let Msg::Remote(request, reply) = msg else { continue };
+                let answer = match request {
+                    Request::Queue => Reply::Tracks(vec![
+                        TrackInfo { path: PathBuf::from("/a/one.mp3"), artist: Some("A".to_string()), ..Default::default() },
+                        TrackInfo { path: PathBuf::from("/a/two.mp3"), title: Some("Two".to_string()), ..Default::default() },
+                    ]),
+                    Request::Next => Reply::Done,
+                    _ => Reply::Error("没有正在播放的曲目".to_string()),
+                };
+                reply.send(answer).ok();
+            }
+        });
+
+        assert_eq!(run(&["queue"], &socket).unwrap(), "1. A - one.mp3\n2. Two\n");
+        assert_eq!(run(&["next"], &socket).unwrap(), "");
+        assert!(matches!(run(&["seek", "+10"], &socket), Err(CtlError::Refused(m)) if m == "没有正在播放的曲目"));
+    }
+}
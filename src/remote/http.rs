@@ -0,0 +1,689 @@
+//! Control over HTTP, with a web remote.
+//!
+//! [`HttpServer`] answers plain HTTP/1.1, one request per connection:
+//!
+//! ```text
+//! GET  /                  the web remote (a page served from the binary)
+//! GET  /api/status        {"ok":true,"status":{...}}
+//! GET  /api/queue         {"ok":true,"tracks":[...]}
+//! GET  /api/search?q=...  {"ok":true,"tracks":[...]} (a query, see crate::query)
+//! GET  /api/events        server-sent events, one JSON event per message
+//! POST /api/<cmd>         toggle, play, pause, next, prev, volume, seek,
+//!                         shuffle, repeat, enqueue, clear
+//! ```
+//!
+//! Commands take their arguments as a JSON body with the fields of the
+//! socket protocol (see [`super::json`]), e.g. `{"value":"+5"}` for
+//! `volume` or `{"position":3}` for `play`, and must be sent as
+//! `application/json`, which keeps other web pages from posting them.
+//! Replies and events are the same JSON objects as on the socket; failed
+//! requests are answered with status 400.
+//!
+//! ```sh
+//! curl -X POST -H 'Content-Type: application/json' -d '{"value":"+5"}' localhost:8080/api/volume
+//! ```
+//!
+//! With a token set (`http_token` in `[remote]`), every `/api/` request
+//! must carry it, as `Authorization: Bearer <token>` or as a `token` query
+//! parameter; others are answered with status 401. The page itself is
+//! served to anyone and keeps the token it was first opened with
+//! (`/?token=<token>`) in the browser's local storage. Without a token,
+//! anyone who can reach the address controls the player.
+//!
+//! Event streams carry a `: keep-alive` comment when idle, so that
+//! connections whose client has gone away are noticed and closed.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+use tracing::error;
+
+use super::{json, RemoteError, Reply, Request};
+use crate::app::Msg;
+
+/// The web remote
+const PAGE: &str = include_str!("remote.html");
+
+/// Largest request body accepted
+const MAX_BODY: usize = 64 * 1024;
+
+/// Largest request line and headers accepted, together
+const MAX_HEAD: u64 = 8 * 1024;
+
+/// Most headers accepted in a request
+const MAX_HEADERS: usize = 64;
+
+/// Most connections served at once (event streams included); more are
+/// closed right away
+const MAX_CONNECTIONS: usize = 32;
+
+/// How long a client may take to send its request, or to take a response
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an event stream may stay silent before a keep-alive comment
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Commands answered on `GET`; all others are `POST`ed.
+const QUERIES: [&str; 3] = ["status", "queue", "search"];
+
+/// A listening HTTP server.
+#[derive(Debug)]
+pub struct HttpServer {
+    addr: SocketAddr,
+}
+
+/// What every connection of a server is served with.
+#[derive(Debug)]
+struct Settings {
+    /// Token the API requires, if any
+    token: Option<String>,
+    /// Silence allowed on an event stream
+    keep_alive: Duration,
+}
+
+impl HttpServer {
+    /// Listens on an address and starts passing the requests that arrive to
+    /// the application.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to listen on (port 0 picks a free one)
+    /// * `token` - Token the API requires (none when `None`)
+    /// * `tx` - Channel the requests are sent on as [`Msg::Remote`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the address cannot be bound.
+    pub fn start(addr: SocketAddr, token: Option<String>, tx: Sender<Msg>) -> Result<Self, RemoteError> {
+        Self::listen(addr, Settings { token, keep_alive: KEEP_ALIVE }, tx)
+    }
+
+    /// Like [`Self::start`], with every setting given.
+    fn listen(addr: SocketAddr, settings: Settings, tx: Sender<Msg>) -> Result<Self, RemoteError> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let open = Arc::new(AtomicUsize::new(0));
+        let settings = Arc::new(settings);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let Some(slot) = Slot::take(&open) else { continue };
+                        let tx = tx.clone();
+                        let settings = Arc::clone(&settings);
+                        // A client hanging up is not worth reporting
+                        thread::spawn(move || {
+                            serve(stream, &settings, tx).ok();
+                            drop(slot);
+                        });
+                    }
+                    Err(e) => error!("无法接受 HTTP 连接: {}", e),
+                }
+            }
+        });
+        Ok(Self { addr })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// One of the [`MAX_CONNECTIONS`] connections, given back when dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// Takes a slot, unless all are in use.
+    fn take(open: &Arc<AtomicUsize>) -> Option<Self> {
+        open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_CONNECTIONS).then_some(n + 1)).ok()?;
+        Some(Self(Arc::clone(open)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The parts of a request that matter here.
+#[derive(Debug, Default, PartialEq)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// Decoded query parameters
+    query: Vec<(String, String)>,
+    /// Whether the body is declared as JSON
+    json: bool,
+    /// Token of an `Authorization: Bearer` header
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+/// What to answer a request with.
+#[derive(Debug, PartialEq)]
+enum Route {
+    /// The web remote
+    Page,
+    /// A stream of events
+    Events,
+    /// A request for the application
+    Call(Request),
+    /// An error status and message
+    Fail(u16, String),
+}
+
+/// Answers one request, then closes the connection (or keeps streaming
+/// events).
+fn serve(stream: TcpStream, settings: &Settings, tx: Sender<Msg>) -> Result<(), RemoteError> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let request = match read_request(&mut BufReader::new(stream)) {
+        Ok(request) => request,
+        Err(RemoteError::Protocol(message)) => {
+            let status = match message.as_str() {
+                "body too large" => 413,
+                "head too large" => 431,
+                _ => 400,
+            };
+            return Ok(respond_error(&mut writer, status, &message)?);
+        }
+        Err(e) => return Err(e),
+    };
+    match route(&request, settings.token.as_deref()) {
+        Route::Page => respond(&mut writer, 200, "text/html; charset=utf-8", PAGE.as_bytes())?,
+        Route::Fail(status, message) => respond_error(&mut writer, status, &message)?,
+        Route::Call(request) => {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let reply = tx.send(Msg::Remote(request, reply_tx)).ok().and_then(|_| reply_rx.recv().ok());
+            match reply {
+                Some(reply) => {
+                    let status = if matches!(reply, Reply::Error(_)) { 400 } else { 200 };
+                    respond(&mut writer, status, "application/json", json::encode_reply(&reply).as_bytes())?;
+                }
+                None => respond_error(&mut writer, 503, "the player has quit")?,
+            }
+        }
+        Route::Events => {
+            let (events_tx, events_rx) = mpsc::channel();
+            if tx.send(Msg::Remote(Request::Subscribe, events_tx)).is_err() {
+                return Ok(respond_error(&mut writer, 503, "the player has quit")?);
+            }
+            write!(
+                writer,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+            )?;
+            writer.flush()?;
+            // Events follow until the client or the application hangs up;
+            // a client that is gone fails the next write
+            loop {
+                match events_rx.recv_timeout(settings.keep_alive) {
+                    Ok(reply @ Reply::Event(_)) => write!(writer, "data: {}\n\n", json::encode_reply(&reply))?,
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => write!(writer, ": keep-alive\n\n")?,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                writer.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the request line, the headers and the body of a request.
+///
+/// # Errors
+///
+/// Returns [`RemoteError::Protocol`] if the request is malformed or its
+/// head or body too large, or an I/O error if reading fails.
+fn read_request(reader: &mut impl BufRead) -> Result<HttpRequest, RemoteError> {
+    let malformed = || RemoteError::Protocol("malformed request".to_string());
+    let mut head = reader.by_ref().take(MAX_HEAD);
+    let mut line = String::new();
+    // Reads the next line of the head, which must end before `MAX_HEAD`
+    let mut next_line = |line: &mut String| {
+        line.clear();
+        head.read_line(line)?;
+        if line.ends_with('\n') {
+            Ok(())
+        } else if head.limit() == 0 {
+            Err(RemoteError::Protocol("head too large".to_string()))
+        } else {
+            Err(malformed())
+        }
+    };
+    next_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(malformed());
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = HttpRequest {
+        method: method.to_string(),
+        path: decode_component(path).ok_or_else(malformed)?,
+        query: parse_query(query).ok_or_else(malformed)?,
+        ..Default::default()
+    };
+
+    let mut length = 0;
+    for headers in 0.. {
+        next_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers == MAX_HEADERS {
+            return Err(RemoteError::Protocol("head too large".to_string()));
+        }
+        let (name, value) = line.split_once(':').ok_or_else(malformed)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.parse().map_err(|_| malformed())?;
+        } else if name.eq_ignore_ascii_case("content-type") {
+            request.json = value.split(';').next().is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"));
+        } else if name.eq_ignore_ascii_case("authorization") {
+            request.token = value
+                .split_once(' ')
+                .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+                .map(|(_, token)| token.trim().to_string());
+        }
+    }
+    if length > MAX_BODY {
+        return Err(RemoteError::Protocol("body too large".to_string()));
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+    Ok(request)
+}
+
+/// Decides how to answer a request.
+///
+/// # Arguments
+///
+/// * `request` - The request
+/// * `token` - Token the API requires, if any
+fn route(request: &HttpRequest, token: Option<&str>) -> Route {
+    if request.path == "/" || request.path == "/index.html" {
+        return match request.method.as_str() {
+            "GET" => Route::Page,
+            _ => Route::Fail(405, "method not allowed".to_string()),
+        };
+    }
+    let Some(cmd) = request.path.strip_prefix("/api/") else {
+        return Route::Fail(404, "not found".to_string());
+    };
+    if let Some(token) = token {
+        let given = request.token.as_deref().or_else(|| {
+            request.query.iter().find(|(name, _)| name == "token").map(|(_, value)| value.as_str())
+        });
+        if !given.is_some_and(|given| same_token(given, token)) {
+            return Route::Fail(401, "a valid token is required".to_string());
+        }
+    }
+    if cmd == "events" {
+        return match request.method.as_str() {
+            "GET" => Route::Events,
+            _ => Route::Fail(405, "method not allowed".to_string()),
+        };
+    }
+    // Subscribing is what `events` is for
+    if cmd.is_empty() || cmd.contains('/') || cmd == "subscribe" {
+        return Route::Fail(404, "not found".to_string());
+    }
+    let query = QUERIES.contains(&cmd);
+    let mut object = match (request.method.as_str(), query) {
+        ("GET", true) => {
+            let mut object = Map::new();
+            if let Some((_, q)) = request.query.iter().find(|(name, _)| name == "q") {
+                object.insert("query".to_string(), Value::String(q.clone()));
+            }
+            object
+        }
+        // Even without a body, so that forms on other sites cannot post
+        ("POST", false) if !request.json => {
+            return Route::Fail(415, "the body must be application/json".to_string())
+        }
+        ("POST", false) if request.body.iter().all(u8::is_ascii_whitespace) => Map::new(),
+        ("POST", false) => match serde_json::from_slice(&request.body) {
+            Ok(Value::Object(object)) => object,
+            _ => return Route::Fail(400, "the body must be a JSON object".to_string()),
+        },
+        _ => return Route::Fail(405, "method not allowed".to_string()),
+    };
+    object.insert("cmd".to_string(), Value::String(cmd.to_string()));
+    match json::decode_request(&Value::Object(object).to_string()) {
+        Ok(request) => Route::Call(request),
+        Err(RemoteError::Protocol(message)) if message.starts_with("unknown command") => {
+            Route::Fail(404, "not found".to_string())
+        }
+        Err(e) => Route::Fail(400, e.to_string()),
+    }
+}
+
+/// Compares tokens in time that depends only on their lengths, so that
+/// timing does not give a token away.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Writes a complete response.
+fn respond(writer: &mut impl Write, status: u16, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        body.len()
+    )?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+/// Writes an error response with a JSON body like the replies'.
+fn respond_error(writer: &mut impl Write, status: u16, message: &str) -> io::Result<()> {
+    let body = json::encode_reply(&Reply::Error(message.to_string()));
+    respond(writer, status, "application/json", body.as_bytes())
+}
+
+/// Returns the reason phrase of a status code.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Splits a query string into decoded name/value pairs.
+fn parse_query(query: &str) -> Option<Vec<(String, String)>> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((decode_component(name)?, decode_component(value)?))
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes and `+` (a space) in a part of a URL.
+///
+/// # Returns
+///
+/// Returns `None` if an escape is malformed or the result is not UTF-8.
+fn decode_component(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'%' => {
+                let hex = [input.next()?, input.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::app::{update, App, Effect};
+    use crate::command::Amount;
+    use crate::common::Track;
+    use crate::harness::TempDir;
+
+    fn request(method: &str, path: &str, body: &str) -> HttpRequest {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: parse_query(query).unwrap(),
+            json: !body.is_empty(),
+            body: body.as_bytes().to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw = "POST /api/volume?x=a%20b+c HTTP/1.1\r\nHost: x\r\ncontent-type: application/json; charset=utf-8\r\nContent-Length: 16\r\n\r\n{\"value\":\"+5\"}\r\nrest";
+        let request = read_request(&mut raw.as_bytes()).unwrap();
+        assert_eq!(request, HttpRequest {
+            method: "POST".to_string(),
+            path: "/api/volume".to_string(),
+            query: vec![("x".to_string(), "a b c".to_string())],
+            json: true,
+            token: None,
+            body: b"{\"value\":\"+5\"}\r\n".to_vec(),
+        });
+        let raw = "GET /api/status HTTP/1.1\r\nAuthorization: Bearer  s3cret \r\n\r\n";
+        assert_eq!(read_request(&mut raw.as_bytes()).unwrap().token.as_deref(), Some("s3cret"));
+        let raw = "GET /api/status HTTP/1.1\r\nAuthorization: Basic s3cret\r\n\r\n";
+        assert_eq!(read_request(&mut raw.as_bytes()).unwrap().token, None);
+
+        assert!(read_request(&mut "GET /\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request(&mut "GET /%zz HTTP/1.1\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request(&mut "GET / HTTP/1.1\r\nHost: x\r\n".as_bytes()).is_err(), "headers cut short");
+        let huge = format!("POST /api/next HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert!(matches!(read_request(&mut huge.as_bytes()), Err(RemoteError::Protocol(m)) if m == "body too large"));
+        let too_large = |raw: &str| matches!(read_request(&mut raw.as_bytes()), Err(RemoteError::Protocol(m)) if m == "head too large");
+        assert!(too_large(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD as usize))));
+        assert!(too_large(&format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(MAX_HEADERS + 1))));
+        assert!(!too_large(&format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(MAX_HEADERS))));
+    }
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(route(&request("GET", "/", ""), None), Route::Page);
+        assert_eq!(route(&request("GET", "/api/events", ""), None), Route::Events);
+        assert_eq!(route(&request("GET", "/api/status", ""), None), Route::Call(Request::Status));
+        assert_eq!(
+            route(&request("GET", "/api/search?q=artist%3Adavis+year%3A%3C1960", ""), None),
+            Route::Call(Request::Search("artist:davis year:<1960".to_string()))
+        );
+        assert_eq!(route(&request("POST", "/api/volume", r#"{"value":"+5"}"#), None), Route::Call(Request::Volume(Amount::Up(5))));
+        assert_eq!(route(&request("POST", "/api/play", r#"{"position":2}"#), None), Route::Call(Request::Play(Some(2))));
+
+        let next = HttpRequest { json: true, ..request("POST", "/api/next", "") };
+        assert_eq!(route(&next, None), Route::Call(Request::Next));
+        let form = HttpRequest { json: false, ..request("POST", "/api/next", "a=b") };
+        assert!(matches!(route(&form, None), Route::Fail(415, _)));
+        assert!(matches!(route(&request("POST", "/api/next", ""), None), Route::Fail(415, _)), "cross-origin forms carry no JSON");
+
+        assert!(matches!(route(&request("POST", "/api/volume", r#"{"value":"loud"}"#), None), Route::Fail(400, _)));
+        assert!(matches!(route(&request("POST", "/api/volume", "[1]"), None), Route::Fail(400, _)));
+        assert!(matches!(route(&request("GET", "/api/next", ""), None), Route::Fail(405, _)));
+        assert!(matches!(route(&request("POST", "/api/status", "{}"), None), Route::Fail(405, _)));
+        assert!(matches!(route(&request("POST", "/api/fly", "{}"), None), Route::Fail(404, _)));
+        assert!(matches!(route(&request("POST", "/api/subscribe", "{}"), None), Route::Fail(404, _)));
+        assert!(matches!(route(&request("GET", "/favicon.ico", ""), None), Route::Fail(404, _)));
+    }
+
+    #[test]
+    fn test_token() {
+        let token = Some("s3cret");
+        assert_eq!(route(&request("GET", "/", ""), token), Route::Page, "the page has no secrets");
+        assert!(matches!(route(&request("GET", "/api/status", ""), token), Route::Fail(401, _)));
+        assert!(matches!(route(&request("GET", "/api/events?token=s3cre", ""), token), Route::Fail(401, _)));
+        assert!(matches!(route(&request("GET", "/api/nowhere", ""), token), Route::Fail(401, _)), "nothing is revealed");
+        assert_eq!(route(&request("GET", "/api/events?token=s3cret", ""), token), Route::Events);
+        assert_eq!(
+            route(&request("GET", "/api/search?token=s3cret&q=can", ""), token),
+            Route::Call(Request::Search("can".to_string()))
+        );
+        let bearer = |value: &str| HttpRequest { token: Some(value.to_string()), ..request("POST", "/api/next", "{}") };
+        assert_eq!(route(&bearer("s3cret"), token), Route::Call(Request::Next));
+        assert!(matches!(route(&bearer("s3cret!"), token), Route::Fail(401, _)));
+        assert_eq!(route(&bearer("anything"), None), Route::Call(Request::Next));
+    }
+
+    /// Sends a request and reads the whole response.
+    fn fetch(server: &HttpServer, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    /// Whether the page is served, which fails while all slots are taken.
+    fn page_served(server: &HttpServer) -> bool {
+        let attempt = || -> io::Result<bool> {
+            let mut stream = TcpStream::connect(server.local_addr())?;
+            write!(stream, "GET / HTTP/1.1\r\n\r\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response.starts_with("HTTP/1.1 200"))
+        };
+        attempt().unwrap_or(false)
+    }
+
+    #[test]
+    fn test_api_and_events() {
+        // The application thread outlives the test, so the test owns its data directory
+        let dir = TempDir::new("http");
+        let data_dir = dir.path().to_path_buf();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let tracks = (0..3)
+                .map(|i| Track {
+                    id: i,
+                    path: PathBuf::from(format!("/music/track{}.mp3", i)),
+                    artist: Some(if i == 0 { "Miles Davis" } else { "Can" }.to_string()),
+                    ..Default::default()
+                })
+                .collect();
+            let mut app = App::new(tracks).with_data_dir(&data_dir);
+            for msg in rx {
+                // Answers and events go out as in the event loop
+                for effect in update(&mut app, msg) {
+                    match effect {
+                        Effect::Reply(reply, answer) => {
+                            reply.send(answer).ok();
+                        }
+                        Effect::Publish(event) => app.send_event(&event),
+                        _ => {}
+                    }
+                }
+            }
+        });
+        let server = HttpServer::start(([127, 0, 0, 1], 0).into(), None, tx).unwrap();
+
+        let (status, page) = fetch(&server, "GET", "/", "");
+        assert_eq!(status, 200);
+        assert!(page.contains("/api/events"));
+
+        let events = TcpStream::connect(server.local_addr()).unwrap();
+        write!(&events, "GET /api/events HTTP/1.1\r\n\r\n").unwrap();
+        let mut events = BufReader::new(events);
+        let mut line = String::new();
+        events.read_line(&mut line).unwrap();
+        assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+
+        assert_eq!(fetch(&server, "POST", "/api/volume", r#"{"value":40}"#), (200, r#"{"ok":true}"#.to_string()));
+        let (status, body) = fetch(&server, "GET", "/api/status", "");
+        assert_eq!(status, 200);
+        assert!(body.contains(r#""volume":40"#), "{}", body);
+
+        let (_, found) = fetch(&server, "GET", "/api/search?q=artist%3Amiles", "");
+        assert!(found.contains("/music/track0.mp3") && !found.contains("track1"), "{}", found);
+        assert_eq!(fetch(&server, "POST", "/api/enqueue", r#"{"paths":["/music/track2.mp3"]}"#).0, 200);
+        let (_, queue) = fetch(&server, "GET", "/api/queue", "");
+        assert!(queue.contains("/music/track2.mp3"), "{}", queue);
+        assert_eq!(
+            fetch(&server, "POST", "/api/play", r#"{"position":5}"#),
+            (400, r#"{"error":"队列中没有第 5 首","ok":false}"#.to_string())
+        );
+        assert_eq!(fetch(&server, "GET", "/nowhere", "").0, 404);
+
+        // The volume change reached the event stream
+        loop {
+            line.clear();
+            assert!(events.read_line(&mut line).unwrap() > 0);
+            if line.starts_with("data: ") {
+                assert_eq!(line, "data: {\"event\":\"volume\",\"volume\":40}\n");
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        let (tx, _rx) = mpsc::channel();
+        let server = HttpServer::start(([127, 0, 0, 1], 0).into(), None, tx).unwrap();
+        let idle: Vec<_> = (0..MAX_CONNECTIONS).map(|_| TcpStream::connect(server.local_addr()).unwrap()).collect();
+        let mut refused = TcpStream::connect(server.local_addr()).unwrap();
+        let mut response = String::new();
+        // Closed without a response (or reset, as the request was never read)
+        if refused.read_to_string(&mut response).is_ok() {
+            assert_eq!(response, "");
+        }
+
+        // Connections that end give their slot back
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !page_served(&server) {
+            assert!(std::time::Instant::now() < deadline, "slots were not given back");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_keep_alive_releases_dead_streams() {
+        // Nobody answers, so the streams only ever carry keep-alives
+        let (tx, _rx) = mpsc::channel();
+        let settings = Settings { token: None, keep_alive: Duration::from_millis(20) };
+        let server = HttpServer::listen(([127, 0, 0, 1], 0).into(), settings, tx).unwrap();
+        let mut streams: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| {
+                let stream = TcpStream::connect(server.local_addr()).unwrap();
+                write!(&stream, "GET /api/events HTTP/1.1\r\n\r\n").unwrap();
+                BufReader::new(stream)
+            })
+            .collect();
+        let mut line = String::new();
+        while line != ": keep-alive\n" {
+            line.clear();
+            assert!(streams[0].read_line(&mut line).unwrap() > 0);
+        }
+
+        drop(streams);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !page_served(&server) {
+            assert!(std::time::Instant::now() < deadline, "dead streams kept their slots");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
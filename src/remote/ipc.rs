@@ -0,0 +1,334 @@
+//! Remote control over a Unix socket.
+//!
+//! [`Server`] listens on a socket (normally [`crate::paths::socket_file`])
+//! and serves every connection on a thread of its own: each line read is
+//! decoded as a request (see [`super::json`]), passed to the application as a
+//! [`Msg::Remote`], and the reply is written back as a line. After a
+//! `subscribe` request the connection only receives events, one per line,
+//! until it is closed.
+//!
+//! ```sh
+//! echo '{"cmd":"toggle"}' | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/tools-rs.sock
+//! ```
+//!
+//! [`Client`] is the other end, for programs written against this crate;
+//! [`attach`] connects an application to the player as a client.
+
+use std::fs;
+use std::io::{BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use tracing::error;
+
+use super::{json, read_line, RemoteError, Reply, Request};
+use crate::app::Msg;
+
+/// Longest line accepted, enough for a queue holding a large library
+const MAX_LINE: usize = 16 * 1024 * 1024;
+
+/// A listening remote-control socket.
+///
+/// The socket file is removed when the server is dropped.
+#[derive(Debug)]
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Server {
+    /// Listens on a socket and starts passing the requests that arrive to
+    /// the application.
+    ///
+    /// A socket file left behind by an instance that did not quit cleanly
+    /// is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Socket file to create
+    /// * `tx` - Channel the requests are sent on as [`Msg::Remote`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RemoteError::AlreadyRunning`] if another instance answers on
+    /// the socket, or an I/O error if the socket cannot be created.
+    pub fn start(path: &Path, tx: Sender<Msg>) -> Result<Self, RemoteError> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(RemoteError::AlreadyRunning(path.to_path_buf()));
+            }
+            fs::remove_file(path)?;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let tx = tx.clone();
+                        // A client hanging up is not worth reporting
+                        thread::spawn(move || serve(stream, tx).ok());
+                    }
+                    Err(e) => error!("无法接受远程控制连接: {}", e),
+                }
+            }
+        });
+        Ok(Self { path: path.to_path_buf() })
+    }
+
+    /// Returns the socket file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Answers the requests of one connection until it is closed, or until it
+/// sends a line longer than [`MAX_LINE`].
+fn serve(stream: UnixStream, tx: Sender<Msg>) -> Result<(), RemoteError> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    loop {
+        let line = match read_line(&mut reader, MAX_LINE) {
+            Ok(Some(line)) => line,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                writeln!(writer, "{}", json::encode_reply(&Reply::Error(e.to_string())))?;
+                return Err(e);
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match json::decode_request(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                writeln!(writer, "{}", json::encode_reply(&Reply::Error(e.to_string())))?;
+                continue;
+            }
+        };
+        let subscribe = request == Request::Subscribe;
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(Msg::Remote(request, reply_tx)).map_err(|_| RemoteError::Closed)?;
+        let reply = reply_rx.recv().map_err(|_| RemoteError::Closed)?;
+        writeln!(writer, "{}", json::encode_reply(&reply))?;
+        if subscribe {
+            // Events follow until the client or the application hangs up
+            for event in reply_rx {
+                writeln!(writer, "{}", json::encode_reply(&event))?;
+            }
+            return Ok(());
+        }
+    }
+}
+
+/// A connection to a running player.
+#[derive(Debug)]
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Connects to the player listening on a socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if nothing listens on the socket.
+    pub fn connect(path: &Path) -> Result<Self, RemoteError> {
+        let writer = UnixStream::connect(path)?;
+        Ok(Self { reader: BufReader::new(writer.try_clone()?), writer })
+    }
+
+    /// Sends a request and waits for the reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or the reply is malformed.
+    pub fn request(&mut self, request: &Request) -> Result<Reply, RemoteError> {
+        writeln!(self.writer, "{}", json::encode_request(request))?;
+        self.receive()
+    }
+
+    /// Waits for the next reply or event (after [`Request::Subscribe`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RemoteError::Closed`] once the player has closed the
+    /// connection, or another error if it fails or a line is malformed.
+    pub fn receive(&mut self) -> Result<Reply, RemoteError> {
+        let line = read_line(&mut self.reader, MAX_LINE)?.ok_or(RemoteError::Closed)?;
+        json::decode_reply(&line)
+    }
+}
+
+/// Connects an application to the player listening on a socket (see
+/// [`App::attached`](crate::app::App::attached)).
+///
+/// Replies to the requests sent on the returned channel and the player's
+/// events arrive as [`Msg::Daemon`]; when the player quits, the error is
+/// logged and [`Msg::Quit`] follows.
+///
+/// # Errors
+///
+/// Returns an error if nothing listens on the socket or it does not accept
+/// a subscription.
+pub fn attach(path: &Path, tx: Sender<Msg>) -> Result<Sender<Request>, RemoteError> {
+    let mut events = Client::connect(path)?;
+    match events.request(&Request::Subscribe)? {
+        Reply::Done => {}
+        reply => return Err(RemoteError::Protocol(format!("unexpected reply {:?}", reply))),
+    }
+    let mut client = Client::connect(path)?;
+    let (request_tx, request_rx) = mpsc::channel::<Request>();
+    let replies = tx.clone();
+    thread::spawn(move || {
+        for request in request_rx {
+            let Ok(reply) = client.request(&request) else { break };
+            if replies.send(Msg::Daemon(reply)).is_err() {
+                break;
+            }
+        }
+    });
+    thread::spawn(move || {
+        let error = loop {
+            match events.receive() {
+                Ok(reply) => {
+                    if tx.send(Msg::Daemon(reply)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => break e,
+            }
+        };
+        error!("与播放器的连接已断开: {}", error);
+        tx.send(Msg::Quit).ok();
+    });
+    Ok(request_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use crate::remote::{Event, Status};
+
+    fn socket_path() -> (TempDir, PathBuf) {
+        let dir = TempDir::new("ipc");
+        let path = dir.join("tools-rs.sock");
+        (dir, path)
+    }
+
+    /// Answers requests like the application: status with the volume, and
+    /// events to subscribers whenever the volume is set.
+    fn fake_app(rx: mpsc::Receiver<Msg>) {
+        thread::spawn(move || {
+            let mut volume = 100;
+            let mut subscribers: Vec<Sender<Reply>> = Vec::new();
+            for msg in rx {
+                let Msg::Remote(request, reply) = msg else { continue };
+                let answer = match request {
+                    Request::Status => Reply::Status(Status {
+                        state: crate::common::PlaybackStatus::Stopped,
+                        track: None,
+                        position: Default::default(),
+                        duration: None,
+                        volume,
+                        shuffle: false,
+                        repeat: Default::default(),
+                        queue_position: None,
+                        queue_length: 0,
+                    }),
+                    Request::Volume(amount) => {
+                        volume = amount.apply(volume);
+                        subscribers.retain(|s| s.send(Reply::Event(Event::Volume(volume))).is_ok());
+                        Reply::Done
+                    }
+                    Request::Subscribe => {
+                        subscribers.push(reply.clone());
+                        Reply::Done
+                    }
+                    _ => Reply::Error("unsupported".to_string()),
+                };
+                reply.send(answer).ok();
+            }
+        });
+    }
+
+    #[test]
+    fn test_requests_and_events_over_the_socket() {
+        let (_dir, path) = socket_path();
+        let (tx, rx) = mpsc::channel();
+        let server = Server::start(&path, tx).unwrap();
+        fake_app(rx);
+
+        let mut listener = Client::connect(&path).unwrap();
+        assert_eq!(listener.request(&Request::Subscribe).unwrap(), Reply::Done);
+
+        let mut client = Client::connect(&path).unwrap();
+        assert_eq!(client.request(&Request::Volume(crate::command::Amount::Down(30))).unwrap(), Reply::Done);
+        match client.request(&Request::Status).unwrap() {
+            Reply::Status(status) => assert_eq!(status.volume, 70),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(client.request(&Request::Next).unwrap(), Reply::Error("unsupported".to_string()));
+        assert_eq!(listener.receive().unwrap(), Reply::Event(Event::Volume(70)));
+
+        // Malformed lines are answered with an error and the connection stays usable
+        writeln!(client.writer, "not json").unwrap();
+        assert!(matches!(client.receive().unwrap(), Reply::Error(_)));
+        assert!(matches!(client.request(&Request::Status).unwrap(), Reply::Status(_)));
+
+        drop(server);
+        assert!(!path.exists(), "the socket is removed on drop");
+    }
+
+    #[test]
+    fn test_attach() {
+        let (_dir, path) = socket_path();
+        let (tx, rx) = mpsc::channel();
+        let server = Server::start(&path, tx).unwrap();
+        fake_app(rx);
+
+        let (app_tx, app_rx) = mpsc::channel();
+        let requests = attach(&path, app_tx).unwrap();
+        requests.send(Request::Volume(crate::command::Amount::To(40))).unwrap();
+        let received: Vec<Reply> = (0..2)
+            .map(|_| match app_rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap() {
+                Msg::Daemon(reply) => reply,
+                _ => panic!("expected a reply or event"),
+            })
+            .collect();
+        assert!(received.contains(&Reply::Done), "{:?}", received);
+        assert!(received.contains(&Reply::Event(Event::Volume(40))), "{:?}", received);
+
+        drop(server);
+        drop(requests);
+    }
+
+    #[test]
+    fn test_second_instance_and_stale_socket() {
+        let (_dir, path) = socket_path();
+        let (tx, rx) = mpsc::channel();
+        let server = Server::start(&path, tx.clone()).unwrap();
+        assert!(matches!(Server::start(&path, tx.clone()), Err(RemoteError::AlreadyRunning(_))));
+        drop(server);
+
+        // A socket file nobody listens on is left over from a crash
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let server = Server::start(&path, tx).unwrap();
+        drop(rx);
+        let mut client = Client::connect(server.path()).unwrap();
+        assert!(client.request(&Request::Status).is_err(), "no application to answer");
+        drop(server);
+    }
+}
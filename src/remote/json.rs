@@ -0,0 +1,427 @@
+//! Requests, replies and events as lines of JSON.
+//!
+//! Every message is one JSON object on a line of its own. Requests name the
+//! command in `cmd`:
+//!
+//! ```text
+//! {"cmd":"toggle"}                    {"cmd":"volume","value":"+5"}
+//! {"cmd":"play","position":3}         {"cmd":"seek","value":"1:30"}
+//! {"cmd":"play","path":"/music/a.flac"}
+//! {"cmd":"pause"}                     {"cmd":"shuffle","value":true}
+//! {"cmd":"next"}  {"cmd":"prev"}      {"cmd":"repeat","value":"all"}
+//! {"cmd":"enqueue","paths":["/music/a.flac","/music/album"]}
+//! {"cmd":"clear"}  {"cmd":"status"}  {"cmd":"queue"}  {"cmd":"subscribe"}
+//! {"cmd":"search","query":"artist:davis year:<1960"}
+//! ```
+//!
+//! A volume or seek `value` is a number (percent, seconds) or a string as
+//! on the command line (`80`, `+5`, `-10`, `1:30`); `play` without a
+//! position and `shuffle`/`repeat` without a value behave like the keys.
+//! `play` with a `path` plays that library file, queueing it first if it is
+//! not queued.
+//!
+//! Replies carry `ok`, and `status`, `tracks` or `error` when there is more
+//! to tell:
+//!
+//! ```text
+//! {"ok":true}
+//! {"ok":true,"status":{"state":"play","track":{"path":"/music/a.flac","title":"A",
+//!   "artist":null,"album":null,"duration":200.0},"position":12.5,"duration":200.0,
+//!   "volume":80,"shuffle":false,"repeat":"off","queue_position":0,"queue_length":12}}
+//! {"ok":true,"tracks":[{"path":"/music/a.flac",...}]}
+//! {"ok":false,"error":"..."}
+//! ```
+//!
+//! Events for subscribers carry `event` (`track-started` with `track`,
+//! `progress` with `position`, `track-ended`, `error` with `message`, `state`
+//! with `state`, `volume` with `volume`, `queue`, `options`). Times are in
+//! seconds; states are `play`, `pause` and `stop`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use super::{Event, RemoteError, Reply, Request, Status, TrackInfo};
+use crate::command::{self, Amount};
+use crate::common::PlaybackStatus;
+
+/// Encodes a request as one line (without the newline).
+pub fn encode_request(request: &Request) -> String {
+    let value = match request {
+        Request::Toggle => json!({ "cmd": "toggle" }),
+        Request::Play(None) => json!({ "cmd": "play" }),
+        Request::Play(Some(position)) => json!({ "cmd": "play", "position": position }),
+        Request::PlayFile(path) => json!({ "cmd": "play", "path": path.to_string_lossy() }),
+        Request::Pause => json!({ "cmd": "pause" }),
+        Request::Next => json!({ "cmd": "next" }),
+        Request::Prev => json!({ "cmd": "prev" }),
+        Request::Volume(amount) => json!({ "cmd": "volume", "value": amount_text(*amount, |v| v.to_string()) }),
+        Request::Seek(amount) => {
+            json!({ "cmd": "seek", "value": amount_text(*amount, |d| d.as_secs().to_string()) })
+        }
+        Request::Shuffle(shuffle) => json!({ "cmd": "shuffle", "value": shuffle }),
+        Request::Repeat(repeat) => json!({ "cmd": "repeat", "value": repeat.map(|r| r.to_string()) }),
+        Request::Enqueue(paths) => {
+            let paths: Vec<_> = paths.iter().map(|p| p.to_string_lossy()).collect();
+            json!({ "cmd": "enqueue", "paths": paths })
+        }
+        Request::ClearQueue => json!({ "cmd": "clear" }),
+        Request::Status => json!({ "cmd": "status" }),
+        Request::Queue => json!({ "cmd": "queue" }),
+        Request::Search(query) => json!({ "cmd": "search", "query": query }),
+        Request::Subscribe => json!({ "cmd": "subscribe" }),
+    };
+    value.to_string()
+}
+
+/// Decodes a request line.
+///
+/// # Errors
+///
+/// Returns [`RemoteError::Protocol`] if the line is not JSON, names no known
+/// command or has an invalid argument.
+pub fn decode_request(line: &str) -> Result<Request, RemoteError> {
+    let object = parse_object(line)?;
+    let cmd = object.get("cmd").and_then(Value::as_str).ok_or_else(|| protocol("missing \"cmd\""))?;
+    let field = |key: &str| object.get(key).filter(|v| !v.is_null());
+    let invalid = |key: &str| protocol(format!("{}: invalid \"{}\"", cmd, key));
+    let request = match cmd {
+        "toggle" => Request::Toggle,
+        "play" => match (field("position"), field("path")) {
+            (Some(position), _) => Request::Play(Some(position.as_u64().ok_or_else(|| invalid("position"))? as usize)),
+            (None, Some(path)) => Request::PlayFile(PathBuf::from(path.as_str().ok_or_else(|| invalid("path"))?)),
+            (None, None) => Request::Play(None),
+        },
+        "pause" => Request::Pause,
+        "next" => Request::Next,
+        "prev" => Request::Prev,
+        "volume" => {
+            let amount = match field("value") {
+                Some(Value::Number(n)) => n.as_u64().and_then(|n| u32::try_from(n).ok()).map(Amount::To),
+                Some(Value::String(s)) => command::parse_amount(s, |s| s.parse().ok()),
+                _ => None,
+            };
+            Request::Volume(amount.ok_or_else(|| invalid("value"))?)
+        }
+        "seek" => {
+            let amount = match field("value") {
+                Some(Value::Number(n)) => seconds(n.as_f64()).map(Amount::To),
+                Some(Value::String(s)) => command::parse_amount(s, command::parse_position),
+                _ => None,
+            };
+            Request::Seek(amount.ok_or_else(|| invalid("value"))?)
+        }
+        "shuffle" => match field("value") {
+            Some(value) => Request::Shuffle(Some(value.as_bool().ok_or_else(|| invalid("value"))?)),
+            None => Request::Shuffle(None),
+        },
+        "repeat" => match field("value") {
+            Some(value) => {
+                Request::Repeat(Some(value.as_str().and_then(|s| s.parse().ok()).ok_or_else(|| invalid("value"))?))
+            }
+            None => Request::Repeat(None),
+        },
+        "enqueue" => {
+            let paths = field("paths").and_then(Value::as_array).ok_or_else(|| invalid("paths"))?;
+            let paths = paths.iter().map(|p| p.as_str().map(PathBuf::from)).collect::<Option<Vec<_>>>();
+            Request::Enqueue(paths.ok_or_else(|| invalid("paths"))?)
+        }
+        "clear" => Request::ClearQueue,
+        "status" => Request::Status,
+        "queue" => Request::Queue,
+        "search" => Request::Search(field("query").and_then(Value::as_str).ok_or_else(|| invalid("query"))?.to_string()),
+        "subscribe" => Request::Subscribe,
+        other => return Err(protocol(format!("unknown command \"{}\"", other))),
+    };
+    Ok(request)
+}
+
+/// Encodes a reply or event as one line (without the newline).
+pub fn encode_reply(reply: &Reply) -> String {
+    let value = match reply {
+        Reply::Done => json!({ "ok": true }),
+        Reply::Status(status) => json!({ "ok": true, "status": status_value(status) }),
+        Reply::Tracks(tracks) => json!({ "ok": true, "tracks": tracks.iter().map(track_value).collect::<Vec<_>>() }),
+        Reply::Event(event) => event_value(event),
+        Reply::Error(message) => json!({ "ok": false, "error": message }),
+    };
+    value.to_string()
+}
+
+/// Decodes a reply or event line.
+///
+/// # Errors
+///
+/// Returns [`RemoteError::Protocol`] if the line is not a reply or event.
+pub fn decode_reply(line: &str) -> Result<Reply, RemoteError> {
+    let object = parse_object(line)?;
+    if let Some(event) = object.get("event") {
+        let event = event.as_str().ok_or_else(|| protocol("invalid \"event\""))?;
+        return decode_event(event, &object).map(Reply::Event);
+    }
+    match object.get("ok").and_then(Value::as_bool) {
+        Some(true) => {
+            if let Some(status) = object.get("status") {
+                decode_status(status).map(Reply::Status)
+            } else if let Some(tracks) = object.get("tracks") {
+                let tracks = tracks.as_array().ok_or_else(|| protocol("invalid \"tracks\""))?;
+                tracks.iter().map(decode_track).collect::<Result<_, _>>().map(Reply::Tracks)
+            } else {
+                Ok(Reply::Done)
+            }
+        }
+        Some(false) => {
+            let message = object.get("error").and_then(Value::as_str).unwrap_or_default();
+            Ok(Reply::Error(message.to_string()))
+        }
+        None => Err(protocol("missing \"ok\" or \"event\"")),
+    }
+}
+
+/// Returns the name of a playback state (`play`, `pause`, `stop`).
+pub fn state_name(state: PlaybackStatus) -> &'static str {
+    match state {
+        PlaybackStatus::Playing => "play",
+        PlaybackStatus::Paused => "pause",
+        PlaybackStatus::Stopped => "stop",
+    }
+}
+
+/// Parses the name of a playback state.
+fn parse_state(name: &str) -> Option<PlaybackStatus> {
+    match name {
+        "play" => Some(PlaybackStatus::Playing),
+        "pause" => Some(PlaybackStatus::Paused),
+        "stop" => Some(PlaybackStatus::Stopped),
+        _ => None,
+    }
+}
+
+/// Writes an amount as on the command line (`80`, `+5`, `-10`).
+fn amount_text<T>(amount: Amount<T>, text: impl Fn(&T) -> String) -> String {
+    match amount {
+        Amount::To(value) => text(&value),
+        Amount::Up(delta) => format!("+{}", text(&delta)),
+        Amount::Down(delta) => format!("-{}", text(&delta)),
+    }
+}
+
+/// Converts seconds to a duration, rejecting negative, non-finite and too
+/// large values.
+fn seconds(secs: Option<f64>) -> Option<Duration> {
+    secs.and_then(|s| Duration::try_from_secs_f64(s).ok())
+}
+
+/// Parses a line holding a JSON object.
+fn parse_object(line: &str) -> Result<Map<String, Value>, RemoteError> {
+    match serde_json::from_str(line) {
+        Ok(Value::Object(object)) => Ok(object),
+        Ok(_) => Err(protocol("expected an object")),
+        Err(e) => Err(protocol(e.to_string())),
+    }
+}
+
+/// Returns a protocol error.
+fn protocol(message: impl Into<String>) -> RemoteError {
+    RemoteError::Protocol(message.into())
+}
+
+/// Returns the JSON object of a track.
+fn track_value(track: &TrackInfo) -> Value {
+    json!({
+        "path": track.path.to_string_lossy(),
+        "title": track.title,
+        "artist": track.artist,
+        "album": track.album,
+        "duration": track.duration.map(|d| d.as_secs_f64()),
+    })
+}
+
+/// Returns the JSON object of a status.
+fn status_value(status: &Status) -> Value {
+    json!({
+        "state": state_name(status.state),
+        "track": status.track.as_ref().map(track_value),
+        "position": status.position.as_secs_f64(),
+        "duration": status.duration.map(|d| d.as_secs_f64()),
+        "volume": status.volume,
+        "shuffle": status.shuffle,
+        "repeat": status.repeat.to_string(),
+        "queue_position": status.queue_position,
+        "queue_length": status.queue_length,
+    })
+}
+
+/// Returns the JSON object of an event.
+fn event_value(event: &Event) -> Value {
+    match event {
+        Event::TrackStarted(track) => json!({ "event": "track-started", "track": track_value(track) }),
+        Event::Progress(position) => json!({ "event": "progress", "position": position.as_secs_f64() }),
+        Event::TrackEnded => json!({ "event": "track-ended" }),
+        Event::Error(message) => json!({ "event": "error", "message": message }),
+        Event::State(state) => json!({ "event": "state", "state": state_name(*state) }),
+        Event::Volume(volume) => json!({ "event": "volume", "volume": volume }),
+        Event::Queue => json!({ "event": "queue" }),
+        Event::Options => json!({ "event": "options" }),
+    }
+}
+
+/// Reads a track from its JSON object.
+fn decode_track(value: &Value) -> Result<TrackInfo, RemoteError> {
+    let text = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+    Ok(TrackInfo {
+        path: text("path").map(PathBuf::from).ok_or_else(|| protocol("track without \"path\""))?,
+        title: text("title"),
+        artist: text("artist"),
+        album: text("album"),
+        duration: seconds(value.get("duration").and_then(Value::as_f64)),
+    })
+}
+
+/// Reads a status from its JSON object.
+fn decode_status(value: &Value) -> Result<Status, RemoteError> {
+    let invalid = |key: &str| protocol(format!("status: invalid \"{}\"", key));
+    let field = |key: &str| value.get(key).filter(|v| !v.is_null());
+    Ok(Status {
+        state: field("state").and_then(Value::as_str).and_then(parse_state).ok_or_else(|| invalid("state"))?,
+        track: field("track").map(decode_track).transpose()?,
+        position: seconds(field("position").and_then(Value::as_f64)).ok_or_else(|| invalid("position"))?,
+        duration: seconds(field("duration").and_then(Value::as_f64)),
+        volume: field("volume").and_then(Value::as_u64).ok_or_else(|| invalid("volume"))? as u32,
+        shuffle: field("shuffle").and_then(Value::as_bool).ok_or_else(|| invalid("shuffle"))?,
+        repeat: field("repeat").and_then(Value::as_str).and_then(|s| s.parse().ok()).ok_or_else(|| invalid("repeat"))?,
+        queue_position: field("queue_position").and_then(Value::as_u64).map(|p| p as usize),
+        queue_length: field("queue_length").and_then(Value::as_u64).ok_or_else(|| invalid("queue_length"))? as usize,
+    })
+}
+
+/// Reads the event with a name from its JSON object.
+fn decode_event(event: &str, object: &Map<String, Value>) -> Result<Event, RemoteError> {
+    let invalid = |key: &str| protocol(format!("{}: invalid \"{}\"", event, key));
+    let event = match event {
+        "track-started" => Event::TrackStarted(decode_track(object.get("track").ok_or_else(|| invalid("track"))?)?),
+        "progress" => {
+            Event::Progress(seconds(object.get("position").and_then(Value::as_f64)).ok_or_else(|| invalid("position"))?)
+        }
+        "track-ended" => Event::TrackEnded,
+        "error" => Event::Error(object.get("message").and_then(Value::as_str).unwrap_or_default().to_string()),
+        "state" => {
+            Event::State(object.get("state").and_then(Value::as_str).and_then(parse_state).ok_or_else(|| invalid("state"))?)
+        }
+        "volume" => Event::Volume(object.get("volume").and_then(Value::as_u64).ok_or_else(|| invalid("volume"))? as u32),
+        "queue" => Event::Queue,
+        "options" => Event::Options,
+        other => return Err(protocol(format!("unknown event \"{}\"", other))),
+    };
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::RepeatMode;
+
+    fn track() -> TrackInfo {
+        TrackInfo {
+            path: PathBuf::from("/music/Kind of Blue/So What.flac"),
+            title: Some("So What".to_string()),
+            artist: Some("Miles Davis".to_string()),
+            album: None,
+            duration: Some(Duration::from_secs(562)),
+        }
+    }
+
+    #[test]
+    fn test_requests_round_trip() {
+        let requests = [
+            Request::Toggle,
+            Request::Play(None),
+            Request::Play(Some(3)),
+            Request::PlayFile(PathBuf::from("/music/a.flac")),
+            Request::Pause,
+            Request::Next,
+            Request::Prev,
+            Request::Volume(Amount::To(80)),
+            Request::Volume(Amount::Up(5)),
+            Request::Seek(Amount::Down(Duration::from_secs(10))),
+            Request::Shuffle(Some(true)),
+            Request::Shuffle(None),
+            Request::Repeat(Some(RepeatMode::All)),
+            Request::Repeat(None),
+            Request::Enqueue(vec![PathBuf::from("/music/a.flac"), PathBuf::from("/music/album")]),
+            Request::ClearQueue,
+            Request::Status,
+            Request::Queue,
+            Request::Search("artist:\"Miles Davis\" year:<1960".to_string()),
+            Request::Subscribe,
+        ];
+        for request in requests {
+            let line = encode_request(&request);
+            assert!(!line.contains('\n'));
+            assert_eq!(decode_request(&line).unwrap(), request, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_decode_request_forms() {
+        let decode = |line: &str| decode_request(line).unwrap();
+        assert_eq!(decode(r#"{"cmd":"volume","value":70}"#), Request::Volume(Amount::To(70)));
+        assert_eq!(decode(r#"{"cmd":"seek","value":"1:30"}"#), Request::Seek(Amount::To(Duration::from_secs(90))));
+        assert_eq!(decode(r#"{"cmd":"seek","value":2.5}"#), Request::Seek(Amount::To(Duration::from_millis(2500))));
+        assert_eq!(decode(r#" {"cmd":"play","position":null} "#), Request::Play(None));
+
+        for line in [
+            "",
+            "[]",
+            "{}",
+            r#"{"cmd":"dance"}"#,
+            r#"{"cmd":"volume"}"#,
+            r#"{"cmd":"volume","value":"loud"}"#,
+            r#"{"cmd":"seek","value":-3}"#,
+            r#"{"cmd":"seek","value":1e300}"#,
+            r#"{"cmd":"repeat","value":"twice"}"#,
+            r#"{"cmd":"play","path":7}"#,
+            r#"{"cmd":"enqueue","paths":[1]}"#,
+            r#"{"cmd":"search"}"#,
+        ] {
+            assert!(matches!(decode_request(line), Err(RemoteError::Protocol(_))), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_replies_round_trip() {
+        let status = Status {
+            state: PlaybackStatus::Paused,
+            track: Some(track()),
+            position: Duration::from_millis(12_500),
+            duration: Some(Duration::from_secs(562)),
+            volume: 80,
+            shuffle: true,
+            repeat: RepeatMode::One,
+            queue_position: Some(4),
+            queue_length: 12,
+        };
+        let replies = [
+            Reply::Done,
+            Reply::Status(status.clone()),
+            Reply::Status(Status { state: PlaybackStatus::Stopped, track: None, queue_position: None, ..status }),
+            Reply::Tracks(vec![track(), TrackInfo { path: PathBuf::from("/b.mp3"), ..Default::default() }]),
+            Reply::Error("没有这首曲目".to_string()),
+            Reply::Event(Event::TrackStarted(track())),
+            Reply::Event(Event::Progress(Duration::from_secs(3))),
+            Reply::Event(Event::TrackEnded),
+            Reply::Event(Event::Error("无法解码".to_string())),
+            Reply::Event(Event::State(PlaybackStatus::Playing)),
+            Reply::Event(Event::Volume(120)),
+            Reply::Event(Event::Queue),
+            Reply::Event(Event::Options),
+        ];
+        for reply in replies {
+            let line = encode_reply(&reply);
+            assert!(!line.contains('\n'));
+            assert_eq!(decode_reply(&line).unwrap(), reply, "{}", line);
+        }
+        assert!(decode_reply(r#"{"status":{}}"#).is_err());
+    }
+}
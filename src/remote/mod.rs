@@ -0,0 +1,232 @@
+//! Remote control of a running player.
+//!
+//! Scripts, window-manager key bindings and other terminals control the
+//! player with [`Request`]s. A request reaches the application as
+//! [`Msg::Remote`](crate::app::Msg::Remote) together with the channel its
+//! [`Reply`] goes back on. [`Request::Subscribe`] keeps that channel: the
+//! application then sends an [`Event`] on it whenever playback, the volume,
+//! the queue or its settings change, until the other end hangs up.
+//!
+//! The requests do not depend on a transport:
+//! - `json`: Requests, replies and events as lines of JSON
+//! - `ipc`: Server and client on a Unix socket (see [`crate::paths::socket_file`])
+//! - `ctl`: The `tools-rs ctl` subcommands, a client for scripts and key bindings
+//! - `http`: REST endpoints, server-sent events and a web remote
+//! - `mpd`: A subset of the MPD protocol on localhost, for existing MPD clients
+//!
+//! # Example
+//!
+//! ```
+//! use tools_rs::remote::{json, Request};
+//!
+//! // What a client writes to the socket to skip a track
+//! assert_eq!(json::encode_request(&Request::Next), r#"{"cmd":"next"}"#);
+//! ```
+
+use std::io::{self, BufRead, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::command::Amount;
+use crate::common::{PlaybackStatus, Track};
+use crate::queue::RepeatMode;
+
+pub mod ctl;
+pub mod http;
+#[cfg(unix)]
+pub mod ipc;
+pub mod json;
+pub mod mpd;
+
+/// Errors that can occur while serving or sending requests.
+#[derive(Debug, Error)]
+pub enum RemoteError {
+    /// Reading from or writing to the connection failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Another instance already listens on the socket
+    #[error("another instance is already listening on {0}")]
+    AlreadyRunning(PathBuf),
+    /// A line is not a valid request, reply or event
+    #[error("invalid message: {0}")]
+    Protocol(String),
+    /// The application stopped before replying
+    #[error("the player has quit")]
+    Closed,
+}
+
+/// Something another program asks the player to do or tell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Pause or resume playback, or start it while stopped
+    Toggle,
+    /// Start or resume playback; with a queue position, play that entry
+    Play(Option<usize>),
+    /// Play a library file: its entry in the queue, or else a new entry at
+    /// the end
+    PlayFile(PathBuf),
+    /// Pause playback
+    Pause,
+    /// Skip to the next track in the queue
+    Next,
+    /// Go back to the previous track in the queue
+    Prev,
+    /// Set or change the volume, in percent
+    Volume(Amount<u32>),
+    /// Seek to a position or by an offset
+    Seek(Amount<Duration>),
+    /// Set shuffle, or toggle it
+    Shuffle(Option<bool>),
+    /// Set the repeat mode, or cycle it
+    Repeat(Option<RepeatMode>),
+    /// Add library tracks to the end of the queue; a directory adds the
+    /// tracks below it
+    Enqueue(Vec<PathBuf>),
+    /// Empty the queue
+    ClearQueue,
+    /// Tell the playback state
+    Status,
+    /// Tell the queued tracks
+    Queue,
+    /// Tell the library tracks matching a query (see [`crate::query`]), or
+    /// all of them for a blank query
+    Search(String),
+    /// Send an [`Event`] for every change from now on
+    Subscribe,
+}
+
+/// The answer to a [`Request`], or an [`Event`] for a subscriber.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    /// The request was carried out
+    Done,
+    /// The playback state
+    Status(Status),
+    /// A list of tracks (the queue or search results)
+    Tracks(Vec<TrackInfo>),
+    /// Something changed (after [`Request::Subscribe`])
+    Event(Event),
+    /// The request could not be carried out
+    Error(String),
+}
+
+/// A change announced to subscribers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// The player started a track
+    TrackStarted(TrackInfo),
+    /// The position in the playing track moved
+    Progress(Duration),
+    /// The playing track reached its end
+    TrackEnded,
+    /// Playback failed
+    Error(String),
+    /// Playback started, paused or stopped
+    State(PlaybackStatus),
+    /// The volume changed, in percent
+    Volume(u32),
+    /// Tracks were added to or removed from the queue, or it was reordered
+    Queue,
+    /// Shuffle or repeat changed
+    Options,
+}
+
+/// A track as told to other programs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrackInfo {
+    /// File system path to the audio file
+    pub path: PathBuf,
+    /// Title from the file's tags (if available)
+    pub title: Option<String>,
+    /// Performing artist from the file's tags (if available)
+    pub artist: Option<String>,
+    /// Album name from the file's tags (if available)
+    pub album: Option<String>,
+    /// Total duration of the track (if known)
+    pub duration: Option<Duration>,
+}
+
+impl From<&Track> for TrackInfo {
+    fn from(track: &Track) -> Self {
+        Self {
+            path: track.path.clone(),
+            title: track.title.clone(),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            duration: track.duration,
+        }
+    }
+}
+
+/// The playback state as told to other programs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Status {
+    /// Whether a track is playing, paused or stopped
+    pub state: PlaybackStatus,
+    /// The playing or paused track (if any)
+    pub track: Option<TrackInfo>,
+    /// Position within the track
+    pub position: Duration,
+    /// Duration of the track as reported by the player (if known)
+    pub duration: Option<Duration>,
+    /// Volume in percent (0 to 200)
+    pub volume: u32,
+    /// Whether the queue is shuffled
+    pub shuffle: bool,
+    /// What happens at the end of a track or the queue
+    pub repeat: RepeatMode,
+    /// Position of the current track within the queue
+    pub queue_position: Option<usize>,
+    /// Number of queued tracks
+    pub queue_length: usize,
+}
+
+/// Reads a line sent by the other end, without its line ending.
+///
+/// # Arguments
+///
+/// * `reader` - Connection to read from
+/// * `max` - Longest line accepted, in bytes
+///
+/// # Returns
+///
+/// Returns `None` once the other end has closed the connection.
+///
+/// # Errors
+///
+/// Returns [`RemoteError::Protocol`] for a line longer than `max`, which
+/// would otherwise be buffered without bound, or an I/O error if reading
+/// fails.
+pub(crate) fn read_line(reader: &mut impl BufRead, max: usize) -> Result<Option<String>, RemoteError> {
+    let mut line = String::new();
+    if reader.take(max as u64 + 1).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    } else if line.len() > max {
+        return Err(RemoteError::Protocol("line too long".to_string()));
+    }
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_line_is_bounded() {
+        let mut input = "ping\r\nstatus\nlast".as_bytes();
+        assert_eq!(read_line(&mut input, 6).unwrap().as_deref(), Some("ping"));
+        assert_eq!(read_line(&mut input, 6).unwrap().as_deref(), Some("status"));
+        assert_eq!(read_line(&mut input, 6).unwrap().as_deref(), Some("last"));
+        assert_eq!(read_line(&mut input, 6).unwrap(), None);
+        let mut endless = io::repeat(b'x');
+        assert!(matches!(read_line(&mut io::BufReader::new(&mut endless), 1024), Err(RemoteError::Protocol(_))));
+    }
+}
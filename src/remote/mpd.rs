@@ -0,0 +1,809 @@
+//! A subset of the MPD protocol, for existing clients.
+//!
+//! [`MpdServer`] speaks enough of the protocol of the Music Player Daemon
+//! (<https://mpd.readthedocs.io/en/latest/protocol.html>) for clients such
+//! as ncmpcpp, mobile apps and status bar widgets to show and control
+//! playback:
+//!
+//! - Status: `status`, `currentsong`, `idle`, `noidle`, `ping`
+//! - Playback: `play`, `playid`, `pause`, `next`, `previous`, `seek`,
+//!   `seekid`, `seekcur`, `setvol`, `repeat`, `random`, `single`
+//! - Queue: `playlistinfo`, `add`, `clear`
+//! - Library: `lsinfo`, `search`
+//! - Command lists (`command_list_begin`, `command_list_ok_begin`)
+//!
+//! Every command becomes a [`Request`] to the application. URIs are paths
+//! relative to the music directory (see [`music_dir`]), and a track's
+//! position in the queue doubles as its song id. The server only listens on
+//! localhost, since the protocol has no authentication here.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use tracing::error;
+
+use super::{json, read_line, Event, RemoteError, Reply, Request, Status, TrackInfo};
+use crate::app::Msg;
+use crate::command::{self, Amount};
+use crate::common::PlaybackStatus;
+use crate::queue::RepeatMode;
+
+/// First line sent to every client: the protocol version implemented
+const GREETING: &str = "OK MPD 0.23.0\n";
+
+/// Longest command line accepted; the connection is closed after a longer one
+const MAX_LINE: usize = 64 * 1024;
+
+/// Error code for invalid arguments
+const ACK_ERROR_ARG: u8 = 2;
+/// Error code for unknown commands
+const ACK_ERROR_UNKNOWN: u8 = 5;
+/// Error code for songs, directories and positions that do not exist
+const ACK_ERROR_NO_EXIST: u8 = 50;
+/// Error code for failures of the player itself
+const ACK_ERROR_SYSTEM: u8 = 52;
+
+/// Commands understood, as listed by `commands`.
+const COMMANDS: [&str; 27] = [
+    "add", "clear", "close", "command_list_begin", "command_list_end", "command_list_ok_begin", "commands",
+    "currentsong", "idle", "lsinfo", "next", "noidle", "notcommands", "pause", "ping", "play", "playid",
+    "playlistinfo", "previous", "random", "repeat", "search", "seek", "seekcur", "seekid", "setvol", "status",
+];
+
+/// A listening MPD protocol server.
+#[derive(Debug)]
+pub struct MpdServer {
+    addr: SocketAddr,
+}
+
+impl MpdServer {
+    /// Listens on a port of localhost and starts passing the commands of
+    /// the clients that connect to the application.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - Port to listen on (0 picks a free one)
+    /// * `music_dir` - Directory the URIs are relative to
+    /// * `tx` - Channel the requests are sent on as [`Msg::Remote`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the port cannot be bound.
+    pub fn start(port: u16, music_dir: PathBuf, tx: Sender<Msg>) -> Result<Self, RemoteError> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let addr = listener.local_addr()?;
+        let music_dir = Arc::new(music_dir);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let (music_dir, tx) = (Arc::clone(&music_dir), tx.clone());
+                        // A client hanging up is not worth reporting
+                        thread::spawn(move || serve(stream, music_dir, tx).ok());
+                    }
+                    Err(e) => error!("无法接受 MPD 连接: {}", e),
+                }
+            }
+        });
+        Ok(Self { addr })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+/// Returns the music directory for library roots: the deepest directory
+/// containing all of them.
+pub fn music_dir(roots: &[PathBuf]) -> PathBuf {
+    let Some((first, rest)) = roots.split_first() else { return PathBuf::new() };
+    let mut dir = first.clone();
+    for root in rest {
+        while !root.starts_with(&dir) && dir.pop() {}
+    }
+    dir
+}
+
+/// Something a connection reacts to.
+enum Input {
+    /// A line sent by the client
+    Line(String),
+    /// A change announced by the application
+    Event(Event),
+    /// The client hung up
+    Closed,
+}
+
+/// An `ACK` error response.
+#[derive(Debug)]
+struct Ack {
+    code: u8,
+    message: String,
+}
+
+impl Ack {
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// Formats the response for the command at a position of a command list.
+    fn response(&self, index: usize, command: &str) -> String {
+        format!("ACK [{}@{}] {{{}}} {}\n", self.code, index, command, self.message)
+    }
+}
+
+/// State of one client connection.
+struct Connection {
+    writer: TcpStream,
+    tx: Sender<Msg>,
+    music_dir: Arc<PathBuf>,
+    /// Subsystems changed since `idle` last reported them
+    changed: BTreeSet<&'static str>,
+    /// Subsystems an `idle` in progress waits for (empty for all)
+    idle: Option<Vec<String>>,
+    /// Commands of a command list being received, and whether each is
+    /// acknowledged with `list_OK`
+    list: Option<(Vec<Vec<String>>, bool)>,
+    /// Version of the queue, increased whenever it changes
+    playlist_version: u32,
+}
+
+/// Serves one client until it hangs up.
+///
+/// Lines from the client and events from the application arrive on one
+/// channel, so that `idle` can be answered by either.
+fn serve(stream: TcpStream, music_dir: Arc<PathBuf>, tx: Sender<Msg>) -> Result<(), RemoteError> {
+    let (input_tx, input_rx) = mpsc::channel();
+    let reader = stream.try_clone()?;
+    let lines_tx = input_tx.clone();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(line)) = read_line(&mut reader, MAX_LINE) {
+            if lines_tx.send(Input::Line(line)).is_err() {
+                return;
+            }
+        }
+        lines_tx.send(Input::Closed).ok();
+    });
+    let (events_tx, events_rx) = mpsc::channel();
+    tx.send(Msg::Remote(Request::Subscribe, events_tx)).map_err(|_| RemoteError::Closed)?;
+    thread::spawn(move || {
+        for reply in events_rx {
+            if let Reply::Event(event) = reply {
+                if input_tx.send(Input::Event(event)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut connection = Connection {
+        writer: stream,
+        tx,
+        music_dir,
+        changed: BTreeSet::new(),
+        idle: None,
+        list: None,
+        playlist_version: 1,
+    };
+    connection.writer.write_all(GREETING.as_bytes())?;
+    for input in input_rx {
+        match input {
+            Input::Line(line) => {
+                if !connection.handle_line(&line)? {
+                    break;
+                }
+            }
+            Input::Event(event) => connection.record(event)?,
+            Input::Closed => break,
+        }
+    }
+    Ok(())
+}
+
+impl Connection {
+    /// Handles a line from the client.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` when the connection should be closed.
+    fn handle_line(&mut self, line: &str) -> io::Result<bool> {
+        let args = match tokenize(line) {
+            Ok(args) => args,
+            Err(message) => {
+                self.writer.write_all(Ack::new(ACK_ERROR_ARG, message).response(0, "").as_bytes())?;
+                return Ok(true);
+            }
+        };
+        let Some(name) = args.first().map(String::as_str) else { return Ok(true) };
+
+        // While idle, only `noidle` is allowed
+        if self.idle.is_some() {
+            if name != "noidle" {
+                return Ok(false);
+            }
+            self.idle = Some(Vec::new());
+            let response = self.take_changes().unwrap_or_default();
+            self.idle = None;
+            self.writer.write_all(format!("{}OK\n", response).as_bytes())?;
+            return Ok(true);
+        }
+
+        if let Some((commands, _)) = &mut self.list {
+            if name != "command_list_end" {
+                commands.push(args);
+                return Ok(true);
+            }
+            let (commands, list_ok) = self.list.take().unwrap_or_default();
+            let mut response = String::new();
+            for (index, args) in commands.iter().enumerate() {
+                match self.execute(args) {
+                    Ok(output) => {
+                        response.push_str(&output);
+                        if list_ok {
+                            response.push_str("list_OK\n");
+                        }
+                    }
+                    Err(ack) => {
+                        response.push_str(&ack.response(index, &args[0]));
+                        self.writer.write_all(response.as_bytes())?;
+                        return Ok(true);
+                    }
+                }
+            }
+            self.writer.write_all(format!("{}OK\n", response).as_bytes())?;
+            return Ok(true);
+        }
+
+        match name {
+            "close" => return Ok(false),
+            // Sent when an `idle` was answered meanwhile: not answered itself
+            "noidle" => {}
+            "command_list_begin" | "command_list_ok_begin" => {
+                self.list = Some((Vec::new(), name == "command_list_ok_begin"));
+            }
+            "idle" => {
+                self.idle = Some(args[1..].to_vec());
+                if let Some(response) = self.take_changes() {
+                    self.idle = None;
+                    self.writer.write_all(format!("{}OK\n", response).as_bytes())?;
+                }
+            }
+            _ => {
+                let response = match self.execute(&args) {
+                    Ok(output) => format!("{}OK\n", output),
+                    Err(ack) => ack.response(0, name),
+                };
+                self.writer.write_all(response.as_bytes())?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Notes the subsystem an event changed, answering an `idle` waiting
+    /// for it.
+    fn record(&mut self, event: Event) -> io::Result<()> {
+        let subsystem = match event {
+            Event::Progress(_) => return Ok(()),
+            Event::Volume(_) => "mixer",
+            Event::Queue => {
+                self.playlist_version += 1;
+                "playlist"
+            }
+            Event::Options => "options",
+            Event::TrackStarted(_) | Event::TrackEnded | Event::Error(_) | Event::State(_) => "player",
+        };
+        self.changed.insert(subsystem);
+        if self.idle.is_some() {
+            if let Some(response) = self.take_changes() {
+                self.idle = None;
+                self.writer.write_all(format!("{}OK\n", response).as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the changed subsystems the `idle` in progress waits for.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `changed:` lines, or `None` if nothing it waits for changed.
+    fn take_changes(&mut self) -> Option<String> {
+        let wanted = self.idle.as_ref()?;
+        let changed: Vec<&'static str> = self
+            .changed
+            .iter()
+            .filter(|s| wanted.is_empty() || wanted.iter().any(|w| w == *s))
+            .copied()
+            .collect();
+        if changed.is_empty() {
+            return None;
+        }
+        changed.iter().for_each(|s| {
+            self.changed.remove(s);
+        });
+        Some(changed.iter().map(|s| format!("changed: {}\n", s)).collect())
+    }
+
+    /// Runs a command outside of `idle` and command-list handling.
+    ///
+    /// # Returns
+    ///
+    /// Returns the response lines before the final `OK`.
+    fn execute(&mut self, args: &[String]) -> Result<String, Ack> {
+        let (name, args) = (args[0].as_str(), &args[1..]);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match (name, args.as_slice()) {
+            ("ping", []) => Ok(String::new()),
+            ("commands", []) => Ok(COMMANDS.iter().map(|c| format!("command: {}\n", c)).collect()),
+            ("notcommands", []) => Ok(String::new()),
+            ("status", []) => {
+                let status = self.status()?;
+                Ok(self.format_status(&status))
+            }
+            ("currentsong", []) => {
+                let status = self.status()?;
+                Ok(match status.track {
+                    Some(track) => {
+                        let track = TrackInfo { duration: status.duration.or(track.duration), ..track };
+                        self.song(&track, status.queue_position)
+                    }
+                    None => String::new(),
+                })
+            }
+            ("play", []) => self.act(Request::Play(None)),
+            ("play" | "playid", [position]) => self.act(Request::Play(Some(number(position)?))),
+            ("pause", []) => self.act(Request::Toggle),
+            ("pause", [pause]) => self.act(if flag(pause)? { Request::Pause } else { Request::Play(None) }),
+            ("next", []) => self.act(Request::Next),
+            ("previous", []) => self.act(Request::Prev),
+            ("seek" | "seekid", [position, time]) => {
+                let position = number(position)?;
+                let time = seconds(time).ok_or_else(|| Ack::new(ACK_ERROR_ARG, "invalid time"))?;
+                if self.status()?.queue_position != Some(position) {
+                    self.act(Request::Play(Some(position)))?;
+                }
+                self.act(Request::Seek(Amount::To(time)))
+            }
+            ("seekcur", [time]) => {
+                let amount = command::parse_amount(time, seconds).ok_or_else(|| Ack::new(ACK_ERROR_ARG, "invalid time"))?;
+                self.act(Request::Seek(amount))
+            }
+            ("setvol", [volume]) => {
+                let volume = number(volume)?;
+                if volume > 100 {
+                    return Err(Ack::new(ACK_ERROR_ARG, "volume must be between 0 and 100"));
+                }
+                self.act(Request::Volume(Amount::To(volume as u32)))
+            }
+            ("random", [shuffle]) => self.act(Request::Shuffle(Some(flag(shuffle)?))),
+            ("repeat" | "single", [on]) => {
+                let on = flag(on)?;
+                let current = self.status()?.repeat;
+                let repeat = match (name, on) {
+                    ("repeat", true) if current == RepeatMode::One => RepeatMode::One,
+                    ("repeat", true) => RepeatMode::All,
+                    ("repeat", false) => RepeatMode::Off,
+                    (_, true) => RepeatMode::One,
+                    (_, false) if current == RepeatMode::One => RepeatMode::All,
+                    (_, false) => current,
+                };
+                self.act(Request::Repeat(Some(repeat)))
+            }
+            ("playlistinfo", range) if range.len() <= 1 => {
+                let tracks = self.tracks(Request::Queue)?;
+                let range = match range.first() {
+                    Some(range) => parse_range(range, tracks.len())?,
+                    None => 0..tracks.len(),
+                };
+                Ok(range.map(|i| self.song(&tracks[i], Some(i))).collect())
+            }
+            ("add", [uri]) => self.act(Request::Enqueue(vec![self.path(uri)?])),
+            ("clear", []) => self.act(Request::ClearQueue),
+            ("lsinfo", uri) if uri.len() <= 1 => self.lsinfo(uri.first().copied().unwrap_or("")),
+            ("search", terms) if !terms.is_empty() && terms.len() % 2 == 0 => {
+                let query = search_query(terms)?;
+                let tracks = self.tracks(Request::Search(query))?;
+                Ok(tracks.iter().map(|t| self.song(t, None)).collect())
+            }
+            _ if COMMANDS.contains(&name) => Err(Ack::new(ACK_ERROR_ARG, "wrong number of arguments")),
+            _ => Err(Ack::new(ACK_ERROR_UNKNOWN, format!("unknown command \"{}\"", name))),
+        }
+    }
+
+    /// Sends a request to the application and waits for the reply.
+    fn request(&self, request: Request) -> Result<Reply, Ack> {
+        let closed = || Ack::new(ACK_ERROR_SYSTEM, "the player has quit");
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.tx.send(Msg::Remote(request, reply_tx)).map_err(|_| closed())?;
+        match reply_rx.recv().map_err(|_| closed())? {
+            Reply::Error(message) => Err(Ack::new(ACK_ERROR_NO_EXIST, message)),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Sends a request that only acts.
+    fn act(&self, request: Request) -> Result<String, Ack> {
+        self.request(request).map(|_| String::new())
+    }
+
+    /// Asks for the playback state.
+    fn status(&self) -> Result<Status, Ack> {
+        match self.request(Request::Status)? {
+            Reply::Status(status) => Ok(status),
+            _ => Err(Ack::new(ACK_ERROR_SYSTEM, "unexpected reply")),
+        }
+    }
+
+    /// Asks for a list of tracks.
+    fn tracks(&self, request: Request) -> Result<Vec<TrackInfo>, Ack> {
+        match self.request(request)? {
+            Reply::Tracks(tracks) => Ok(tracks),
+            _ => Err(Ack::new(ACK_ERROR_SYSTEM, "unexpected reply")),
+        }
+    }
+
+    /// Lists the directories and tracks directly inside a directory of the
+    /// library.
+    fn lsinfo(&self, uri: &str) -> Result<String, Ack> {
+        let dir = self.path(uri)?;
+        let mut dirs = BTreeSet::new();
+        let mut files = String::new();
+        for track in self.tracks(Request::Search(String::new()))? {
+            let Ok(rest) = track.path.strip_prefix(&dir) else { continue };
+            let mut components = rest.components();
+            match (components.next(), components.next()) {
+                (Some(first), Some(_)) => {
+                    dirs.insert(self.uri(&dir.join(first)));
+                }
+                _ => files.push_str(&self.song(&track, None)),
+            }
+        }
+        if dirs.is_empty() && files.is_empty() && !uri.is_empty() {
+            return Err(Ack::new(ACK_ERROR_NO_EXIST, "No such directory"));
+        }
+        Ok(dirs.iter().map(|d| format!("directory: {}\n", d)).collect::<String>() + &files)
+    }
+
+    /// Formats the response to `status`.
+    fn format_status(&self, status: &Status) -> String {
+        let mut lines = vec![
+            format!("volume: {}", status.volume.min(100)),
+            format!("repeat: {}", u8::from(status.repeat != RepeatMode::Off)),
+            format!("random: {}", u8::from(status.shuffle)),
+            format!("single: {}", u8::from(status.repeat == RepeatMode::One)),
+            "consume: 0".to_string(),
+            format!("playlist: {}", self.playlist_version),
+            format!("playlistlength: {}", status.queue_length),
+            format!("state: {}", json::state_name(status.state)),
+        ];
+        if let Some(position) = status.queue_position {
+            lines.push(format!("song: {}", position));
+            lines.push(format!("songid: {}", position));
+        }
+        if status.state != PlaybackStatus::Stopped {
+            let duration = status.duration.or(status.track.as_ref().and_then(|t| t.duration)).unwrap_or_default();
+            lines.push(format!("time: {}:{}", status.position.as_secs(), duration.as_secs()));
+            lines.push(format!("elapsed: {:.3}", status.position.as_secs_f64()));
+            lines.push(format!("duration: {:.3}", duration.as_secs_f64()));
+        }
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Formats a track, with its queue position and id when queued.
+    fn song(&self, track: &TrackInfo, position: Option<usize>) -> String {
+        let mut lines = vec![format!("file: {}", self.uri(&track.path))];
+        let tags = [("Title", &track.title), ("Artist", &track.artist), ("Album", &track.album)];
+        lines.extend(tags.iter().filter_map(|(tag, value)| value.as_ref().map(|v| format!("{}: {}", tag, v))));
+        if let Some(duration) = track.duration {
+            lines.push(format!("Time: {}", duration.as_secs()));
+            lines.push(format!("duration: {:.3}", duration.as_secs_f64()));
+        }
+        if let Some(position) = position {
+            lines.push(format!("Pos: {}", position));
+            lines.push(format!("Id: {}", position));
+        }
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Returns the URI of a library path.
+    fn uri(&self, path: &Path) -> String {
+        path.strip_prefix(self.music_dir.as_path()).unwrap_or(path).to_string_lossy().into_owned()
+    }
+
+    /// Returns the library path of a URI, which must stay inside the music
+    /// directory.
+    fn path(&self, uri: &str) -> Result<PathBuf, Ack> {
+        let uri = Path::new(uri.trim_matches('/'));
+        if !uri.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(Ack::new(ACK_ERROR_ARG, "malformed URI"));
+        }
+        Ok(self.music_dir.join(uri))
+    }
+}
+
+/// Splits a command line into its name and arguments; arguments may be
+/// quoted, with `\` escaping the next character.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut arg = String::new();
+        if c == '"' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => arg.push(chars.next().ok_or("unterminated string")?),
+                    Some(c) => arg.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                arg.push(c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+    Ok(args)
+}
+
+/// Parses a non-negative integer argument.
+fn number(arg: &str) -> Result<usize, Ack> {
+    arg.parse().map_err(|_| Ack::new(ACK_ERROR_ARG, format!("expected a number: {}", arg)))
+}
+
+/// Parses a `0`/`1` argument.
+fn flag(arg: &str) -> Result<bool, Ack> {
+    match arg {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(Ack::new(ACK_ERROR_ARG, format!("expected 0 or 1: {}", arg))),
+    }
+}
+
+/// Parses a time in (possibly fractional) seconds.
+fn seconds(arg: &str) -> Option<Duration> {
+    arg.parse::<f64>().ok().and_then(|s| Duration::try_from_secs_f64(s).ok())
+}
+
+/// Parses a queue position `N` or range `START:END` (END may be omitted).
+fn parse_range(arg: &str, len: usize) -> Result<std::ops::Range<usize>, Ack> {
+    let range = match arg.split_once(':') {
+        Some((start, "")) => number(start)?..len,
+        Some((start, end)) => number(start)?..number(end)?.min(len),
+        None => {
+            let start = number(arg)?;
+            start..start.checked_add(1).ok_or_else(|| Ack::new(ACK_ERROR_ARG, "Bad song index"))?
+        }
+    };
+    if range.start >= len && !(range.is_empty() && range.start == len) {
+        return Err(Ack::new(ACK_ERROR_ARG, "Bad song index"));
+    }
+    Ok(range)
+}
+
+/// Turns `search` tag/value pairs into a query (see [`crate::query`]):
+/// every value must match its tag as a case-insensitive substring.
+fn search_query(terms: &[&str]) -> Result<String, Ack> {
+    terms
+        .chunks(2)
+        .map(|pair| {
+            let field = match pair[0].to_lowercase().as_str() {
+                "any" => "",
+                "file" | "base" => "path:",
+                "artist" | "albumartist" => "artist:",
+                "album" => "album:",
+                "title" => "title:",
+                "genre" => "genre:",
+                tag => return Err(Ack::new(ACK_ERROR_ARG, format!("unknown tag type \"{}\"", tag))),
+            };
+            // Query values cannot contain quotes
+            Ok(format!("{}\"{}\"", field, pair[1].replace('"', "")))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|terms| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+
+    use super::*;
+    use crate::app::{update, App, Effect};
+    use crate::common::{AppEvent, Track};
+    use crate::harness::TempDir;
+
+    #[test]
+    fn test_tokenize_and_arguments() {
+        assert_eq!(tokenize(r#"search artist "Miles \"Dewey\" Davis"  title x"#).unwrap(), [
+            "search",
+            "artist",
+            "Miles \"Dewey\" Davis",
+            "title",
+            "x"
+        ]);
+        assert!(tokenize(r#"add "unterminated"#).is_err());
+        assert!(tokenize("   ").unwrap().is_empty());
+
+        assert_eq!(parse_range("1", 3).unwrap(), 1..2);
+        assert_eq!(parse_range("1:", 3).unwrap(), 1..3);
+        assert_eq!(parse_range("0:9", 3).unwrap(), 0..3);
+        assert!(parse_range("3", 3).is_err());
+        assert_eq!(search_query(&["Artist", "davis", "any", "so \"what\""]).unwrap(), r#"artist:"davis" "so what""#);
+        assert!(search_query(&["mood", "blue"]).is_err());
+
+        let roots = [PathBuf::from("/music/jazz"), PathBuf::from("/music/rock/70s"), PathBuf::from("/music/rock")];
+        assert_eq!(music_dir(&roots), PathBuf::from("/music"));
+        assert_eq!(music_dir(&roots[1..]), PathBuf::from("/music/rock"));
+    }
+
+    /// A client connection reading whole responses.
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(server: &MpdServer) -> Self {
+            let writer = TcpStream::connect(server.local_addr()).unwrap();
+            writer.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut client = Self { reader: BufReader::new(writer.try_clone().unwrap()), writer };
+            assert_eq!(client.read(), GREETING);
+            client
+        }
+
+        /// Reads up to and including the line ending a response.
+        fn read(&mut self) -> String {
+            let mut response = String::new();
+            loop {
+                let mut line = String::new();
+                assert!(self.reader.read_line(&mut line).unwrap() > 0, "connection closed after {:?}", response);
+                response.push_str(&line);
+                if line.starts_with("OK") || line.starts_with("ACK") {
+                    return response;
+                }
+            }
+        }
+
+        fn send(&mut self, line: &str) {
+            writeln!(self.writer, "{}", line).unwrap();
+        }
+
+        fn command(&mut self, line: &str) -> String {
+            self.send(line);
+            self.read()
+        }
+    }
+
+    /// Runs an application over four tracks in two directories, passing
+    /// every message through [`update`].
+    ///
+    /// The application thread outlives the test, so the returned directory
+    /// holding its data is the test's to drop.
+    fn start() -> (MpdServer, Sender<Msg>, TempDir) {
+        let dir = TempDir::new("mpd");
+        let data_dir = dir.path().to_path_buf();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let tracks = (0..4)
+                .map(|i| Track {
+                    id: i,
+                    path: PathBuf::from(format!("/music/{}/track{}.mp3", if i < 2 { "jazz" } else { "rock" }, i)),
+                    title: Some(format!("Track {}", i)),
+                    artist: Some(if i < 2 { "Miles Davis" } else { "Can" }.to_string()),
+                    duration: Some(Duration::from_secs(100)),
+                    ..Default::default()
+                })
+                .collect();
+            let mut app = App::new(tracks).with_data_dir(&data_dir);
+            for msg in rx {
+                // Answers and events go out as in the event loop
+                for effect in update(&mut app, msg) {
+                    match effect {
+                        Effect::Reply(reply, answer) => {
+                            reply.send(answer).ok();
+                        }
+                        Effect::Publish(event) => app.send_event(&event),
+                        _ => {}
+                    }
+                }
+            }
+        });
+        (MpdServer::start(0, PathBuf::from("/music"), tx.clone()).unwrap(), tx, dir)
+    }
+
+    #[test]
+    fn test_commands() {
+        let (server, tx, _dir) = start();
+        let mut client = Client::connect(&server);
+
+        let status = client.command("status");
+        assert!(status.contains("state: stop\n") && status.contains("playlistlength: 0\n"), "{}", status);
+        assert!(status.ends_with("OK\n"));
+        assert_eq!(client.command("lsinfo"), "directory: jazz\ndirectory: rock\nOK\n");
+        assert!(client.command("lsinfo jazz").starts_with("file: jazz/track0.mp3\nTitle: Track 0\nArtist: Miles Davis\n"));
+        assert_eq!(client.command("lsinfo blues"), "ACK [50@0] {lsinfo} No such directory\n");
+        assert_eq!(client.command("lsinfo ../etc"), "ACK [2@0] {lsinfo} malformed URI\n");
+
+        let found = client.command("search artist \"miles\" title 1");
+        assert!(found.starts_with("file: jazz/track1.mp3\n") && found.matches("file:").count() == 1, "{}", found);
+
+        assert_eq!(client.command("add rock"), "OK\n");
+        assert_eq!(client.command("add jazz/track0.mp3"), "OK\n");
+        assert_eq!(client.command("add pop"), "ACK [50@0] {add} 不在音乐库中: /music/pop\n");
+        let queue = client.command("playlistinfo");
+        assert_eq!(queue.matches("file:").count(), 3);
+        assert!(queue.contains("file: jazz/track0.mp3\nTitle: Track 0\nArtist: Miles Davis\nTime: 100\nduration: 100.000\nPos: 2\nId: 2\n"));
+        assert!(client.command("playlistinfo 1").starts_with("file: rock/track3.mp3\n"));
+
+        assert_eq!(client.command("play 1"), "OK\n");
+        tx.send(Msg::Player(AppEvent::TrackStarted { index: 3, duration: Some(Duration::from_secs(100)) })).unwrap();
+        tx.send(Msg::Player(AppEvent::Progress { position: Duration::from_secs(12) })).unwrap();
+        let song = client.command("currentsong");
+        assert!(song.starts_with("file: rock/track3.mp3\n") && song.contains("Pos: 1\n"), "{}", song);
+        let status = client.command("status");
+        assert!(status.contains("state: play\nsong: 1\nsongid: 1\ntime: 12:100\nelapsed: 12.000\n"), "{}", status);
+
+        assert_eq!(client.command("setvol 40"), "OK\n");
+        assert_eq!(client.command("single 1"), "OK\n");
+        assert_eq!(client.command("pause 1"), "OK\n");
+        let status = client.command("status");
+        assert!(status.starts_with("volume: 40\nrepeat: 1\nrandom: 0\nsingle: 1\n") && status.contains("state: pause"), "{}", status);
+
+        assert_eq!(client.command("setvol 101"), "ACK [2@0] {setvol} volume must be between 0 and 100\n");
+        assert_eq!(client.command("play 9"), "ACK [50@0] {play} 队列中没有第 9 首\n");
+        assert_eq!(client.command("seekcur 1e300"), "ACK [2@0] {seekcur} invalid time\n");
+        assert_eq!(client.command("playlistinfo 18446744073709551615"), "ACK [2@0] {playlistinfo} Bad song index\n");
+        assert_eq!(client.command("next 2"), "ACK [2@0] {next} wrong number of arguments\n");
+        assert_eq!(client.command("outputs"), "ACK [5@0] {outputs} unknown command \"outputs\"\n");
+
+        // Command lists stop at the first error
+        client.send("command_list_ok_begin\nping\nclear\nplaylistinfo\ncommand_list_end");
+        assert_eq!(client.read(), "list_OK\nlist_OK\nlist_OK\nOK\n");
+        client.send("command_list_begin\nping\nfly\nclear\ncommand_list_end");
+        assert_eq!(client.read(), "ACK [5@1] {fly} unknown command \"fly\"\n");
+
+        // An overlong line closes the connection
+        client.writer.write_all(&vec![b'x'; MAX_LINE + 1]).unwrap();
+        assert_eq!(client.reader.read_line(&mut String::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_idle() {
+        let (server, _tx, _dir) = start();
+        let mut idler = Client::connect(&server);
+        let mut client = Client::connect(&server);
+        // Both connections have subscribed once they answer
+        idler.command("ping");
+
+        idler.send("idle playlist mixer");
+        assert_eq!(client.command("random 1"), "OK\n");
+        assert_eq!(client.command("add jazz"), "OK\n");
+        assert_eq!(idler.read(), "changed: playlist\nOK\n");
+
+        // Changes while not idle are reported by the next idle
+        assert_eq!(client.command("setvol 70"), "OK\n");
+        assert_eq!(idler.command("idle"), "changed: mixer\nchanged: options\nOK\n");
+        assert!(idler.command("status").contains("playlist: 2\n"));
+
+        idler.send("idle");
+        assert_eq!(idler.command("noidle"), "OK\n");
+        // A late `noidle` is ignored, keeping responses in step
+        idler.send("noidle");
+        assert_eq!(idler.command("ping"), "OK\n");
+    }
+}
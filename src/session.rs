@@ -0,0 +1,271 @@
+//! Session state saved on quit and restored on startup.
+//!
+//! The session captures where listening left off: the current track and
+//! position, volume, the queue with its shuffle and repeat settings, and the
+//! view and sort order of the track list. It is stored as `session.state` in
+//! the data directory, one `key=value` pair per line:
+//!
+//! ```text
+//! volume=0.85
+//! track=/music/so-what.flac
+//! position=123.5
+//! repeat=all
+//! queue=/music/so-what.flac
+//! queue=/music/freddie-freeloader.flac
+//! ```
+//!
+//! Tracks are stored by path so the session survives changes to the library.
+//! Unknown keys are ignored, so older players can read newer files, and so
+//! are invalid values, which keep their defaults rather than losing the
+//! rest of the session.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::library::{escape, unescape};
+use crate::queue::RepeatMode;
+
+/// File name of the session state
+const SESSION_FILE: &str = "session.state";
+
+/// Errors that can occur while loading or saving the session.
+#[derive(Debug, Error)]
+pub enum SessionError {
+    /// Reading or writing the session file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// A line has an invalid value
+    #[error("{SESSION_FILE}:{line}: invalid value for '{key}'")]
+    Parse {
+        /// 1-based line number
+        line: usize,
+        /// Key of the offending line
+        key: String,
+    },
+}
+
+/// Saved player session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    /// Path of the track that was playing
+    pub track: Option<PathBuf>,
+    /// Playback position within that track
+    pub position: Duration,
+    /// Volume level (0.0 to 2.0)
+    pub volume: f32,
+    /// Queued track paths in playback order
+    pub queue: Vec<PathBuf>,
+    /// Position of the current track within the queue
+    pub queue_position: Option<usize>,
+    /// Name of the playlist the queue was loaded from
+    pub queue_source: Option<String>,
+    /// Whether shuffle was on
+    pub shuffle: bool,
+    /// Repeat mode
+    pub repeat: RepeatMode,
+    /// Track list view (`library`, `recent`, `most-played`, `playlist:<name>`
+    /// or `smart:<name>`)
+    pub view: String,
+    /// Path of the selected track in the track list
+    pub selected: Option<PathBuf>,
+    /// Sort column of the track list (empty for the view order)
+    pub sort: String,
+    /// Whether the sort order was reversed
+    pub sort_reversed: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            track: None,
+            position: Duration::ZERO,
+            volume: 1.0,
+            queue: Vec::new(),
+            queue_position: None,
+            queue_source: None,
+            shuffle: false,
+            repeat: RepeatMode::Off,
+            view: "library".to_string(),
+            selected: None,
+            sort: String::new(),
+            sort_reversed: false,
+        }
+    }
+}
+
+impl Session {
+    /// Loads the session saved in `dir`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the session and a [`SessionError::Parse`] for each line whose
+    /// value was ignored, or `Ok(None)` if no session has been saved yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read.
+    pub fn load(dir: &Path) -> Result<Option<(Self, Vec<SessionError>)>, SessionError> {
+        match fs::read_to_string(dir.join(SESSION_FILE)) {
+            Ok(content) => Ok(Some(Self::parse(&content))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves the session to `dir`, replacing the previous file atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, dir: &Path) -> Result<(), SessionError> {
+        fs::create_dir_all(dir)?;
+        let tmp = dir.join(format!("{}.tmp", SESSION_FILE));
+        fs::write(&tmp, self.serialize())?;
+        fs::rename(tmp, dir.join(SESSION_FILE))?;
+        Ok(())
+    }
+
+    /// Parses the contents of a session file. Lines with an invalid value
+    /// are ignored, leaving the default in place.
+    ///
+    /// # Returns
+    ///
+    /// Returns the session and a [`SessionError::Parse`] for each ignored
+    /// line.
+    pub fn parse(content: &str) -> (Self, Vec<SessionError>) {
+        let mut session = Self::default();
+        let mut skipped = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, raw)) = line.split_once('=') else { continue };
+            if session.set(key, unescape(raw)).is_none() {
+                skipped.push(SessionError::Parse { line: i + 1, key: key.to_string() });
+            }
+        }
+        (session, skipped)
+    }
+
+    /// Sets the field a key of the file names; unknown keys are ignored.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the value is invalid for the key.
+    fn set(&mut self, key: &str, value: String) -> Option<()> {
+        match key {
+            "track" => self.track = Some(PathBuf::from(value)),
+            "position" => self.position = Duration::try_from_secs_f64(value.parse().ok()?).ok()?,
+            "volume" => {
+                let volume: f32 = value.parse().ok()?;
+                self.volume = if volume.is_finite() { volume.clamp(0.0, 2.0) } else { 1.0 };
+            }
+            "queue" => self.queue.push(PathBuf::from(value)),
+            "queue_position" => self.queue_position = Some(value.parse().ok()?),
+            "queue_source" => self.queue_source = Some(value),
+            "shuffle" => self.shuffle = value.parse().ok()?,
+            "repeat" => self.repeat = value.parse().ok()?,
+            "view" => self.view = value,
+            "selected" => self.selected = Some(PathBuf::from(value)),
+            "sort" => self.sort = value,
+            "sort_reversed" => self.sort_reversed = value.parse().ok()?,
+            _ => {}
+        }
+        Some(())
+    }
+
+    /// Serializes the session in the file format.
+    pub fn serialize(&self) -> String {
+        let mut out = String::from("# tools-rs session\n");
+        let mut put = |key: &str, value: &str| {
+            out.push_str(&format!("{}={}\n", key, escape(value)));
+        };
+        put("volume", &self.volume.to_string());
+        if let Some(track) = &self.track {
+            put("track", &track.to_string_lossy());
+        }
+        put("position", &self.position.as_secs_f64().to_string());
+        put("shuffle", &self.shuffle.to_string());
+        put("repeat", &self.repeat.to_string());
+        put("view", &self.view);
+        if let Some(selected) = &self.selected {
+            put("selected", &selected.to_string_lossy());
+        }
+        put("sort", &self.sort);
+        put("sort_reversed", &self.sort_reversed.to_string());
+        if let Some(source) = &self.queue_source {
+            put("queue_source", source);
+        }
+        if let Some(position) = self.queue_position {
+            put("queue_position", &position.to_string());
+        }
+        for path in &self.queue {
+            put("queue", &path.to_string_lossy());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+
+    fn sample() -> Session {
+        Session {
+            track: Some(PathBuf::from("/music/so what=live.flac")),
+            position: Duration::from_millis(123_500),
+            volume: 0.85,
+            queue: vec![PathBuf::from("/music/a.mp3"), PathBuf::from("/music/b\nc.mp3")],
+            queue_position: Some(1),
+            queue_source: Some("road trip".to_string()),
+            shuffle: true,
+            repeat: RepeatMode::All,
+            view: "playlist:road trip".to_string(),
+            selected: Some(PathBuf::from("/music/a.mp3")),
+            sort: "plays".to_string(),
+            sort_reversed: true,
+        }
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let session = sample();
+        let (parsed, skipped) = Session::parse(&session.serialize());
+        assert_eq!(parsed, session);
+        assert!(skipped.is_empty());
+        assert_eq!(Session::parse("").0, Session::default());
+    }
+
+    #[test]
+    fn test_parse_skips_invalid_lines() {
+        let (session, skipped) = Session::parse("volume=0.5\nrepeat=sometimes\nposition=-1\ntrack=/music/a.mp3\n");
+        assert_eq!(session.volume, 0.5);
+        assert_eq!(session.repeat, RepeatMode::Off);
+        assert_eq!(session.position, Duration::ZERO);
+        assert_eq!(session.track, Some(PathBuf::from("/music/a.mp3")), "the rest of the session survives");
+        let skipped: Vec<_> = skipped.iter().map(ToString::to_string).collect();
+        assert_eq!(skipped, [
+            "session.state:2: invalid value for 'repeat'",
+            "session.state:3: invalid value for 'position'",
+        ]);
+        let (session, skipped) = Session::parse("volume=9\nfuture=1\n");
+        assert_eq!(session.volume, 2.0);
+        assert!(skipped.is_empty(), "unknown keys are not errors");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = TempDir::new("session");
+        assert!(Session::load(dir.path()).unwrap().is_none());
+
+        sample().save(dir.path()).unwrap();
+        let (session, skipped) = Session::load(dir.path()).unwrap().unwrap();
+        assert_eq!(session, sample());
+        assert!(skipped.is_empty());
+    }
+}
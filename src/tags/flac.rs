@@ -0,0 +1,274 @@
+//! FLAC metadata reading and rating writing.
+//!
+//! A FLAC file starts with the `fLaC` marker followed by metadata blocks. The
+//! `STREAMINFO` block provides the duration and the `VORBIS_COMMENT` block the
+//! descriptive tags, including the rating.
+
+use std::io::{self, Read};
+use std::time::Duration;
+
+use super::{TagError, TagInfo};
+
+/// Metadata block type of `STREAMINFO`
+pub(crate) const BLOCK_STREAMINFO: u8 = 0;
+/// Metadata block type of `VORBIS_COMMENT`
+pub(crate) const BLOCK_VORBIS_COMMENT: u8 = 4;
+
+/// Reads the metadata blocks at the start of a FLAC stream.
+///
+/// # Errors
+///
+/// Returns an error only on I/O failure; a missing marker yields an empty
+/// [`TagInfo`].
+pub fn read(reader: &mut impl Read) -> io::Result<TagInfo> {
+    let mut info = TagInfo::default();
+    let mut marker = [0u8; 4];
+    if reader.read_exact(&mut marker).is_err() || &marker != b"fLaC" {
+        return Ok(info);
+    }
+
+    loop {
+        let mut header = [0u8; 4];
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        // Grows with what is actually read, whatever length the header claims
+        let mut block = Vec::new();
+        reader.take(len as u64).read_to_end(&mut block)?;
+
+        match block_type {
+            BLOCK_STREAMINFO => info.duration = stream_duration(&block),
+            BLOCK_VORBIS_COMMENT => {
+                for entry in parse_vorbis_comments(&block).unwrap_or_default().1 {
+                    if let Some((key, value)) = split_comment(&entry) {
+                        info.set_vorbis_comment(&key, &value);
+                    }
+                }
+            }
+            _ => {}
+        }
+        if is_last {
+            break;
+        }
+    }
+    Ok(info)
+}
+
+/// Computes the duration from a `STREAMINFO` block.
+fn stream_duration(block: &[u8]) -> Option<Duration> {
+    if block.len() < 18 {
+        return None;
+    }
+    // Bytes 10..18: 20-bit sample rate, 3-bit channels, 5-bit bits per sample,
+    // 36-bit total samples
+    let packed = u64::from_be_bytes(block[10..18].try_into().ok()?);
+    let sample_rate = packed >> 44;
+    let total_samples = packed & 0xF_FFFF_FFFF;
+    if sample_rate == 0 || total_samples == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(total_samples as f64 / sample_rate as f64))
+}
+
+/// Parses a Vorbis comment block into its vendor string and `KEY=value`
+/// entries.
+///
+/// Both are kept as raw bytes, so that writing the block back changes
+/// nothing but the entries that were meant to change, even if they are not
+/// valid UTF-8 or lack the `=`.
+pub(crate) fn parse_vorbis_comments(block: &[u8]) -> Option<(Vec<u8>, Vec<Vec<u8>>)> {
+    let mut pos = 0;
+    let next_u32 = |pos: &mut usize| -> Option<usize> {
+        let bytes = block.get(*pos..*pos + 4)?;
+        *pos += 4;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+    };
+
+    let vendor_len = next_u32(&mut pos)?;
+    let vendor = block.get(pos..pos.checked_add(vendor_len)?)?.to_vec();
+    pos += vendor_len;
+    let count = next_u32(&mut pos)?;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let len = next_u32(&mut pos)?;
+        entries.push(block.get(pos..pos.checked_add(len)?)?.to_vec());
+        pos += len;
+    }
+    Some((vendor, entries))
+}
+
+/// Splits a comment entry into its key and value, decoding them leniently.
+///
+/// Returns `None` for entries without a `=`.
+fn split_comment(entry: &[u8]) -> Option<(String, String)> {
+    let at = entry.iter().position(|&b| b == b'=')?;
+    let (key, value) = (&entry[..at], &entry[at + 1..]);
+    Some((String::from_utf8_lossy(key).into_owned(), String::from_utf8_lossy(value).into_owned()))
+}
+
+/// Returns whether a comment entry holds a rating (`FMPS_RATING` or `RATING`).
+fn is_rating_comment(entry: &[u8]) -> bool {
+    let key = entry.split(|&b| b == b'=').next().unwrap_or_default();
+    key.eq_ignore_ascii_case(b"FMPS_RATING") || key.eq_ignore_ascii_case(b"RATING")
+}
+
+/// Serializes a Vorbis comment block (the inverse of [`parse_vorbis_comments`]).
+pub(crate) fn build_vorbis_comments(vendor: &[u8], entries: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    out.extend_from_slice(vendor);
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        out.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+        out.extend_from_slice(entry);
+    }
+    out
+}
+
+/// Returns the file contents with the `FMPS_RATING` and `RATING` comments
+/// replaced.
+///
+/// Existing rating comments are dropped and, unless `stars` is 0, new ones are
+/// added; every other comment is kept byte for byte. A Vorbis comment block is created after `STREAMINFO` if the file has
+/// none.
+///
+/// # Errors
+///
+/// Returns [`TagError::Unsupported`] if the data is not a FLAC stream or its
+/// metadata blocks are truncated or malformed.
+pub(crate) fn write_rating(data: &[u8], stars: u8) -> Result<Vec<u8>, TagError> {
+    let malformed = || TagError::Unsupported("malformed FLAC metadata".to_string());
+    if data.get(..4) != Some(b"fLaC") {
+        return Err(malformed());
+    }
+
+    // Collect the metadata blocks as (type, data)
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    let mut pos = 4;
+    loop {
+        let header = data.get(pos..pos + 4).ok_or_else(malformed)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let block = data.get(pos + 4..pos + 4 + len).ok_or_else(malformed)?;
+        blocks.push((header[0] & 0x7F, block.to_vec()));
+        pos += 4 + len;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+
+    let index = match blocks.iter().position(|(t, _)| *t == BLOCK_VORBIS_COMMENT) {
+        Some(index) => index,
+        None => {
+            let at = blocks.iter().position(|(t, _)| *t == BLOCK_STREAMINFO).map_or(0, |i| i + 1);
+            blocks.insert(at, (BLOCK_VORBIS_COMMENT, build_vorbis_comments(b"tools-rs", &[])));
+            at
+        }
+    };
+    let (vendor, mut entries) = parse_vorbis_comments(&blocks[index].1).ok_or_else(malformed)?;
+    entries.retain(|entry| !is_rating_comment(entry));
+    if stars > 0 {
+        entries.push(format!("FMPS_RATING={}", f64::from(stars) / 5.0).into_bytes());
+        entries.push(format!("RATING={}", u32::from(stars) * 20).into_bytes());
+    }
+    blocks[index].1 = build_vorbis_comments(&vendor, &entries);
+
+    let mut out = b"fLaC".to_vec();
+    let last = blocks.len() - 1;
+    for (i, (block_type, block)) in blocks.iter().enumerate() {
+        if block.len() >= 1 << 24 {
+            return Err(malformed());
+        }
+        out.push(if i == last { 0x80 | block_type } else { *block_type });
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&data[pos..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a minimal FLAC header with STREAMINFO and a Vorbis comment block.
+    pub(crate) fn build_flac(comments: &[(&str, &str)], sample_rate: u64, total_samples: u64) -> Vec<u8> {
+        let entries: Vec<Vec<u8>> = comments.iter().map(|(k, v)| format!("{}={}", k, v).into_bytes()).collect();
+        build_flac_raw(b"test", &entries, sample_rate, total_samples)
+    }
+
+    /// Like [`build_flac`], with the vendor string and comment entries as raw bytes.
+    fn build_flac_raw(vendor: &[u8], entries: &[Vec<u8>], sample_rate: u64, total_samples: u64) -> Vec<u8> {
+        let mut streaminfo = vec![0u8; 34];
+        let packed = (sample_rate << 44) | (1 << 41) | (15 << 36) | total_samples;
+        streaminfo[10..18].copy_from_slice(&packed.to_be_bytes());
+
+        let vorbis = build_vorbis_comments(vendor, entries);
+
+        let mut data = b"fLaC".to_vec();
+        data.push(BLOCK_STREAMINFO);
+        data.extend_from_slice(&(streaminfo.len() as u32).to_be_bytes()[1..]);
+        data.extend_from_slice(&streaminfo);
+        data.push(0x80 | BLOCK_VORBIS_COMMENT);
+        data.extend_from_slice(&(vorbis.len() as u32).to_be_bytes()[1..]);
+        data.extend_from_slice(&vorbis);
+        data.extend_from_slice(&[0xFF, 0xF8]);
+        data
+    }
+
+    #[test]
+    fn test_read_comments_and_duration() {
+        let data = build_flac(
+            &[("TITLE", "Blue in Green"), ("artist", "Miles Davis"), ("DATE", "1959"), ("GENRE", "Jazz")],
+            44100,
+            44100 * 337,
+        );
+        let info = read(&mut data.as_slice()).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("Blue in Green"));
+        assert_eq!(info.artist.as_deref(), Some("Miles Davis"));
+        assert_eq!(info.genre.as_deref(), Some("Jazz"));
+        assert_eq!(info.year, Some(1959));
+        assert_eq!(info.duration, Some(Duration::from_secs(337)));
+    }
+
+    #[test]
+    fn test_write_rating_replaces_comments() {
+        let data = build_flac(&[("TITLE", "So What"), ("rating", "20")], 44100, 44100);
+        let updated = write_rating(&data, 4).unwrap();
+        let info = read(&mut updated.as_slice()).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("So What"));
+        assert_eq!(info.rating, Some(4));
+        assert_eq!(info.duration, Some(Duration::from_secs(1)));
+        assert!(updated.ends_with(&[0xFF, 0xF8]), "audio frames must be kept");
+        assert!(matches!(write_rating(b"OggS", 1), Err(TagError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_write_rating_keeps_other_comments_byte_for_byte() {
+        let vendor = b"ven\xffdor".to_vec();
+        let entries: Vec<Vec<u8>> =
+            [&b"TITLE=caf\xe9"[..], b"no separator", b"B=x=y", b"Rating=20"].iter().map(|e| e.to_vec()).collect();
+        let data = build_flac_raw(&vendor, &entries, 44100, 44100);
+
+        let updated = write_rating(&data, 3).unwrap();
+        // The Vorbis comment block follows the marker and STREAMINFO
+        let (kept_vendor, kept) = parse_vorbis_comments(&updated[4 + 4 + 34 + 4..]).unwrap();
+        assert_eq!(kept_vendor, vendor);
+        assert_eq!(kept[..3], entries[..3]);
+        assert_eq!(kept[3..], [b"FMPS_RATING=0.6".to_vec(), b"RATING=60".to_vec()]);
+        assert_eq!(read(&mut updated.as_slice()).unwrap().title.as_deref(), Some("caf\u{fffd}"));
+    }
+
+    #[test]
+    fn test_read_does_not_trust_block_lengths() {
+        // A block claiming 16 MiB in a file of a few bytes
+        let data = [&b"fLaC"[..], &[0x84, 0xFF, 0xFF, 0xFF], b"short"].concat();
+        assert_eq!(read(&mut data.as_slice()).unwrap(), TagInfo::default());
+        assert!(matches!(write_rating(&data, 1), Err(TagError::Unsupported(_))));
+    }
+}
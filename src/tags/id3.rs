@@ -0,0 +1,363 @@
+//! ID3v2 tag reading and rating writing.
+//!
+//! Supports the text frames of ID3v2.2, v2.3 and v2.4 that the library uses
+//! (title, artist, album, genre and year) and the `POPM` (popularimeter)
+//! frame holding the rating. Tags using unsynchronisation are read as-is,
+//! which is harmless for the frames of interest.
+
+use std::io::{self, Read};
+
+use super::{parse_year, TagError, TagInfo};
+
+/// Padding added when a rewritten tag no longer fits its old size
+const PADDING: usize = 1024;
+
+/// Size of the fixed ID3v2 header in bytes
+pub(crate) const HEADER_LEN: usize = 10;
+
+/// Reads an ID3v2 tag from the start of `reader`.
+///
+/// # Errors
+///
+/// Returns an error only on I/O failure; a missing or truncated tag yields
+/// whatever fields could be decoded.
+pub fn read(reader: &mut impl Read) -> io::Result<TagInfo> {
+    let mut header = [0u8; HEADER_LEN];
+    if read_fully(reader, &mut header)? < HEADER_LEN || &header[..3] != b"ID3" {
+        return Ok(TagInfo::default());
+    }
+    let version = header[3];
+    let size = syncsafe(&header[6..10]) as usize;
+    // Grows with what is actually read, whatever size the header claims
+    let mut body = Vec::new();
+    reader.take(size as u64).read_to_end(&mut body)?;
+
+    // Skip the v2.3/v2.4 extended header when present
+    let mut offset = 0;
+    if header[5] & 0x40 != 0 && body.len() >= 4 {
+        offset = match version {
+            3 => 4 + u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize,
+            4 => syncsafe(&body[..4]) as usize,
+            _ => 0,
+        };
+    }
+
+    Ok(parse_frames(&body[offset.min(body.len())..], version))
+}
+
+/// Decodes the frames of a tag body.
+fn parse_frames(mut body: &[u8], version: u8) -> TagInfo {
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut info = TagInfo::default();
+
+    while body.len() >= header_len && body[0] != 0 {
+        let id = &body[..id_len];
+        let size = match version {
+            2 => u32::from_be_bytes([0, body[3], body[4], body[5]]) as usize,
+            3 => u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize,
+            _ => syncsafe(&body[4..8]) as usize,
+        };
+        let Some(data) = body.get(header_len..header_len + size) else { break };
+
+        let slot = match id {
+            b"TIT2" | b"TT2" => Some(&mut info.title),
+            b"TPE1" | b"TP1" => Some(&mut info.artist),
+            b"TALB" | b"TAL" => Some(&mut info.album),
+            b"TCON" | b"TCO" => Some(&mut info.genre),
+            b"TYER" | b"TYE" | b"TDRC" => {
+                if info.year.is_none() {
+                    info.year = decode_text(data).as_deref().and_then(parse_year);
+                }
+                None
+            }
+            b"POPM" | b"POP" => {
+                // Email (null-terminated), rating byte, optional play counter
+                let rating = data.iter().position(|&b| b == 0).and_then(|end| data.get(end + 1));
+                if info.rating.is_none() {
+                    info.rating = rating.and_then(|&r| popm_to_stars(r));
+                }
+                None
+            }
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            if slot.is_none() {
+                *slot = decode_text(data).map(|t| clean_genre(id, t));
+            }
+        }
+        body = &body[header_len + size..];
+    }
+    info
+}
+
+/// Decodes a text frame payload (encoding byte followed by the text).
+///
+/// Only the first of multiple null-separated values is returned.
+pub(crate) fn decode_text(data: &[u8]) -> Option<String> {
+    let (&encoding, text) = data.split_first()?;
+    let decoded = match encoding {
+        0 => text.iter().take_while(|&&b| b != 0).map(|&b| b as char).collect(),
+        1 | 2 => {
+            let mut units: Vec<u16> =
+                text.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            let little_endian = encoding == 1 && units.first() == Some(&0xFFFE);
+            if little_endian {
+                units = units.iter().map(|u| u.swap_bytes()).collect();
+            }
+            if units.first() == Some(&0xFEFF) {
+                units.remove(0);
+            }
+            let end = units.iter().position(|&u| u == 0).unwrap_or(units.len());
+            String::from_utf16_lossy(&units[..end])
+        }
+        _ => {
+            let end = text.iter().position(|&b| b == 0).unwrap_or(text.len());
+            String::from_utf8_lossy(&text[..end]).into_owned()
+        }
+    };
+    let trimmed = decoded.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+/// Strips ID3v1 genre references such as `(8)` when a genre name follows.
+fn clean_genre(id: &[u8], text: String) -> String {
+    if id != b"TCON" && id != b"TCO" {
+        return text;
+    }
+    match text.strip_prefix('(').and_then(|t| t.split_once(')')) {
+        Some((num, rest)) if num.chars().all(|c| c.is_ascii_digit()) && !rest.trim().is_empty() => {
+            rest.trim().to_string()
+        }
+        _ => text,
+    }
+}
+
+/// Converts a `POPM` rating byte to stars, using the common Windows Media
+/// Player mapping (1, 64, 128, 196, 255).
+fn popm_to_stars(value: u8) -> Option<u8> {
+    match value {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// Converts stars (1-5) to a `POPM` rating byte.
+fn stars_to_popm(stars: u8) -> u8 {
+    match stars {
+        0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+/// Returns the file contents with the `POPM` rating replaced.
+///
+/// Existing `POPM` frames are dropped and, unless `stars` is 0, a new one is
+/// added. Files without a tag get a new ID3v2.3 tag. The extended header and
+/// footer are not carried over.
+///
+/// # Errors
+///
+/// Returns [`TagError::Unsupported`] for ID3v2.2 tags, tags using
+/// unsynchronisation, which this writer does not re-encode, and tags with a
+/// truncated frame.
+pub(crate) fn write_rating(data: &[u8], stars: u8) -> Result<Vec<u8>, TagError> {
+    let (version, old_size, frames, audio) = if data.len() >= HEADER_LEN && &data[..3] == b"ID3" {
+        let version = data[3];
+        let flags = data[5];
+        if version < 3 {
+            return Err(TagError::Unsupported(format!("ID3v2.{} tags", version)));
+        }
+        if flags & 0x80 != 0 {
+            return Err(TagError::Unsupported("unsynchronised ID3 tags".to_string()));
+        }
+        let size = syncsafe(&data[6..10]) as usize;
+        let body = data.get(HEADER_LEN..HEADER_LEN + size).ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let footer = if flags & 0x10 != 0 { HEADER_LEN } else { 0 };
+        let audio = data.get(HEADER_LEN + size + footer..).unwrap_or_default();
+
+        let mut offset = 0;
+        if flags & 0x40 != 0 && body.len() >= 4 {
+            offset = match version {
+                3 => 4 + u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize,
+                _ => syncsafe(&body[..4]) as usize,
+            };
+        }
+        (version, size, raw_frames(&body[offset.min(body.len())..], version)?, audio)
+    } else {
+        (3, 0, Vec::new(), data)
+    };
+
+    let mut body: Vec<u8> = frames.into_iter().filter(|f| &f[..4] != b"POPM").flatten().collect();
+    if stars > 0 {
+        let payload = [0, stars_to_popm(stars)];
+        body.extend_from_slice(b"POPM");
+        let size = payload.len() as u32;
+        body.extend_from_slice(&if version == 4 { to_syncsafe(size) } else { size.to_be_bytes() });
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&payload);
+    }
+    // Keep the old tag size when possible so the audio does not move
+    let size = if body.len() <= old_size { old_size } else { body.len() + PADDING };
+    body.resize(size, 0);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + size + audio.len());
+    out.extend_from_slice(&[b'I', b'D', b'3', version, 0, 0]);
+    out.extend_from_slice(&to_syncsafe(size as u32));
+    out.extend_from_slice(&body);
+    out.extend_from_slice(audio);
+    Ok(out)
+}
+
+/// Splits a v2.3/v2.4 tag body into raw frames (header and data), stopping at
+/// the padding.
+///
+/// # Errors
+///
+/// Returns [`TagError::Unsupported`] if a frame runs past the end of the tag:
+/// rewriting the tag would lose it and every frame after it.
+fn raw_frames(mut body: &[u8], version: u8) -> Result<Vec<Vec<u8>>, TagError> {
+    let truncated = || TagError::Unsupported("truncated ID3 frame".to_string());
+    let mut frames = Vec::new();
+    while !body.is_empty() && body[0] != 0 {
+        let header = body.get(..10).ok_or_else(truncated)?;
+        let size = match version {
+            3 => u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize,
+            _ => syncsafe(&header[4..8]) as usize,
+        };
+        let frame = body.get(..10 + size).ok_or_else(truncated)?;
+        frames.push(frame.to_vec());
+        body = &body[10 + size..];
+    }
+    Ok(frames)
+}
+
+/// Encodes a 28-bit syncsafe integer.
+fn to_syncsafe(value: u32) -> [u8; 4] {
+    [(value >> 21) as u8 & 0x7F, (value >> 14) as u8 & 0x7F, (value >> 7) as u8 & 0x7F, value as u8 & 0x7F]
+}
+
+/// Decodes a 28-bit syncsafe integer.
+pub(crate) fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().take(4).fold(0, |acc, &b| (acc << 7) | (b & 0x7F) as u32)
+}
+
+/// Reads until `buf` is full or the reader is exhausted.
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds an ID3v2.3 tag from `(frame id, payload)` pairs.
+    pub(crate) fn build_v23(frames: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (id, payload) in frames {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            body.extend_from_slice(&[0, 0]);
+            body.extend_from_slice(payload);
+        }
+        let mut tag = b"ID3\x03\x00\x00".to_vec();
+        tag.extend_from_slice(&to_syncsafe(body.len() as u32));
+        tag.extend_from_slice(&body);
+        tag
+    }
+
+    fn latin1(text: &str) -> Vec<u8> {
+        let mut v = vec![0];
+        v.extend(text.bytes());
+        v
+    }
+
+    #[test]
+    fn test_read_v23_text_frames() {
+        let mut utf16 = vec![1, 0xFF, 0xFE];
+        for unit in "Kind of Blue".encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        let tag = build_v23(&[
+            (b"TIT2", latin1("So What")),
+            (b"TPE1", latin1("Miles Davis")),
+            (b"TALB", utf16),
+            (b"TCON", latin1("(8)Jazz")),
+            (b"TYER", latin1("1959")),
+        ]);
+        let mut data = tag.clone();
+        data.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+
+        let info = read(&mut data.as_slice()).unwrap();
+        assert_eq!(info.title.as_deref(), Some("So What"));
+        assert_eq!(info.artist.as_deref(), Some("Miles Davis"));
+        assert_eq!(info.album.as_deref(), Some("Kind of Blue"));
+        assert_eq!(info.genre.as_deref(), Some("Jazz"));
+        assert_eq!(info.year, Some(1959));
+    }
+
+    #[test]
+    fn test_read_without_tag() {
+        let info = read(&mut &b"\xFF\xFB\x90\x00 not a tag"[..]).unwrap();
+        assert_eq!(info, TagInfo::default());
+    }
+
+    #[test]
+    fn test_read_truncated_tag() {
+        // The header claims a 256 MB tag; only one frame follows
+        let mut data = build_v23(&[(b"TIT2", latin1("So What"))]);
+        data[6..10].copy_from_slice(&to_syncsafe(0x0FFF_FFFF));
+        let info = read(&mut data.as_slice()).unwrap();
+        assert_eq!(info.title.as_deref(), Some("So What"));
+    }
+
+    #[test]
+    fn test_write_rating_keeps_frames_and_audio() {
+        let audio = [0xFF, 0xFB, 0x90, 0x00];
+        let data = [&build_v23(&[(b"TPE1", latin1("Miles Davis")), (b"POPM", b"a@b\0\x01".to_vec())])[..], &audio].concat();
+
+        let updated = write_rating(&data, 5).unwrap();
+        assert!(updated.ends_with(&audio));
+        let info = read(&mut updated.as_slice()).unwrap();
+        assert_eq!(info.artist.as_deref(), Some("Miles Davis"));
+        assert_eq!(info.rating, Some(5));
+        assert_eq!(updated.windows(4).filter(|w| w == b"POPM").count(), 1);
+
+        let cleared = write_rating(&updated, 0).unwrap();
+        assert_eq!(read(&mut cleared.as_slice()).unwrap().rating, None);
+        assert_eq!(cleared.len(), updated.len(), "tag size should be kept");
+
+        let v22 = b"ID3\x02\x00\x00\x00\x00\x00\x00";
+        assert!(matches!(write_rating(v22, 3), Err(TagError::Unsupported(_))));
+
+        // A frame claiming more data than the tag holds is not dropped silently
+        let mut truncated = build_v23(&[(b"TPE1", latin1("Miles Davis")), (b"TALB", latin1("Kind of Blue"))]);
+        truncated[HEADER_LEN + 7] = 0xFF;
+        assert!(matches!(write_rating(&truncated, 3), Err(TagError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_syncsafe_roundtrip() {
+        for value in [0, 1, 127, 128, 0x0FFF_FFFF] {
+            assert_eq!(syncsafe(&to_syncsafe(value)), value);
+        }
+    }
+}
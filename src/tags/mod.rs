@@ -0,0 +1,300 @@
+//! Audio file metadata (tag) reading and rating writing.
+//!
+//! This module extracts the descriptive metadata used by the library, search
+//! and smart playlists from the tags embedded in audio files:
+//! - `id3`: ID3v2.2/2.3/2.4 tags at the start of MP3 files
+//! - `flac`: Vorbis comments and stream info in FLAC metadata blocks
+//!
+//! Ogg Vorbis comments are read through `lewton`. Files without supported tags
+//! yield an empty [`TagInfo`] rather than an error, so scanning never fails
+//! because of unusual files.
+//!
+//! # Ratings
+//!
+//! Star ratings (0-5) can be written back with [`write_rating`]: as a `POPM`
+//! frame in ID3v2.3/2.4 tags, and as `FMPS_RATING` (0.0-1.0) plus `RATING`
+//! (0-100) Vorbis comments in FLAC files. Writing Ogg files is not supported.
+//! Ratings read from the tags seed the library database for tracks it does
+//! not know yet.
+//! Since the whole file is rewritten, the player leaves this to the thread
+//! started by [`spawn_rating_writer`].
+
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use thiserror::Error;
+use tracing::error;
+
+use crate::common::Track;
+
+pub mod flac;
+pub mod id3;
+
+/// Metadata read from an audio file's tags.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagInfo {
+    /// Track title
+    pub title: Option<String>,
+    /// Performing artist
+    pub artist: Option<String>,
+    /// Album name
+    pub album: Option<String>,
+    /// Genre name
+    pub genre: Option<String>,
+    /// Release year
+    pub year: Option<u32>,
+    /// Total duration, when the container stores it
+    pub duration: Option<Duration>,
+    /// Star rating from 1 to 5 (`None` when unrated)
+    pub rating: Option<u8>,
+}
+
+/// Errors that can occur while writing tags.
+#[derive(Debug, Error)]
+pub enum TagError {
+    /// Reading or writing the file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The file type or tag layout cannot be written
+    #[error("cannot write tags: {0}")]
+    Unsupported(String),
+}
+
+impl TagInfo {
+    /// Copies the available fields onto a track, keeping existing values
+    /// for fields the tags do not provide.
+    pub fn apply_to(self, track: &mut Track) {
+        if self.title.is_some() {
+            track.title = self.title;
+        }
+        track.artist = self.artist.or(track.artist.take());
+        track.album = self.album.or(track.album.take());
+        track.genre = self.genre.or(track.genre.take());
+        track.year = self.year.or(track.year);
+        track.duration = self.duration.or(track.duration);
+        track.rating = self.rating.or(track.rating);
+    }
+
+    /// Stores one Vorbis comment (`KEY=value`) in the matching field.
+    ///
+    /// Keys are case-insensitive; the first value of a repeated key wins.
+    pub(crate) fn set_vorbis_comment(&mut self, key: &str, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        let slot = match key.to_ascii_uppercase().as_str() {
+            "TITLE" => &mut self.title,
+            "ARTIST" => &mut self.artist,
+            "ALBUM" => &mut self.album,
+            "GENRE" => &mut self.genre,
+            "DATE" | "YEAR" => {
+                if self.year.is_none() {
+                    self.year = parse_year(value);
+                }
+                return;
+            }
+            // FMPS_RATING is 0.0-1.0; RATING is 0-100, or 1-5 in some players
+            "FMPS_RATING" | "RATING" => {
+                if let Ok(number) = value.parse::<f64>() {
+                    let stars = match key.to_ascii_uppercase().as_str() {
+                        "FMPS_RATING" => number * 5.0,
+                        _ if number <= 5.0 => number,
+                        _ => number / 20.0,
+                    };
+                    let stars = stars.round().clamp(0.0, 5.0) as u8;
+                    if self.rating.is_none() && stars > 0 {
+                        self.rating = Some(stars);
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+        if slot.is_none() {
+            *slot = Some(value.to_string());
+        }
+    }
+}
+
+/// Reads the tags of an audio file, choosing the reader by extension.
+///
+/// # Errors
+///
+/// Returns an error only if the file cannot be opened or read; missing or
+/// malformed tags produce an empty [`TagInfo`].
+pub fn read(path: &Path) -> io::Result<TagInfo> {
+    let ext = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    match ext.as_deref() {
+        Some("mp3") => id3::read(&mut BufReader::new(File::open(path)?)),
+        Some("flac") => flac::read(&mut BufReader::new(File::open(path)?)),
+        Some("ogg") | Some("oga") => Ok(read_ogg(path)?),
+        _ => Ok(TagInfo::default()),
+    }
+}
+
+/// Writes a star rating (0 clears it) into the tags of an audio file.
+///
+/// The file is rewritten through a temporary file in the same directory, so
+/// an interrupted write leaves the original intact.
+///
+/// # Arguments
+///
+/// * `path` - Audio file to update (MP3 or FLAC)
+/// * `rating` - Stars from 0 to 5
+///
+/// # Errors
+///
+/// Returns [`TagError::Unsupported`] for other file types and tag layouts the
+/// writer does not handle, or an I/O error if the file cannot be rewritten.
+pub fn write_rating(path: &Path, rating: u8) -> Result<(), TagError> {
+    let rating = rating.min(5);
+    let ext = path.extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    let data = fs::read(path)?;
+    let updated = match ext.as_deref() {
+        Some("mp3") => id3::write_rating(&data, rating)?,
+        Some("flac") => flac::write_rating(&data, rating)?,
+        _ => return Err(TagError::Unsupported(format!("{} files", ext.unwrap_or_default()))),
+    };
+    replace_file(path, &updated)
+}
+
+/// Starts a thread that writes ratings into the tags of audio files (see
+/// [`write_rating`]), one after the other in the order they are sent.
+///
+/// Failures are reported with `error!`, which the player shows as toasts.
+///
+/// # Returns
+///
+/// Returns the channel to send `(path, rating)` pairs on, and the thread,
+/// which ends once the channel is dropped and the pending writes are done.
+pub fn spawn_rating_writer() -> (Sender<(PathBuf, u8)>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel::<(PathBuf, u8)>();
+    let handle = thread::spawn(move || {
+        for (path, rating) in rx {
+            if let Err(e) = write_rating(&path, rating) {
+                error!("无法写入评分标签 {}: {}", path.display(), e);
+            }
+        }
+    });
+    (tx, handle)
+}
+
+/// Replaces a file's contents via a temporary sibling file, keeping permissions.
+fn replace_file(path: &Path, contents: &[u8]) -> Result<(), TagError> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("tags");
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+    fs::write(&tmp, contents)?;
+    if let Err(e) = fs::metadata(path).and_then(|m| fs::set_permissions(&tmp, m.permissions())) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Reads the Vorbis comment header of an Ogg Vorbis file.
+fn read_ogg(path: &Path) -> io::Result<TagInfo> {
+    let file = BufReader::new(File::open(path)?);
+    let mut info = TagInfo::default();
+    if let Ok(reader) = lewton::inside_ogg::OggStreamReader::new(file) {
+        for (key, value) in &reader.comment_hdr.comment_list {
+            info.set_vorbis_comment(key, value);
+        }
+    }
+    Ok(info)
+}
+
+/// Extracts a year from date strings such as `1959`, `1959-08-17` or `(c) 1959`.
+pub(crate) fn parse_year(value: &str) -> Option<u32> {
+    let digits: String = value
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    if digits.len() == 4 {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::TempDir;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_year() {
+        assert_eq!(parse_year("1959"), Some(1959));
+        assert_eq!(parse_year("1959-08-17"), Some(1959));
+        assert_eq!(parse_year("(c) 2001 Label"), Some(2001));
+        assert_eq!(parse_year("59"), None);
+        assert_eq!(parse_year(""), None);
+    }
+
+    #[test]
+    fn test_vorbis_comment_first_value_wins() {
+        let mut info = TagInfo::default();
+        info.set_vorbis_comment("artist", "Miles Davis");
+        info.set_vorbis_comment("ARTIST", "John Coltrane");
+        info.set_vorbis_comment("Date", "1959-08-17");
+        info.set_vorbis_comment("GENRE", "  ");
+        assert_eq!(info.artist.as_deref(), Some("Miles Davis"));
+        assert_eq!(info.year, Some(1959));
+        assert_eq!(info.genre, None);
+    }
+
+    #[test]
+    fn test_vorbis_rating_scales() {
+        let rating = |key: &str, value: &str| {
+            let mut info = TagInfo::default();
+            info.set_vorbis_comment(key, value);
+            info.rating
+        };
+        assert_eq!(rating("FMPS_RATING", "0.8"), Some(4));
+        assert_eq!(rating("RATING", "60"), Some(3));
+        assert_eq!(rating("rating", "5"), Some(5));
+        assert_eq!(rating("RATING", "0"), None);
+    }
+
+    #[test]
+    fn test_write_rating_roundtrip() {
+        let dir = TempDir::new("tags");
+        let mp3 = dir.join("a.mp3");
+        let flac = dir.join("b.flac");
+        fs::write(&mp3, [&id3::tests::build_v23(&[(b"TIT2", b"\0Title".to_vec())])[..], b"\xFF\xFB"].concat())
+            .unwrap();
+        fs::write(&flac, flac::tests::build_flac(&[("TITLE", "Title")], 44100, 44100)).unwrap();
+
+        for path in [&mp3, &flac] {
+            write_rating(path, 4).unwrap();
+            let info = read(path).unwrap();
+            assert_eq!(info.rating, Some(4), "{}", path.display());
+            assert_eq!(info.title.as_deref(), Some("Title"));
+            write_rating(path, 0).unwrap();
+            assert_eq!(read(path).unwrap().rating, None);
+        }
+        assert!(matches!(write_rating(&dir.join("c.ogg"), 3), Err(TagError::Io(_))));
+        fs::write(dir.join("c.ogg"), b"OggS").unwrap();
+        assert!(matches!(write_rating(&dir.join("c.ogg"), 3), Err(TagError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_apply_keeps_filename_title_without_tag() {
+        let mut track = Track {
+            id: 0,
+            path: PathBuf::from("/m/a.mp3"),
+            title: Some("a.mp3".to_string()),
+            ..Track::default()
+        };
+        TagInfo { artist: Some("X".to_string()), ..TagInfo::default() }.apply_to(&mut track);
+        assert_eq!(track.title.as_deref(), Some("a.mp3"));
+        assert_eq!(track.artist.as_deref(), Some("X"));
+    }
+}
@@ -9,6 +9,7 @@
 //! The layout follows a three-layer vertical structure:
 //! 1. **Top**: Now Playing area (fixed height: 3-5 lines)
 //! 2. **Middle**: Main content area (flexible height)
+//!    - Playlist sidebar (optional, leftmost, fixed width)
 //!    - Track list (left, 55% width)
 //!    - Visualization (right, 45% width, hidden in compact mode)
 //! 3. **Bottom**: Playback controls (fixed height: 4-5 lines)
@@ -17,6 +18,7 @@
 //! # Responsive Behavior
 //!
 //! - **Width < 80 columns**: Compact mode - hides visualization, track list takes full width
+//!   (the breakpoint is configurable with [`LayoutManager::with_compact_width`])
 //! - **Height < 20 lines**: Reduces fixed heights to fit more content
 //!
 //! # Example
@@ -39,6 +41,8 @@ use ratatui::prelude::*;
 pub struct AppLayout {
     /// Top area displaying current playing track information
     pub now_playing: Rect,
+    /// Leftmost middle area showing the playlist sidebar (None when hidden)
+    pub sidebar: Option<Rect>,
     /// Middle-left area showing the track list
     pub track_list: Rect,
     /// Middle-right area showing visualization (None in compact mode)
@@ -49,19 +53,77 @@ pub struct AppLayout {
     pub status_bar: Rect,
 }
 
+/// A region of the layout, as found by [`AppLayout::region_at`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// The now playing area
+    NowPlaying,
+    /// The playlist sidebar
+    Sidebar,
+    /// The track list
+    TrackList,
+    /// The visualization area
+    Visualization,
+    /// The playback controls
+    PlaybackControl,
+    /// The status bar
+    StatusBar,
+}
+
+impl AppLayout {
+    /// Returns the region containing a terminal cell, for mouse hit-testing.
+    pub fn region_at(&self, column: u16, row: u16) -> Option<Region> {
+        let contains = |area: Rect| {
+            column >= area.x && column < area.x + area.width && row >= area.y && row < area.y + area.height
+        };
+        [
+            (Some(self.now_playing), Region::NowPlaying),
+            (self.sidebar, Region::Sidebar),
+            (Some(self.track_list), Region::TrackList),
+            (self.visualization, Region::Visualization),
+            (Some(self.playback_control), Region::PlaybackControl),
+            (Some(self.status_bar), Region::StatusBar),
+        ]
+        .into_iter()
+        .find_map(|(area, region)| area.filter(|&area| contains(area)).map(|_| region))
+    }
+}
+
 /// Manages layout calculation and responsive behavior
 pub struct LayoutManager {
     terminal_size: Rect,
+    show_sidebar: bool,
+    compact_width: u16,
 }
 
+/// Preferred width of the playlist sidebar in columns
+const SIDEBAR_WIDTH: u16 = 24;
+
+/// Default width below which the compact layout is used
+pub const DEFAULT_COMPACT_WIDTH: u16 = 80;
+
 impl LayoutManager {
     /// Creates a new LayoutManager with the given terminal size
     pub fn new(size: Rect) -> Self {
         Self {
             terminal_size: size,
+            show_sidebar: false,
+            compact_width: DEFAULT_COMPACT_WIDTH,
         }
     }
 
+    /// Enables or disables the playlist sidebar
+    pub fn with_sidebar(mut self, show: bool) -> Self {
+        self.show_sidebar = show;
+        self
+    }
+
+    /// Sets the width below which the compact layout is used
+    pub fn with_compact_width(mut self, width: u16) -> Self {
+        self.compact_width = width;
+        self
+    }
+
     /// Calculates the complete layout based on terminal size
     pub fn calculate_layout(&self) -> AppLayout {
         let size = self.terminal_size;
@@ -86,6 +148,18 @@ impl LayoutManager {
         let middle_area = vertical_chunks[1];
         let playback_control = vertical_chunks[2];
         let status_bar = vertical_chunks[3];
+
+        // Carve the sidebar off the left edge; at most a third of the width
+        let (sidebar, middle_area) = if self.show_sidebar {
+            let width = SIDEBAR_WIDTH.min(middle_area.width / 3);
+            let chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(width), Constraint::Min(0)])
+                .split(middle_area);
+            (Some(chunks[0]), chunks[1])
+        } else {
+            (None, middle_area)
+        };
         
         // Handle middle area: split horizontally if not in compact mode
         let (track_list, visualization) = if self.is_compact_mode() {
@@ -107,6 +181,7 @@ impl LayoutManager {
         
         AppLayout {
             now_playing,
+            sidebar,
             track_list,
             visualization,
             playback_control,
@@ -114,9 +189,9 @@ impl LayoutManager {
         }
     }
 
-    /// Determines if the UI should be in compact mode (width < 80)
+    /// Determines if the UI should be in compact mode (width below the breakpoint)
     pub fn is_compact_mode(&self) -> bool {
-        self.terminal_size.width < 80
+        self.terminal_size.width < self.compact_width
     }
 }
 
@@ -127,6 +202,19 @@ mod tests {
 
     // **Feature: modern-player-layout, Property 1: 布局三区域结构**
     // **Validates: Requirements 1.1, 1.3**
+    #[test]
+    fn test_region_at() {
+        let layout = LayoutManager::new(Rect::new(0, 0, 100, 30)).with_sidebar(true).calculate_layout();
+        let sidebar = layout.sidebar.unwrap();
+        assert_eq!(layout.region_at(0, 0), Some(Region::NowPlaying));
+        assert_eq!(layout.region_at(sidebar.x, sidebar.y), Some(Region::Sidebar));
+        assert_eq!(layout.region_at(layout.track_list.x, layout.track_list.y + 2), Some(Region::TrackList));
+        assert_eq!(layout.region_at(99, layout.track_list.y), Some(Region::Visualization));
+        assert_eq!(layout.region_at(50, layout.playback_control.y), Some(Region::PlaybackControl));
+        assert_eq!(layout.region_at(50, 29), Some(Region::StatusBar));
+        assert_eq!(layout.region_at(100, 0), None);
+    }
+
     proptest! {
         #![proptest_config(ProptestConfig::with_cases(100))]
         
@@ -309,4 +397,32 @@ mod tests {
                 "Playback control should be above status bar");
         }
     }
+
+    #[test]
+    fn test_sidebar_left_of_track_list() {
+        let size = Rect::new(0, 0, 120, 30);
+        let without = LayoutManager::new(size).calculate_layout();
+        assert!(without.sidebar.is_none());
+
+        let layout = LayoutManager::new(size).with_sidebar(true).calculate_layout();
+        let sidebar = layout.sidebar.expect("sidebar should be shown");
+        assert_eq!(sidebar.x, 0);
+        assert_eq!(sidebar.width, SIDEBAR_WIDTH);
+        assert_eq!(sidebar.y, layout.track_list.y);
+        assert_eq!(layout.track_list.x, sidebar.x + sidebar.width);
+        assert!(layout.visualization.is_some());
+
+        let narrow = LayoutManager::new(Rect::new(0, 0, 45, 20)).with_sidebar(true).calculate_layout();
+        assert_eq!(narrow.sidebar.map(|r| r.width), Some(15));
+    }
+
+    #[test]
+    fn test_compact_width_breakpoint() {
+        let size = Rect::new(0, 0, 90, 30);
+        assert!(!LayoutManager::new(size).is_compact_mode());
+        let manager = LayoutManager::new(size).with_compact_width(100);
+        assert!(manager.is_compact_mode());
+        assert!(manager.calculate_layout().visualization.is_none());
+        assert!(LayoutManager::new(Rect::new(0, 0, 40, 30)).with_compact_width(0).calculate_layout().visualization.is_some());
+    }
 }
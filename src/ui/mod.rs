@@ -12,10 +12,24 @@
 //! - `layout`: Handles layout calculation and responsive behavior
 //! - `widgets`: Custom widget components for different UI regions
 //! - `theme`: Color theme and styling system
+//!
+//! # Terminal State
+//!
+//! [`TerminalGuard`] sets up raw mode, the alternate screen and mouse capture
+//! and undoes them when dropped, so an early return or an unwinding panic
+//! leaves the user's shell usable. [`install_panic_hook`] additionally
+//! restores the terminal before the panic message is printed, so the message
+//! is not lost on the alternate screen. [`TerminalGuard::suspend`] hands the
+//! terminal back to the shell while the process is stopped (Ctrl-Z).
+
+use std::io::Stdout;
+use std::ops::{Deref, DerefMut};
 
 use anyhow::Result;
+use crossterm::cursor::Show;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::execute;
-use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 
@@ -28,6 +42,7 @@ pub mod theme;
 ///
 /// This function:
 /// - Enters alternate screen mode to preserve terminal history
+/// - Enables mouse capture
 /// - Creates a new terminal instance with crossterm backend
 ///
 /// # Returns
@@ -39,7 +54,7 @@ pub mod theme;
 /// Returns an error if terminal initialization fails.
 pub fn init_terminal() -> Result<Terminal<CrosstermBackend<std::io::Stdout>>> {
     let stdout = std::io::stdout();
-    execute!(stdout.lock(), EnterAlternateScreen)?;
+    execute!(stdout.lock(), EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(std::io::stdout());
     let terminal = Terminal::new(backend)?;
     Ok(terminal)
@@ -49,6 +64,7 @@ pub fn init_terminal() -> Result<Terminal<CrosstermBackend<std::io::Stdout>>> {
 ///
 /// This function:
 /// - Shows the cursor
+/// - Disables mouse capture
 /// - Leaves alternate screen mode
 ///
 /// # Arguments
@@ -64,6 +80,106 @@ pub fn init_terminal() -> Result<Terminal<CrosstermBackend<std::io::Stdout>>> {
 /// Returns an error if terminal restoration fails.
 pub fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>) -> Result<()> {
     terminal.show_cursor()?;
-    execute!(std::io::stdout(), LeaveAlternateScreen)?;
+    execute!(std::io::stdout(), DisableMouseCapture, LeaveAlternateScreen)?;
     Ok(())
 }
+
+/// Puts the terminal back into its normal state without a [`Terminal`]:
+/// leaves raw mode and the alternate screen, and shows the cursor.
+///
+/// Safe to call when the terminal was never set up, or already restored.
+///
+/// # Errors
+///
+/// Returns an error if writing to the terminal fails.
+pub fn restore() -> Result<()> {
+    disable_raw_mode()?;
+    execute!(std::io::stdout(), DisableMouseCapture, LeaveAlternateScreen, Show)?;
+    Ok(())
+}
+
+/// Installs a panic hook that restores the terminal before the default hook
+/// prints the panic message.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Nothing sensible is left to do if restoring fails while panicking
+        let _ = restore();
+        default_hook(info);
+    }));
+}
+
+/// The terminal set up for the UI, restored when dropped.
+///
+/// Dereferences to the [`Terminal`] to draw on.
+pub struct TerminalGuard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl TerminalGuard {
+    /// Enters raw mode and initializes the terminal (see [`init_terminal`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminal cannot be set up; whatever was set up
+    /// already is undone.
+    pub fn new() -> Result<Self> {
+        enable_raw_mode()?;
+        match init_terminal() {
+            Ok(terminal) => Ok(Self { terminal }),
+            Err(e) => {
+                let _ = restore();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl TerminalGuard {
+    /// Suspends the process to the shell, as Ctrl-Z does outside raw mode.
+    ///
+    /// Restores the terminal, stops the process with SIGTSTP and, once it is
+    /// continued (SIGCONT, e.g. from `fg`), sets the terminal up again and
+    /// clears it so that the next draw repaints everything. Other threads
+    /// (such as the player) keep running until the process stops. Does
+    /// nothing on platforms without job control.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminal cannot be restored or set up again.
+    pub fn suspend(&mut self) -> Result<()> {
+        #[cfg(unix)]
+        {
+            disable_raw_mode()?;
+            restore_terminal(&mut self.terminal)?;
+            // Stops here until continued, even if a SIGTSTP handler is registered
+            signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+            enable_raw_mode()?;
+            execute!(std::io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+            self.terminal.clear()?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for TerminalGuard {
+    type Target = Terminal<CrosstermBackend<Stdout>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for TerminalGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // Errors cannot be reported from a destructor
+        let _ = disable_raw_mode();
+        let _ = restore_terminal(&mut self.terminal);
+    }
+}
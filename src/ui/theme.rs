@@ -4,6 +4,19 @@
 //! the visual appearance of the music player interface. It includes
 //! predefined color schemes and style helper methods.
 //!
+//! # Built-in and Custom Themes
+//!
+//! The built-in themes are listed in [`BUILTIN_THEMES`]. Custom themes are
+//! TOML files named `<name>.toml` in the themes directory, optionally based
+//! on another theme; colors are names (`light-blue`), 256-color indices
+//! (`208` or `"208"`) or hex values (`"#ffd700"`):
+//!
+//! ```toml
+//! base = "nord"
+//! highlight = "#ffd700"
+//! border = 240
+//! ```
+//!
 //! # Design Philosophy
 //!
 //! The theme system follows modern UI design principles:
@@ -22,52 +35,240 @@
 //! let highlight_style = theme.style_highlight();
 //! ```
 
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
 use ratatui::prelude::*;
+use thiserror::Error;
+use toml::{Table, Value};
+
+/// Names of the built-in themes, in switching order.
+pub const BUILTIN_THEMES: &[&str] = &["dark", "light", "solarized", "gruvbox", "nord", "high-contrast", "monochrome"];
+
+/// Names of the theme colors, as used in theme files and the config.
+pub const COLOR_NAMES: &[&str] =
+    &["primary", "secondary", "accent", "text", "text_dim", "border", "highlight", "progress", "background"];
+
+/// Errors that can occur while loading a theme.
+#[derive(Debug, Error)]
+pub enum ThemeError {
+    /// No built-in theme or theme file has this name
+    #[error("unknown theme '{0}'")]
+    NotFound(String),
+    /// Reading the theme file failed
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The theme file is not valid TOML
+    #[error("{name}.toml: {message}")]
+    Syntax {
+        /// Theme name
+        name: String,
+        /// Parser message
+        message: String,
+    },
+    /// A key is unknown or has an invalid value
+    #[error("{name}.toml: {key}: {message}")]
+    Invalid {
+        /// Theme name
+        name: String,
+        /// Offending key
+        key: String,
+        /// What is wrong with it
+        message: String,
+    },
+}
 
 /// Color theme configuration for the UI
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Theme {
     /// Primary color for main UI elements
     pub primary: Color,
-    /// Secondary color for supporting elements
-    #[allow(dead_code)]
+    /// Secondary color for supporting elements (background of selections)
     pub secondary: Color,
     /// Accent color for highlights and emphasis
-    #[allow(dead_code)]
     pub accent: Color,
     /// Main text color
-    #[allow(dead_code)]
     pub text: Color,
     /// Dimmed text color for less important information
-    #[allow(dead_code)]
     pub text_dim: Color,
     /// Border color
     pub border: Color,
     /// Highlight color for selected items
-    #[allow(dead_code)]
     pub highlight: Color,
     /// Progress bar color
-    #[allow(dead_code)]
     pub progress: Color,
+    /// Background color (`Reset` keeps the terminal's background)
+    pub background: Color,
 }
 
 impl Default for Theme {
     /// Creates a default theme with modern color scheme
     fn default() -> Self {
-        Self {
-            primary: Color::Cyan,
-            secondary: Color::Blue,
-            accent: Color::Magenta,
-            text: Color::White,
-            text_dim: Color::Gray,
-            border: Color::DarkGray,
-            highlight: Color::Yellow,
-            progress: Color::Green,
-        }
+        Self::DARK
     }
 }
 
 impl Theme {
+    /// The default theme for dark terminals
+    pub const DARK: Theme = Theme {
+        primary: Color::Cyan,
+        secondary: Color::Blue,
+        accent: Color::Magenta,
+        text: Color::White,
+        text_dim: Color::Gray,
+        border: Color::DarkGray,
+        highlight: Color::Yellow,
+        progress: Color::Green,
+        background: Color::Reset,
+    };
+
+    /// Returns a built-in theme by name.
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let theme = match name {
+            "dark" => Self::DARK,
+            "light" => Theme {
+                primary: Color::Blue,
+                secondary: Color::Rgb(0xd0, 0xe0, 0xf8),
+                accent: Color::Magenta,
+                text: Color::Black,
+                text_dim: Color::DarkGray,
+                border: Color::Gray,
+                highlight: Color::Rgb(0xaf, 0x5f, 0x00),
+                progress: Color::Rgb(0x00, 0x87, 0x00),
+                background: Color::White,
+            },
+            "solarized" => Theme {
+                primary: Color::Rgb(0x26, 0x8b, 0xd2),
+                secondary: Color::Rgb(0x07, 0x36, 0x42),
+                accent: Color::Rgb(0xd3, 0x36, 0x82),
+                text: Color::Rgb(0x83, 0x94, 0x96),
+                text_dim: Color::Rgb(0x58, 0x6e, 0x75),
+                border: Color::Rgb(0x58, 0x6e, 0x75),
+                highlight: Color::Rgb(0xb5, 0x89, 0x00),
+                progress: Color::Rgb(0x85, 0x99, 0x00),
+                background: Color::Rgb(0x00, 0x2b, 0x36),
+            },
+            "gruvbox" => Theme {
+                primary: Color::Rgb(0x83, 0xa5, 0x98),
+                secondary: Color::Rgb(0x3c, 0x38, 0x36),
+                accent: Color::Rgb(0xd3, 0x86, 0x9b),
+                text: Color::Rgb(0xeb, 0xdb, 0xb2),
+                text_dim: Color::Rgb(0xa8, 0x99, 0x84),
+                border: Color::Rgb(0x50, 0x49, 0x45),
+                highlight: Color::Rgb(0xfa, 0xbd, 0x2f),
+                progress: Color::Rgb(0xb8, 0xbb, 0x26),
+                background: Color::Rgb(0x28, 0x28, 0x28),
+            },
+            "nord" => Theme {
+                primary: Color::Rgb(0x88, 0xc0, 0xd0),
+                secondary: Color::Rgb(0x43, 0x4c, 0x5e),
+                accent: Color::Rgb(0xb4, 0x8e, 0xad),
+                text: Color::Rgb(0xec, 0xef, 0xf4),
+                text_dim: Color::Rgb(0x61, 0x6e, 0x88),
+                border: Color::Rgb(0x4c, 0x56, 0x6a),
+                highlight: Color::Rgb(0xeb, 0xcb, 0x8b),
+                progress: Color::Rgb(0xa3, 0xbe, 0x8c),
+                background: Color::Rgb(0x2e, 0x34, 0x40),
+            },
+            "high-contrast" => Theme {
+                primary: Color::LightCyan,
+                secondary: Color::Blue,
+                accent: Color::LightMagenta,
+                text: Color::White,
+                text_dim: Color::White,
+                border: Color::White,
+                highlight: Color::LightYellow,
+                progress: Color::LightGreen,
+                background: Color::Black,
+            },
+            "monochrome" => Theme {
+                primary: Color::Reset,
+                secondary: Color::DarkGray,
+                accent: Color::Reset,
+                text: Color::Reset,
+                text_dim: Color::DarkGray,
+                border: Color::DarkGray,
+                highlight: Color::White,
+                progress: Color::Gray,
+                background: Color::Reset,
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Loads a theme by name: a built-in theme or `<name>.toml` in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no such theme exists or its file is invalid.
+    pub fn load(name: &str, dir: &Path) -> Result<Theme, ThemeError> {
+        Self::load_nested(name, dir, 0)
+    }
+
+    fn load_nested(name: &str, dir: &Path, depth: usize) -> Result<Theme, ThemeError> {
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+        // Names come from config files and commands; keep them inside `dir`
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(ThemeError::NotFound(name.to_string()));
+        }
+        let content = match fs::read_to_string(dir.join(format!("{}.toml", name))) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(ThemeError::NotFound(name.to_string())),
+            Err(e) => return Err(e.into()),
+        };
+        let table: Table = content
+            .parse()
+            .map_err(|e: toml::de::Error| ThemeError::Syntax { name: name.to_string(), message: e.to_string() })?;
+        let invalid = |key: &str, message: &str| ThemeError::Invalid {
+            name: name.to_string(),
+            key: key.to_string(),
+            message: message.to_string(),
+        };
+        let mut theme = match table.get("base") {
+            None => Self::DARK,
+            Some(_) if depth >= 8 => return Err(invalid("base", "themes are based on each other in a cycle")),
+            Some(Value::String(base)) => Self::load_nested(base, dir, depth + 1)?,
+            Some(_) => return Err(invalid("base", "expected a theme name")),
+        };
+        for (key, value) in table.iter().filter(|(key, _)| *key != "base") {
+            theme.set_color(key, value).map_err(|message| invalid(key, &message))?;
+        }
+        Ok(theme)
+    }
+
+    /// Sets one color by name from a config value.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if the name is not in [`COLOR_NAMES`] or the value
+    /// is not a color.
+    pub fn set_color(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        let slot = match name {
+            "primary" => &mut self.primary,
+            "secondary" => &mut self.secondary,
+            "accent" => &mut self.accent,
+            "text" => &mut self.text,
+            "text_dim" => &mut self.text_dim,
+            "border" => &mut self.border,
+            "highlight" => &mut self.highlight,
+            "progress" => &mut self.progress,
+            "background" => &mut self.background,
+            _ => return Err("unknown color".to_string()),
+        };
+        *slot = parse_color(value).ok_or("expected a color name, 0-255 or #rrggbb")?;
+        Ok(())
+    }
+
+    /// Returns a style for the whole screen (text on the background)
+    pub fn style_base(&self) -> Style {
+        Style::default().fg(self.text).bg(self.background)
+    }
+
     /// Returns a style for title text
     pub fn style_title(&self) -> Style {
         Style::default()
@@ -76,13 +277,16 @@ impl Theme {
     }
 
     /// Returns a style for regular text
-    #[allow(dead_code)]
     pub fn style_text(&self) -> Style {
         Style::default().fg(self.text)
     }
 
+    /// Returns a style for secondary information such as hints
+    pub fn style_dim(&self) -> Style {
+        Style::default().fg(self.text_dim)
+    }
+
     /// Returns a style for highlighted/selected items
-    #[allow(dead_code)]
     pub fn style_highlight(&self) -> Style {
         Style::default()
             .fg(self.highlight)
@@ -90,15 +294,56 @@ impl Theme {
             .add_modifier(Modifier::BOLD)
     }
 
+    /// Returns a style for the selected row of an unfocused list
+    pub fn style_selected(&self) -> Style {
+        Style::default()
+            .fg(self.highlight)
+            .add_modifier(Modifier::BOLD)
+    }
+
     /// Returns a style for borders
     pub fn style_border(&self) -> Style {
         Style::default().fg(self.border)
     }
+
+    /// Returns a style for progress bars
+    pub fn style_progress(&self) -> Style {
+        Style::default().fg(self.progress).bg(self.background)
+    }
+}
+
+/// Parses a color: a name, a 256-color index or `#rrggbb`.
+pub fn parse_color(value: &Value) -> Option<Color> {
+    match value {
+        Value::Integer(index) => u8::try_from(*index).ok().map(Color::Indexed),
+        Value::String(s) => Color::from_str(s).ok(),
+        _ => None,
+    }
+}
+
+/// Lists the themes that can be switched to: the built-in ones followed by
+/// the theme files in `dir`, sorted by name.
+pub fn available(dir: &Path) -> Vec<String> {
+    let mut custom: Vec<String> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "toml" {
+                return None;
+            }
+            let name = path.file_stem()?.to_str()?.to_string();
+            (!BUILTIN_THEMES.contains(&name.as_str())).then_some(name)
+        })
+        .collect();
+    custom.sort();
+    BUILTIN_THEMES.iter().map(|name| name.to_string()).chain(custom).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::harness::TempDir;
 
     #[test]
     fn test_theme_default_colors() {
@@ -150,6 +395,44 @@ mod tests {
         assert_eq!(style.fg, Some(Color::DarkGray));
     }
 
+    #[test]
+    fn test_builtin_themes() {
+        for name in BUILTIN_THEMES {
+            assert!(Theme::builtin(name).is_some(), "{}", name);
+        }
+        assert_eq!(Theme::builtin("dark"), Some(Theme::default()));
+        assert_ne!(Theme::builtin("nord"), Theme::builtin("gruvbox"));
+        assert!(Theme::builtin("nope").is_none());
+    }
+
+    #[test]
+    fn test_load_custom_theme() {
+        let dir = TempDir::new("themes");
+        fs::write(dir.join("mine.toml"), "base = \"nord\"\nhighlight = \"#ffd700\"\nborder = 240\ntext = \"light-red\"\n").unwrap();
+        fs::write(dir.join("derived.toml"), "base = \"mine\"\nprimary = \"7\"\n").unwrap();
+        fs::write(dir.join("bad.toml"), "primary = \"octarine\"\n").unwrap();
+        fs::write(dir.join("loop.toml"), "base = \"loop\"\n").unwrap();
+
+        let mine = Theme::load("mine", dir.path()).unwrap();
+        assert_eq!(mine.highlight, Color::Rgb(0xff, 0xd7, 0x00));
+        assert_eq!(mine.border, Color::Indexed(240));
+        assert_eq!(mine.text, Color::LightRed);
+        assert_eq!(mine.background, Theme::builtin("nord").unwrap().background);
+        let derived = Theme::load("derived", dir.path()).unwrap();
+        assert_eq!(derived.primary, Color::Indexed(7));
+        assert_eq!(derived.highlight, mine.highlight);
+
+        let error = Theme::load("bad", dir.path()).unwrap_err().to_string();
+        assert_eq!(error, "bad.toml: primary: expected a color name, 0-255 or #rrggbb");
+        assert!(matches!(Theme::load("loop", dir.path()), Err(ThemeError::Invalid { .. })));
+        assert!(matches!(Theme::load("missing", dir.path()), Err(ThemeError::NotFound(_))));
+        assert!(matches!(Theme::load("../mine", dir.path()), Err(ThemeError::NotFound(_))));
+
+        let names = available(dir.path());
+        assert_eq!(&names[..BUILTIN_THEMES.len()], BUILTIN_THEMES);
+        assert_eq!(&names[BUILTIN_THEMES.len()..], ["bad", "derived", "loop", "mine"]);
+    }
+
     #[test]
     fn test_theme_clone() {
         let theme1 = Theme::default();
@@ -8,14 +8,23 @@
 //!
 //! - [`NowPlayingWidget`]: Displays current track information and playback status
 //! - [`TrackListWidget`]: Shows the list of available tracks with selection
-//! - [`VisualizationWidget`]: Renders audio waveform visualization
+//! - [`VisualizationWidget`]: Renders audio waveform visualization in one of
+//!   several [`VisualizationMode`]s
 //! - [`PlaybackControlWidget`]: Displays playback controls, progress bar, and time
 //! - [`StatusBarWidget`]: Shows keyboard shortcuts and status information
+//! - [`PlaylistSidebarWidget`]: Lists the library and saved playlists
+//! - [`PromptWidget`]: Single-line text input shown in place of the status bar
+//! - [`BookmarksWidget`]: Lists the named bookmarks of the playing track
+//! - [`HelpWidget`]: Popup listing every key binding and command
+//! - [`ToastWidget`]: Transient notifications stacked in a corner
+//! - [`MessageLogWidget`]: Popup listing all notifications
 //!
 //! # Design Pattern
 //!
 //! All widgets implement the `ratatui::widgets::Widget` trait, making them
-//! composable and easy to integrate into the layout system.
+//! composable and easy to integrate into the layout system. Every widget takes
+//! its colors from a [`Theme`]; those without a theme argument in `new` accept
+//! one through a `theme` builder method and use [`Theme::DARK`] otherwise.
 //!
 //! # Example
 //!
@@ -31,9 +40,14 @@
 
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, Paragraph};
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
 
 use crate::common::{PlaybackStatus, Track};
+use crate::bookmarks::Bookmark;
+use crate::library::{self, TrackStats};
+use crate::notify::{Level, Notification};
+use crate::queue::RepeatMode;
 use super::theme::Theme;
 
 /// Widget displaying current playing track information
@@ -41,6 +55,8 @@ pub struct NowPlayingWidget<'a> {
     track: Option<&'a Track>,
     status: PlaybackStatus,
     theme: &'a Theme,
+    rating: u8,
+    favorite: bool,
 }
 
 /// Widget displaying the track list
@@ -48,30 +64,118 @@ pub struct TrackListWidget<'a> {
     tracks: &'a [Track],
     selected: usize,
     playing: Option<usize>,
+    rows: Option<&'a [usize]>,
+    title: &'a str,
+    stats: Option<(&'a [TrackStats], SystemTime)>,
+    offset: usize,
+    theme: &'a Theme,
+}
+
+/// How [`VisualizationWidget`] draws the waveform
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VisualizationMode {
+    /// Sparkline of the recent samples
+    #[default]
+    Wave,
+    /// Bars averaging groups of samples
+    Bars,
+    /// Gauge of the latest sample
+    Level,
+}
+
+impl VisualizationMode {
+    /// Returns the next mode in the cycle Wave → Bars → Level → Wave.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Wave => Self::Bars,
+            Self::Bars => Self::Level,
+            Self::Level => Self::Wave,
+        }
+    }
 }
 
 /// Widget displaying audio visualization
 pub struct VisualizationWidget<'a> {
     wave_data: &'a [u64],
     is_playing: bool,
+    mode: VisualizationMode,
+    theme: &'a Theme,
 }
 
 /// Widget displaying playback controls and progress
-pub struct PlaybackControlWidget {
+pub struct PlaybackControlWidget<'a> {
     position: Duration,
     total: Option<Duration>,
     volume: f32,
     status: PlaybackStatus,
+    shuffle: bool,
+    repeat: RepeatMode,
+    theme: &'a Theme,
 }
 
 /// Widget displaying status bar with keyboard shortcuts
 pub struct StatusBarWidget<'a> {
     hints: &'a [(&'a str, &'a str)],
+    mode: Option<&'a str>,
+    pending: &'a str,
+    theme: &'a Theme,
+}
+
+/// Widget displaying the library entry and saved playlists
+pub struct PlaylistSidebarWidget<'a> {
+    items: &'a [&'a str],
+    selected: usize,
+    active: Option<usize>,
+    focused: bool,
+    theme: &'a Theme,
+}
+
+/// Widget displaying the bookmarks of a track
+pub struct BookmarksWidget<'a> {
+    bookmarks: &'a [Bookmark],
+    selected: usize,
+    focused: bool,
+    theme: &'a Theme,
+}
+
+/// Popup widget listing key bindings and commands in titled sections
+pub struct HelpWidget<'a> {
+    sections: &'a [(&'a str, Vec<(String, &'a str)>)],
+    scroll: usize,
+    theme: &'a Theme,
+}
+
+/// Widget stacking notifications in the top-right corner of its area
+pub struct ToastWidget<'a> {
+    toasts: &'a [&'a Notification],
+    theme: &'a Theme,
+}
+
+/// Popup listing notifications, oldest first
+pub struct MessageLogWidget<'a> {
+    log: &'a VecDeque<Notification>,
+    scroll: usize,
+    now: SystemTime,
+    theme: &'a Theme,
+}
+
+/// Widget displaying a single-line text prompt
+pub struct PromptWidget<'a> {
+    label: &'a str,
+    input: &'a str,
+    theme: &'a Theme,
 }
 
 impl<'a> NowPlayingWidget<'a> {
     pub fn new(track: Option<&'a Track>, status: PlaybackStatus, theme: &'a Theme) -> Self {
-        Self { track, status, theme }
+        Self { track, status, theme, rating: 0, favorite: false }
+    }
+
+    /// Shows the star rating (0 for none) and favorite mark of the track.
+    pub fn rating(mut self, rating: u8, favorite: bool) -> Self {
+        self.rating = rating;
+        self.favorite = favorite;
+        self
     }
 }
 
@@ -88,7 +192,14 @@ impl<'a> Widget for NowPlayingWidget<'a> {
         let content = if let Some(track) = self.track {
             // Display track title and status icon
             let title = track.title.as_deref().unwrap_or("Unknown Track");
-            format!("{} {}", status_icon, title)
+            let mut content = format!("{} {}", status_icon, title);
+            if self.rating > 0 {
+                content.push_str(&format!("  {}", stars(self.rating)));
+            }
+            if self.favorite {
+                content.push_str("  ♥");
+            }
+            content
         } else {
             // Display welcome message when no track is playing
             "🎵 Welcome to Music Player".to_string()
@@ -115,34 +226,120 @@ impl<'a> TrackListWidget<'a> {
             tracks,
             selected,
             playing,
+            rows: None,
+            title: "Track List",
+            stats: None,
+            offset: 0,
+            theme: &Theme::DARK,
         }
     }
+
+    /// Returns the list row shown at a terminal row, for mouse hit-testing.
+    ///
+    /// # Arguments
+    ///
+    /// * `area` - Area the list is rendered in
+    /// * `with_stats` - Whether the statistics columns (and their header) are shown
+    /// * `offset` - First row shown
+    /// * `row` - Terminal row
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` on the border and header; the row may be past the end
+    /// of the list.
+    pub fn row_at(area: Rect, with_stats: bool, offset: usize, row: u16) -> Option<usize> {
+        let first = area.y + 1 + with_stats as u16;
+        let end = (area.y + area.height).saturating_sub(1);
+        (row >= first && row < end).then(|| offset + (row - first) as usize)
+    }
+
+    /// Scrolls the list so that the row at `offset` is the first one shown.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Shows rating, play count, skip count and last-played columns.
+    ///
+    /// `stats` is indexed like `tracks`; `now` is the reference time for the
+    /// last-played ages.
+    pub fn stats(mut self, stats: &'a [TrackStats], now: SystemTime) -> Self {
+        self.stats = Some((stats, now));
+        self
+    }
+
+    /// Restricts the list to the given track indices, in order.
+    ///
+    /// `selected` then refers to a position in `rows`, while `playing` stays
+    /// an index into `tracks`.
+    pub fn rows(mut self, rows: &'a [usize]) -> Self {
+        self.rows = Some(rows);
+        self
+    }
+
+    /// Sets the block title (defaults to "Track List").
+    pub fn title(mut self, title: &'a str) -> Self {
+        self.title = title;
+        self
+    }
 }
 
 impl<'a> Widget for TrackListWidget<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         use ratatui::widgets::List;
         
+        // Resolve the rows to display (all tracks unless restricted)
+        let rows: Vec<usize> = match self.rows {
+            Some(rows) => rows.iter().copied().filter(|&i| i < self.tracks.len()).collect(),
+            None => (0..self.tracks.len()).collect(),
+        };
+
         // Handle empty list case
-        if self.tracks.is_empty() {
-            let empty_msg = Paragraph::new("未找到音频文件")
-                .style(Style::default().fg(Color::Gray))
+        if rows.is_empty() {
+            let message = if self.rows.is_some() && !self.tracks.is_empty() {
+                "播放列表为空"
+            } else {
+                "未找到音频文件"
+            };
+            let empty_msg = Paragraph::new(message)
+                .style(self.theme.style_dim())
                 .block(
                     Block::default()
                         .borders(Borders::ALL)
-                        .title("Track List")
+                        .border_style(self.theme.style_border())
+                        .title(self.title)
                 )
                 .alignment(Alignment::Center);
             empty_msg.render(area, buf);
             return;
         }
         
+        // Width left for the track name when statistics columns are shown
+        // (borders, sequence number, play icon and the columns)
+        let name_width = (area.width as usize).saturating_sub(2 + 5 + 2 + STATS_COLUMNS_WIDTH).max(8);
+
         // Build list items with sequence number, play icon (if playing), and track name
-        let items: Vec<ratatui::widgets::ListItem> = self.tracks
+        let mut items: Vec<ratatui::widgets::ListItem> = Vec::with_capacity(rows.len() + 1);
+        if self.stats.is_some() {
+            let header = format!("{:7}{:<name_width$}{}", "", "Title", stats_columns("Rating", "Plays", "Skips", "Last"));
+            items.push(
+                ratatui::widgets::ListItem::new(header)
+                    .style(self.theme.style_dim().add_modifier(Modifier::BOLD)),
+            );
+        }
+        items.extend(rows
             .iter()
             .enumerate()
-            .map(|(idx, track)| {
-                let play_icon = if Some(idx) == self.playing {
+            .skip(self.offset.min(rows.len() - 1))
+            .map(|(idx, &track_idx)| {
+                let track = &self.tracks[track_idx];
+                let play_icon = if Some(track_idx) == self.playing {
                     "▶ "
                 } else {
                     "  "
@@ -153,38 +350,104 @@ impl<'a> Widget for TrackListWidget<'a> {
                         .and_then(|n| n.to_str())
                         .unwrap_or("Unknown"));
                 
-                let content = format!("{:3}. {}{}", idx + 1, play_icon, track_name);
+                let content = match self.stats {
+                    Some((stats, now)) => {
+                        let stats = stats.get(track_idx).copied().unwrap_or_default();
+                        let last = stats.last_played.map(|t| library::format_age(t, now));
+                        format!(
+                            "{:3}. {}{}{}",
+                            idx + 1,
+                            play_icon,
+                            fit(track_name, name_width),
+                            stats_columns(
+                                &rating_column(stats.rating, stats.favorite),
+                                &stats.play_count.to_string(),
+                                &stats.skip_count.to_string(),
+                                last.as_deref().unwrap_or("-"),
+                            ),
+                        )
+                    }
+                    None => format!("{:3}. {}{}", idx + 1, play_icon, track_name),
+                };
                 
                 // Apply highlight style to selected track
                 let style = if idx == self.selected {
-                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                    self.theme.style_selected()
                 } else {
-                    Style::default().fg(Color::White)
+                    self.theme.style_text()
                 };
                 
                 ratatui::widgets::ListItem::new(content).style(style)
-            })
-            .collect();
+            }));
         
         // Create and render the list widget
         let list = List::new(items)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Track List")
+                    .border_style(self.theme.style_border())
+                    .title(self.title)
             );
         
         Widget::render(list, area, buf);
     }
 }
 
+/// Total width of the statistics columns of [`TrackListWidget`]
+const STATS_COLUMNS_WIDTH: usize = 25;
+
+/// Formats the rating column left-aligned and the plays, skips and
+/// last-played columns right-aligned.
+fn stats_columns(rating: &str, plays: &str, skips: &str, last: &str) -> String {
+    format!(" {:<6}{:>6}{:>6}{:>6}", rating, plays, skips, last)
+}
+
+/// Formats the rating column: a favorite heart followed by the stars.
+fn rating_column(rating: u8, favorite: bool) -> String {
+    format!("{}{}", if favorite { "♥" } else { " " }, stars(rating))
+}
+
+/// Renders a 1-5 star rating as filled and empty stars (empty when unrated).
+fn stars(rating: u8) -> String {
+    if rating == 0 {
+        return String::new();
+    }
+    let filled = rating.min(5) as usize;
+    format!("{}{}", "★".repeat(filled), "☆".repeat(5 - filled))
+}
+
+/// Truncates or pads text to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() > width {
+        let mut out: String = text.chars().take(width.saturating_sub(1)).collect();
+        out.push('…');
+        out
+    } else {
+        format!("{:<width$}", text)
+    }
+}
+
 impl<'a> VisualizationWidget<'a> {
     pub fn new(wave_data: &'a [u64], is_playing: bool) -> Self {
         Self {
             wave_data,
             is_playing,
+            mode: VisualizationMode::default(),
+            theme: &Theme::DARK,
         }
     }
+
+    /// Sets how the waveform is drawn.
+    pub fn mode(mut self, mode: VisualizationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
 }
 
 impl<'a> Widget for VisualizationWidget<'a> {
@@ -208,35 +471,77 @@ impl<'a> Widget for VisualizationWidget<'a> {
         
         // Apply color gradient style based on playback state
         let sparkline_style = if self.is_playing {
-            Style::default().fg(Color::Cyan)
+            Style::default().fg(self.theme.primary)
         } else {
-            Style::default().fg(Color::DarkGray)
+            self.theme.style_border()
         };
         
-        // Create sparkline widget with color gradient
-        let sparkline = Sparkline::default()
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("Visualization")
-            )
-            .data(&display_data)
-            .style(sparkline_style);
-        
-        sparkline.render(area, buf);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(self.theme.style_border())
+            .title("Visualization");
+        match self.mode {
+            VisualizationMode::Wave => {
+                // Create sparkline widget with color gradient
+                let sparkline = Sparkline::default()
+                    .block(block)
+                    .data(&display_data)
+                    .style(sparkline_style);
+                sparkline.render(area, buf);
+            }
+            VisualizationMode::Bars => {
+                // Each bar (two columns and a gap) averages three samples
+                let bars: Vec<(&str, u64)> = display_data
+                    .chunks(3)
+                    .map(|group| ("", group.iter().sum::<u64>() / group.len() as u64))
+                    .collect();
+                let chart = ratatui::widgets::BarChart::default()
+                    .block(block)
+                    .data(&bars)
+                    .bar_width(2)
+                    .bar_gap(1)
+                    .max(100)
+                    .bar_style(sparkline_style);
+                chart.render(area, buf);
+            }
+            VisualizationMode::Level => {
+                let level = display_data.last().copied().unwrap_or(0).min(100);
+                let gauge = ratatui::widgets::Gauge::default()
+                    .block(block)
+                    .gauge_style(sparkline_style)
+                    .percent(level as u16);
+                gauge.render(area, buf);
+            }
+        }
     }
 }
 
-impl PlaybackControlWidget {
+impl<'a> PlaybackControlWidget<'a> {
     pub fn new(position: Duration, total: Option<Duration>, volume: f32, status: PlaybackStatus) -> Self {
         Self {
             position,
             total,
             volume,
             status,
+            shuffle: false,
+            repeat: RepeatMode::Off,
+            theme: &Theme::DARK,
         }
     }
 
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Shows the shuffle and repeat settings next to the volume.
+    pub fn modes(mut self, shuffle: bool, repeat: RepeatMode) -> Self {
+        self.shuffle = shuffle;
+        self.repeat = repeat;
+        self
+    }
+
     /// Format duration as MM:SS
     fn format_time(duration: Duration) -> String {
         let total_secs = duration.as_secs();
@@ -245,6 +550,40 @@ impl PlaybackControlWidget {
         format!("{:02}:{:02}", minutes, seconds)
     }
 
+    /// Splits the area into rows for the progress bar (with border), the
+    /// time and volume info, and the control hints.
+    fn chunks(area: Rect) -> std::rc::Rc<[Rect]> {
+        use ratatui::layout::{Layout, Constraint};
+
+        Layout::default()
+            .direction(ratatui::layout::Direction::Vertical)
+            .constraints([
+                Constraint::Length(3), // Progress bar with border
+                Constraint::Length(1), // Time and volume info
+                Constraint::Length(1), // Control hints
+            ])
+            .split(area)
+    }
+
+    /// Returns the area of the progress bar, including its border.
+    pub fn progress_area(area: Rect) -> Rect {
+        Self::chunks(area)[0]
+    }
+
+    /// Returns the line showing the time and volume.
+    pub fn info_area(area: Rect) -> Rect {
+        Self::chunks(area)[1]
+    }
+
+    /// Returns the fraction of the track at a column of the progress bar,
+    /// for seeking by mouse.
+    pub fn progress_ratio_at(area: Rect, column: u16) -> f64 {
+        let bar = Self::progress_area(area);
+        let start = bar.x + 1;
+        let width = bar.width.saturating_sub(2).max(1);
+        (column.saturating_sub(start) as f64 / width as f64).min(1.0)
+    }
+
     /// Calculate progress ratio (0.0 to 1.0)
     fn progress_ratio(&self) -> f64 {
         if let Some(total) = self.total {
@@ -259,20 +598,11 @@ impl PlaybackControlWidget {
     }
 }
 
-impl Widget for PlaybackControlWidget {
+impl<'a> Widget for PlaybackControlWidget<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         use ratatui::widgets::{Gauge, Paragraph};
-        use ratatui::layout::{Layout, Constraint};
 
-        // Split the area into rows for different elements
-        let chunks = Layout::default()
-            .direction(ratatui::layout::Direction::Vertical)
-            .constraints([
-                Constraint::Length(3), // Progress bar with border
-                Constraint::Length(1), // Time and volume info
-                Constraint::Length(1), // Control hints
-            ])
-            .split(area);
+        let chunks = Self::chunks(area);
 
         // Render progress bar (occupies full width)
         let progress_ratio = self.progress_ratio();
@@ -287,8 +617,8 @@ impl Widget for PlaybackControlWidget {
         };
 
         let gauge = Gauge::default()
-            .block(Block::default().borders(Borders::ALL).title("Progress"))
-            .gauge_style(Style::default().fg(Color::Green).bg(Color::Black))
+            .block(Block::default().borders(Borders::ALL).border_style(self.theme.style_border()).title("Progress"))
+            .gauge_style(self.theme.style_progress())
             .ratio(progress_ratio)
             .label(progress_label);
 
@@ -302,10 +632,16 @@ impl Widget for PlaybackControlWidget {
         };
         
         let volume_percent = (self.volume * 100.0).round() as i32;
-        let info_text = format!("{}  |  Volume: {}%", time_str, volume_percent);
+        let mut info_text = format!("{}  |  Volume: {}%", time_str, volume_percent);
+        if self.shuffle {
+            info_text.push_str("  |  Shuffle");
+        }
+        if self.repeat != RepeatMode::Off {
+            info_text.push_str(&format!("  |  Repeat: {}", self.repeat));
+        }
         
         let info_paragraph = Paragraph::new(info_text)
-            .style(Style::default().fg(Color::White))
+            .style(self.theme.style_text())
             .alignment(Alignment::Center);
         
         info_paragraph.render(chunks[1], buf);
@@ -318,7 +654,7 @@ impl Widget for PlaybackControlWidget {
         };
         
         let hints_paragraph = Paragraph::new(hints)
-            .style(Style::default().fg(Color::Gray))
+            .style(self.theme.style_dim())
             .alignment(Alignment::Center);
         
         hints_paragraph.render(chunks[2], buf);
@@ -327,7 +663,26 @@ impl Widget for PlaybackControlWidget {
 
 impl<'a> StatusBarWidget<'a> {
     pub fn new(hints: &'a [(&'a str, &'a str)]) -> Self {
-        Self { hints }
+        Self { hints, mode: None, pending: "", theme: &Theme::DARK }
+    }
+
+    /// Shows the input mode (e.g. `NORMAL`) at the left edge.
+    pub fn mode(mut self, mode: &'a str) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Shows the keys typed so far of an incomplete command (e.g. a count)
+    /// at the right edge.
+    pub fn pending(mut self, pending: &'a str) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
     }
 }
 
@@ -343,10 +698,352 @@ impl<'a> Widget for StatusBarWidget<'a> {
         
         // Create paragraph with the hints
         let paragraph = Paragraph::new(content)
-            .style(Style::default().fg(Color::Gray))
+            .style(self.theme.style_dim())
             .alignment(Alignment::Center);
         
         paragraph.render(area, buf);
+
+        // Mode and pending keys are drawn over the ends of the hints
+        if let Some(mode) = self.mode {
+            buf.set_stringn(area.x, area.y, format!(" {} ", mode), area.width as usize, self.theme.style_highlight());
+        }
+        if !self.pending.is_empty() {
+            let label = format!(" {} ", self.pending);
+            let width = (label.chars().count() as u16).min(area.width);
+            buf.set_stringn(area.x + area.width - width, area.y, label, width as usize, self.theme.style_title());
+        }
+    }
+}
+
+impl<'a> PlaylistSidebarWidget<'a> {
+    /// Creates a sidebar listing `items` (the library entry followed by playlists).
+    ///
+    /// `active` marks the item whose tracks are currently shown, and `focused`
+    /// highlights the border while the sidebar receives keyboard input.
+    pub fn new(
+        items: &'a [&'a str],
+        selected: usize,
+        active: Option<usize>,
+        focused: bool,
+        theme: &'a Theme,
+    ) -> Self {
+        Self { items, selected, active, focused, theme }
+    }
+}
+
+impl<'a> Widget for PlaylistSidebarWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        use ratatui::widgets::{List, ListItem};
+
+        let items: Vec<ListItem> = self.items
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                let marker = if Some(idx) == self.active { "♫ " } else { "  " };
+                let style = if idx == self.selected && self.focused {
+                    self.theme.style_highlight()
+                } else if idx == self.selected {
+                    Style::default().fg(self.theme.highlight)
+                } else {
+                    self.theme.style_text()
+                };
+                ListItem::new(format!("{}{}", marker, name)).style(style)
+            })
+            .collect();
+
+        let border_style = if self.focused {
+            Style::default().fg(self.theme.primary)
+        } else {
+            self.theme.style_border()
+        };
+
+        let list = List::new(items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(border_style)
+                .title("Playlists"),
+        );
+
+        Widget::render(list, area, buf);
+    }
+}
+
+impl<'a> BookmarksWidget<'a> {
+    /// Creates a panel listing `bookmarks` with their positions.
+    ///
+    /// `focused` highlights the border and selection while the panel receives
+    /// keyboard input.
+    pub fn new(bookmarks: &'a [Bookmark], selected: usize, focused: bool, theme: &'a Theme) -> Self {
+        Self { bookmarks, selected, focused, theme }
+    }
+}
+
+impl<'a> Widget for BookmarksWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        use ratatui::widgets::{List, ListItem};
+
+        let border_style = if self.focused {
+            Style::default().fg(self.theme.primary)
+        } else {
+            self.theme.style_border()
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title("Bookmarks");
+
+        if self.bookmarks.is_empty() {
+            Paragraph::new("无书签 (b 添加)")
+                .style(self.theme.style_dim())
+                .block(block)
+                .alignment(Alignment::Center)
+                .render(area, buf);
+            return;
+        }
+
+        let items: Vec<ListItem> = self.bookmarks
+            .iter()
+            .enumerate()
+            .map(|(idx, mark)| {
+                let style = if idx == self.selected && self.focused {
+                    self.theme.style_highlight()
+                } else {
+                    self.theme.style_text()
+                };
+                ListItem::new(format!("{:>8}  {}", format_position(mark.position), mark.name)).style(style)
+            })
+            .collect();
+
+        Widget::render(List::new(items).block(block), area, buf);
+    }
+}
+
+impl<'a> HelpWidget<'a> {
+    /// Creates a help popup.
+    ///
+    /// # Arguments
+    ///
+    /// * `sections` - Section titles with their (keys, description) lines
+    /// * `scroll` - Number of lines scrolled past at the top
+    pub fn new(sections: &'a [(&'a str, Vec<(String, &'a str)>)], scroll: usize) -> Self {
+        Self { sections, scroll, theme: &Theme::DARK }
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Returns the number of lines the sections take up: a title per
+    /// section, its entries and a blank line between sections.
+    pub fn content_height(sections: &[(&str, Vec<(String, &str)>)]) -> usize {
+        sections.iter().map(|(_, entries)| entries.len() + 1).sum::<usize>() + sections.len().saturating_sub(1)
+    }
+
+    /// Returns the popup area: centered, leaving a margin around it.
+    fn popup_area(area: Rect) -> Rect {
+        let width = area.width.saturating_sub(4).clamp(area.width.min(20), 72);
+        let height = area.height.saturating_sub(2);
+        Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+    }
+}
+
+impl<'a> Widget for HelpWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        use ratatui::widgets::{Clear, Scrollbar, ScrollbarOrientation, ScrollbarState};
+
+        let popup = Self::popup_area(area);
+        Clear.render(popup, buf);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(self.theme.primary))
+            .title(" 帮助 (↑/↓ 滚动, Esc 关闭) ")
+            .style(self.theme.style_base());
+        let inner = block.inner(popup);
+        block.render(popup, buf);
+
+        // Keys are padded to a common column, capped so descriptions stay visible
+        let key_width = self.sections
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().map(|(keys, _)| keys.chars().count()))
+            .max()
+            .unwrap_or(0)
+            .min(inner.width as usize / 2);
+        let mut lines: Vec<Line> = Vec::new();
+        for (i, (title, entries)) in self.sections.iter().enumerate() {
+            if i > 0 {
+                lines.push(Line::default());
+            }
+            lines.push(Line::styled(*title, self.theme.style_title()));
+            lines.extend(entries.iter().map(|(keys, description)| {
+                Line::from(vec![
+                    Span::styled(format!("  {:<key_width$}  ", keys), self.theme.style_highlight()),
+                    Span::styled(*description, self.theme.style_text()),
+                ])
+            }));
+        }
+
+        let max_scroll = lines.len().saturating_sub(inner.height as usize);
+        let scroll = self.scroll.min(max_scroll);
+        Paragraph::new(lines).scroll((scroll as u16, 0)).render(inner, buf);
+
+        if max_scroll > 0 {
+            let mut state = ScrollbarState::new(max_scroll).position(scroll);
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .style(self.theme.style_dim())
+                .render(popup, buf, &mut state);
+        }
+    }
+}
+
+/// Returns the color of a notification level.
+fn level_color(theme: &Theme, level: Level) -> Color {
+    match level {
+        Level::Info => theme.accent,
+        Level::Warning => Color::Yellow,
+        Level::Error => Color::Red,
+    }
+}
+
+impl<'a> ToastWidget<'a> {
+    /// Creates the toast stack, oldest toast at the top.
+    pub fn new(toasts: &'a [&'a Notification]) -> Self {
+        Self { toasts, theme: &Theme::DARK }
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl<'a> Widget for ToastWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        use ratatui::widgets::Clear;
+
+        let mut y = area.y;
+        for toast in self.toasts {
+            if y + 3 > area.y + area.height {
+                break;
+            }
+            let line = Line::from(toast.message.as_str());
+            let width = (line.width() as u16 + 4).clamp(20, 60).min(area.width);
+            let popup = Rect::new(area.x + area.width - width, y, width, 3);
+            let color = level_color(self.theme, toast.level);
+            Clear.render(popup, buf);
+            Paragraph::new(line)
+                .style(self.theme.style_text())
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(color))
+                        .title(Span::styled(format!(" {} ", toast.level.label()), Style::default().fg(color)))
+                        .style(self.theme.style_base()),
+                )
+                .render(popup, buf);
+            y += 3;
+        }
+    }
+}
+
+impl<'a> MessageLogWidget<'a> {
+    /// Creates a message log popup.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - Notifications, oldest first
+    /// * `scroll` - Number of lines scrolled past at the top
+    /// * `now` - Current time, for the age of each message
+    pub fn new(log: &'a VecDeque<Notification>, scroll: usize, now: SystemTime) -> Self {
+        Self { log, scroll, now, theme: &Theme::DARK }
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Returns the number of lines the log takes up.
+    pub fn content_height(log: &VecDeque<Notification>) -> usize {
+        log.len().max(1)
+    }
+}
+
+impl<'a> Widget for MessageLogWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        use ratatui::widgets::{Clear, Scrollbar, ScrollbarOrientation, ScrollbarState};
+
+        let popup = HelpWidget::popup_area(area);
+        Clear.render(popup, buf);
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(self.theme.primary))
+            .title(" 消息记录 (↑/↓ 滚动, Esc 关闭) ")
+            .style(self.theme.style_base());
+        let inner = block.inner(popup);
+        block.render(popup, buf);
+
+        let lines: Vec<Line> = if self.log.is_empty() {
+            vec![Line::styled("没有消息", self.theme.style_dim())]
+        } else {
+            self.log
+                .iter()
+                .map(|n| {
+                    Line::from(vec![
+                        Span::styled(format!("{:>4} ", library::format_age(n.time, self.now)), self.theme.style_dim()),
+                        Span::styled(format!("{} ", n.level.label()), Style::default().fg(level_color(self.theme, n.level))),
+                        Span::styled(n.message.as_str(), self.theme.style_text()),
+                    ])
+                })
+                .collect()
+        };
+
+        let max_scroll = lines.len().saturating_sub(inner.height as usize);
+        let scroll = self.scroll.min(max_scroll);
+        Paragraph::new(lines).scroll((scroll as u16, 0)).render(inner, buf);
+
+        if max_scroll > 0 {
+            let mut state = ScrollbarState::new(max_scroll).position(scroll);
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .style(self.theme.style_dim())
+                .render(popup, buf, &mut state);
+        }
+    }
+}
+
+/// Formats a position as `M:SS`, or `H:MM:SS` from one hour on.
+fn format_position(position: Duration) -> String {
+    let secs = position.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+impl<'a> PromptWidget<'a> {
+    pub fn new(label: &'a str, input: &'a str) -> Self {
+        Self { label, input, theme: &Theme::DARK }
+    }
+
+    /// Sets the color theme.
+    pub fn theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+}
+
+impl<'a> Widget for PromptWidget<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let line = Line::from(vec![
+            Span::styled(format!("{}: ", self.label), Style::default().fg(self.theme.highlight)),
+            Span::styled(self.input, self.theme.style_text()),
+            Span::styled("█", self.theme.style_dim()),
+        ]);
+        Paragraph::new(line).render(area, buf);
     }
 }
 
@@ -389,6 +1086,7 @@ mod tests {
                 path: PathBuf::from("/test/path.mp3"),
                 duration: Some(Duration::from_secs(180)),
                 title: Some(title.clone()),
+                ..Default::default()
             };
             
             // Test with all playback statuses
@@ -423,6 +1121,7 @@ mod tests {
                 path: PathBuf::from("/test/path.mp3"),
                 duration: Some(Duration::from_secs(180)),
                 title: Some(title.clone()),
+                ..Default::default()
             };
             
             // Test Playing status - should contain play icon
@@ -456,6 +1155,15 @@ mod tests {
 
     // Unit test: When no track is playing, display welcome message
     // Validates: Requirements 2.3
+    #[test]
+    fn test_now_playing_shows_rating() {
+        let theme = Theme::default();
+        let track = Track { title: Some("So What".to_string()), ..Default::default() };
+        let widget = NowPlayingWidget::new(Some(&track), PlaybackStatus::Playing, &theme).rating(3, true);
+        let rendered = render_to_string(widget, 60, 3);
+        assert!(rendered.contains("So What  ★★★☆☆  ♥"), "{}", rendered);
+    }
+
     #[test]
     fn test_now_playing_shows_welcome_when_no_track() {
         let theme = Theme::default();
@@ -644,6 +1352,7 @@ mod tests {
                     path: PathBuf::from(format!("/test/track_{}.mp3", i)),
                     duration: Some(Duration::from_secs(180 + i as u64 * 10)),
                     title: Some(format!("Track {}", i + 1)),
+                    ..Default::default()
                 })
                 .collect();
             
@@ -693,6 +1402,7 @@ mod tests {
                     path: PathBuf::from(format!("/test/track_{}.mp3", i)),
                     duration: Some(Duration::from_secs(180 + i as u64 * 10)),
                     title: Some(format!("Track {}", i + 1)),
+                    ..Default::default()
                 })
                 .collect();
             
@@ -931,4 +1641,208 @@ mod tests {
             rendered
         );
     }
+
+    #[test]
+    fn test_track_list_rows_subset() {
+        let tracks: Vec<Track> = (0..4)
+            .map(|i| Track {
+                id: i,
+                path: PathBuf::from(format!("/test/track_{}.mp3", i)),
+                duration: None,
+                title: Some(format!("Song {}", i)),
+                ..Default::default()
+            })
+            .collect();
+        let rows = [3, 1];
+
+        let widget = TrackListWidget::new(&tracks, 0, Some(1)).rows(&rows).title("Mix");
+        let rendered = render_to_string(widget, 40, 6);
+
+        assert!(rendered.contains("Mix"));
+        assert!(rendered.contains("1. ") && rendered.contains("Song 3"));
+        assert!(rendered.contains("2. ▶ Song 1"));
+        assert!(!rendered.contains("Song 0") && !rendered.contains("Song 2"));
+
+        let empty = TrackListWidget::new(&tracks, 0, None).rows(&[]);
+        let rendered = render_to_string(empty, 40, 6);
+        assert!(rendered.contains("播") && rendered.contains("空"));
+    }
+
+    #[test]
+    fn test_track_list_offset_and_status_mode() {
+        let tracks: Vec<Track> = (0..10)
+            .map(|i| Track { id: i, title: Some(format!("Song {}", i)), ..Default::default() })
+            .collect();
+        let rendered = render_to_string(TrackListWidget::new(&tracks, 6, None).offset(5), 40, 5);
+        assert!(rendered.contains("6. ") && rendered.contains("Song 5"), "{}", rendered);
+        assert!(rendered.contains("8. ") && !rendered.contains("Song 4"), "{}", rendered);
+
+        let hints = [("q", "Quit")];
+        let rendered = render_to_string(StatusBarWidget::new(&hints).mode("NORMAL").pending("5g"), 40, 1);
+        assert!(rendered.starts_with(" NORMAL "), "{}", rendered);
+        assert!(rendered.trim_end().ends_with("5g") && rendered.contains("q:Quit"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_track_list_stats_columns() {
+        let tracks: Vec<Track> = (0..2)
+            .map(|i| Track {
+                id: i,
+                path: PathBuf::from(format!("/test/track_{}.mp3", i)),
+                title: Some(format!("Song {}", i)),
+                ..Default::default()
+            })
+            .collect();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(10 * 86_400);
+        let stats = [
+            TrackStats {
+                play_count: 12,
+                skip_count: 3,
+                last_played: Some(now - Duration::from_secs(2 * 86_400)),
+                rating: 4,
+                favorite: true,
+            },
+            TrackStats::default(),
+        ];
+
+        let widget = TrackListWidget::new(&tracks, 0, None).stats(&stats, now);
+        let rendered = render_to_string(widget, 60, 6);
+
+        assert!(rendered.contains("Rating") && rendered.contains("Plays") && rendered.contains("Last"));
+        assert!(rendered.contains("♥★★★★☆    12     3    2d"), "{}", rendered);
+        assert!(rendered.contains("     0     0     -"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_playback_control_shows_modes() {
+        let widget = PlaybackControlWidget::new(Duration::from_secs(5), None, 0.5, PlaybackStatus::Playing)
+            .modes(true, RepeatMode::All);
+        let rendered = render_to_string(widget, 80, 5);
+        assert!(rendered.contains("Shuffle") && rendered.contains("Repeat: all"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_widgets_use_theme_colors() {
+        let theme = Theme::builtin("gruvbox").unwrap();
+        let tracks = [Track { id: 0, path: PathBuf::from("/a.mp3"), title: Some("A".to_string()), ..Default::default() }];
+        let area = Rect::new(0, 0, 30, 4);
+
+        let mut buffer = Buffer::empty(area);
+        TrackListWidget::new(&tracks, 0, None).theme(&theme).render(area, &mut buffer);
+        assert_eq!(buffer.get(0, 0).fg, theme.border);
+        assert_eq!(buffer.get(1, 1).fg, theme.highlight);
+
+        let mut buffer = Buffer::empty(area);
+        StatusBarWidget::new(&[("q", "Quit")]).theme(&theme).render(area, &mut buffer);
+        assert_eq!(buffer.get(0, 0).fg, theme.text_dim);
+
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 30, 5));
+        PlaybackControlWidget::new(Duration::ZERO, None, 1.0, PlaybackStatus::Playing)
+            .theme(&theme)
+            .render(Rect::new(0, 0, 30, 5), &mut buffer);
+        assert_eq!(buffer.get(0, 0).fg, theme.border);
+        assert_eq!(buffer.get(1, 1).bg, theme.background);
+    }
+
+    #[test]
+    fn test_bookmarks_list_positions() {
+        let theme = Theme::default();
+        let marks = [
+            Bookmark { name: "Intro".to_string(), position: Duration::from_secs(75) },
+            Bookmark { name: "Chapter 3".to_string(), position: Duration::from_secs(3 * 3600 + 5) },
+        ];
+        let rendered = render_to_string(BookmarksWidget::new(&marks, 1, true, &theme), 40, 5);
+        assert!(rendered.contains("Bookmarks"));
+        assert!(rendered.contains("1:15  Intro"), "{}", rendered);
+        assert!(rendered.contains("3:00:05  Chapter 3"), "{}", rendered);
+
+        let empty = render_to_string(BookmarksWidget::new(&[], 0, false, &theme), 40, 5);
+        assert!(empty.contains("书") && empty.contains("签"));
+    }
+
+    #[test]
+    fn test_toasts_and_message_log() {
+        use crate::notify::Level;
+
+        let error = Notification::new(Level::Error, "cannot save");
+        let info = Notification::new(Level::Info, "saved");
+        let toasts = [&error, &info];
+        let area = Rect::new(0, 0, 60, 10);
+        let mut buffer = Buffer::empty(area);
+        ToastWidget::new(&toasts).render(area, &mut buffer);
+        let row = |y: u16| (0..60).map(|x| buffer.get(x, y).symbol()).collect::<String>();
+        // Stacked at the right edge, oldest first
+        assert!(row(0).starts_with(&" ".repeat(40)) && row(0).contains("错"), "{}", row(0));
+        assert!(row(1).contains("│cannot save"), "{}", row(1));
+        assert!(row(3).contains("信") && row(4).contains("│saved"), "{}", row(4));
+        // Toasts that do not fit are left out
+        assert!(!render_to_string(ToastWidget::new(&toasts), 60, 5).contains("saved"));
+
+        let log: VecDeque<Notification> = [error.clone(), info.clone()].into();
+        let rendered = render_to_string(MessageLogWidget::new(&log, 0, SystemTime::now()), 60, 10);
+        assert!(rendered.contains("消"), "{}", rendered);
+        assert!(rendered.contains(" now 错") && rendered.contains("cannot save"), "{}", rendered);
+        assert_eq!(MessageLogWidget::content_height(&log), 2);
+        let empty = VecDeque::new();
+        assert!(render_to_string(MessageLogWidget::new(&empty, 0, SystemTime::now()), 60, 10).contains("没"));
+    }
+
+    #[test]
+    fn test_mouse_geometry() {
+        let area = Rect::new(10, 20, 42, 5);
+        assert_eq!(PlaybackControlWidget::progress_area(area), Rect::new(10, 20, 42, 3));
+        assert_eq!(PlaybackControlWidget::info_area(area), Rect::new(10, 23, 42, 1));
+        assert_eq!(PlaybackControlWidget::progress_ratio_at(area, 11), 0.0);
+        assert_eq!(PlaybackControlWidget::progress_ratio_at(area, 31), 0.5);
+        assert_eq!(PlaybackControlWidget::progress_ratio_at(area, 60), 1.0);
+
+        let list = Rect::new(0, 5, 40, 10);
+        assert_eq!(TrackListWidget::row_at(list, false, 0, 5), None);
+        assert_eq!(TrackListWidget::row_at(list, false, 0, 6), Some(0));
+        assert_eq!(TrackListWidget::row_at(list, true, 7, 8), Some(8));
+        assert_eq!(TrackListWidget::row_at(list, true, 0, 14), None);
+    }
+
+    #[test]
+    fn test_visualization_modes() {
+        let wave: Vec<u64> = (0..30).map(|i| i * 3).collect();
+        let mut mode = VisualizationMode::default();
+        for _ in 0..3 {
+            let rendered = render_to_string(VisualizationWidget::new(&wave, true).mode(mode), 30, 8);
+            assert!(rendered.contains("Visualization"), "{:?}: {}", mode, rendered);
+            mode = mode.next();
+        }
+        assert_eq!(mode, VisualizationMode::Wave);
+        let level = render_to_string(VisualizationWidget::new(&wave, true).mode(VisualizationMode::Level), 30, 5);
+        assert!(level.contains("87%"), "{}", level);
+    }
+
+    #[test]
+    fn test_help_sections_and_scroll() {
+        let sections = [
+            ("全局", vec![("q".to_string(), "退出"), ("↑, k".to_string(), "上移")]),
+            ("命令", (0..20).map(|i| (format!(":cmd{}", i), "命令")).collect()),
+        ];
+        assert_eq!(HelpWidget::content_height(&sections), 2 + 1 + 1 + 21);
+
+        let rendered = render_to_string(HelpWidget::new(&sections, 0), 60, 14);
+        assert!(rendered.contains("帮") && rendered.contains("全") && rendered.contains("↑, k"), "{}", rendered);
+        assert!(!rendered.contains(":cmd19"));
+
+        // Scrolling past the end stops at the last line
+        let rendered = render_to_string(HelpWidget::new(&sections, 100), 60, 14);
+        assert!(rendered.contains(":cmd19") && !rendered.contains("↑, k"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_playlist_sidebar_marks_active() {
+        let theme = Theme::default();
+        let items = ["Library", "jazz", "road trip"];
+        let widget = PlaylistSidebarWidget::new(&items, 2, Some(1), true, &theme);
+        let rendered = render_to_string(widget, 24, 6);
+
+        assert!(rendered.contains("Playlists"));
+        assert!(rendered.contains("♫ jazz"));
+        assert!(rendered.contains("  road trip"));
+    }
 }